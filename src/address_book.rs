@@ -0,0 +1,129 @@
+//! Export of the payout address of every farm.
+//!
+//! Corrections are usually sent per farm, so the treasury needs a single address and amount for
+//! every farm. The address book lists, for every farm with titan nodes in the report, the payout
+//! address used in the latest receipt of the farm together with the total amount owed to the
+//! farm for the periods its nodes were in it. Farms which used multiple payout addresses are
+//! flagged, so they can be checked before anything is sent.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use trace_titans::{format_diff_tft, tolerance::Tolerances, NodeResult};
+
+/// Address book entry of a single farm.
+#[derive(Serialize)]
+pub struct FarmEntry {
+    pub farm_id: u32,
+    /// Name of the farm in the latest receipt.
+    pub farm_name: String,
+    /// Payout address in the latest receipt.
+    pub payout_address: String,
+    /// Total amount of TFT owed to the farm, negative if the farm received too much.
+    pub total_owed: String,
+    /// Amount of titan nodes of the farm in the report.
+    pub nodes: usize,
+    /// Indicates the farm used more than one payout address in the scanned periods.
+    pub conflict: bool,
+    /// All payout addresses used by the farm, in alphabetical order.
+    pub addresses: BTreeSet<String>,
+}
+
+/// Build the address book from the reported nodes.
+///
+/// Every period of a node is attributed to the farm it has in its receipt of the period, so a node
+/// which moved between farms is owed by each farm what it missed while in that farm.
+pub fn build(node_results: &[(u32, NodeResult)], tolerances: &Tolerances) -> Vec<FarmEntry> {
+    // farm id -> (latest period index, entry, owed in TFT units)
+    let mut farms = BTreeMap::<u32, (usize, FarmEntry, i64)>::new();
+    for (_, result) in node_results {
+        for (farm_id, part) in result.by_farm() {
+            let (latest_idx, entry, owed) = farms.entry(farm_id).or_insert_with(|| {
+                (
+                    0,
+                    FarmEntry {
+                        farm_id,
+                        farm_name: String::new(),
+                        payout_address: String::new(),
+                        total_owed: String::new(),
+                        nodes: 0,
+                        conflict: false,
+                        addresses: BTreeSet::new(),
+                    },
+                    0,
+                )
+            });
+            entry.nodes += 1;
+            *owed += part.difference(tolerances);
+            for (idx, period) in part.into_iter().enumerate() {
+                if period.farm_id == 0 {
+                    continue;
+                }
+                entry.addresses.insert(period.payout_address.clone());
+                if idx >= *latest_idx {
+                    *latest_idx = idx;
+                    entry.farm_name.clone_from(&period.farm_name);
+                    entry.payout_address.clone_from(&period.payout_address);
+                }
+            }
+        }
+    }
+
+    farms
+        .into_values()
+        .map(|(_, mut entry, owed)| {
+            entry.total_owed = format_diff_tft(owed);
+            entry.conflict = entry.addresses.len() > 1;
+            entry
+        })
+        .collect()
+}
+
+/// Write the address book to a file. Files with a `.json` extension are written as JSON, all
+/// others as CSV.
+pub fn write(path: &Path, entries: &[FarmEntry]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::to_writer_pretty(&mut file, entries)?;
+        writeln!(file)?;
+    } else {
+        writeln!(
+            file,
+            "farm_id,farm_name,payout_address,total owed TFT,nodes,conflict,addresses"
+        )?;
+        for entry in entries {
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{}",
+                entry.farm_id,
+                csv_field(&entry.farm_name),
+                entry.payout_address,
+                entry.total_owed,
+                entry.nodes,
+                entry.conflict,
+                entry
+                    .addresses
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(";")
+            )?;
+        }
+    }
+    file.flush()
+}
+
+/// Quote a free form value for use in a CSV file if needed.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,151 @@
+//! BLAKE2b hashing, following RFC 7693.
+//!
+//! Minting hashes receipts with BLAKE2b to form the memo of their payment, TFChain hashes the keys
+//! of some storage entries with a 16 byte BLAKE2b digest. Like [`crate::sha256`], speed doesn't
+//! matter and a small implementation is preferred over an additional dependency.
+
+const IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// Size of a block of input, in bytes.
+const BLOCK: usize = 128;
+
+/// Hash of the data, with a 32 byte digest.
+pub fn digest(data: &[u8]) -> [u8; 32] {
+    digest_sized(data)
+}
+
+/// Hash of the data with a digest of `OUT` bytes, at most 64.
+pub fn digest_sized<const OUT: usize>(data: &[u8]) -> [u8; OUT] {
+    let mut h = IV;
+    // Parameter block: digest length, no key, fanout and depth of 1.
+    h[0] ^= 0x0101_0000 ^ OUT as u64;
+
+    // The last block is always compressed with the final flag, even if it is full or empty.
+    let blocks = data.len().div_ceil(BLOCK).max(1);
+    for i in 0..blocks {
+        let chunk = &data[i * BLOCK..data.len().min((i + 1) * BLOCK)];
+        let mut block = [0u8; BLOCK];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let counter = (i * BLOCK + chunk.len()) as u128;
+        compress(&mut h, &block, counter, i == blocks - 1);
+    }
+
+    let mut out = [0u8; OUT];
+    for (chunk, word) in out.chunks_mut(8).zip(h) {
+        chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+    }
+    out
+}
+
+fn compress(h: &mut [u64; 8], block: &[u8; BLOCK], counter: u128, last: bool) {
+    let mut m = [0u64; 16];
+    for (word, bytes) in m.iter_mut().zip(block.chunks_exact(8)) {
+        *word = u64::from_le_bytes(bytes.try_into().expect("Chunks are 8 bytes"));
+    }
+
+    let mut v = [0u64; 16];
+    v[..8].copy_from_slice(h);
+    v[8..].copy_from_slice(&IV);
+    v[12] ^= counter as u64;
+    v[13] ^= (counter >> 64) as u64;
+    if last {
+        v[14] = !v[14];
+    }
+
+    for round in 0..12 {
+        let s = &SIGMA[round % 10];
+        mix(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+        mix(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+        mix(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+        mix(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+        mix(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+        mix(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+        mix(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+        mix(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+    }
+
+    for i in 0..8 {
+        h[i] ^= v[i] ^ v[i + 8];
+    }
+}
+
+fn mix(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Bytes of a length which don't repeat within a block.
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn known_digests() {
+        assert_eq!(
+            hex(&digest_sized::<64>(b"abc")),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        assert_eq!(
+            hex(&digest_sized::<16>(b"abc")),
+            "cf4ab791c62b8d2b2109c90275287816"
+        );
+        // An empty input, a full block and a block with a byte more.
+        for (len, expected) in [
+            (
+                0,
+                "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8",
+            ),
+            (
+                3,
+                "3d8c3d594928271f44aad7a04b177154806867bcf918e1549c0bc16f9da2b09b",
+            ),
+            (
+                128,
+                "c3582f71ebb2be66fa5dd750f80baae97554f3b015663c8be377cfcb2488c1d1",
+            ),
+            (
+                129,
+                "f7f3c46ba2564ff4c4c162da1f5b605f9f1c4aa6a20652a9f9a337c1a2f5b9c9",
+            ),
+        ] {
+            assert_eq!(hex(&digest(&data(len))), expected, "{len} bytes");
+        }
+    }
+}
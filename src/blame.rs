@@ -0,0 +1,97 @@
+//! History of the receipts of a node in a git repository of receipts.
+//!
+//! Receipts are sometimes republished with fixes, so the numbers of a node can change after a
+//! report was made. Blame walks the commits which touched the receipts of a node, and shows which
+//! of the values the payout depends on every commit changed.
+
+use trace_titans::{
+    format_tft,
+    receipt::{FixupReceipt, ReceiptSummary},
+    source::GitSource,
+    Error,
+};
+
+use crate::cli::Args;
+
+/// Print the history of the receipts of a node in the scanned periods, up to the revision of
+/// `--at` or `HEAD`.
+pub fn blame(args: &Args, node_id: u32) -> Result<(), Error> {
+    let source = GitSource::open(args.receipts_dir(), args.at.as_deref().unwrap_or("HEAD"))?
+        .with_period_dirs(args.period_dirs.clone());
+    println!("Node {node_id}, receipts up to commit {}", source.commit());
+    for &period in &args.periods {
+        let paths = source.node_files(period, node_id, &crate::print_event)?;
+        if paths.is_empty() {
+            println!("Period {period}: no receipt");
+        }
+        for path in paths {
+            println!("Period {period}, {path}:");
+            let history = source.history(&path)?;
+            let commits = history.iter().map(|(c, _)| c.as_str()).collect::<Vec<_>>();
+            let revisions = source.read_revisions(&path, &commits)?;
+            let mut previous: Option<Vec<(&str, String)>> = None;
+            for ((commit, date), data) in history.iter().zip(revisions) {
+                let change = match data {
+                    None => {
+                        previous = None;
+                        "removed".to_string()
+                    }
+                    Some(data) => match serde_json::from_slice::<ReceiptSummary>(&data) {
+                        Err(e) => {
+                            previous = None;
+                            match serde_json::from_slice::<FixupReceipt>(&data) {
+                                Ok(fixup) => format!(
+                                    "fixup, reward {} TFT",
+                                    format_tft(fixup.fixup_reward.tft)
+                                ),
+                                Err(_) => format!("not a valid receipt: {e}"),
+                            }
+                        }
+                        Ok(receipt) => {
+                            let current = payout_values(&receipt);
+                            let change = describe(previous.as_deref(), &current);
+                            previous = Some(current);
+                            change
+                        }
+                    },
+                };
+                println!("  {} {date}: {change}", &commit[..commit.len().min(12)]);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The values of a receipt the payout depends on, with their name.
+fn payout_values(receipt: &ReceiptSummary) -> Vec<(&'static str, String)> {
+    vec![
+        ("farming policy", receipt.farming_policy_id().to_string()),
+        ("node type", receipt.node_type.to_string()),
+        ("cu", receipt.cloud_units.cu.to_string()),
+        ("su", receipt.cloud_units.su.to_string()),
+        ("nu", receipt.cloud_units.nu.to_string()),
+        ("ip", receipt.resource_utilization.ip.to_string()),
+        ("measured uptime", receipt.measured_uptime.to_string()),
+        ("connection price", receipt.tft_connection_price.to_string()),
+        ("reward", format!("{} TFT", format_tft(receipt.reward.tft))),
+    ]
+}
+
+/// Describe how the values changed from the previous revision of a receipt.
+fn describe(previous: Option<&[(&str, String)]>, current: &[(&str, String)]) -> String {
+    let Some(previous) = previous else {
+        let reward = &current[current.len() - 1];
+        return format!("added, {} {}", reward.0, reward.1);
+    };
+    let changes = previous
+        .iter()
+        .zip(current)
+        .filter(|((_, before), (_, after))| before != after)
+        .map(|((name, before), (_, after))| format!("{name} {before} → {after}"))
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        "no change affecting the payout".to_string()
+    } else {
+        changes.join(", ")
+    }
+}
@@ -0,0 +1,173 @@
+//! Bundle of everything known about a node, to attach to a support ticket.
+//!
+//! A farmer questioning their compensation needs the same evidence as the person answering them:
+//! the receipts as minting published them, what was calculated from them and why. The bundle is
+//! a ZIP archive with:
+//!
+//! - `receipts/<period>/`: the raw minting and fixup receipts of the node;
+//! - `results.json`: the result of every period, with the breakdown of the expected reward;
+//! - `findings.json`: the findings about the node and its payout addresses;
+//! - `explain/<period>.txt`: the output of `explain` for every period with a receipt;
+//! - `bundle.json`: what the bundle was made from.
+
+use std::{collections::BTreeSet, path::Path};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use trace_titans::{
+    cancel, date, format_diff_tft, format_percentage, format_tft,
+    pipeline::{self, Metrics},
+    source::{self, ReceiptSource},
+    tolerance, Error, NodePeriodResult,
+};
+
+use crate::{cli::Args, explain, labels::NodeLabels, received, subperiod::UptimeEvents, zip::Zip};
+
+/// The node id of a minting or fixup receipt.
+#[derive(Deserialize)]
+struct NodeOf {
+    node_id: u32,
+}
+
+/// Write the bundle of a node over the scanned periods to a file.
+pub fn write(
+    args: &Args,
+    source: &dyn ReceiptSource,
+    node_id: u32,
+    path: &Path,
+) -> Result<(), Error> {
+    let labels = match &args.labels {
+        Some(path) => NodeLabels::load(path)?,
+        None => NodeLabels::default(),
+    };
+    let events = args
+        .uptime_events
+        .as_deref()
+        .map(UptimeEvents::load)
+        .transpose()?;
+    let mut node_receipts = pipeline::load(
+        source,
+        &args.periods,
+        args.pipeline,
+        &Metrics::default(),
+        &crate::print_event,
+    )?;
+    crate::check_receipts(&node_receipts, args)?;
+    if cancel::is_interrupted() {
+        return Err("run interrupted, nothing is written from partial results".into());
+    }
+    if args.received_from_chain {
+        received::reconcile(&mut node_receipts, args, source)?;
+    }
+    if node_receipts
+        .get(&node_id)
+        .is_none_or(|receipts| receipts.is_empty())
+    {
+        return Err(format!("node {node_id} has no receipts in the scanned periods").into());
+    }
+
+    let mut zip = Zip::default();
+    let mut periods = Vec::new();
+    let (mut total_expected, mut total_received) = (0, 0);
+    let mut addresses = BTreeSet::new();
+    for &period in &args.periods {
+        let Some(parsed) = node_receipts[&node_id].get(&period) else {
+            periods.push(json!({ "period": period, "receipt": null }));
+            continue;
+        };
+        let r = NodePeriodResult::from_parsed(
+            parsed,
+            &args.policies,
+            args.titan_signal,
+            args.uptime_sla.unwrap_or_default(),
+        );
+        total_expected += r.expected_payout;
+        total_received += r.actual_payout;
+        addresses.insert(r.payout_address.clone());
+        periods.push(json!({
+            "period": period,
+            "receipt": parsed.origin,
+            "farm_id": r.farm_id,
+            "twin_id": r.twin_id,
+            "payout_address": r.payout_address,
+            "farming_policy": r.farming_policy,
+            "titan": r.is_titan(),
+            "uptime": format_percentage(r.uptime_percentage),
+            "expected_tft": format_tft(r.expected_payout),
+            "received_tft": format_tft(r.actual_payout),
+            "fixup_tft": format_tft(r.fixup_payout),
+            "recomputed_tft": format_tft(r.recomputed_payout),
+            "expected": r.expected,
+        }));
+
+        let mut transcript = Vec::new();
+        explain::write(&mut transcript, parsed, events.as_ref(), args)?;
+        zip.add(&format!("explain/{period}.txt"), &transcript)?;
+    }
+    let difference = tolerance::denoise(
+        total_expected as i64 - total_received as i64,
+        args.tolerances.difference,
+    );
+    let results = json!({
+        "node_id": node_id,
+        "periods": periods,
+        "total_expected_tft": format_tft(total_expected),
+        "total_received_tft": format_tft(total_received),
+        "difference_tft": format_diff_tft(difference),
+    });
+    zip.add("results.json", &pretty(&results)?)?;
+
+    let (_, findings) = crate::analyze(node_receipts, args, &labels, &crate::statuses(args)?)?;
+    let findings = findings
+        .iter()
+        .filter(|f| match f.node_id {
+            Some(id) => id == node_id,
+            None => addresses
+                .iter()
+                .any(|address| f.message.contains(address.as_str())),
+        })
+        .collect::<Vec<_>>();
+    zip.add("findings.json", &pretty(&serde_json::to_value(&findings)?)?)?;
+
+    let mut receipts = Vec::new();
+    for &period in &args.periods {
+        for receipt in source.discover(period, &crate::print_event)? {
+            let raw = source.read(&receipt)?;
+            if raw.parse::<NodeOf>()?.node_id != node_id {
+                continue;
+            }
+            let name = Path::new(&raw.origin)
+                .file_name()
+                .map(|name| source::strip_compression(&name.to_string_lossy()).to_string())
+                .filter(|name| name.ends_with(".json"))
+                .unwrap_or_else(|| format!("{}.json", receipts.len() + 1));
+            let file = format!("receipts/{period}/{name}");
+            zip.add(&file, raw.data())?;
+            receipts.push(json!({ "period": period, "origin": raw.origin, "file": file }));
+        }
+    }
+
+    let manifest = json!({
+        "node_id": node_id,
+        "periods": args.periods,
+        "created_at": date::format_timestamp(date::now()),
+        "receipts": receipts,
+    });
+    zip.add("bundle.json", &pretty(&manifest)?)?;
+    zip.write(path)?;
+    eprintln!(
+        "Bundled {} receipt(s) and {} finding(s) of node {node_id} in {}",
+        receipts.len(),
+        findings.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Pretty printed JSON, with a trailing newline.
+fn pretty(value: &Value) -> Result<Vec<u8>, Error> {
+    let mut data = serde_json::to_vec_pretty(value)?;
+    data.push(b'\n');
+    Ok(data)
+}
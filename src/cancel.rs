@@ -0,0 +1,83 @@
+//! Graceful cancellation of a run.
+//!
+//! The first interrupt (Ctrl-C) only sets a flag, which makes the pipeline stop loading new
+//! receipts so the receipts loaded so far can still be reported. The handler then restores the
+//! default behavior, so a second interrupt terminates the process immediately.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the process received an interrupt.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Indicates if the process was interrupted.
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Set the flag as the interrupt handler does, or clear it again.
+#[cfg(test)]
+pub(crate) fn set_interrupted(interrupted: bool) {
+    INTERRUPTED.store(interrupted, Ordering::Relaxed);
+}
+
+/// Install the interrupt handler.
+pub fn install_handler() {
+    sys::install();
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: i32 = 2;
+    const SIG_DFL: usize = 0;
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_interrupt(_: i32) {
+        super::INTERRUPTED.store(true, Ordering::Relaxed);
+        // Both operations are async signal safe.
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    pub fn install() {
+        unsafe {
+            signal(SIGINT, on_interrupt as extern "C" fn(i32) as usize);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::sync::atomic::Ordering;
+
+    const CTRL_C_EVENT: u32 = 0;
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    extern "system" fn on_ctrl(event: u32) -> i32 {
+        // Only handle the first Ctrl-C, afterwards fall through to the default handler.
+        if event == CTRL_C_EVENT && !super::INTERRUPTED.swap(true, Ordering::Relaxed) {
+            1
+        } else {
+            0
+        }
+    }
+
+    pub fn install() {
+        unsafe {
+            SetConsoleCtrlHandler(Some(on_ctrl), 1);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    pub fn install() {}
+}
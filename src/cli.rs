@@ -0,0 +1,1244 @@
+//! Command line argument handling.
+//!
+//! The tool only takes a handful of flags, so they are parsed by hand rather than pulling in an
+//! argument parsing library.
+
+use std::{
+    fmt,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use trace_titans::{
+    hardware::{HardwareClass, SpecTolerances},
+    period::Period,
+    pipeline::PipelineConfig,
+    policy::{Policies, TitanSignal, UptimeSla},
+    receipt::ResourceRewards,
+    source::PeriodDirs,
+    tolerance::Tolerances,
+    NumberStyle,
+};
+
+use crate::{
+    config::{self, Config},
+    lang::Lang,
+    ledger::Status,
+    lock::LockMode,
+    missing::MissingPeriods,
+    query::{Column, Query},
+    serve::Schedule,
+    subperiod::Split,
+};
+
+/// Environment variable with the receipts directory, used if `--receipts-dir` is not given.
+pub const RECEIPTS_DIR_ENV: &str = "TRACE_TITANS_RECEIPTS_DIR";
+
+/// Options for a run of the tool.
+#[derive(Debug, Default)]
+pub struct Args {
+    /// What to do.
+    pub command: Command,
+    /// Offsets of the periods to scan, in order.
+    pub periods: Vec<u32>,
+    /// File attaching labels to nodes.
+    pub labels: Option<PathBuf>,
+    /// Only report nodes which carry at least one of these labels. Empty means no filtering.
+    pub label_filter: Vec<String>,
+    /// Only report nodes with one of these hardware classes. Empty means no filtering.
+    pub class_filter: Vec<HardwareClass>,
+    /// Only report nodes with one of these statuses in the ledger. Empty means no filtering.
+    pub status_filter: Vec<Status>,
+    /// Only report these nodes. Empty means no filtering.
+    pub node_filter: Vec<u32>,
+    /// Only report nodes of one of these farms. Empty means no filtering.
+    pub farm_filter: Vec<u32>,
+    /// Only report nodes of one of these twins. Empty means no filtering.
+    pub twin_filter: Vec<u32>,
+    /// Only report nodes matching this expression.
+    pub query: Option<Query>,
+    /// Derived columns from the configuration file, added to every row of a node.
+    pub columns: Vec<Column>,
+    /// Only report nodes which are owed more than this amount, in TFT units.
+    pub min_diff: Option<u64>,
+    /// Only report nodes which received more than this amount too much, in TFT units. With
+    /// `min_diff`, nodes matching either are reported.
+    pub max_diff: Option<u64>,
+    /// Aggregate rows of the report instead of printing one row per node.
+    pub group_by: Option<GroupBy>,
+    /// How the report is written.
+    pub format: ReportFormat,
+    /// Directory with the period directories, the working directory if not set.
+    pub receipts_dir: Option<PathBuf>,
+    /// Directories with period directories to take receipts from if they are missing in the
+    /// receipts directory, in priority order.
+    pub fallback_dirs: Vec<PathBuf>,
+    /// Read receipt files which are symbolic links instead of skipping them.
+    pub follow_symlinks: bool,
+    /// How the period directories of the receipts and fallback directories are named.
+    pub period_dirs: PeriodDirs,
+    /// Read the period directories from this revision of the git repository in the receipts
+    /// directory, instead of the files.
+    pub at: Option<String>,
+    /// What to do with scanned periods which have no directory in the receipts directory.
+    pub missing: MissingPeriods,
+    /// Minting API to fetch missing periods from, the public one if not set.
+    pub minting_url: Option<String>,
+    /// Read receipts from this CSV file instead of the period directories.
+    pub csv: Option<PathBuf>,
+    /// File mapping receipt fields to the columns of the CSV file.
+    pub csv_mapping: Option<PathBuf>,
+    /// Language of the report headers, HTML and PDF outputs.
+    pub lang: Lang,
+    /// Tolerances of the amount comparisons.
+    pub tolerances: Tolerances,
+    /// How amounts are written in the outputs for people.
+    pub number_styles: NumberStyles,
+    /// File with the total payout of every period published by minting, to check the receipts
+    /// against.
+    pub published_totals: Option<PathBuf>,
+    /// Cross-check the reward of every receipt against the calculation of earlier versions.
+    pub cross_check_legacy: bool,
+    /// Take the received payouts from the payments on Stellar instead of the receipts.
+    pub received_from_chain: bool,
+    /// Registry of farms which opted out of compensation.
+    pub opt_outs: Option<PathBuf>,
+    /// SSH allowed signers file, of which one must have signed the registry of opt-outs.
+    pub opt_outs_signers: Option<PathBuf>,
+    /// Let `approve` and `pay` use a registry of opt-outs which isn't signed.
+    pub allow_unsigned_opt_outs: bool,
+    /// File or URL of the historical TFT prices, to value the differences in USD.
+    pub prices: Option<String>,
+    /// Add the carbon offset of every period to the report.
+    pub include_carbon: bool,
+    /// Append the totals over all reported nodes to the report.
+    pub summary: bool,
+    /// Append the start and end dates of the scanned periods to the report.
+    pub period_dates: bool,
+    /// Farms which receive the carbon offset separately, of which it is counted as part of the
+    /// expected and received payout.
+    pub carbon_farms: Vec<u32>,
+    /// Reward rates of the titan policy in every period, from the configuration file.
+    pub titan_rates: Option<ResourceRewards>,
+    /// node_type values of certified nodes from the configuration file, empty to use the default.
+    pub certified_node_types: Vec<String>,
+    /// URL of a TFChain node or file with policy snapshots, to take the reward rates of the titan
+    /// policy in every period from.
+    pub policy_rates: Option<String>,
+    /// The policies the expected payouts are calculated with, set from the reward rates and node
+    /// types once the rates are loaded.
+    pub policies: Policies,
+    /// Which signal decides if a node is a titan when its farming policy and node type disagree.
+    pub titan_signal: TitanSignal,
+    /// How periods below the minimal uptime of the titan policy are handled, which also adds a
+    /// column marking them to the report. Not set means they are only counted as usual.
+    pub uptime_sla: Option<UptimeSla>,
+    /// Refuse receipts which don't declare their farming policy, instead of assuming the default.
+    pub no_policy_default: bool,
+    /// Tolerances of the comparison of node hardware with the titan spec.
+    pub spec_tolerances: SpecTolerances,
+    /// Split the period of `explain` in windows.
+    pub split: Option<Split>,
+    /// Uptime reports of nodes, to attribute rewards to the windows of a split period.
+    pub uptime_events: Option<PathBuf>,
+    /// Write the report to this file instead of stdout.
+    pub output: Option<PathBuf>,
+    /// Add the time of the run to the name of the `output` file.
+    pub output_timestamp: bool,
+    /// Write the payout address book of all reported farms to this file.
+    pub address_book: Option<PathBuf>,
+    /// Write an HTML comparison of declared, recomputed and expected payouts to this file.
+    pub html: Option<PathBuf>,
+    /// Write all findings to this file.
+    pub findings: Option<PathBuf>,
+    /// Write the receipts which could not be used, with the field which is wrong, to this file.
+    pub invalid_receipts: Option<PathBuf>,
+    /// Write all findings as issue tracker items to this file.
+    pub issues: Option<PathBuf>,
+    /// Write the changes since the report in `since` to this file.
+    pub digest: Option<PathBuf>,
+    /// `report.json` of a previous run to compare the report with.
+    pub since: Option<PathBuf>,
+    /// File to record the statistics of every report run in, and to list them from.
+    pub runs: Option<PathBuf>,
+    /// Worker configuration for loading receipts.
+    pub pipeline: PipelineConfig,
+    /// Print timing and throughput of every stage on stderr after the run.
+    pub timings: bool,
+    /// Private SSH key `publish site` signs a token of every farm with.
+    pub sign_tokens: Option<PathBuf>,
+    /// SSH allowed signers file `verify-token` checks the signature of a token against.
+    pub token_signers: Option<PathBuf>,
+    /// Snapshots `prune` checks the periods against.
+    pub snapshots: Option<PathBuf>,
+    /// Amount of the last periods of which `prune` keeps the receipts.
+    pub keep_periods: Option<u32>,
+    /// When `serve` runs the report.
+    pub schedule: Option<Schedule>,
+    /// Program `serve` runs after every run, with its status and snapshot directory.
+    pub notify: Option<String>,
+    /// Output directory of `publish site`, `statements`, `pay` and `serve`.
+    pub out_dir: Option<PathBuf>,
+    /// Ledger of compensation payments, `ledger.json` if not set.
+    pub ledger: Option<PathBuf>,
+    /// Note to record with the status change of `ledger mark`.
+    pub note: Option<String>,
+    /// Reviewer of `approve`.
+    pub reviewer: Option<String>,
+    /// File with the nodes to `approve`.
+    pub nodes: Option<PathBuf>,
+    /// Stellar account `pay` prepares the payments from.
+    pub payer: Option<String>,
+    /// Let `pay` also prepare payments of nodes which aren't approved.
+    pub allow_unapproved: bool,
+    /// Archive `bundle` writes.
+    pub out: Option<PathBuf>,
+    /// Source of `ledger import`.
+    pub import_from: Option<ImportFrom>,
+    /// Horizon instance to use, the public one if not set.
+    pub horizon_url: Option<String>,
+    /// Refuse to run anything which writes to disk.
+    pub read_only: bool,
+    /// Print what the command would write, download or send, without doing it.
+    pub plan: bool,
+    /// What to do if another run holds the run lock.
+    pub lock: LockMode,
+}
+
+/// The subcommands of the tool.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Print the report for all nodes, the default if no subcommand is given.
+    #[default]
+    Report,
+    /// Show how the expected reward of a node in a period is calculated.
+    Explain { node_id: u32, period: u32 },
+    /// Show the commits of the receipts repository which changed the receipts of a node.
+    Blame { node_id: u32 },
+    /// Print statistics about the receipts of every period.
+    Stats,
+    /// Print the presence and values of every receipt field in the periods of a directory.
+    ProfileSchema { dir: PathBuf },
+    /// Write the report as a static website to a directory.
+    PublishSite { out_dir: PathBuf },
+    /// Write a PDF statement of every farm to a directory.
+    Statements { out_dir: PathBuf },
+    /// Work with the ledger of compensation payments.
+    Ledger(LedgerCommand),
+    /// Approve the amounts owed to the nodes listed in a file.
+    Approve { reviewer: String, nodes: PathBuf },
+    /// Write unsigned Stellar transactions paying the approved amounts to a directory.
+    Pay { payer: String, out_dir: PathBuf },
+    /// Upgrade the files the tool keeps state in to the current format.
+    Migrate,
+    /// Download receipts from the minting API into the receipts directory.
+    Fetch(FetchTarget),
+    /// Check the memos of the payments of the receipts against the hashes of the receipts.
+    VerifyMemos,
+    /// Compare the measured uptime of the receipts with the up and down windows in a file.
+    CompareUptime { path: PathBuf },
+    /// Write the test vectors of the hash and reward math to a file.
+    TestVectors { path: PathBuf },
+    /// Write the receipts, results, findings and explanations of a node to a ZIP archive.
+    Bundle { node_id: u32, path: PathBuf },
+    /// List the report runs recorded with `--runs`.
+    HistoryRuns,
+    /// Check the signature of a farm token and show what it says.
+    VerifyToken { token: String },
+    /// Remove the receipts of old periods which are snapshotted and reconciled, keeping their
+    /// hashes.
+    Prune { snapshots: PathBuf, keep: u32 },
+    /// Run the report on a schedule, writing a snapshot of every run to a directory.
+    Serve {
+        out_dir: PathBuf,
+        schedule: Schedule,
+    },
+}
+
+/// The receipts `fetch` downloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchTarget {
+    /// The receipts of a node.
+    Node(u32),
+    /// The receipts of all nodes of a farm.
+    Farm(u32),
+    /// All receipts of a period.
+    Period(u32),
+}
+
+impl fmt::Display for FetchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchTarget::Node(node_id) => write!(f, "node {node_id}"),
+            FetchTarget::Farm(farm_id) => write!(f, "farm {farm_id}"),
+            FetchTarget::Period(period) => write!(f, "period {period}"),
+        }
+    }
+}
+
+/// The subcommands of `ledger`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedgerCommand {
+    /// Reconcile the payments in the ledger against the amounts owed.
+    Report,
+    /// Backfill the ledger with past payments.
+    Import(ImportFrom),
+    /// Move nodes to a new status.
+    Mark { status: Status, node_ids: Vec<u32> },
+}
+
+/// Where to import past payments from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportFrom {
+    /// All TFT payments sent by this Stellar account.
+    Horizon(String),
+    /// A CSV file with one payment per row.
+    Csv(PathBuf),
+}
+
+impl Args {
+    /// Set the source of `ledger import`, of which there can only be one.
+    fn set_import(&mut self, from: ImportFrom) -> Result<(), ArgsError> {
+        if self.import_from.is_some() {
+            return Err(ArgsError(
+                "ledger import takes a single --from-horizon or --from-csv".into(),
+            ));
+        }
+        self.import_from = Some(from);
+        Ok(())
+    }
+
+    /// Indicates the command can print a plan of its side effects with `--plan`.
+    pub fn plans(&self) -> bool {
+        matches!(
+            self.command,
+            Command::Fetch(_)
+                | Command::Prune { .. }
+                | Command::PublishSite { .. }
+                | Command::Ledger(LedgerCommand::Import(_) | LedgerCommand::Mark { .. })
+                | Command::Approve { .. }
+                | Command::Pay { .. }
+        )
+    }
+
+    /// Indicates the command stops at an interrupt and handles what it loaded until then, so the
+    /// interrupt is caught. Other commands are stopped by it right away.
+    pub fn handles_interrupts(&self) -> bool {
+        matches!(
+            self.command,
+            Command::Report
+                | Command::PublishSite { .. }
+                | Command::Ledger(LedgerCommand::Report)
+                | Command::Approve { .. }
+                | Command::Pay { .. }
+                | Command::Statements { .. }
+                | Command::Bundle { .. }
+        )
+    }
+
+    /// Describes the first thing the run would write to disk, if anything. Runs which write must
+    /// not run concurrently with other runs, and are refused in read-only mode. Runs which only
+    /// print their plan write nothing.
+    pub fn writes(&self) -> Option<&'static str> {
+        if self.plan {
+            return None;
+        }
+        let command = match self.command {
+            Command::Report
+            | Command::Explain { .. }
+            | Command::Blame { .. }
+            | Command::Stats
+            | Command::ProfileSchema { .. }
+            | Command::VerifyMemos
+            | Command::CompareUptime { .. }
+            | Command::HistoryRuns
+            | Command::VerifyToken { .. } => None,
+            // The runs it starts take the lock.
+            Command::Serve { .. } => None,
+            Command::PublishSite { .. } => Some("publish site"),
+            Command::Statements { .. } => Some("statements"),
+            Command::Ledger(LedgerCommand::Report) => None,
+            Command::Ledger(LedgerCommand::Import(_)) => Some("ledger import"),
+            Command::Ledger(LedgerCommand::Mark { .. }) => Some("ledger mark"),
+            Command::Approve { .. } => Some("approve"),
+            Command::Pay { .. } => Some("pay"),
+            Command::Migrate => Some("migrate"),
+            Command::Fetch(_) => Some("fetch"),
+            Command::Prune { .. } => Some("prune"),
+            Command::TestVectors { .. } => Some("test-vectors"),
+            Command::Bundle { .. } => Some("bundle"),
+        };
+        let exports = [
+            (self.output.is_some(), "--output"),
+            (self.address_book.is_some(), "--address-book"),
+            (self.html.is_some(), "--html"),
+            (self.findings.is_some(), "--findings"),
+            (self.issues.is_some(), "--issues"),
+            (self.invalid_receipts.is_some(), "--invalid-receipts"),
+            (self.digest.is_some(), "--digest"),
+            (
+                self.runs.is_some() && self.command == Command::Report,
+                "--runs",
+            ),
+            (self.missing.fetches(), "--missing fetch"),
+        ];
+        command.or_else(|| {
+            exports
+                .into_iter()
+                .find_map(|(set, flag)| set.then_some(flag))
+        })
+    }
+
+    /// Periods the command reads from the receipts directory.
+    pub fn scanned_periods(&self) -> &[u32] {
+        match &self.command {
+            Command::Explain { period, .. } => std::slice::from_ref(period),
+            Command::ProfileSchema { .. }
+            | Command::Blame { .. }
+            | Command::Migrate
+            | Command::Fetch(_)
+            | Command::TestVectors { .. }
+            | Command::HistoryRuns
+            | Command::Serve { .. }
+            | Command::Prune { .. }
+            | Command::VerifyToken { .. }
+            | Command::Ledger(LedgerCommand::Import(_) | LedgerCommand::Mark { .. }) => &[],
+            _ => &self.periods,
+        }
+    }
+
+    /// Directory with the period directories: `--receipts-dir`, else the directory in the
+    /// [`RECEIPTS_DIR_ENV`] environment variable, else the working directory.
+    pub fn receipts_dir(&self) -> PathBuf {
+        self.receipts_dir
+            .clone()
+            .or_else(|| std::env::var_os(RECEIPTS_DIR_ENV).map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// File to write the report to, if any. With `output_timestamp`, the time of the run is added
+    /// to the file name before the extension, e.g. `report-20230608T134500Z.csv`.
+    pub fn output_path(&self, now: i64) -> Option<PathBuf> {
+        let path = self.output.as_ref()?;
+        if !self.output_timestamp {
+            return Some(path.clone());
+        }
+        // Basic format, as `:` is not allowed in file names on Windows.
+        let timestamp = trace_titans::date::format_timestamp(now).replace(['-', ':'], "");
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!("{stem}-{timestamp}.{}", extension.to_string_lossy()),
+            None => format!("{stem}-{timestamp}"),
+        };
+        Some(path.with_file_name(name))
+    }
+
+    /// Location of the ledger.
+    pub fn ledger_path(&self) -> &Path {
+        self.ledger
+            .as_deref()
+            .unwrap_or(Path::new(crate::ledger::DEFAULT_PATH))
+    }
+}
+
+/// Number style of every output for people. Machine readable outputs, like the CSV report and
+/// the JSON of the site, always write amounts in full.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NumberStyles {
+    /// The `--html` report.
+    pub html: NumberStyle,
+    /// The PDF statements.
+    pub pdf: NumberStyle,
+    /// The pages of the published site.
+    pub site: NumberStyle,
+    /// The output of `explain`.
+    pub explain: NumberStyle,
+    /// The report with `--format table`.
+    pub table: NumberStyle,
+}
+
+impl NumberStyles {
+    /// Names of all outputs, as used on the command line.
+    pub const OUTPUTS: [&'static str; 5] = ["html", "pdf", "site", "explain", "table"];
+
+    /// Set the style given in the command line format: either a style, which sets the style of
+    /// every output, or `<output>=<style>` for a single output.
+    pub fn set(&mut self, value: &str) -> Result<(), String> {
+        let (output, style) = match value.split_once('=') {
+            Some((output, style)) => (Some(output), style),
+            None => (None, value),
+        };
+        let style = style.parse()?;
+        match output {
+            None => {
+                self.html = style;
+                self.pdf = style;
+                self.site = style;
+                self.explain = style;
+                self.table = style;
+            }
+            Some("html") => self.html = style,
+            Some("pdf") => self.pdf = style,
+            Some("site") => self.site = style,
+            Some("explain") => self.explain = style,
+            Some("table") => self.table = style,
+            Some(output) => {
+                return Err(format!(
+                    "unknown output {output}, expected one of {}",
+                    Self::OUTPUTS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Key used to aggregate report rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// One row per node label.
+    Label,
+    /// One row per hardware class.
+    Class,
+    /// One row per farm, by the farm of the latest receipt of every node.
+    Farm,
+    /// One row per twin, i.e. farmer account, by the twin of the latest receipt of every node.
+    Twin,
+}
+
+/// Format of the report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Comma separated values to read, with every column of every period.
+    #[default]
+    Csv,
+    /// Comma separated values to ingest: English snake case column names whatever the language,
+    /// and numbers without units.
+    CsvMachine,
+    /// An aligned table to read in a terminal, with only the difference of every period.
+    Table,
+}
+
+impl Args {
+    /// Parse the arguments the process was started with.
+    pub fn parse() -> Result<Self, ArgsError> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    /// Parse arguments from an iterator, which must not include the program name.
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self, ArgsError> {
+        let mut parsed = Args::default();
+        let mut positionals = Vec::new();
+        // Queries are checked against the scanned periods, which are only known after all flags.
+        let mut query = None;
+        let (mut from_period, mut to_period) = (None, None);
+        let mut config_path = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Allow both `--flag value` and `--flag=value`.
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = || match inline_value.clone() {
+                Some(value) => Ok(value),
+                None => args
+                    .next()
+                    .ok_or_else(|| ArgsError(format!("{flag} requires a value"))),
+            };
+            match flag.as_str() {
+                "--config" => config_path = Some(PathBuf::from(value()?)),
+                "--labels" => parsed.labels = Some(value()?.into()),
+                "--label" => parsed.label_filter.push(value()?),
+                "--where" => query = Some(value()?),
+                "--min-diff" => parsed.min_diff = Some(parse_amount("--min-diff", &value()?)?),
+                "--max-diff" => parsed.max_diff = Some(parse_amount("--max-diff", &value()?)?),
+                "--period" => parsed.periods.push(parse_id("period", &value()?)?),
+                "--from-period" => from_period = Some(parse_id("period", &value()?)?),
+                "--to-period" => to_period = Some(parse_id("period", &value()?)?),
+                "--class" => parsed
+                    .class_filter
+                    .push(value()?.parse().map_err(ArgsError)?),
+                "--status" => parsed
+                    .status_filter
+                    .push(value()?.parse().map_err(ArgsError)?),
+                "--node-id" => parsed.node_filter.push(parse_id("node id", &value()?)?),
+                "--farm-id" => parsed.farm_filter.push(parse_id("farm id", &value()?)?),
+                "--twin-id" => parsed.twin_filter.push(parse_id("twin id", &value()?)?),
+                "--group-by" => parsed.group_by = Some(value()?.parse()?),
+                "--format" => parsed.format = value()?.parse()?,
+                "--receipts-dir" => parsed.receipts_dir = Some(value()?.into()),
+                "--at" => parsed.at = Some(value()?),
+                "--fallback-dir" => parsed.fallback_dirs.push(value()?.into()),
+                "--follow-symlinks" => parsed.follow_symlinks = true,
+                "--period-dirs" => parsed.period_dirs = value()?.parse().map_err(ArgsError)?,
+                "--missing" => parsed.missing.set(&value()?).map_err(ArgsError)?,
+                "--minting-url" => parsed.minting_url = Some(value()?),
+                "--csv" => parsed.csv = Some(value()?.into()),
+                "--csv-mapping" => parsed.csv_mapping = Some(value()?.into()),
+                "--lang" => parsed.lang = value()?.parse().map_err(ArgsError)?,
+                "--tolerance" => parsed.tolerances.set(&value()?).map_err(ArgsError)?,
+                "--number-style" => parsed.number_styles.set(&value()?).map_err(ArgsError)?,
+                "--published-totals" => parsed.published_totals = Some(value()?.into()),
+                "--cross-check-legacy" => parsed.cross_check_legacy = true,
+                "--opt-outs" => parsed.opt_outs = Some(value()?.into()),
+                "--opt-outs-signers" => parsed.opt_outs_signers = Some(value()?.into()),
+                "--allow-unsigned-opt-outs" => parsed.allow_unsigned_opt_outs = true,
+                "--prices" => parsed.prices = Some(value()?),
+                "--include-carbon" => parsed.include_carbon = true,
+                "--summary" => parsed.summary = true,
+                "--period-dates" => parsed.period_dates = true,
+                "--carbon-farm" => parsed.carbon_farms.push(parse_id("farm id", &value()?)?),
+                "--policy-rates" => parsed.policy_rates = Some(value()?),
+                "--received-from-chain" => parsed.received_from_chain = true,
+                "--titan-signal" => parsed.titan_signal = value()?.parse().map_err(ArgsError)?,
+                "--uptime-sla" => parsed.uptime_sla = Some(value()?.parse().map_err(ArgsError)?),
+                "--no-policy-default" => parsed.no_policy_default = true,
+                "--spec-tolerance" => parsed.spec_tolerances.set(&value()?).map_err(ArgsError)?,
+                "--split" => parsed.split = Some(value()?.parse().map_err(ArgsError)?),
+                "--uptime-events" => parsed.uptime_events = Some(value()?.into()),
+                "--output" => parsed.output = Some(value()?.into()),
+                "--output-timestamp" => parsed.output_timestamp = true,
+                "--address-book" => parsed.address_book = Some(value()?.into()),
+                "--html" => parsed.html = Some(value()?.into()),
+                "--findings" => parsed.findings = Some(value()?.into()),
+                "--issues" => parsed.issues = Some(value()?.into()),
+                "--invalid-receipts" => parsed.invalid_receipts = Some(value()?.into()),
+                "--digest" => parsed.digest = Some(value()?.into()),
+                "--since" => parsed.since = Some(value()?.into()),
+                "--runs" => parsed.runs = Some(value()?.into()),
+                "--timings" => parsed.timings = true,
+                "--ledger" => parsed.ledger = Some(value()?.into()),
+                "--from-horizon" => parsed.set_import(ImportFrom::Horizon(value()?))?,
+                "--from-csv" => parsed.set_import(ImportFrom::Csv(value()?.into()))?,
+                "--reviewer" => parsed.reviewer = Some(value()?),
+                "--payer" => parsed.payer = Some(value()?),
+                "--allow-unapproved" => parsed.allow_unapproved = true,
+                "--nodes" => parsed.nodes = Some(value()?.into()),
+                "--note" => parsed.note = Some(value()?),
+                "--horizon-url" => parsed.horizon_url = Some(value()?),
+                "--read-only" => parsed.read_only = true,
+                "--plan" => parsed.plan = true,
+                "--wait" => parsed.lock = set_lock(parsed.lock, LockMode::Wait)?,
+                "--force" => parsed.lock = set_lock(parsed.lock, LockMode::Force)?,
+                "--out-dir" => parsed.out_dir = Some(value()?.into()),
+                "--out" => parsed.out = Some(value()?.into()),
+                "--sign-tokens" => parsed.sign_tokens = Some(value()?.into()),
+                "--token-signers" => parsed.token_signers = Some(value()?.into()),
+                "--snapshots" => parsed.snapshots = Some(value()?.into()),
+                "--keep-periods" => {
+                    parsed.keep_periods = Some(value()?.parse().map_err(|_| {
+                        ArgsError("--keep-periods requires an amount of periods".into())
+                    })?)
+                }
+                "--schedule" => parsed.schedule = Some(value()?.parse().map_err(ArgsError)?),
+                "--notify" => parsed.notify = Some(value()?),
+                "--read-workers" => parsed.pipeline.read_workers = parse_workers(&value()?)?,
+                "--parse-workers" => parsed.pipeline.parse_workers = parse_workers(&value()?)?,
+                _ if !flag.starts_with("--") => positionals.push(flag),
+                _ => return Err(ArgsError(format!("unknown argument {flag}"))),
+            }
+        }
+
+        // The configuration file only fills in what isn't given on the command line.
+        let config = match config_path {
+            Some(path) => Config::load(&path).map_err(|e| ArgsError(e.to_string()))?,
+            None if Path::new(config::DEFAULT_PATH).is_file() => {
+                Config::load(Path::new(config::DEFAULT_PATH))
+                    .map_err(|e| ArgsError(e.to_string()))?
+            }
+            None => Config::default(),
+        };
+        if parsed.periods.is_empty() && from_period.is_none() && to_period.is_none() {
+            parsed.periods = config.periods;
+            (from_period, to_period) = (config.from_period, config.to_period);
+        }
+        if parsed.receipts_dir.is_none()
+            && parsed.csv.is_none()
+            && std::env::var_os(RECEIPTS_DIR_ENV).is_none()
+        {
+            parsed.receipts_dir = config.receipts_dir;
+        }
+        parsed.titan_rates = config.titan_rates;
+        parsed.certified_node_types = config.certified_node_types;
+        let columns = config.columns;
+
+        parsed.periods = match (from_period, to_period) {
+            (None, None) if parsed.periods.is_empty() => trace_titans::DEFAULT_PERIODS.to_vec(),
+            (None, None) => {
+                let mut periods = parsed.periods;
+                periods.sort_unstable();
+                periods.dedup();
+                periods
+            }
+            _ if !parsed.periods.is_empty() => {
+                return Err(ArgsError(
+                    "--period can't be combined with --from-period or --to-period".into(),
+                ))
+            }
+            (None, Some(_)) => return Err(ArgsError("--to-period requires --from-period".into())),
+            (Some(from), to) => {
+                // Without an end, scan up to the last period which is over.
+                let to = match to {
+                    Some(to) => to,
+                    None => (Period::current().offset() - 1).try_into().unwrap_or(0),
+                };
+                if from > to {
+                    return Err(ArgsError(format!(
+                        "--from-period {from} is after the last period {to}"
+                    )));
+                }
+                (from..=to).collect()
+            }
+        };
+        if let Some(query) = query {
+            parsed.query = Some(
+                Query::for_nodes(&query, &parsed.periods)
+                    .map_err(|e| ArgsError(format!("invalid --where expression: {e}")))?,
+            );
+        }
+        parsed.columns = columns
+            .iter()
+            .map(|(name, expr)| {
+                Column::parse(name, expr, &parsed.periods)
+                    .map_err(|e| ArgsError(format!("invalid column {name}: {e}")))
+            })
+            .collect::<Result<_, _>>()?;
+
+        parsed.command = match positionals
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [] => Command::Report,
+            ["stats"] => Command::Stats,
+            ["verify-memos"] => Command::VerifyMemos,
+            ["profile-schema", dir] => Command::ProfileSchema { dir: dir.into() },
+            ["test-vectors", path] => Command::TestVectors { path: path.into() },
+            ["compare-uptime", path] => Command::CompareUptime { path: path.into() },
+            ["migrate"] => Command::Migrate,
+            ["history", "runs"] => Command::HistoryRuns,
+            ["explain", node_id, period] => Command::Explain {
+                node_id: parse_id("node id", node_id)?,
+                period: parse_id("period", period)?,
+            },
+            ["fetch", "node", node_id] => {
+                Command::Fetch(FetchTarget::Node(parse_id("node id", node_id)?))
+            }
+            ["fetch", "farm", farm_id] => {
+                Command::Fetch(FetchTarget::Farm(parse_id("farm id", farm_id)?))
+            }
+            ["fetch", "period", period] => {
+                Command::Fetch(FetchTarget::Period(parse_id("period", period)?))
+            }
+            ["blame", node_id] => Command::Blame {
+                node_id: parse_id("node id", node_id)?,
+            },
+            ["bundle", node_id] => Command::Bundle {
+                node_id: parse_id("node id", node_id)?,
+                path: parsed
+                    .out
+                    .clone()
+                    .ok_or_else(|| ArgsError("bundle requires --out".into()))?,
+            },
+            ["publish", "site"] => Command::PublishSite {
+                out_dir: parsed
+                    .out_dir
+                    .clone()
+                    .ok_or_else(|| ArgsError("publish site requires --out-dir".into()))?,
+            },
+            ["verify-token", token] => match &parsed.token_signers {
+                Some(_) => Command::VerifyToken {
+                    token: token.to_string(),
+                },
+                None => {
+                    return Err(ArgsError(
+                        "usage: verify-token <token> --token-signers <file>".into(),
+                    ))
+                }
+            },
+            ["prune"] => match (&parsed.snapshots, parsed.keep_periods) {
+                (Some(snapshots), Some(keep)) => Command::Prune {
+                    snapshots: snapshots.clone(),
+                    keep,
+                },
+                _ => {
+                    return Err(ArgsError(
+                        "usage: prune --snapshots <dir> --keep-periods <amount>".into(),
+                    ))
+                }
+            },
+            ["serve"] => match (&parsed.schedule, &parsed.out_dir) {
+                (Some(schedule), Some(out_dir)) => Command::Serve {
+                    out_dir: out_dir.clone(),
+                    schedule: schedule.clone(),
+                },
+                _ => {
+                    return Err(ArgsError(
+                        "usage: serve --schedule <cron expression> --out-dir <dir>".into(),
+                    ))
+                }
+            },
+            ["statements"] => Command::Statements {
+                out_dir: parsed
+                    .out_dir
+                    .clone()
+                    .ok_or_else(|| ArgsError("statements requires --out-dir".into()))?,
+            },
+            ["approve"] => match (&parsed.reviewer, &parsed.nodes) {
+                (Some(reviewer), Some(nodes)) => Command::Approve {
+                    reviewer: reviewer.clone(),
+                    nodes: nodes.clone(),
+                },
+                _ => {
+                    return Err(ArgsError(
+                        "usage: approve --reviewer <name> --nodes <file>".into(),
+                    ))
+                }
+            },
+            ["pay"] => match (&parsed.payer, &parsed.out_dir) {
+                (Some(payer), Some(out_dir)) => Command::Pay {
+                    payer: payer.clone(),
+                    out_dir: out_dir.clone(),
+                },
+                _ => {
+                    return Err(ArgsError(
+                        "usage: pay --payer <account> --out-dir <dir>".into(),
+                    ))
+                }
+            },
+            ["ledger", "report"] => Command::Ledger(LedgerCommand::Report),
+            ["ledger", "import"] => Command::Ledger(LedgerCommand::Import(
+                parsed.import_from.clone().ok_or_else(|| {
+                    ArgsError("ledger import requires --from-horizon or --from-csv".into())
+                })?,
+            )),
+            ["ledger", "mark", status, node_ids @ ..] if !node_ids.is_empty() => {
+                Command::Ledger(LedgerCommand::Mark {
+                    status: status.parse().map_err(ArgsError)?,
+                    node_ids: node_ids
+                        .iter()
+                        .map(|id| parse_id("node id", id))
+                        .collect::<Result<_, _>>()?,
+                })
+            }
+            ["ledger", ..] => {
+                return Err(ArgsError(
+                    "usage: ledger report | ledger import --from-horizon <account> | ledger import --from-csv <file> | ledger mark <status> <node_id>...".into(),
+                ))
+            }
+            ["publish", ..] => return Err(ArgsError("usage: publish site --out-dir <dir>".into())),
+            ["explain", ..] => return Err(ArgsError("usage: explain <node_id> <period>".into())),
+            ["blame", ..] => return Err(ArgsError("usage: blame <node_id>".into())),
+            ["fetch", ..] => {
+                return Err(ArgsError(
+                    "usage: fetch node <node_id> | fetch farm <farm_id> | fetch period <period>"
+                        .into(),
+                ))
+            }
+            ["profile-schema", ..] => {
+                return Err(ArgsError("usage: profile-schema <dir>".into()))
+            }
+            ["test-vectors", ..] => return Err(ArgsError("usage: test-vectors <file>".into())),
+            ["compare-uptime", ..] => {
+                return Err(ArgsError("usage: compare-uptime <file>".into()))
+            }
+            ["history", ..] => return Err(ArgsError("usage: history runs --runs <file>".into())),
+            ["bundle", ..] => {
+                return Err(ArgsError(
+                    "usage: bundle <node_id> --out <file>".into(),
+                ))
+            }
+            [command, ..] => return Err(ArgsError(format!("unknown command {command}"))),
+        };
+
+        if (!parsed.label_filter.is_empty() || parsed.group_by == Some(GroupBy::Label))
+            && parsed.labels.is_none()
+        {
+            return Err(ArgsError(
+                "filtering or grouping by label requires --labels".into(),
+            ));
+        }
+
+        if parsed.sign_tokens.is_some() && !matches!(parsed.command, Command::PublishSite { .. }) {
+            return Err(ArgsError(
+                "--sign-tokens can only be used with publish site".into(),
+            ));
+        }
+
+        if (parsed.schedule.is_some() || parsed.notify.is_some())
+            && !matches!(parsed.command, Command::Serve { .. })
+        {
+            return Err(ArgsError(
+                "--schedule and --notify can only be used with serve".into(),
+            ));
+        }
+
+        if parsed.out_dir.is_some()
+            && !matches!(
+                parsed.command,
+                Command::PublishSite { .. }
+                    | Command::Statements { .. }
+                    | Command::Pay { .. }
+                    | Command::Serve { .. }
+            )
+        {
+            return Err(ArgsError(
+                "--out-dir can only be used with publish site, statements, pay or serve".into(),
+            ));
+        }
+
+        if parsed.out.is_some() && !matches!(parsed.command, Command::Bundle { .. }) {
+            return Err(ArgsError("--out can only be used with bundle".into()));
+        }
+
+        if (parsed.payer.is_some() || parsed.allow_unapproved)
+            && !matches!(parsed.command, Command::Pay { .. })
+        {
+            return Err(ArgsError(
+                "--payer and --allow-unapproved can only be used with pay".into(),
+            ));
+        }
+
+        if parsed.import_from.is_some()
+            && !matches!(parsed.command, Command::Ledger(LedgerCommand::Import(_)))
+        {
+            return Err(ArgsError(
+                "--from-horizon and --from-csv can only be used with ledger import".into(),
+            ));
+        }
+
+        if (parsed.reviewer.is_some() || parsed.nodes.is_some())
+            && !matches!(parsed.command, Command::Approve { .. })
+        {
+            return Err(ArgsError(
+                "--reviewer and --nodes can only be used with approve".into(),
+            ));
+        }
+
+        if parsed.note.is_some()
+            && !matches!(parsed.command, Command::Ledger(LedgerCommand::Mark { .. }))
+        {
+            return Err(ArgsError("--note can only be used with ledger mark".into()));
+        }
+
+        if parsed.split.is_some() != parsed.uptime_events.is_some() {
+            return Err(ArgsError(
+                "--split and --uptime-events must be used together".into(),
+            ));
+        }
+
+        if parsed.split.is_some() && !matches!(parsed.command, Command::Explain { .. }) {
+            return Err(ArgsError("--split can only be used with explain".into()));
+        }
+
+        if parsed.output.is_some() && parsed.command != Command::Report {
+            return Err(ArgsError(
+                "--output can only be used with the report".into(),
+            ));
+        }
+
+        if parsed.format != ReportFormat::Csv
+            && !matches!(
+                parsed.command,
+                Command::Report | Command::CompareUptime { .. }
+            )
+        {
+            return Err(ArgsError(
+                "--format can only be used with the report and compare-uptime".into(),
+            ));
+        }
+
+        if parsed.runs.is_some()
+            && !matches!(parsed.command, Command::Report | Command::HistoryRuns)
+        {
+            return Err(ArgsError(
+                "--runs can only be used with the report or history runs".into(),
+            ));
+        }
+        if parsed.command == Command::HistoryRuns && parsed.runs.is_none() {
+            return Err(ArgsError("history runs requires --runs".into()));
+        }
+
+        if parsed.prices.is_some() && parsed.command != Command::Report {
+            return Err(ArgsError(
+                "--prices can only be used with the report".into(),
+            ));
+        }
+
+        if parsed.include_carbon && parsed.command != Command::Report {
+            return Err(ArgsError(
+                "--include-carbon can only be used with the report".into(),
+            ));
+        }
+        if parsed.include_carbon && parsed.group_by.is_some() {
+            return Err(ArgsError(
+                "--include-carbon can't be used with --group-by".into(),
+            ));
+        }
+        if parsed.plan && !parsed.plans() {
+            return Err(ArgsError(
+                "--plan can only be used with fetch, publish site, ledger import, ledger mark, \
+                 approve and pay"
+                    .into(),
+            ));
+        }
+        if parsed.summary && parsed.command != Command::Report {
+            return Err(ArgsError(
+                "--summary can only be used with the report".into(),
+            ));
+        }
+        if parsed.invalid_receipts.is_some() && parsed.command != Command::Report {
+            return Err(ArgsError(
+                "--invalid-receipts can only be used with the report".into(),
+            ));
+        }
+        if parsed.period_dates && parsed.command != Command::Report {
+            return Err(ArgsError(
+                "--period-dates can only be used with the report".into(),
+            ));
+        }
+        // A second table after the report would break the CSV for the tools reading it.
+        if parsed.format == ReportFormat::CsvMachine && (parsed.summary || parsed.period_dates) {
+            return Err(ArgsError(
+                "--summary and --period-dates can't be used with --format csv-machine".into(),
+            ));
+        }
+        if parsed.opt_outs_signers.is_some() && parsed.opt_outs.is_none() {
+            return Err(ArgsError("--opt-outs-signers requires --opt-outs".into()));
+        }
+        // The registry decides who isn't paid, so anyone who can edit an unsigned one can keep a
+        // farm from being paid.
+        if matches!(
+            parsed.command,
+            Command::Approve { .. } | Command::Pay { .. }
+        ) && parsed.opt_outs.is_some()
+            && parsed.opt_outs_signers.is_none()
+            && !parsed.allow_unsigned_opt_outs
+        {
+            return Err(ArgsError(
+                "the --opt-outs of approve and pay must be signed, give --opt-outs-signers, or \
+                 --allow-unsigned-opt-outs to use it unsigned"
+                    .into(),
+            ));
+        }
+        if !parsed.carbon_farms.is_empty() && !parsed.include_carbon {
+            return Err(ArgsError("--carbon-farm requires --include-carbon".into()));
+        }
+
+        if parsed.output_timestamp && parsed.output.is_none() {
+            return Err(ArgsError("--output-timestamp requires --output".into()));
+        }
+
+        if parsed.digest.is_some() != parsed.since.is_some() {
+            return Err(ArgsError(
+                "--digest and --since must be used together".into(),
+            ));
+        }
+
+        if parsed.digest.is_some() && parsed.command != Command::Report {
+            return Err(ArgsError(
+                "--digest can only be used with the report".into(),
+            ));
+        }
+
+        if parsed.read_only {
+            if let Some(write) = parsed.writes() {
+                return Err(ArgsError(format!(
+                    "{write} writes to disk, which is disabled by --read-only"
+                )));
+            }
+        }
+
+        if !parsed.fallback_dirs.is_empty() && parsed.csv.is_some() {
+            return Err(ArgsError("--fallback-dir can't be used with --csv".into()));
+        }
+
+        if parsed.at.is_some() && (parsed.csv.is_some() || !parsed.fallback_dirs.is_empty()) {
+            return Err(ArgsError(
+                "--at can't be used with --csv or --fallback-dir".into(),
+            ));
+        }
+
+        if parsed.follow_symlinks && (parsed.csv.is_some() || parsed.at.is_some()) {
+            return Err(ArgsError(
+                "--follow-symlinks can't be used with --csv or --at".into(),
+            ));
+        }
+
+        if parsed.period_dirs != PeriodDirs::default() && parsed.csv.is_some() {
+            return Err(ArgsError("--period-dirs can't be used with --csv".into()));
+        }
+
+        if parsed.missing.fetches() && (parsed.csv.is_some() || parsed.at.is_some()) {
+            return Err(ArgsError(
+                "--missing fetch can't be used with --csv or --at".into(),
+            ));
+        }
+
+        if parsed.minting_url.is_some()
+            && !parsed.missing.fetches()
+            && !matches!(parsed.command, Command::Fetch(_) | Command::Serve { .. })
+        {
+            return Err(ArgsError(
+                "--minting-url requires fetch, serve or --missing fetch".into(),
+            ));
+        }
+
+        if matches!(parsed.command, Command::Fetch(_))
+            && (parsed.csv.is_some() || parsed.at.is_some())
+        {
+            return Err(ArgsError(
+                "fetch writes to the receipts directory and can't be used with --csv or --at"
+                    .into(),
+            ));
+        }
+
+        if matches!(parsed.command, Command::Prune { .. })
+            && (parsed.csv.is_some() || parsed.at.is_some() || !parsed.fallback_dirs.is_empty())
+        {
+            return Err(ArgsError(
+                "prune removes receipts from the receipts directory and can't be used with \
+                 --csv, --at or --fallback-dir"
+                    .into(),
+            ));
+        }
+
+        if matches!(parsed.command, Command::Blame { .. }) && parsed.csv.is_some() {
+            return Err(ArgsError(
+                "blame reads from a git repository and can't be used with --csv".into(),
+            ));
+        }
+
+        if parsed.receipts_dir.is_some() && parsed.csv.is_some() {
+            return Err(ArgsError("--receipts-dir can't be used with --csv".into()));
+        }
+
+        if parsed.csv_mapping.is_some() && parsed.csv.is_none() {
+            return Err(ArgsError("--csv-mapping requires --csv".into()));
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Parse a numeric id given as positional argument.
+fn parse_id(name: &str, value: &str) -> Result<u32, ArgsError> {
+    value
+        .parse()
+        .map_err(|_| ArgsError(format!("invalid {name} {value}")))
+}
+
+/// Parse an amount of TFT given to a flag.
+fn parse_amount(flag: &str, value: &str) -> Result<u64, ArgsError> {
+    trace_titans::parse_tft(value)
+        .ok_or_else(|| ArgsError(format!("invalid amount {value} for {flag}, expected TFT")))
+}
+
+/// Set the lock mode, which can only be changed from the default once.
+fn set_lock(current: LockMode, mode: LockMode) -> Result<LockMode, ArgsError> {
+    if current != LockMode::Fail && current != mode {
+        return Err(ArgsError("--wait and --force can't be combined".into()));
+    }
+    Ok(mode)
+}
+
+/// Parse a worker count, which must be at least 1.
+fn parse_workers(value: &str) -> Result<NonZeroUsize, ArgsError> {
+    value
+        .parse()
+        .map_err(|_| ArgsError(format!("invalid worker count {value}, expected at least 1")))
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = ArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "label" => Ok(GroupBy::Label),
+            "class" => Ok(GroupBy::Class),
+            "farm" => Ok(GroupBy::Farm),
+            "twin" => Ok(GroupBy::Twin),
+            _ => Err(ArgsError(format!(
+                "unknown grouping {s}, expected label, class, farm or twin"
+            ))),
+        }
+    }
+}
+
+impl std::str::FromStr for ReportFormat {
+    type Err = ArgsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" | "csv-human" => Ok(ReportFormat::Csv),
+            "csv-machine" => Ok(ReportFormat::CsvMachine),
+            "table" => Ok(ReportFormat::Table),
+            _ => Err(ArgsError(format!(
+                "unknown format {s}, expected csv-human, csv-machine or table"
+            ))),
+        }
+    }
+}
+
+/// Invalid command line arguments.
+#[derive(Debug)]
+pub struct ArgsError(String);
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, ArgsError> {
+        Args::parse_from(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn payments_need_signed_opt_outs() {
+        let pay = "pay --payer G --out-dir o --opt-outs o.csv";
+        let approve = "approve --reviewer r --nodes n --opt-outs o.csv";
+        for command in [pay, approve] {
+            let e = parse(command).unwrap_err();
+            assert!(e.0.contains("must be signed"), "{command}: {}", e.0);
+            assert!(parse(&format!("{command} --opt-outs-signers s")).is_ok());
+            assert!(parse(&format!("{command} --allow-unsigned-opt-outs")).is_ok());
+        }
+        // The report only shows the opt-outs.
+        assert!(parse("--opt-outs o.csv").is_ok());
+        assert!(parse("--opt-outs-signers s").is_err());
+    }
+
+    #[test]
+    fn serve_needs_a_schedule_and_an_out_dir() {
+        let args = parse("serve --schedule=0_3_5_*_* --out-dir o");
+        assert!(args.unwrap_err().0.contains("invalid schedule"));
+        assert!(parse("serve --out-dir o").is_err());
+        let args = Args::parse_from(
+            [
+                "serve",
+                "--schedule",
+                "0 3 5 * *",
+                "--out-dir",
+                "o",
+                "--minting-url",
+                "u",
+            ]
+            .map(str::to_string),
+        )
+        .unwrap();
+        assert!(matches!(args.command, Command::Serve { .. }));
+        assert_eq!(args.writes(), None);
+        assert!(parse("--notify n").is_err());
+    }
+}
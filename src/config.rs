@@ -0,0 +1,457 @@
+//! The `trace_titans.toml` configuration file, with the parameters a report is made with.
+//!
+//! Keeping the parameters in a file next to the receipts allows to version them with the
+//! receipts, and to make the same report again later. Flags on the command line take precedence
+//! over the file. Only the part of TOML the file needs is parsed: tables, and keys with strings,
+//! integers and arrays of them.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use trace_titans::{policy, receipt::ResourceRewards, Error, TFT_PRECISION};
+
+/// File the configuration is read from if no other file is given, if it exists.
+pub const DEFAULT_PATH: &str = "trace_titans.toml";
+
+/// Parameters from the configuration file.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Offsets of the periods to scan.
+    pub periods: Vec<u32>,
+    /// First and last period to scan, as an alternative to `periods`.
+    pub from_period: Option<u32>,
+    pub to_period: Option<u32>,
+    /// Directory with the period directories, relative to the directory of the file.
+    pub receipts_dir: Option<PathBuf>,
+    /// Reward rates of the titan policy in every period.
+    pub titan_rates: Option<ResourceRewards>,
+    /// node_type values of certified nodes.
+    pub certified_node_types: Vec<String>,
+    /// Names and expressions of derived columns, as [`crate::query::Column`], in the order of the
+    /// file.
+    pub columns: Vec<(String, String)>,
+}
+
+impl Config {
+    /// Read a configuration file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let values = parse(&data).map_err(|e| format!("invalid {}: {e}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Config::from_values(values, dir).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    fn from_values(values: Vec<(String, Value)>, dir: &Path) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut rates = BTreeMap::<u32, BTreeMap<String, u64>>::new();
+        for (key, value) in values {
+            match key.as_str() {
+                "periods" => {
+                    config.periods = value
+                        .array(&key)?
+                        .iter()
+                        .map(|period| period.id(&key))
+                        .collect::<Result<_, _>>()?
+                }
+                "from_period" => config.from_period = Some(value.id(&key)?),
+                "to_period" => config.to_period = Some(value.id(&key)?),
+                "receipts_dir" => config.receipts_dir = Some(dir.join(value.string(&key)?)),
+                "tft_precision" => {
+                    // Amounts are kept in the units of Stellar, which the file can't change, but
+                    // stating the precision makes the file describe the report on its own.
+                    let precision = value.integer(&key)?;
+                    if precision != TFT_PRECISION as i64 {
+                        return Err(format!(
+                            "tft_precision {precision} differs from the precision of TFT on \
+                             Stellar, {TFT_PRECISION}"
+                        ));
+                    }
+                }
+                "certified_node_types" => {
+                    config.certified_node_types = value
+                        .array(&key)?
+                        .iter()
+                        .map(|node_type| node_type.string(&key).map(str::to_string))
+                        .collect::<Result<_, _>>()?
+                }
+                _ if key.starts_with("columns.") => {
+                    let expr = value.string(&key)?.to_string();
+                    config
+                        .columns
+                        .push((key["columns.".len()..].to_string(), expr));
+                }
+                _ => match key
+                    .strip_prefix("policies.")
+                    .and_then(|k| k.split_once('.'))
+                {
+                    Some((id, rate)) if ["cu", "su", "nu", "ipv4"].contains(&rate) => {
+                        let id = id
+                            .parse()
+                            .map_err(|_| format!("invalid farming policy id {id}"))?;
+                        let amount = value.integer(&key)?;
+                        let amount = u64::try_from(amount)
+                            .map_err(|_| format!("{key} can't be negative"))?;
+                        rates
+                            .entry(id)
+                            .or_default()
+                            .insert(rate.to_string(), amount);
+                    }
+                    _ => return Err(format!("unknown key {key}")),
+                },
+            }
+        }
+        if !config.periods.is_empty()
+            && (config.from_period.is_some() || config.to_period.is_some())
+        {
+            return Err("periods can't be combined with from_period or to_period".into());
+        }
+        if config.to_period.is_some() && config.from_period.is_none() {
+            return Err("to_period requires from_period".into());
+        }
+        for (id, rates) in rates {
+            // Only the titan policy is used to calculate the expected payouts.
+            if id != policy::TITAN.id {
+                return Err(format!(
+                    "policies.{id}: only the rates of farming policy {} are used",
+                    policy::TITAN.id
+                ));
+            }
+            let rate = |name: &str| {
+                rates
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| format!("policies.{id} has no {name} rate"))
+            };
+            config.titan_rates = Some(ResourceRewards {
+                cu: rate("cu")?,
+                su: rate("su")?,
+                nu: rate("nu")?,
+                ipv4: rate("ipv4")?,
+            });
+        }
+        Ok(config)
+    }
+}
+
+/// A value in the file.
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn string(&self, key: &str) -> Result<&str, String> {
+        match self {
+            Value::String(s) => Ok(s),
+            _ => Err(format!("{key} must be a string")),
+        }
+    }
+
+    fn integer(&self, key: &str) -> Result<i64, String> {
+        match self {
+            Value::Integer(i) => Ok(*i),
+            _ => Err(format!("{key} must be an integer")),
+        }
+    }
+
+    fn id(&self, key: &str) -> Result<u32, String> {
+        u32::try_from(self.integer(key)?).map_err(|_| format!("{key} must be a period offset"))
+    }
+
+    fn array(&self, key: &str) -> Result<&[Value], String> {
+        match self {
+            Value::Array(values) => Ok(values),
+            _ => Err(format!("{key} must be an array")),
+        }
+    }
+}
+
+/// Parse the values of a file by their dotted key, including the table they are in, in the order
+/// of the file.
+fn parse(data: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut values = Vec::<(String, Value)>::new();
+    let mut table = String::new();
+    let mut lines = data.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let line_error = |e: String| format!("line {}: {e}", index + 1);
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            if header.starts_with('[') {
+                return Err(line_error("arrays of tables are not supported".into()));
+            }
+            let name = header
+                .strip_suffix(']')
+                .ok_or_else(|| line_error(format!("invalid table header {line}")))?;
+            table = parse_key(name).map_err(line_error)?;
+            continue;
+        }
+        // Arrays may span lines, up to the closing bracket.
+        while depth(&line) > 0 {
+            let (_, next) = lines
+                .next()
+                .ok_or_else(|| line_error("array is not closed".into()))?;
+            line.push(' ');
+            line.push_str(strip_comment(next).trim());
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| line_error(format!("expected key = value, found {line}")))?;
+        let mut key = parse_key(key).map_err(line_error)?;
+        if !table.is_empty() {
+            key = format!("{table}.{key}");
+        }
+        let mut rest = value.trim();
+        let value = parse_value(&mut rest).map_err(line_error)?;
+        if !rest.trim().is_empty() {
+            return Err(line_error(format!("unexpected {rest} after the value")));
+        }
+        if values.iter().any(|(k, _)| *k == key) {
+            return Err(line_error(format!("{key} is defined twice")));
+        }
+        values.push((key, value));
+    }
+    Ok(values)
+}
+
+/// A dotted key, of bare or quoted parts, joined with dots.
+fn parse_key(key: &str) -> Result<String, String> {
+    let mut parts = Vec::new();
+    let mut rest = key.trim();
+    loop {
+        if rest.starts_with('"') || rest.starts_with('\'') {
+            match parse_value(&mut rest)? {
+                Value::String(part) => parts.push(part),
+                _ => unreachable!("Quoted values are strings"),
+            }
+        } else {
+            let end = rest.find(['.', ' ', '\t']).unwrap_or(rest.len());
+            let part = &rest[..end];
+            if part.is_empty()
+                || !part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(format!("invalid key {}", key.trim()));
+            }
+            parts.push(part.to_string());
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+        match rest.strip_prefix('.') {
+            Some(next) => rest = next.trim_start(),
+            None if rest.is_empty() => return Ok(parts.join(".")),
+            None => return Err(format!("invalid key {}", key.trim())),
+        }
+    }
+}
+
+/// Parse the value at the start of `rest`, and advance it past the value.
+fn parse_value(rest: &mut &str) -> Result<Value, String> {
+    let input = rest.trim_start();
+    if let Some(array) = input.strip_prefix('[') {
+        let mut values = Vec::new();
+        let mut inner = array.trim_start();
+        loop {
+            if let Some(after) = inner.strip_prefix(']') {
+                *rest = after;
+                return Ok(Value::Array(values));
+            }
+            values.push(parse_value(&mut inner)?);
+            inner = inner.trim_start();
+            match inner.strip_prefix(',') {
+                Some(after) => inner = after.trim_start(),
+                None if inner.starts_with(']') => {}
+                None => return Err("expected , or ] in array".into()),
+            }
+        }
+    }
+    if let Some(quoted) = input.strip_prefix('\'') {
+        let end = quoted.find('\'').ok_or("string is not closed")?;
+        *rest = &quoted[end + 1..];
+        return Ok(Value::String(quoted[..end].to_string()));
+    }
+    if let Some(quoted) = input.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    *rest = &quoted[i + 1..];
+                    return Ok(Value::String(string));
+                }
+                '\\' => string.push(match chars.next().map(|(_, c)| c) {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    other => return Err(format!("unsupported escape \\{}", other.unwrap_or(' '))),
+                }),
+                c => string.push(c),
+            }
+        }
+        return Err("string is not closed".into());
+    }
+    let end = input.find([',', ']', ' ', '\t']).unwrap_or(input.len());
+    let (token, after) = input.split_at(end);
+    *rest = after;
+    token
+        .replace('_', "")
+        .parse()
+        .map(Value::Integer)
+        .map_err(|_| format!("unsupported value {token}"))
+}
+
+/// The line without a comment, which starts at a `#` outside of a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Amount of brackets opened outside of strings and not yet closed.
+fn depth(line: &str) -> i32 {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut depth = 0;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+        escaped = false;
+    }
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn parse_subset() {
+        let data = r#"
+# Parameters of the report.
+periods = [52, 53] # trailing comment
+receipts_dir = "receipts # not a comment"
+tft_precision = 10_000_000
+
+[columns]
+"owed tft" = 'difference / 10000000'
+escaped = "a \"b\" \\ c"
+
+[policies.1]
+types = [
+    "a]b", # a bracket in a string
+    'c',
+]
+"#;
+        assert_eq!(
+            parse(data).unwrap(),
+            [
+                (
+                    "periods".into(),
+                    Value::Array(vec![Value::Integer(52), Value::Integer(53)])
+                ),
+                ("receipts_dir".into(), string("receipts # not a comment")),
+                ("tft_precision".into(), Value::Integer(10_000_000)),
+                ("columns.owed tft".into(), string("difference / 10000000")),
+                ("columns.escaped".into(), string(r#"a "b" \ c"#)),
+                (
+                    "policies.1.types".into(),
+                    Value::Array(vec![string("a]b"), string("c")])
+                ),
+            ]
+        );
+        assert_eq!(
+            parse("a . 'b.c' . d = -1").unwrap(),
+            [("a.b.c.d".into(), Value::Integer(-1))]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (data, error) in [
+            ("[[periods]]", "line 1: arrays of tables are not supported"),
+            ("a = 1\na = 2", "line 2: a is defined twice"),
+            ("[t]\na = 1\n[t]\na = 1", "line 4: t.a is defined twice"),
+            ("a = \"b", "line 1: string is not closed"),
+            ("a = [1,\n2", "line 1: array is not closed"),
+            ("a = 1 2", "line 1: unexpected  2 after the value"),
+            ("a = true", "line 1: unsupported value true"),
+            ("a b = 1", "line 1: invalid key a b"),
+            ("a", "line 1: expected key = value, found a"),
+            ("a = \"\\x\"", "line 1: unsupported escape \\x"),
+        ] {
+            assert_eq!(parse(data).unwrap_err(), error, "{data}");
+        }
+    }
+
+    #[test]
+    fn config_values() {
+        let load = |data: &str| Config::from_values(parse(data).unwrap(), Path::new("base"));
+        let titan = policy::TITAN.id;
+        let config = load(&format!(
+            "from_period = 52\nto_period = 57\nreceipts_dir = 'receipts'\n\
+             certified_node_types = ['certified']\n[columns]\nowed = 'difference'\n\
+             [policies.{titan}]\ncu = 1\nsu = 2\nnu = 3\nipv4 = 4"
+        ))
+        .unwrap();
+        assert_eq!((config.from_period, config.to_period), (Some(52), Some(57)));
+        assert_eq!(config.receipts_dir, Some(PathBuf::from("base/receipts")));
+        assert_eq!(config.certified_node_types, ["certified"]);
+        assert_eq!(config.columns, [("owed".into(), "difference".into())]);
+        let rates = config.titan_rates.unwrap();
+        assert_eq!((rates.cu, rates.su, rates.nu, rates.ipv4), (1, 2, 3, 4));
+
+        for (data, error) in [
+            (
+                "periods = [1]\nfrom_period = 1",
+                "periods can't be combined",
+            ),
+            ("to_period = 1", "to_period requires from_period"),
+            ("tft_precision = 100", "tft_precision 100 differs"),
+            ("periods = [-1]", "periods must be a period offset"),
+            ("receipts_dir = 1", "receipts_dir must be a string"),
+            ("unknown = 1", "unknown key unknown"),
+            ("[policies.1]\ncu = 1", "only the rates of farming policy"),
+        ] {
+            let e = load(data).unwrap_err();
+            assert!(e.contains(error), "{data}: {e}");
+        }
+        let e = load(&format!("[policies.{titan}]\ncu = 1")).unwrap_err();
+        assert_eq!(e, format!("policies.{titan} has no su rate"));
+    }
+}
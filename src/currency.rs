@@ -0,0 +1,267 @@
+//! Amounts of currency.
+//!
+//! Amounts are handled as integers in the smallest unit of their denomination, and only
+//! converted to decimals to show or read them. The amount of decimals is part of the
+//! denomination, so nothing else needs to know the precision of an asset.
+//!
+//! Machine readable outputs always write all decimals, so amounts can be compared as strings and
+//! read back exactly. Outputs for people can write them in a shorter [`NumberStyle`].
+
+use std::{fmt, str::FromStr};
+
+/// A denomination of amounts, e.g. TFT.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Denomination {
+    /// Asset code, e.g. as used on Stellar.
+    pub code: &'static str,
+    /// Amount of decimals of the smallest unit.
+    pub decimals: u32,
+}
+
+impl Denomination {
+    /// Amount of units in one whole coin.
+    pub const fn precision(&self) -> u64 {
+        10u64.pow(self.decimals)
+    }
+}
+
+/// TFT, in units of 1e-7 TFT like on Stellar.
+pub const TFT: Denomination = Denomination {
+    code: "TFT",
+    decimals: 7,
+};
+
+/// Precision of 1 TFT.
+pub const TFT_PRECISION: u64 = TFT.precision();
+
+/// USD, in units of 1e-6 USD, which is precise enough for the price of 1 TFT.
+pub const USD: Denomination = Denomination {
+    code: "USD",
+    decimals: 6,
+};
+
+/// An amount in a denomination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amount {
+    /// Amount of the smallest unit of the denomination, wide enough for any `u64` or `i64`.
+    pub units: i128,
+    pub denomination: &'static Denomination,
+}
+
+impl Amount {
+    pub fn new(units: impl Into<i128>, denomination: &'static Denomination) -> Self {
+        Amount {
+            units: units.into(),
+            denomination,
+        }
+    }
+
+    /// Parse a non-negative decimal amount, e.g. `1.5`. Digits beyond the precision of the
+    /// denomination are not allowed.
+    pub fn parse(amount: &str, denomination: &'static Denomination) -> Option<Self> {
+        let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+        let digits = denomination.decimals as usize;
+        if (whole.is_empty() && fraction.is_empty())
+            || fraction.len() > digits
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let whole = if whole.is_empty() {
+            0
+        } else {
+            whole.parse::<u64>().ok()?
+        };
+        let fraction = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse::<u64>().ok()? * 10u64.pow((digits - fraction.len()) as u32)
+        };
+        let units = whole
+            .checked_mul(denomination.precision())?
+            .checked_add(fraction)?;
+        Some(Amount::new(units, denomination))
+    }
+
+    /// The amount in whole coins, for calculations which don't need to be exact.
+    pub fn as_f64(self) -> f64 {
+        self.units as f64 / self.denomination.precision() as f64
+    }
+}
+
+/// How amounts are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NumberStyle {
+    /// All decimals of the denomination, e.g. `1012.5000000`.
+    #[default]
+    Full,
+    /// Without trailing zeros, e.g. `1012.5`.
+    Human,
+    /// Without trailing zeros and with thousands separators, e.g. `1,012.5`.
+    Grouped,
+}
+
+impl NumberStyle {
+    /// All styles.
+    pub const ALL: [NumberStyle; 3] = [NumberStyle::Full, NumberStyle::Human, NumberStyle::Grouped];
+
+    /// Name of the style, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            NumberStyle::Full => "full",
+            NumberStyle::Human => "human",
+            NumberStyle::Grouped => "grouped",
+        }
+    }
+}
+
+impl FromStr for NumberStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NumberStyle::ALL
+            .into_iter()
+            .find(|style| style.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown number style {s}, expected one of {}",
+                    NumberStyle::ALL.map(NumberStyle::name).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for NumberStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Amount {
+    /// Format the amount in a style, without the asset code.
+    pub fn format(&self, style: NumberStyle) -> String {
+        let full = self.to_string();
+        if style == NumberStyle::Full {
+            return full;
+        }
+        let trimmed = full.trim_end_matches('0').trim_end_matches('.');
+        if style == NumberStyle::Human {
+            return trimmed.to_string();
+        }
+        let (sign, unsigned) = match trimmed.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", trimmed),
+        };
+        let (whole, fraction) = match unsigned.split_once('.') {
+            Some((whole, fraction)) => (whole, format!(".{fraction}")),
+            None => (unsigned, String::new()),
+        };
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        format!("{sign}{grouped}{fraction}")
+    }
+}
+
+/// Formats the amount with all decimals of the denomination, without the asset code.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = self.denomination.precision() as u128;
+        let units = self.units.unsigned_abs();
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            if self.units < 0 { "-" } else { "" },
+            units / precision,
+            units % precision,
+            width = self.denomination.decimals as usize
+        )
+    }
+}
+
+/// Formats an amount of TFT units as decimal TFT.
+pub fn format_tft(amount: u64) -> String {
+    Amount::new(amount, &TFT).to_string()
+}
+
+/// Formats an amount of TFT units, which can be negative, as decimal TFT.
+pub fn format_diff_tft(amount: i64) -> String {
+    Amount::new(amount, &TFT).to_string()
+}
+
+/// Formats an amount of USD units, which can be negative, as decimal USD.
+pub fn format_usd(amount: i64) -> String {
+    Amount::new(amount, &USD).to_string()
+}
+
+/// Formats an amount of TFT units, which can be negative, as decimal TFT in a style.
+pub fn format_tft_styled(amount: impl Into<i128>, style: NumberStyle) -> String {
+    Amount::new(amount, &TFT).format(style)
+}
+
+/// Parses a decimal amount of TFT, e.g. `1.5`, to TFT units. Digits beyond the precision of TFT
+/// are not allowed.
+pub fn parse_tft(amount: &str) -> Option<u64> {
+    Amount::parse(amount, &TFT).map(|amount| amount.units as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_has_every_decimal() {
+        assert_eq!(format_tft(0), "0.0000000");
+        assert_eq!(format_tft(15_000_001), "1.5000001");
+        assert_eq!(format_diff_tft(-5), "-0.0000005");
+        assert_eq!(format_usd(-1_250_000), "-1.250000");
+        assert_eq!(
+            Amount::new(u64::MAX, &TFT).to_string(),
+            "1844674407370.9551615"
+        );
+    }
+
+    #[test]
+    fn styles() {
+        let amount = |units: i64| Amount::new(units, &TFT);
+        assert_eq!(
+            amount(10_125_000_000).format(NumberStyle::Full),
+            "1012.5000000"
+        );
+        assert_eq!(amount(10_125_000_000).format(NumberStyle::Human), "1012.5");
+        assert_eq!(
+            amount(10_125_000_000).format(NumberStyle::Grouped),
+            "1,012.5"
+        );
+        assert_eq!(
+            amount(-10_000_000_000_000).format(NumberStyle::Grouped),
+            "-1,000,000"
+        );
+        assert_eq!(amount(1_000_000_000).format(NumberStyle::Human), "100");
+        assert_eq!(amount(0).format(NumberStyle::Grouped), "0");
+        assert_eq!("grouped".parse(), Ok(NumberStyle::Grouped));
+        assert!("short".parse::<NumberStyle>().is_err());
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_tft("1.5"), Some(15_000_000));
+        assert_eq!(parse_tft("0.0000001"), Some(1));
+        assert_eq!(parse_tft(".5"), Some(5_000_000));
+        assert_eq!(parse_tft("12"), Some(120_000_000));
+        assert_eq!(parse_tft("1844674407370.9551615"), Some(u64::MAX));
+        for invalid in ["", ".", "0.00000001", "-1", "1.5e3", "1,5", "1844674407371"] {
+            assert_eq!(parse_tft(invalid), None, "{invalid}");
+        }
+        for units in [0, 1, 15_000_000, u64::MAX] {
+            assert_eq!(parse_tft(&format_tft(units)), Some(units));
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Formatting of timestamps as UTC dates.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current unix timestamp.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock is after the unix epoch")
+        .as_secs() as i64
+}
+
+/// Format a unix timestamp as RFC 3339 date and time, e.g. `2023-06-08T13:45:00Z`.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let secs = timestamp.rem_euclid(86_400);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_date(timestamp),
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Format the date of a unix timestamp, e.g. `2023-06-08`.
+pub fn format_date(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(86_400));
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Month, day of the month and day of the week of a unix timestamp, with Sunday as day 0.
+pub fn calendar_day(timestamp: i64) -> (u32, u32, u32) {
+    let days = timestamp.div_euclid(86_400);
+    let (_, month, day) = civil_from_days(days);
+    // The unix epoch was a Thursday.
+    (month, day, (days + 4).rem_euclid(7) as u32)
+}
+
+/// Convert days since the unix epoch to a (year, month, day) date in the proleptic Gregorian
+/// calendar, following <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
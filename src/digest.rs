@@ -0,0 +1,135 @@
+//! Short summary of what changed since a previous report.
+//!
+//! The working group posts a weekly status update, which only needs the delta: periods which were
+//! added, nodes of which the amounts changed and nodes which got settled. The previous report is
+//! the `report.json` of a published site.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use trace_titans::{format_diff_tft, format_tft, Error, NodeResult};
+
+use crate::cli::Args;
+
+/// The parts of a `report.json` the digest is made from.
+#[derive(Deserialize)]
+pub struct Snapshot {
+    nodes: Vec<SnapshotNode>,
+}
+
+#[derive(Deserialize)]
+struct SnapshotNode {
+    node_id: u32,
+    periods: Vec<SnapshotPeriod>,
+    total_expected: String,
+    total_received: String,
+    difference: String,
+    /// Not in reports of older versions, in which nodes are taken as not settled.
+    #[serde(default)]
+    settled: bool,
+}
+
+#[derive(Deserialize)]
+struct SnapshotPeriod {
+    period: u32,
+}
+
+impl Snapshot {
+    /// Load the `report.json` of a published site.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = fs::read(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        serde_json::from_slice(&data)
+            .map_err(|e| format!("invalid report {}: {e}", path.display()).into())
+    }
+
+    /// The periods of the report.
+    pub fn periods(&self) -> impl Iterator<Item = u32> + '_ {
+        self.nodes
+            .iter()
+            .flat_map(|node| node.periods.iter().map(|period| period.period))
+    }
+}
+
+/// Write the changes of the given report since the snapshot to a file.
+pub fn write(
+    path: &Path,
+    snapshot: &Snapshot,
+    node_results: &[(u32, NodeResult)],
+    settled: &BTreeSet<u32>,
+    args: &Args,
+) -> io::Result<()> {
+    let previous = snapshot
+        .nodes
+        .iter()
+        .map(|node| (node.node_id, node))
+        .collect::<BTreeMap<_, _>>();
+    let previous_periods = snapshot
+        .nodes
+        .iter()
+        .flat_map(|node| node.periods.iter().map(|p| p.period))
+        .collect::<BTreeSet<_>>();
+    let new_periods = args
+        .periods
+        .iter()
+        .filter(|period| !previous_periods.contains(period))
+        .map(u32::to_string)
+        .collect::<Vec<_>>();
+
+    let mut changed = Vec::new();
+    let mut newly_settled = Vec::new();
+    for (node_id, result) in node_results {
+        let total_expected = format_tft(result.total_expected());
+        let total_received = format_tft(result.total_received());
+        let difference = format_diff_tft(result.difference(&args.tolerances));
+        match previous.get(node_id) {
+            None => changed.push(format!(
+                "node {node_id}: new, difference to send {difference} TFT"
+            )),
+            Some(before) => {
+                if before.total_expected != total_expected
+                    || before.total_received != total_received
+                    || before.difference != difference
+                {
+                    changed.push(format!(
+                        "node {node_id}: expected {} → {total_expected} TFT, received {} → \
+                         {total_received} TFT, difference to send {} → {difference} TFT",
+                        before.total_expected, before.total_received, before.difference
+                    ));
+                }
+            }
+        }
+        if settled.contains(node_id) && !previous.get(node_id).is_some_and(|b| b.settled) {
+            newly_settled.push(format!("node {node_id}: {difference} TFT paid"));
+        }
+    }
+    let current = node_results
+        .iter()
+        .map(|(node_id, _)| *node_id)
+        .collect::<BTreeSet<_>>();
+    for node_id in previous.keys().filter(|node_id| !current.contains(node_id)) {
+        changed.push(format!("node {node_id}: no longer in the report"));
+    }
+
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    if new_periods.is_empty() {
+        writeln!(file, "New periods: none")?;
+    } else {
+        writeln!(file, "New periods: {}", new_periods.join(", "))?;
+    }
+    for (title, lines) in [
+        ("Changed nodes", changed),
+        ("Newly settled nodes", newly_settled),
+    ] {
+        writeln!(file, "{title}: {}", lines.len())?;
+        for line in lines {
+            writeln!(file, "- {line}")?;
+        }
+    }
+    file.flush()
+}
@@ -0,0 +1,78 @@
+//! Progress of a run, for tools which render their own progress.
+//!
+//! The sources, the pipeline, the checks and the findings report what they do to an
+//! [`EventHandler`] as it happens. Events are reported from the worker threads, in the order they
+//! happen on each thread.
+
+use crate::findings::Finding;
+
+/// Something which happened during a run.
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// The receipts of a period were found.
+    Discovered { period: u32, receipts: usize },
+    /// A file of a period is not read as a receipt, for the given reason.
+    SkippedFile {
+        period: u32,
+        path: &'a str,
+        reason: &'a str,
+    },
+    /// Receipts missing in the primary root of a period were taken from a fallback root.
+    Fallback {
+        period: u32,
+        receipts: usize,
+        primary: &'a str,
+        fallback: &'a str,
+    },
+    /// A receipt was read.
+    Read { origin: &'a str, bytes: usize },
+    /// A receipt was parsed.
+    Parsed { origin: &'a str },
+    /// A receipt could not be read, parsed or validated, and is left out.
+    InvalidReceipt {
+        period: u32,
+        origin: &'a str,
+        error: &'a str,
+    },
+    /// A receipt was added to the receipts of its node.
+    Aggregated { node_id: u32, period: u32 },
+    /// A node has multiple receipts in a period, only the one of `kept` is used.
+    DuplicateReceipt {
+        node_id: u32,
+        period: u32,
+        kept: &'a str,
+        ignored: &'a str,
+    },
+    /// A fixup receipt corrects a period in which its node has no minting receipt, and is
+    /// ignored.
+    UnmatchedFixup {
+        node_id: u32,
+        period: u32,
+        origin: &'a str,
+    },
+    /// Receipts of a period don't declare their farming policy, and are assumed to have the given
+    /// policy.
+    DefaultFarmingPolicy {
+        period: u32,
+        receipts: usize,
+        farming_policy: u32,
+    },
+    /// A finding was made about the results.
+    Finding(&'a Finding),
+}
+
+/// Receives the events of a run. Closures taking an [`Event`] are handlers, e.g. one sending
+/// the events over a channel to a UI thread.
+pub trait EventHandler: Sync {
+    fn handle(&self, event: Event);
+}
+
+impl<F: Fn(Event) + Sync> EventHandler for F {
+    fn handle(&self, event: Event) {
+        self(event)
+    }
+}
+
+/// Handler which ignores all events.
+pub fn ignore(_: Event) {}
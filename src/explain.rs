@@ -0,0 +1,266 @@
+//! Step by step derivation of the expected reward of a node in a single period.
+//!
+//! Every intermediate value of the calculation is printed together with the formula, so the
+//! expected amount can be verified by hand.
+
+use std::io::{self, Write};
+
+use trace_titans::{
+    format_percentage, format_tft_styled,
+    period::STANDARD_PERIOD_DURATION,
+    pipeline::{self, Metrics, ParsedReceipt},
+    policy::UptimeSla,
+    receipt::Reward,
+    reward,
+    source::ReceiptSource,
+    tolerance, Error, NodePeriodResult, TFT_PRECISION,
+};
+
+use crate::{
+    cli::Args,
+    subperiod::{self, UptimeEvents},
+};
+
+/// Print the derivation of the expected titan reward of a node in a period.
+pub fn explain(
+    source: &dyn ReceiptSource,
+    node_id: u32,
+    period: u32,
+    args: &Args,
+) -> Result<(), Error> {
+    let events = args
+        .uptime_events
+        .as_deref()
+        .map(UptimeEvents::load)
+        .transpose()?;
+    let mut node_receipts = pipeline::load(
+        source,
+        &[period],
+        args.pipeline,
+        &Metrics::default(),
+        &crate::print_event,
+    )?;
+    let parsed = node_receipts
+        .get_mut(&node_id)
+        .and_then(|receipts| receipts.remove(&period))
+        .ok_or_else(|| format!("no receipt for node {node_id} in period {period}"))?;
+    write(&mut io::stdout().lock(), &parsed, events.as_ref(), args)?;
+    Ok(())
+}
+
+/// Write the derivation of the expected titan reward of a loaded receipt. The rewards in every
+/// window of the period are included if the uptime events are given and `--split` is set.
+pub fn write(
+    out: &mut dyn Write,
+    parsed: &ParsedReceipt,
+    events: Option<&UptimeEvents>,
+    args: &Args,
+) -> io::Result<()> {
+    let (node_id, period) = (parsed.receipt.node_id, parsed.period);
+    let receipt = &parsed.receipt;
+    let result = NodePeriodResult::from_parsed(
+        parsed,
+        &args.policies,
+        args.titan_signal,
+        args.uptime_sla.unwrap_or_default(),
+    );
+    let policy = args.policies.for_period(period);
+    let rewards = &policy.rewards;
+    let steps = reward::expected_rewards(std::iter::once(receipt), &policy)
+        .pop()
+        .expect("A reward is calculated for every receipt");
+    let style = args.number_styles.explain;
+    let tft = |reward: Reward| {
+        format!(
+            "{} mUSD, {} TFT",
+            reward.musd,
+            format_tft_styled(reward.tft, style)
+        )
+    };
+
+    writeln!(out, "Node {node_id}, period {period}")?;
+    writeln!(out, "Receipt: {}", parsed.origin)?;
+    writeln!(
+        out,
+        "Farming policy {}{}, node type {}, titan: {}",
+        receipt.farming_policy_id(),
+        if receipt.declared_farming_policy_id.is_none() {
+            " (not in the receipt, assumed)"
+        } else {
+            ""
+        },
+        receipt.node_type,
+        result.is_titan()
+    )?;
+    writeln!(
+        out,
+        "Resource units: cru {}, mru {}, hru {}, sru {}, hardware class {}",
+        receipt.resource_units.cru,
+        receipt.resource_units.mru,
+        receipt.resource_units.hru,
+        receipt.resource_units.sru,
+        result.hardware_class(&args.spec_tolerances)
+    )?;
+    writeln!(out)?;
+    writeln!(out, "Formula:")?;
+    writeln!(
+        out,
+        "  expected = (cu × {} + su × {} + nu × {} + ip × {}) × {TFT_PRECISION} ÷ connection price × measured uptime ÷ period duration",
+        rewards.cu, rewards.su, rewards.nu, rewards.ipv4
+    )?;
+    writeln!(
+        out,
+        "  (reward rates of farming policy {} in mUSD per unit, every step rounds down)",
+        policy.id
+    )?;
+    writeln!(out)?;
+    writeln!(out, "1. Upscale cloud units by {TFT_PRECISION}:")?;
+    writeln!(
+        out,
+        "   cu = {} → {}",
+        receipt.cloud_units.cu, steps.cu_upscaled
+    )?;
+    writeln!(
+        out,
+        "   su = {} → {}",
+        receipt.cloud_units.su, steps.su_upscaled
+    )?;
+    writeln!(
+        out,
+        "   nu = {} → {}",
+        receipt.cloud_units.nu, steps.nu_upscaled
+    )?;
+    writeln!(
+        out,
+        "   ip = {} → {}",
+        receipt.resource_utilization.ip, steps.ip_upscaled
+    )?;
+    writeln!(out, "2. Full period reward in mUSD (upscaled):")?;
+    writeln!(
+        out,
+        "   cu: {} × {} = {} ({})",
+        steps.cu_upscaled,
+        rewards.cu,
+        steps.cu_musd_upscaled,
+        tft(steps.cu_reward)
+    )?;
+    writeln!(
+        out,
+        "   su: {} × {} = {} ({})",
+        steps.su_upscaled,
+        rewards.su,
+        steps.su_musd_upscaled,
+        tft(steps.su_reward)
+    )?;
+    writeln!(
+        out,
+        "   nu: {} × {} = {} ({})",
+        steps.nu_upscaled,
+        rewards.nu,
+        steps.nu_musd_upscaled,
+        tft(steps.nu_reward)
+    )?;
+    writeln!(
+        out,
+        "   ip: {} × {} = {} ({})",
+        steps.ip_upscaled,
+        rewards.ipv4,
+        steps.ip_musd_upscaled,
+        tft(steps.ip_reward)
+    )?;
+    writeln!(
+        out,
+        "   {} + {} + {} + {} = {}",
+        steps.cu_musd_upscaled,
+        steps.su_musd_upscaled,
+        steps.nu_musd_upscaled,
+        steps.ip_musd_upscaled,
+        steps.full_musd_reward_upscaled
+    )?;
+    writeln!(
+        out,
+        "3. Convert to TFT at the connection price of {} mUSD:",
+        receipt.tft_connection_price
+    )?;
+    writeln!(
+        out,
+        "   {} ÷ {} = {} ({})",
+        steps.full_musd_reward_upscaled,
+        receipt.tft_connection_price,
+        steps.full_period_reward.tft,
+        tft(steps.full_period_reward)
+    )?;
+    writeln!(
+        out,
+        "4. Scale to the measured uptime of {}s ({}) over a period of {STANDARD_PERIOD_DURATION}s:",
+        receipt.measured_uptime,
+        format_percentage(result.uptime_percentage)
+    )?;
+    writeln!(
+        out,
+        "   {} × {} ÷ {STANDARD_PERIOD_DURATION} = {} ({})",
+        steps.full_period_reward.tft,
+        receipt.measured_uptime,
+        steps.reward.tft,
+        tft(steps.reward)
+    )?;
+    if result.below_minimal_uptime {
+        writeln!(
+            out,
+            "5. The uptime of {} is below the minimal uptime of {} of farming policy {}{}",
+            format_percentage(result.uptime_percentage),
+            format_percentage(policy.minimal_uptime),
+            policy.id,
+            if args.uptime_sla == Some(UptimeSla::Enforce) {
+                ", no payout is due"
+            } else {
+                ", which is not enforced without --uptime-sla enforce"
+            }
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Expected: {} TFT",
+        format_tft_styled(result.expected_payout, style)
+    )?;
+    if parsed.fixups.is_empty() {
+        writeln!(
+            out,
+            "Received: {} TFT",
+            format_tft_styled(result.actual_payout, style)
+        )?;
+    } else {
+        writeln!(
+            out,
+            "Received: {} TFT, {} TFT minted and {} TFT with {} fixup(s)",
+            format_tft_styled(result.actual_payout, style),
+            format_tft_styled(receipt.reward.tft, style),
+            format_tft_styled(result.fixup_payout, style),
+            parsed.fixups.len()
+        )?;
+    }
+    let difference = result.expected_payout as i64 - result.actual_payout as i64;
+    let denoised = tolerance::denoise(difference, args.tolerances.difference);
+    if denoised == difference {
+        writeln!(
+            out,
+            "Difference: {} TFT",
+            format_tft_styled(difference, style)
+        )?;
+    } else {
+        writeln!(
+            out,
+            "Difference: {} TFT, within the tolerance of {} TFT",
+            format_tft_styled(difference, style),
+            format_tft_styled(args.tolerances.difference, style)
+        )?;
+    }
+
+    if let (Some(split), Some(events)) = (args.split, events) {
+        writeln!(out)?;
+        subperiod::write(out, period, &steps, receipt, events, split, style)?;
+    }
+
+    Ok(())
+}
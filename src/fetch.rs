@@ -0,0 +1,71 @@
+//! Download of receipts from the minting API into the receipts directory.
+//!
+//! Populating the period directories by hand is tedious, so `fetch` downloads the receipts of a
+//! node, of all nodes of a farm or of a whole period into the layout the other commands read.
+
+use std::collections::BTreeMap;
+
+use trace_titans::Error;
+
+use crate::{
+    cli::{Args, FetchTarget},
+    minting,
+    plan::Plan,
+};
+
+/// Download the receipts of the target. For nodes and farms only the receipts of the scanned
+/// periods are written. With `--plan`, the receipts are downloaded but only the files they would
+/// be written to are printed.
+pub fn fetch(args: &Args, target: FetchTarget) -> Result<(), Error> {
+    let url = args.minting_url.as_deref().unwrap_or(minting::DEFAULT_URL);
+    let root = args.receipts_dir();
+    let (receipts, periods) = match target {
+        FetchTarget::Node(node_id) => (minting::node_receipts(url, node_id)?, args.periods.clone()),
+        FetchTarget::Farm(farm_id) => (minting::farm_receipts(url, farm_id)?, args.periods.clone()),
+        FetchTarget::Period(period) => (minting::period_receipts(url, period)?, vec![period]),
+    };
+
+    let mut per_period = BTreeMap::<u32, Vec<_>>::new();
+    let mut other_periods = 0;
+    for receipt in receipts {
+        let period = minting::receipt_period(&receipt)?;
+        if periods.contains(&period) {
+            per_period.entry(period).or_default().push(receipt);
+        } else {
+            other_periods += 1;
+        }
+    }
+
+    if per_period.is_empty() && other_periods == 0 {
+        println!("No receipts of {target} at {url}");
+    }
+    if args.plan {
+        let mut plan = Plan::default();
+        for (period, receipts) in &per_period {
+            let dir = args.period_dirs.path(&root, *period);
+            plan.files(
+                &format!("period {period}, receipts of {target} from {url}"),
+                &minting::receipt_files(&dir, *period, receipts)?,
+            );
+        }
+        if other_periods > 0 {
+            plan.note(format!(
+                "{other_periods} receipts of other periods are skipped, see --period"
+            ));
+        }
+        plan.print("fetch");
+        return Ok(());
+    }
+    for (period, receipts) in &per_period {
+        let dir = args.period_dirs.path(&root, *period);
+        let written = minting::write_receipts(&dir, *period, receipts)?;
+        println!(
+            "Period {period}: {written} receipts written to {}",
+            dir.display()
+        );
+    }
+    if other_periods > 0 {
+        println!("{other_periods} receipts of other periods are skipped, see --period");
+    }
+    Ok(())
+}
@@ -0,0 +1,373 @@
+//! Findings are things in the receipts which are not necessarily wrong, but which need a closer
+//! look before corrections are sent.
+//!
+//! A summary of the findings is printed on stderr after every run, the full list can be written
+//! to a file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{
+    events::{Event, EventHandler},
+    format_diff_tft, format_tft,
+    hardware::{self, SpecTolerances},
+    policy,
+    receipt::ResourceUnits,
+    tolerance::Tolerances,
+    NodeResult, TFT_PRECISION,
+};
+
+/// A single finding.
+#[derive(Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// The node the finding is about, if it is about a single node.
+    pub node_id: Option<u32>,
+    /// The period the finding is about, if it is about a single period.
+    pub period: Option<u32>,
+    /// Human readable description of the finding.
+    pub message: String,
+}
+
+/// The kind of a finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum FindingKind {
+    /// A payout address receives payouts for nodes of multiple farms or twins.
+    SharedPayoutAddress,
+    /// The farm or twin of a node changed between periods.
+    IdentityChange,
+    /// The payout in a receipt doesn't match the payout recomputed with the rates in the receipt.
+    PayoutMismatch,
+    /// A node paid as a titan doesn't have the titan hardware.
+    SpecMismatch,
+    /// The farming policy and the node type of a receipt disagree on whether it is a titan.
+    CertificationConflict,
+    /// A node which was up and should have received a reward got nothing at all.
+    NeverPaid,
+    /// The payout of a receipt in TFT doesn't match its payout in USD at its connection price.
+    PriceMismatch,
+}
+
+impl FindingKind {
+    /// Name of the kind, as used in the findings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            FindingKind::SharedPayoutAddress => "shared-payout-address",
+            FindingKind::IdentityChange => "identity-change",
+            FindingKind::PayoutMismatch => "payout-mismatch",
+            FindingKind::SpecMismatch => "spec-mismatch",
+            FindingKind::CertificationConflict => "certification-conflict",
+            FindingKind::NeverPaid => "never-paid",
+            FindingKind::PriceMismatch => "price-mismatch",
+        }
+    }
+}
+
+/// Find payout addresses which receive payouts for nodes belonging to different farms or twins.
+///
+/// This is usually legitimate, as one operator can have many farms, but can also point to a node
+/// which is configured to pay out to the wrong person.
+pub fn shared_payout_addresses(node_results: &[(u32, NodeResult)]) -> Vec<Finding> {
+    // address -> (farms, twins, nodes)
+    let mut addresses = BTreeMap::<&str, (BTreeSet<u32>, BTreeSet<u32>, BTreeSet<u32>)>::new();
+    for (node_id, result) in node_results {
+        for period in result.into_iter().filter(|r| r.farm_id != 0) {
+            let (farms, twins, nodes) = addresses.entry(&period.payout_address).or_default();
+            farms.insert(period.farm_id);
+            twins.insert(period.twin_id);
+            nodes.insert(*node_id);
+        }
+    }
+
+    addresses
+        .into_iter()
+        .filter(|(_, (farms, twins, _))| farms.len() > 1 || twins.len() > 1)
+        .map(|(address, (farms, twins, nodes))| Finding {
+            kind: FindingKind::SharedPayoutAddress,
+            node_id: None,
+            period: None,
+            message: format!(
+                "address {address} receives payouts for {} nodes of farms {} and twins {}",
+                nodes.len(),
+                join(&farms),
+                join(&twins)
+            ),
+        })
+        .collect()
+}
+
+/// Find nodes for which the farm or twin changed between the scanned periods.
+///
+/// A farm transfer changes who should receive the compensation for the periods after the
+/// transfer, so these nodes can't simply be paid out to their latest payout address.
+pub fn identity_changes(node_results: &[(u32, NodeResult)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (node_id, result) in node_results {
+        let mut previous = None;
+        for (period, current) in result.periods().filter(|(_, r)| r.farm_id != 0) {
+            if let Some(previous) = previous.replace(current) {
+                let mut changes = Vec::new();
+                if previous.farm_id != current.farm_id {
+                    changes.push(format!(
+                        "farm changed from {} to {}",
+                        previous.farm_id, current.farm_id
+                    ));
+                }
+                if previous.twin_id != current.twin_id {
+                    changes.push(format!(
+                        "twin changed from {} to {}",
+                        previous.twin_id, current.twin_id
+                    ));
+                }
+                if !changes.is_empty() {
+                    findings.push(Finding {
+                        kind: FindingKind::IdentityChange,
+                        node_id: Some(*node_id),
+                        period: Some(period),
+                        message: changes.join(", "),
+                    });
+                }
+            }
+        }
+    }
+    findings
+}
+
+/// Find receipts where the declared payout differs from the payout recomputed with the reward
+/// rates in the receipt by more than the `recomputed` tolerance.
+///
+/// Unlike the titan difference, minting has no known reason to pay something else than its own
+/// rates, so these point to a problem in the minting or in the receipt data.
+pub fn payout_mismatches(
+    node_results: &[(u32, NodeResult)],
+    tolerances: &Tolerances,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (node_id, result) in node_results {
+        for (period, r) in result.periods().filter(|(_, r)| r.farm_id != 0) {
+            // Fixups are paid on top of the receipt, so they don't count here.
+            let paid = r.actual_payout - r.fixup_payout;
+            let difference = r.recomputed_payout as i64 - paid as i64;
+            if difference.unsigned_abs() > tolerances.recomputed {
+                findings.push(Finding {
+                    kind: FindingKind::PayoutMismatch,
+                    node_id: Some(*node_id),
+                    period: Some(period),
+                    message: format!(
+                        "receipt pays {} TFT, its reward rates give {} TFT, a difference of {} TFT",
+                        format_tft(paid),
+                        format_tft(r.recomputed_payout),
+                        format_diff_tft(difference)
+                    ),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Find receipts of which the farming policy and the node type disagree on whether the node is a
+/// titan, e.g. a DIY node on the titan policy. Whether these nodes are counted as titan depends on
+/// the [`TitanSignal`](crate::policy::TitanSignal) of the run.
+pub fn certification_conflicts(node_results: &[(u32, NodeResult)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (node_id, result) in node_results {
+        for (period, r) in result.periods().filter(|(_, r)| r.farm_id != 0) {
+            if policy::certification_conflict(r.farming_policy, r.is_certified) {
+                findings.push(Finding {
+                    kind: FindingKind::CertificationConflict,
+                    node_id: Some(*node_id),
+                    period: Some(period),
+                    message: format!(
+                        "farming policy {} but {}, counted as titan: {}",
+                        r.farming_policy,
+                        if r.is_certified {
+                            "certified"
+                        } else {
+                            "not certified"
+                        },
+                        r.is_titan()
+                    ),
+                });
+            }
+        }
+    }
+    findings
+}
+
+/// Find nodes which are a titan in a period, but whose resource units in that period deviate from
+/// the titan spec by more than the spec tolerances.
+///
+/// Nodes rarely change hardware, so a node gets a finding for the first period of every
+/// deviating set of resource units rather than for every period.
+pub fn spec_mismatches(
+    node_results: &[(u32, NodeResult)],
+    tolerances: &SpecTolerances,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (node_id, result) in node_results {
+        let mut previous = None;
+        for (period, r) in result.periods().filter(|(_, r)| r.is_titan()) {
+            let units = &r.resource_units;
+            let deviations = tolerances.deviations(units, &hardware::TITAN_V2_1);
+            let seen = previous
+                .replace(*units)
+                .is_some_and(|previous: ResourceUnits| {
+                    (previous.cru, previous.mru, previous.hru, previous.sru)
+                        == (units.cru, units.mru, units.hru, units.sru)
+                });
+            if deviations.is_empty() || seen {
+                continue;
+            }
+            findings.push(Finding {
+                kind: FindingKind::SpecMismatch,
+                node_id: Some(*node_id),
+                period: Some(period),
+                message: format!(
+                    "titan with {}",
+                    deviations
+                        .iter()
+                        .map(|(resource, actual, spec)| format!(
+                            "{resource} {actual} (spec {spec})"
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            });
+        }
+    }
+    findings
+}
+
+/// Find receipts of nodes which were up in the period and should have received a reward, but of
+/// which the reward is 0 and which got no fixup either.
+///
+/// These nodes were skipped by minting entirely, rather than paid under the wrong policy, which
+/// needs to be followed up with minting itself.
+pub fn never_paid(node_results: &[(u32, NodeResult)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (node_id, result) in node_results {
+        for (period, r) in result.periods().filter(|(_, r)| {
+            r.farm_id != 0 && r.measured_uptime > 0 && r.expected_payout > 0 && r.actual_payout == 0
+        }) {
+            findings.push(Finding {
+                kind: FindingKind::NeverPaid,
+                node_id: Some(*node_id),
+                period: Some(period),
+                message: format!(
+                    "up for {}s and expected {} TFT, but the receipt pays nothing",
+                    r.measured_uptime,
+                    format_tft(r.expected_payout)
+                ),
+            });
+        }
+    }
+    findings
+}
+
+/// Find receipts of which the payout in TFT is not the payout in mUSD converted at the connection
+/// price of the receipt.
+///
+/// Minting converts the mUSD reward at the connection price, and the expected payout is converted
+/// the same way, so a receipt in which the two disagree can't be compared with its expected
+/// payout. The mUSD payout is rounded down, so the TFT payout can be up to 1 mUSD worth higher.
+pub fn price_mismatches(node_results: &[(u32, NodeResult)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (node_id, result) in node_results {
+        for (period, r) in result.periods().filter(|(_, r)| {
+            r.farm_id != 0 && r.connection_price != 0 && r.actual_payout > r.fixup_payout
+        }) {
+            let paid = r.actual_payout - r.fixup_payout;
+            // Converting the rounded down payout of the full period and scaling it to the uptime
+            // rounds down twice more, which are at most a unit each.
+            let min = (r.payout_musd * TFT_PRECISION / r.connection_price).saturating_sub(2);
+            let max = (r.payout_musd + 1) * TFT_PRECISION / r.connection_price;
+            if (min..=max).contains(&paid) {
+                continue;
+            }
+            let implied = r.payout_musd as u128 * TFT_PRECISION as u128 * 1000 / paid as u128;
+            findings.push(Finding {
+                kind: FindingKind::PriceMismatch,
+                node_id: Some(*node_id),
+                period: Some(period),
+                message: format!(
+                    "receipt pays {} TFT for {} mUSD, a price of {}.{:03} mUSD, but its connection \
+                     price is {} mUSD",
+                    format_tft(paid),
+                    r.payout_musd,
+                    implied / 1000,
+                    implied % 1000,
+                    r.connection_price
+                ),
+            });
+        }
+    }
+    findings
+}
+
+/// Make all findings about the results, reporting every finding to the event handler.
+pub fn all(
+    node_results: &[(u32, NodeResult)],
+    tolerances: &Tolerances,
+    spec_tolerances: &SpecTolerances,
+    events: &dyn EventHandler,
+) -> Vec<Finding> {
+    let mut findings = shared_payout_addresses(node_results);
+    findings.extend(identity_changes(node_results));
+    findings.extend(payout_mismatches(node_results, tolerances));
+    findings.extend(certification_conflicts(node_results));
+    findings.extend(spec_mismatches(node_results, spec_tolerances));
+    findings.extend(never_paid(node_results));
+    findings.extend(price_mismatches(node_results));
+    for finding in &findings {
+        events.handle(Event::Finding(finding));
+    }
+    findings
+}
+
+/// Print the amount of findings of every kind on stderr.
+pub fn print_summary(findings: &[Finding]) {
+    let mut counts = BTreeMap::<_, usize>::new();
+    for finding in findings {
+        *counts.entry(finding.kind).or_default() += 1;
+    }
+    for (kind, count) in counts {
+        eprintln!("{count} {} finding(s)", kind.name());
+    }
+}
+
+/// Write the findings to a file. Files with a `.json` extension are written as JSON, all others
+/// as CSV.
+pub fn write(path: &Path, findings: &[Finding]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::to_writer_pretty(&mut file, findings)?;
+        writeln!(file)?;
+    } else {
+        writeln!(file, "kind,node_id,period,message")?;
+        for finding in findings {
+            writeln!(
+                file,
+                "{},{},{},\"{}\"",
+                finding.kind.name(),
+                finding.node_id.map(|n| n.to_string()).unwrap_or_default(),
+                finding.period.map(|p| p.to_string()).unwrap_or_default(),
+                finding.message.replace('"', "\"\"")
+            )?;
+        }
+    }
+    file.flush()
+}
+
+/// Join a set of ids with `/`, for use in a message.
+fn join(ids: &BTreeSet<u32>) -> String {
+    ids.iter().map(u32::to_string).collect::<Vec<_>>().join("/")
+}
@@ -0,0 +1,148 @@
+//! Coarse classification of node hardware from the resource units in the receipts.
+//!
+//! Titan compensation is meant for nodes with the standard titan hardware, so the class lets
+//! reviewers spot nodes with other hardware which were paid as a titan.
+//!
+//! Nodes report a bit less memory and disk than they have, as part of it is reserved, so the
+//! resource units are compared to the spec with a tolerance per resource.
+
+use std::{fmt, str::FromStr};
+
+use crate::receipt::ResourceUnits;
+
+/// Resource units of the Titan v2.1: 8 threads, 32 GB of memory and a 1 TB SSD, without HDD.
+pub const TITAN_V2_1: ResourceUnits = ResourceUnits {
+    cru: 8.,
+    mru: 32.,
+    hru: 0.,
+    sru: 1000.,
+};
+
+/// Deviation from the spec a node can have and still match it, per resource, in the unit of the
+/// resource.
+#[derive(Debug, Clone, Copy)]
+pub struct SpecTolerances {
+    pub units: ResourceUnits,
+}
+
+impl Default for SpecTolerances {
+    fn default() -> Self {
+        SpecTolerances {
+            units: ResourceUnits {
+                cru: 0.,
+                mru: 3.2,
+                hru: 0.,
+                sru: 100.,
+            },
+        }
+    }
+}
+
+impl SpecTolerances {
+    /// Names of all resources, as used on the command line.
+    pub const RESOURCES: [&'static str; 4] = ["cru", "mru", "hru", "sru"];
+
+    /// Set the tolerance of a resource given in the command line format, `<resource>=<amount>`.
+    pub fn set(&mut self, value: &str) -> Result<(), String> {
+        let (resource, amount) = value.split_once('=').ok_or_else(|| {
+            format!("invalid spec tolerance {value}, expected <resource>=<amount>")
+        })?;
+        let amount = amount
+            .parse::<f64>()
+            .ok()
+            .filter(|amount| *amount >= 0.)
+            .ok_or_else(|| format!("invalid spec tolerance {amount}"))?;
+        match resource {
+            "cru" => self.units.cru = amount,
+            "mru" => self.units.mru = amount,
+            "hru" => self.units.hru = amount,
+            "sru" => self.units.sru = amount,
+            _ => {
+                return Err(format!(
+                    "unknown resource {resource}, expected one of {}",
+                    Self::RESOURCES.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Resources of which the units deviate more from the spec than their tolerance, with the
+    /// actual and the spec amount.
+    pub fn deviations(
+        &self,
+        units: &ResourceUnits,
+        spec: &ResourceUnits,
+    ) -> Vec<(&'static str, f64, f64)> {
+        let tolerances = &self.units;
+        [
+            ("cru", units.cru, spec.cru, tolerances.cru),
+            ("mru", units.mru, spec.mru, tolerances.mru),
+            ("hru", units.hru, spec.hru, tolerances.hru),
+            ("sru", units.sru, spec.sru, tolerances.sru),
+        ]
+        .into_iter()
+        .filter(|(_, actual, spec, tolerance)| (actual - spec).abs() > *tolerance)
+        .map(|(resource, actual, spec, _)| (resource, actual, spec))
+        .collect()
+    }
+}
+
+/// Hardware class of a node.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HardwareClass {
+    /// The standard Titan v2.1 hardware.
+    TitanV2_1,
+    /// Any other hardware.
+    Nonstandard,
+    /// No receipt to tell the hardware from.
+    #[default]
+    Unknown,
+}
+
+impl HardwareClass {
+    pub const ALL: [HardwareClass; 3] = [
+        HardwareClass::TitanV2_1,
+        HardwareClass::Nonstandard,
+        HardwareClass::Unknown,
+    ];
+
+    /// Classify the hardware with the given resource units.
+    pub fn of(units: &ResourceUnits, tolerances: &SpecTolerances) -> Self {
+        if tolerances.deviations(units, &TITAN_V2_1).is_empty() {
+            HardwareClass::TitanV2_1
+        } else {
+            HardwareClass::Nonstandard
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HardwareClass::TitanV2_1 => "titan-v2.1",
+            HardwareClass::Nonstandard => "nonstandard",
+            HardwareClass::Unknown => "unknown",
+        }
+    }
+}
+
+impl FromStr for HardwareClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HardwareClass::ALL
+            .into_iter()
+            .find(|class| class.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown hardware class {s}, expected one of {}",
+                    HardwareClass::ALL.map(HardwareClass::name).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for HardwareClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,89 @@
+//! A health score of every farm, to track a farm over time with a single number.
+//!
+//! The score is the average of three percentages over the periods a farm's nodes were in it: the
+//! uptime of the nodes, the share of nodes of which the certification didn't change, and the share
+//! of periods in which the payout was right. A titan period is paid right if the received payout
+//! matches the expected titan payout, any other period if it matches the payout recomputed with
+//! the rates of its receipt.
+
+use std::collections::BTreeMap;
+
+use trace_titans::{tolerance::Tolerances, NodeResult, PERCENTAGE_PRECISION};
+
+/// Health of the nodes of a farm over the scanned periods.
+#[derive(Debug, Default)]
+pub struct FarmHealth {
+    /// Name of the farm in its latest receipt.
+    pub name: String,
+    pub nodes: usize,
+    /// Average uptime of the nodes, as a percentage.
+    pub uptime: f64,
+    /// Percentage of nodes which were either certified or not certified in all their periods.
+    pub certification: f64,
+    /// Percentage of periods with a receipt in which the payout was right.
+    pub payouts: f64,
+}
+
+impl FarmHealth {
+    /// Score from 0 to 100, the average of the uptime, certification and payout percentages.
+    pub fn score(&self) -> f64 {
+        (self.uptime + self.certification + self.payouts) / 3.
+    }
+}
+
+/// Health of every farm the nodes were in, by farm id. A node which moved between farms counts for
+/// every farm with the periods it was in it.
+pub fn farms(
+    node_results: &[(u32, NodeResult)],
+    tolerances: &Tolerances,
+) -> BTreeMap<u32, FarmHealth> {
+    // Farm id -> latest period and name, nodes, consistent nodes, periods, summed uptime and
+    // periods paid right.
+    let mut totals = BTreeMap::<u32, ((u32, String), usize, usize, usize, u64, usize)>::new();
+    for (_, result) in node_results {
+        for (farm_id, part) in result.by_farm() {
+            let total = totals.entry(farm_id).or_default();
+            total.1 += 1;
+            let mut certified = part
+                .periods()
+                .filter(|(_, r)| r.farm_id != 0)
+                .map(|(_, r)| r.is_certified);
+            let first = certified.next();
+            if certified.all(|c| Some(c) == first) {
+                total.2 += 1;
+            }
+            for (period, r) in part.periods().filter(|(_, r)| r.farm_id != 0) {
+                if period >= total.0 .0 {
+                    total.0 = (period, r.farm_name.clone());
+                }
+                total.3 += 1;
+                total.4 += r.uptime_percentage as u64;
+                let (target, tolerance) = if r.is_titan() {
+                    (r.expected_payout, tolerances.difference)
+                } else {
+                    (r.recomputed_payout, tolerances.recomputed)
+                };
+                if r.actual_payout.abs_diff(target) <= tolerance {
+                    total.5 += 1;
+                }
+            }
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(
+            |(farm_id, ((_, name), nodes, consistent, periods, uptime, paid_right))| {
+                let percentage = |part: f64, whole: usize| part * 100. / whole.max(1) as f64;
+                let health = FarmHealth {
+                    name,
+                    nodes,
+                    uptime: uptime as f64 / PERCENTAGE_PRECISION as f64 / periods.max(1) as f64,
+                    certification: percentage(consistent as f64, nodes),
+                    payouts: percentage(paid_right as f64, periods),
+                };
+                (farm_id, health)
+            },
+        )
+        .collect()
+}
@@ -0,0 +1,122 @@
+//! Client of the Stellar Horizon API, where the TFT payments are recorded.
+
+use serde::Deserialize;
+
+use trace_titans::{currency::TFT, Error};
+
+use crate::http;
+
+/// Public Horizon instance of the Stellar network.
+pub const DEFAULT_URL: &str = "https://horizon.stellar.org";
+/// Maximum page size Horizon allows.
+const PAGE_LIMIT: u32 = 200;
+
+/// A TFT payment between two accounts.
+#[derive(Debug, Clone)]
+pub struct TftPayment {
+    /// Id of the payment operation, unique per payment.
+    pub operation_id: String,
+    pub transaction_hash: String,
+    /// Time the payment was included in the ledger, as RFC 3339 timestamp.
+    pub created_at: String,
+    pub from: String,
+    pub to: String,
+    /// Amount in TFT, with 7 decimals.
+    pub amount: String,
+    /// Memo of the transaction, if it has one.
+    pub memo: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    _links: Links,
+    _embedded: Embedded,
+}
+
+#[derive(Deserialize)]
+struct Links {
+    next: Link,
+}
+
+#[derive(Deserialize)]
+struct Link {
+    href: String,
+}
+
+#[derive(Deserialize)]
+struct Embedded {
+    records: Vec<Operation>,
+}
+
+/// A payment-like operation. Only plain payments have all fields.
+#[derive(Deserialize)]
+struct Operation {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    transaction_hash: String,
+    created_at: String,
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+    #[serde(default)]
+    asset_code: String,
+    #[serde(default)]
+    amount: String,
+    transaction: Option<Transaction>,
+}
+
+#[derive(Deserialize)]
+struct Transaction {
+    memo: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    sequence: String,
+}
+
+/// Current sequence number of an account.
+pub fn sequence(base_url: &str, account: &str) -> Result<i64, Error> {
+    let url = format!("{}/accounts/{account}", base_url.trim_end_matches('/'));
+    let response: Account = serde_json::from_slice(&http::get(&url)?)
+        .map_err(|e| format!("invalid response from {url}: {e}"))?;
+    response
+        .sequence
+        .parse()
+        .map_err(|_| format!("invalid sequence number {} from {url}", response.sequence).into())
+}
+
+/// Fetch all TFT payments sent or received by an account, oldest first.
+pub fn tft_payments(base_url: &str, account: &str) -> Result<Vec<TftPayment>, Error> {
+    let mut url = format!(
+        "{}/accounts/{account}/payments?order=asc&limit={PAGE_LIMIT}&join=transactions",
+        base_url.trim_end_matches('/')
+    );
+    let mut payments = Vec::new();
+    loop {
+        let page: Page = serde_json::from_slice(&http::get(&url)?)
+            .map_err(|e| format!("invalid response from {url}: {e}"))?;
+        if page._embedded.records.is_empty() {
+            break;
+        }
+        payments.extend(
+            page._embedded
+                .records
+                .into_iter()
+                .filter(|op| op.kind == "payment" && op.asset_code == TFT.code)
+                .map(|op| TftPayment {
+                    operation_id: op.id,
+                    transaction_hash: op.transaction_hash,
+                    created_at: op.created_at,
+                    from: op.from,
+                    to: op.to,
+                    amount: op.amount,
+                    memo: op.transaction.and_then(|tx| tx.memo),
+                }),
+        );
+        url = page._links.next.href;
+    }
+    Ok(payments)
+}
@@ -0,0 +1,171 @@
+//! HTML comparison of the payouts of every reported node.
+//!
+//! For every node and period the page shows three amounts side by side: the payout declared in
+//! the receipt, the payout recomputed with the reward rates in the receipt, and the expected
+//! titan payout. Cells where the declared payout does not match the recomputed or expected payout
+//! are highlighted, which makes the page usable for reviewing nodes one by one.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use trace_titans::{
+    format_tft_styled, period::Period, tolerance::Tolerances, NodeResult, NumberStyle,
+};
+
+use crate::{health, lang::Lang, query::Column};
+
+/// Styling of the page, kept inline so the file can be shared on its own.
+pub const STYLE: &str = "body { font-family: sans-serif; font-size: 13px; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: right; white-space: nowrap; }
+th { background: #eee; }
+td.missing { color: #aaa; text-align: center; }
+td.mismatch { background: #f8d0d0; font-weight: bold; }
+td.titan { background: #e8f0ff; }";
+
+/// Write the comparison page of the given nodes over the scanned periods to a file, with the
+/// derived columns after the difference.
+pub fn write(
+    path: &Path,
+    node_results: &[(u32, NodeResult)],
+    periods: &[u32],
+    columns: &[Column],
+    tolerances: &Tolerances,
+    lang: Lang,
+    style: NumberStyle,
+) -> io::Result<()> {
+    let t = lang.texts();
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    writeln!(file, "<!DOCTYPE html>")?;
+    writeln!(file, "<html lang=\"{lang}\"><head><meta charset=\"utf-8\">")?;
+    writeln!(file, "<title>{}</title>", t.payouts_title)?;
+    writeln!(file, "<style>{STYLE}</style></head><body>")?;
+    writeln!(file, "<h1>{}</h1>", t.payouts_title)?;
+    writeln!(file, "<p>{}</p>", t.payouts_intro)?;
+
+    writeln!(file, "<table><thead><tr><th rowspan=\"2\">{}</th>", t.node)?;
+    for period in periods {
+        writeln!(
+            file,
+            "<th colspan=\"3\">{} {period}<br><small>{}</small></th>",
+            t.period,
+            Period::at_offset(*period as i64).date_range()
+        )?;
+    }
+    write!(file, "<th rowspan=\"2\">{}</th>", t.difference)?;
+    for column in columns {
+        write!(file, "<th rowspan=\"2\">{}</th>", escape(&column.name))?;
+    }
+    writeln!(file, "</tr><tr>")?;
+    for _ in periods {
+        writeln!(
+            file,
+            "<th>{}</th><th>{}</th><th>{}</th>",
+            t.declared, t.recomputed, t.titan
+        )?;
+    }
+    writeln!(file, "</tr></thead><tbody>")?;
+
+    for (node_id, result) in node_results {
+        write!(file, "<tr><th>{node_id}</th>")?;
+        for period in result {
+            if period.farm_id == 0 {
+                write!(
+                    file,
+                    "<td class=\"missing\" colspan=\"3\">{}</td>",
+                    t.no_receipt
+                )?;
+                continue;
+            }
+            let class = if period.is_titan() { "titan" } else { "" };
+            let recomputed_mismatch = !within(
+                period.actual_payout,
+                period.recomputed_payout,
+                tolerances.recomputed,
+            );
+            let expected_mismatch = period.is_titan()
+                && !within(
+                    period.actual_payout,
+                    period.expected_payout,
+                    tolerances.difference,
+                );
+            write!(
+                file,
+                "<td class=\"{}\">{}</td><td class=\"{}\">{}</td><td class=\"{}\">{}</td>",
+                class,
+                format_tft_styled(period.actual_payout, style),
+                if recomputed_mismatch {
+                    "mismatch"
+                } else {
+                    class
+                },
+                format_tft_styled(period.recomputed_payout, style),
+                if expected_mismatch { "mismatch" } else { class },
+                format_tft_styled(period.expected_payout, style),
+            )?;
+        }
+        write!(
+            file,
+            "<td>{}</td>",
+            format_tft_styled(result.difference(tolerances), style)
+        )?;
+        for column in columns {
+            write!(
+                file,
+                "<td>{}</td>",
+                column.value(*node_id, result, tolerances)
+            )?;
+        }
+        writeln!(file, "</tr>")?;
+    }
+
+    writeln!(file, "</tbody></table>")?;
+
+    writeln!(file, "<h2>{}</h2>", t.farm_health)?;
+    writeln!(file, "<p>{}</p>", t.farm_health_intro)?;
+    writeln!(
+        file,
+        "<table><thead><tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th>\
+         <th>{}</th></tr></thead><tbody>",
+        t.farm, t.name, t.nodes, t.uptime, t.certification, t.payouts_right, t.score
+    )?;
+    for (farm_id, farm) in health::farms(node_results, tolerances) {
+        writeln!(
+            file,
+            "<tr><th>{farm_id}</th><td>{}</td><td>{}</td><td>{:.1}%</td><td>{:.1}%</td>\
+             <td>{:.1}%</td><td>{:.1}</td></tr>",
+            escape(&farm.name),
+            farm.nodes,
+            farm.uptime,
+            farm.certification,
+            farm.payouts,
+            farm.score()
+        )?;
+    }
+    writeln!(file, "</tbody></table></body></html>")?;
+    file.flush()
+}
+
+/// Escape text for use in HTML content and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Indicates if two amounts differ by at most the tolerance.
+fn within(a: u64, b: u64, tolerance: u64) -> bool {
+    a.abs_diff(b) <= tolerance
+}
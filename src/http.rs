@@ -0,0 +1,44 @@
+//! HTTP requests.
+//!
+//! Requests are made with the `curl` binary rather than an HTTP library, which keeps the
+//! dependencies of the tool minimal. Only the few commands talking to external services need it.
+
+use std::process::Command;
+
+use trace_titans::Error;
+
+/// Get the body of a URL. Responses with an error status are returned as errors.
+pub fn get(url: &str) -> Result<Vec<u8>, Error> {
+    curl(url, &[])
+}
+
+/// Post a JSON body to a URL and get the body of the response. Responses with an error status are
+/// returned as errors.
+pub fn post_json(url: &str, body: &str) -> Result<Vec<u8>, Error> {
+    curl(
+        url,
+        &[
+            "--header",
+            "Content-Type: application/json",
+            "--data-binary",
+            body,
+        ],
+    )
+}
+
+fn curl(url: &str, args: &[&str]) -> Result<Vec<u8>, Error> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(args)
+        .arg(url)
+        .output()
+        .map_err(|e| format!("could not run curl, which is needed to fetch {url}: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "request to {url} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(output.stdout)
+}
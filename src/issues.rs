@@ -0,0 +1,67 @@
+//! Export of findings as issue tracker items.
+//!
+//! Every finding becomes an item with the shape of the body of the create issue request of the
+//! GitHub and Gitea APIs, so findings can be tracked until they are resolved. The items can be
+//! posted to the API one by one, e.g. with `jq -c '.[]'` and `curl`.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use trace_titans::findings::Finding;
+
+use crate::statements::capitalize;
+
+/// Label added to every issue, to find all issues from this tool.
+const TOOL_LABEL: &str = "trace-titans";
+
+/// An issue, in the shape the issue APIs expect.
+#[derive(Serialize)]
+struct Issue {
+    title: String,
+    body: String,
+    labels: Vec<&'static str>,
+}
+
+/// Write the findings of a scan of the given periods as a JSON list of issues to a file.
+pub fn write(path: &Path, findings: &[Finding], periods: &[u32]) -> io::Result<()> {
+    let issues = findings
+        .iter()
+        .map(|finding| issue(finding, periods))
+        .collect::<Vec<_>>();
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer_pretty(&mut file, &issues)?;
+    writeln!(file)?;
+    file.flush()
+}
+
+fn issue(finding: &Finding, periods: &[u32]) -> Issue {
+    let kind = finding.kind.name();
+    let subject = match (finding.node_id, finding.period) {
+        (Some(node_id), Some(period)) => format!("node {node_id} in period {period}"),
+        (Some(node_id), None) => format!("node {node_id}"),
+        (None, Some(period)) => format!("period {period}"),
+        (None, None) => finding.message.clone(),
+    };
+    let mut body = format!("{}\n\n", capitalize(&finding.message));
+    if let Some(node_id) = finding.node_id {
+        body.push_str(&format!("- Node: {node_id}\n"));
+    }
+    if let Some(period) = finding.period {
+        body.push_str(&format!("- Period: {period}\n"));
+    }
+    body.push_str(&format!(
+        "- Finding: `{kind}`\n\nFound by trace_titans while scanning periods {} to {}.\n",
+        periods[0],
+        periods[periods.len() - 1]
+    ));
+    Issue {
+        title: format!("{kind}: {subject}"),
+        body,
+        labels: vec![TOOL_LABEL, kind],
+    }
+}
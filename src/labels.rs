@@ -0,0 +1,72 @@
+//! Operator defined labels for nodes.
+//!
+//! Labels are read from a plain text file, with one node per line: the node id followed by a
+//! comma separated list of labels, e.g. `1234 rack-3,customer-x`. Empty lines and lines starting
+//! with `#` are ignored. A node can appear on multiple lines, in which case the labels are merged.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+/// Labels attached to nodes.
+#[derive(Debug, Default)]
+pub struct NodeLabels {
+    labels: BTreeMap<u32, BTreeSet<String>>,
+}
+
+impl NodeLabels {
+    /// Load labels from the file at the given path.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    /// Parse labels in the labels file format.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut labels = BTreeMap::<_, BTreeSet<_>>::new();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (node_id, node_labels) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("line {}: expected a node id and labels", idx + 1))?;
+            let node_id = node_id
+                .parse::<u32>()
+                .map_err(|e| format!("line {}: invalid node id: {e}", idx + 1))?;
+            labels.entry(node_id).or_default().extend(
+                node_labels
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|label| !label.is_empty())
+                    .map(String::from),
+            );
+        }
+
+        Ok(NodeLabels { labels })
+    }
+
+    /// Labels of a node, in alphabetical order.
+    pub fn get(&self, node_id: u32) -> impl Iterator<Item = &str> {
+        self.labels
+            .get(&node_id)
+            .into_iter()
+            .flat_map(|labels| labels.iter().map(String::as_str))
+    }
+
+    /// All labels of all nodes, in alphabetical order.
+    pub fn all(&self) -> BTreeSet<&str> {
+        self.labels.values().flatten().map(String::as_str).collect()
+    }
+
+    /// Indicates if the node carries at least one of the given labels.
+    pub fn has_any(&self, node_id: u32, labels: &[String]) -> bool {
+        self.labels
+            .get(&node_id)
+            .map(|node_labels| labels.iter().any(|label| node_labels.contains(label)))
+            .unwrap_or(false)
+    }
+}
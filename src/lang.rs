@@ -0,0 +1,323 @@
+//! Translations of the human facing texts of the report.
+//!
+//! Reports are often forwarded to farmers directly, so the headers of the CSV report and the
+//! texts of the HTML and PDF outputs are available in the languages most of them speak. Messages on
+//! stderr and the machine oriented exports, like the address book and findings, are always in
+//! English.
+
+use std::{fmt, str::FromStr};
+
+/// Language of the report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    #[default]
+    En,
+    Nl,
+    Fr,
+}
+
+impl Lang {
+    /// Texts in this language.
+    pub fn texts(self) -> &'static Texts {
+        match self {
+            Lang::En => &EN,
+            Lang::Nl => &NL,
+            Lang::Fr => &FR,
+        }
+    }
+
+    /// Language code, as used in the `lang` attribute of HTML pages.
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Nl => "nl",
+            Lang::Fr => "fr",
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Lang::En),
+            "nl" => Ok(Lang::Nl),
+            "fr" => Ok(Lang::Fr),
+            _ => Err(format!("unknown language {s}, expected en, nl or fr")),
+        }
+    }
+}
+
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// All translated texts. Texts with `{name}` placeholders are filled in with
+/// [`str::replace`].
+pub struct Texts {
+    pub titan: &'static str,
+    pub uptime: &'static str,
+    pub expected_tft: &'static str,
+    pub received_tft: &'static str,
+    pub recomputed_tft: &'static str,
+    pub titan_tft: &'static str,
+    pub total_expected_tft: &'static str,
+    pub total_received_tft: &'static str,
+    pub difference_to_send: &'static str,
+    pub difference: &'static str,
+    pub difference_usd: &'static str,
+    pub labels: &'static str,
+    pub opted_out: &'static str,
+    pub carbon_tft: &'static str,
+    pub total_carbon_tft: &'static str,
+    pub sla_violations: &'static str,
+    pub label: &'static str,
+    pub hardware_class: &'static str,
+    pub nodes: &'static str,
+    pub node: &'static str,
+    pub period: &'static str,
+    pub declared: &'static str,
+    pub recomputed: &'static str,
+    pub no_receipt: &'static str,
+    pub farm: &'static str,
+    pub farms: &'static str,
+    pub name: &'static str,
+    pub twin: &'static str,
+    pub payout_address: &'static str,
+    pub policy: &'static str,
+    pub certified: &'static str,
+    pub owed_tft: &'static str,
+    pub conflict: &'static str,
+    pub summary: &'static str,
+    pub periods: &'static str,
+    pub correction_to_send: &'static str,
+    pub nodes_owed: &'static str,
+    pub largest_difference: &'static str,
+    pub largest_difference_node: &'static str,
+    pub farm_health: &'static str,
+    pub farm_health_intro: &'static str,
+    pub certification: &'static str,
+    pub payouts_right: &'static str,
+    pub score: &'static str,
+    pub payouts_title: &'static str,
+    pub payouts_intro: &'static str,
+    pub report_title: &'static str,
+    /// Placeholders `{from}` and `{to}`, the first and last period.
+    pub site_intro: &'static str,
+    /// Placeholders `{address}` and `{owed}`.
+    pub farm_summary: &'static str,
+    pub address_conflict: &'static str,
+    /// Placeholders `{expected}`, `{received}` and `{difference}`.
+    pub node_summary: &'static str,
+    pub statement_title: &'static str,
+    /// Placeholders `{from}` and `{to}`, the first and last period.
+    pub periods_covered: &'static str,
+    /// Placeholder `{address}`.
+    pub payout_address_line: &'static str,
+    /// Placeholder `{owed}`.
+    pub total_owed: &'static str,
+}
+
+const EN: Texts = Texts {
+    titan: "titan",
+    uptime: "uptime",
+    expected_tft: "expected TFT",
+    received_tft: "received TFT",
+    recomputed_tft: "recomputed TFT",
+    titan_tft: "titan TFT",
+    total_expected_tft: "Total expected TFT",
+    total_received_tft: "Total received TFT",
+    difference_to_send: "Difference (to send)",
+    difference_usd: "Difference (USD at payout)",
+    difference: "difference",
+    labels: "labels",
+    opted_out: "opted out",
+    carbon_tft: "carbon offset TFT",
+    total_carbon_tft: "Total carbon offset TFT",
+    sla_violations: "below minimal uptime",
+    label: "label",
+    hardware_class: "hardware class",
+    nodes: "nodes",
+    node: "node",
+    period: "period",
+    declared: "declared",
+    recomputed: "recomputed",
+    no_receipt: "no receipt",
+    farm: "farm",
+    farms: "Farms",
+    name: "name",
+    twin: "twin",
+    payout_address: "payout address",
+    policy: "policy",
+    certified: "certified",
+    owed_tft: "owed TFT",
+    conflict: "conflict",
+    summary: "Summary",
+    periods: "Periods",
+    correction_to_send: "Correction to send TFT",
+    nodes_owed: "Nodes owed a correction",
+    largest_difference: "Largest difference TFT",
+    largest_difference_node: "Largest difference node",
+    farm_health: "Farm health",
+    farm_health_intro: "The score of a farm is the average of the uptime of its nodes, the share \
+                        of nodes of which the certification didn't change, and the share of \
+                        periods in which the payout was right.",
+    certification: "consistent certification",
+    payouts_right: "payouts right",
+    score: "score",
+    payouts_title: "Titan payouts",
+    payouts_intro:
+        "Per period: the payout declared in the receipt, the payout recomputed with the \
+        reward rates in the receipt, and the expected titan payout, in TFT. \
+        Highlighted cells do not match the declared payout. Periods in which the node \
+        was a titan are shaded.",
+    report_title: "Titan compensation report",
+    site_intro: "Titan nodes in periods {from} to {to}, compared to the payout they would have \
+                 received with the titan farming policy. Download the report as \
+                 <a href=\"report.csv\">CSV</a> or <a href=\"report.json\">JSON</a>.",
+    farm_summary: "Payout address: {address}. Owed: {owed} TFT.",
+    address_conflict: "The farm used more than one payout address:",
+    node_summary: "Expected {expected} TFT, received {received} TFT, difference (to send) \
+                   {difference} TFT.",
+    statement_title: "Compensation statement",
+    periods_covered: "Periods covered: {from} to {to}",
+    payout_address_line: "Payout address: {address}",
+    total_owed: "Total owed: {owed} TFT",
+};
+
+const NL: Texts = Texts {
+    titan: "titan",
+    uptime: "beschikbaarheid",
+    expected_tft: "verwacht TFT",
+    received_tft: "ontvangen TFT",
+    recomputed_tft: "herberekend TFT",
+    titan_tft: "titan TFT",
+    total_expected_tft: "Totaal verwacht TFT",
+    total_received_tft: "Totaal ontvangen TFT",
+    difference_to_send: "Verschil (te versturen)",
+    difference_usd: "Verschil (USD bij uitbetaling)",
+    difference: "verschil",
+    labels: "labels",
+    opted_out: "afgemeld",
+    carbon_tft: "CO2-compensatie TFT",
+    total_carbon_tft: "Totaal CO2-compensatie TFT",
+    sla_violations: "onder minimale uptime",
+    label: "label",
+    hardware_class: "hardwareklasse",
+    nodes: "nodes",
+    node: "node",
+    period: "periode",
+    declared: "gedeclareerd",
+    recomputed: "herberekend",
+    no_receipt: "geen ontvangstbewijs",
+    farm: "farm",
+    farms: "Farms",
+    name: "naam",
+    twin: "twin",
+    payout_address: "uitbetalingsadres",
+    policy: "beleid",
+    certified: "gecertificeerd",
+    owed_tft: "verschuldigd TFT",
+    conflict: "conflict",
+    summary: "Overzicht",
+    periods: "Perioden",
+    correction_to_send: "Correctie (te versturen)",
+    nodes_owed: "Nodes met een correctie",
+    largest_difference: "Grootste verschil TFT",
+    largest_difference_node: "Node grootste verschil",
+    farm_health: "Gezondheid van de farms",
+    farm_health_intro: "De score van een farm is het gemiddelde van de uptime van haar nodes, \
+                        het aandeel nodes waarvan de certificering niet veranderde, en het \
+                        aandeel periodes waarin de uitbetaling juist was.",
+    certification: "consistente certificering",
+    payouts_right: "juiste uitbetalingen",
+    score: "score",
+    payouts_title: "Titan uitbetalingen",
+    payouts_intro:
+        "Per periode: de uitbetaling in het ontvangstbewijs, de uitbetaling herberekend \
+        met de beloningen in het ontvangstbewijs, en de verwachte titan uitbetaling, \
+        in TFT. Gemarkeerde cellen komen niet overeen met de gedeclareerde \
+        uitbetaling. Periodes waarin de node een titan was zijn gekleurd.",
+    report_title: "Titan compensatierapport",
+    site_intro: "Titan nodes in periodes {from} tot {to}, vergeleken met de uitbetaling die ze \
+                 met het titan farming beleid zouden ontvangen hebben. Download het rapport als \
+                 <a href=\"report.csv\">CSV</a> of <a href=\"report.json\">JSON</a>.",
+    farm_summary: "Uitbetalingsadres: {address}. Verschuldigd: {owed} TFT.",
+    address_conflict: "De farm gebruikte meer dan één uitbetalingsadres:",
+    node_summary: "Verwacht {expected} TFT, ontvangen {received} TFT, verschil (te versturen) \
+                   {difference} TFT.",
+    statement_title: "Compensatie-overzicht",
+    periods_covered: "Periodes: {from} tot {to}",
+    payout_address_line: "Uitbetalingsadres: {address}",
+    total_owed: "Totaal verschuldigd: {owed} TFT",
+};
+
+const FR: Texts = Texts {
+    titan: "titan",
+    uptime: "disponibilité",
+    expected_tft: "TFT attendus",
+    received_tft: "TFT reçus",
+    recomputed_tft: "TFT recalculés",
+    titan_tft: "TFT titan",
+    total_expected_tft: "Total TFT attendus",
+    total_received_tft: "Total TFT reçus",
+    difference_to_send: "Différence (à envoyer)",
+    difference_usd: "Différence (USD au paiement)",
+    difference: "différence",
+    labels: "étiquettes",
+    opted_out: "désisté",
+    carbon_tft: "TFT de compensation carbone",
+    total_carbon_tft: "Total TFT de compensation carbone",
+    sla_violations: "sous la disponibilité minimale",
+    label: "étiquette",
+    hardware_class: "classe matérielle",
+    nodes: "nœuds",
+    node: "nœud",
+    period: "période",
+    declared: "déclaré",
+    recomputed: "recalculé",
+    no_receipt: "aucun reçu",
+    farm: "ferme",
+    farms: "Fermes",
+    name: "nom",
+    twin: "twin",
+    payout_address: "adresse de paiement",
+    policy: "politique",
+    certified: "certifié",
+    owed_tft: "TFT dus",
+    conflict: "conflit",
+    summary: "Résumé",
+    periods: "Périodes",
+    correction_to_send: "Correction à envoyer TFT",
+    nodes_owed: "Nœuds dus une correction",
+    largest_difference: "Différence maximale TFT",
+    largest_difference_node: "Nœud à l'écart maximal",
+    farm_health: "Santé des fermes",
+    farm_health_intro: "Le score d'une ferme est la moyenne de la disponibilité de ses nœuds, de \
+                        la part des nœuds dont la certification n'a pas changé, et de la part \
+                        des périodes dont le paiement était correct.",
+    certification: "certification constante",
+    payouts_right: "paiements corrects",
+    score: "score",
+    payouts_title: "Paiements des titans",
+    payouts_intro:
+        "Par période : le paiement déclaré dans le reçu, le paiement recalculé avec les \
+        taux de récompense du reçu, et le paiement titan attendu, en TFT. Les cellules \
+        en surbrillance ne correspondent pas au paiement déclaré. Les périodes pendant \
+        lesquelles le nœud était un titan sont grisées.",
+    report_title: "Rapport de compensation des titans",
+    site_intro: "Nœuds titan des périodes {from} à {to}, comparés au paiement qu'ils auraient \
+                 reçu avec la politique de farming titan. Téléchargez le rapport en \
+                 <a href=\"report.csv\">CSV</a> ou <a href=\"report.json\">JSON</a>.",
+    farm_summary: "Adresse de paiement : {address}. Dû : {owed} TFT.",
+    address_conflict: "La ferme a utilisé plus d'une adresse de paiement :",
+    node_summary: "Attendu {expected} TFT, reçu {received} TFT, différence (à envoyer) \
+                   {difference} TFT.",
+    statement_title: "Relevé de compensation",
+    periods_covered: "Périodes couvertes : {from} à {to}",
+    payout_address_line: "Adresse de paiement : {address}",
+    total_owed: "Total dû : {owed} TFT",
+};
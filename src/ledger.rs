@@ -0,0 +1,1252 @@
+//! Ledger of compensation payments.
+//!
+//! The ledger records the compensation payments which have been sent, so they can be reconciled
+//! against the amounts the report says are owed. It is a JSON file, by default `ledger.json` in
+//! the working directory, which is only changed by the `ledger` subcommands and is safe to edit
+//! by hand.
+//!
+//! A payment is attributed to a node if it has a node id, to a farm if it has a farm id or is
+//! sent to a payout address used by the farm, and is unmatched otherwise. Payments to a farm
+//! settle the nodes of the farm in order of their node id.
+//!
+//! The ledger also tracks the compensation status of every node through its lifecycle, see
+//! [`Status`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use trace_titans::{
+    date, format_diff_tft, format_percentage, format_tft, parse_tft,
+    policy::PolicySnapshot,
+    source,
+    tolerance::{self, Tolerances},
+    Error, NodeResult,
+};
+
+use crate::{
+    address_book::{self, FarmEntry},
+    cli::{Args, ImportFrom},
+    horizon, migrate,
+    opt_out::OptOuts,
+    plan::Plan,
+    sha256,
+};
+
+/// Default location of the ledger.
+pub const DEFAULT_PATH: &str = "ledger.json";
+
+/// Version of the ledger format, see [`migrate`].
+pub const FORMAT_VERSION: u64 = 1;
+
+/// All recorded payments and node statuses.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Ledger {
+    pub version: u64,
+    #[serde(default)]
+    pub payments: Vec<Payment>,
+    /// Status of nodes which have left [`Status::Computed`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub nodes: BTreeMap<u32, NodeRecord>,
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Ledger {
+            version: FORMAT_VERSION,
+            payments: Vec::new(),
+            nodes: BTreeMap::new(),
+        }
+    }
+}
+
+/// Compensation status of a node. Nodes move through the statuses in order, and can be reset to
+/// [`Status::Computed`] if a mistake is found.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The compensation is calculated in the report, the status of every node without record.
+    #[default]
+    Computed,
+    /// The amount has been reviewed and approved.
+    Approved,
+    /// The payment has been prepared and is waiting to be sent.
+    Queued,
+    /// The payment has been sent.
+    Paid,
+    /// The payment has been confirmed to have arrived.
+    Verified,
+}
+
+impl Status {
+    pub const ALL: [Status; 5] = [
+        Status::Computed,
+        Status::Approved,
+        Status::Queued,
+        Status::Paid,
+        Status::Verified,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Computed => "computed",
+            Status::Approved => "approved",
+            Status::Queued => "queued",
+            Status::Paid => "paid",
+            Status::Verified => "verified",
+        }
+    }
+
+    /// The status following this one in the lifecycle.
+    fn next(self) -> Option<Status> {
+        Status::ALL
+            .iter()
+            .position(|s| *s == self)
+            .and_then(|idx| Status::ALL.get(idx + 1))
+            .copied()
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Status::ALL
+            .into_iter()
+            .find(|status| status.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown status {s}, expected one of {}",
+                    Status::ALL.map(Status::name).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Status of a node with the history of how it got there.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NodeRecord {
+    pub status: Status,
+    /// All status changes, oldest first.
+    #[serde(default)]
+    pub history: Vec<StatusChange>,
+    /// Approval of the current amount, if the node is approved or further along.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<Approval>,
+}
+
+/// Sign-off of a reviewer on the amount owed to a node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Approval {
+    pub reviewer: String,
+    /// Time of the approval, as RFC 3339 timestamp.
+    pub at: String,
+    /// Amount approved, in TFT.
+    pub amount: String,
+    /// Digest of the results of the node the approval was made on, see [`snapshot_digest`].
+    pub snapshot: String,
+    /// Policy the expected payouts of every period were calculated with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policies: Vec<PolicySnapshot>,
+}
+
+impl Approval {
+    /// The approved amount, if the approval was made on the current results of the node and the
+    /// current policies of the periods. Otherwise the node has to be approved again.
+    pub fn check(
+        &self,
+        node_id: u32,
+        result: &NodeResult,
+        policies: &[PolicySnapshot],
+    ) -> Result<u64, String> {
+        let amount = parse_tft(&self.amount)
+            .ok_or_else(|| format!("invalid approved amount {}", self.amount))?;
+        let changed = if self.snapshot != snapshot_digest(node_id, result) {
+            "results"
+        } else if !self.policies.is_empty() && self.policies != policies {
+            "policies"
+        } else {
+            return Ok(amount);
+        };
+        Err(format!(
+            "the {changed} changed since {} approved {} TFT at {}",
+            self.reviewer, self.amount, self.at
+        ))
+    }
+}
+
+/// A change of the status of a node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: Status,
+    /// Time of the change, as RFC 3339 timestamp.
+    pub at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// A compensation payment which has been sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payment {
+    /// Unique id of the payment, e.g. the hash of the transaction.
+    pub id: String,
+    /// Address the payment was sent to.
+    pub address: String,
+    /// Amount sent, in TFT.
+    pub amount: String,
+    /// Node the payment compensates, if it was made for a single node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<u32>,
+    /// Farm the payment compensates, if it was made for a whole farm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub farm_id: Option<u32>,
+    /// Date the payment was sent, as recorded by whoever sent it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+impl Ledger {
+    /// Load the ledger from a file. A missing file is an empty ledger.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Ledger::default()),
+            Err(e) => return Err(format!("could not read ledger {}: {e}", path.display()).into()),
+        };
+        let value = serde_json::from_slice(&data)
+            .map_err(|e| format!("invalid ledger {}: {e}", path.display()))?;
+        migrate::check_version(path, &value, FORMAT_VERSION)?;
+        let ledger: Ledger = serde_json::from_value(value)
+            .map_err(|e| format!("invalid ledger {}: {e}", path.display()))?;
+        for payment in &ledger.payments {
+            payment.tft()?;
+        }
+        Ok(ledger)
+    }
+
+    /// Save the ledger to a file. The ledger is written to a temporary file first, so an
+    /// interrupted write never leaves a corrupt ledger behind.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let tmp = path.with_extension("json.tmp");
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)?;
+        file.into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Status of a node.
+    pub fn status(&self, node_id: u32) -> Status {
+        self.nodes
+            .get(&node_id)
+            .map_or(Status::Computed, |record| record.status)
+    }
+
+    /// Move a node to a new status, which must be the next status in the lifecycle or
+    /// [`Status::Computed`] to start over.
+    pub fn transition(
+        &mut self,
+        node_id: u32,
+        status: Status,
+        note: Option<&str>,
+    ) -> Result<(), String> {
+        let current = self.status(node_id);
+        if current.next() != Some(status) && (status != Status::Computed || current == status) {
+            let mut allowed = current.next().into_iter().collect::<Vec<_>>();
+            if current != Status::Computed {
+                allowed.push(Status::Computed);
+            }
+            return Err(format!(
+                "node {node_id} can't go from {current} to {status}, only to {}",
+                allowed
+                    .iter()
+                    .map(|s| s.name())
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            ));
+        }
+        let record = self.nodes.entry(node_id).or_default();
+        record.status = status;
+        if status == Status::Computed {
+            record.approval = None;
+        }
+        record.history.push(StatusChange {
+            status,
+            at: date::format_timestamp(date::now()),
+            note: note.map(str::to_string),
+        });
+        Ok(())
+    }
+
+    /// Add the payments which are not in the ledger yet, going by their id. Returns the amount of
+    /// payments added.
+    pub fn add(&mut self, payments: impl IntoIterator<Item = Payment>) -> usize {
+        let mut ids = self
+            .payments
+            .iter()
+            .map(|p| p.id.clone())
+            .collect::<HashSet<_>>();
+        let before = self.payments.len();
+        for payment in payments {
+            if ids.insert(payment.id.clone()) {
+                self.payments.push(payment);
+            }
+        }
+        self.payments.len() - before
+    }
+}
+
+impl Payment {
+    /// Amount of the payment in TFT units.
+    pub fn tft(&self) -> Result<u64, Error> {
+        parse_tft(&self.amount).ok_or_else(|| {
+            format!("payment {} has an invalid amount {}", self.id, self.amount).into()
+        })
+    }
+}
+
+/// Settlement of the obligation of a single node.
+#[derive(Debug, Default)]
+struct NodeSettlement {
+    farm_id: u32,
+    /// Amount owed according to the report, in TFT units.
+    owed: i64,
+    /// Amount paid so far, in TFT units.
+    paid: i64,
+}
+
+impl NodeSettlement {
+    /// Amount still to pay, differences within the tolerance are reported as 0.
+    fn outstanding(&self, tolerances: &Tolerances) -> i64 {
+        tolerance::denoise(self.owed - self.paid, tolerances.difference)
+    }
+}
+
+/// A payment, or part of a payment, which doesn't match an obligation, with the amount and the
+/// reason.
+type Unmatched<'a> = (&'a Payment, i64, &'static str);
+
+/// Match the payments in the ledger to the amounts owed to the given nodes. Payments to a farm
+/// are spread over the nodes of the farm in order of node id.
+fn settle<'a>(
+    ledger: &'a Ledger,
+    node_results: &[(u32, NodeResult)],
+    farms: &[FarmEntry],
+    tolerances: &Tolerances,
+) -> Result<(BTreeMap<u32, NodeSettlement>, Vec<Unmatched<'a>>), Error> {
+    // Only nodes which received too little have an obligation.
+    let mut nodes = node_results
+        .iter()
+        .filter_map(|(node_id, result)| {
+            let owed = result.difference(tolerances);
+            (owed > 0).then(|| {
+                (
+                    *node_id,
+                    NodeSettlement {
+                        farm_id: result.latest().map_or(0, |r| r.farm_id),
+                        owed,
+                        paid: 0,
+                    },
+                )
+            })
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    // Payments, or parts of payments, which don't match an obligation, with the reason.
+    let mut unmatched = Vec::new();
+    for payment in &ledger.payments {
+        let amount = payment.tft()? as i64;
+        let farm = match (payment.node_id, payment.farm_id) {
+            (Some(node_id), _) => {
+                match nodes.get_mut(&node_id) {
+                    Some(node) => {
+                        node.paid += amount;
+                        if node.paid > node.owed {
+                            unmatched.push((payment, node.paid - node.owed, "overpays the node"));
+                            node.paid = node.owed;
+                        }
+                    }
+                    None => unmatched.push((payment, amount, "node is not owed anything")),
+                }
+                continue;
+            }
+            (None, Some(farm_id)) => farms.iter().find(|farm| farm.farm_id == farm_id),
+            (None, None) => farms
+                .iter()
+                .find(|farm| farm.addresses.contains(&payment.address)),
+        };
+        let Some(farm) = farm else {
+            unmatched.push((
+                payment,
+                amount,
+                "no farm with this id or payout address is owed anything",
+            ));
+            continue;
+        };
+        if !farm.addresses.contains(&payment.address) {
+            unmatched.push((
+                payment,
+                amount,
+                "address is not a payout address of the farm",
+            ));
+            continue;
+        }
+        let mut remaining = amount;
+        for node in nodes
+            .values_mut()
+            .filter(|node| node.farm_id == farm.farm_id)
+        {
+            let part = remaining.min(node.owed - node.paid);
+            node.paid += part;
+            remaining -= part;
+        }
+        if remaining > 0 {
+            unmatched.push((payment, remaining, "overpays the farm"));
+        }
+    }
+
+    Ok((nodes, unmatched))
+}
+
+/// Nodes owed compensation in the report of which the payments in the ledger settle the amount.
+pub fn settled_nodes(
+    ledger: &Ledger,
+    node_results: &[(u32, NodeResult)],
+    tolerances: &Tolerances,
+) -> Result<BTreeSet<u32>, Error> {
+    let farms = address_book::build(node_results, tolerances);
+    let (nodes, _) = settle(ledger, node_results, &farms, tolerances)?;
+    Ok(nodes
+        .into_iter()
+        .filter(|(_, node)| node.outstanding(tolerances) == 0)
+        .map(|(node_id, _)| node_id)
+        .collect())
+}
+
+/// Print the reconciliation of the payments in the ledger against the amounts owed to the given
+/// nodes.
+pub fn report(
+    ledger: &Ledger,
+    node_results: &[(u32, NodeResult)],
+    args: &Args,
+) -> Result<(), Error> {
+    let tolerances = &args.tolerances;
+    let farms = address_book::build(node_results, tolerances);
+    let (nodes, unmatched) = settle(ledger, node_results, &farms, tolerances)?;
+    let outstanding = |node: &NodeSettlement| node.outstanding(tolerances);
+    let settled = nodes.values().filter(|n| outstanding(n) == 0).count();
+    let unpaid = nodes.values().filter(|n| n.paid == 0).count();
+    println!("Nodes owed compensation: {}", nodes.len());
+    println!("  settled: {settled}");
+    println!("  partially settled: {}", nodes.len() - settled - unpaid);
+    println!("  outstanding: {unpaid}");
+    for (node_id, node) in &nodes {
+        let status = if outstanding(node) == 0 {
+            "settled"
+        } else if node.paid == 0 {
+            "outstanding"
+        } else {
+            "partially settled"
+        };
+        println!(
+            "  node {node_id} (farm {}): owed {}, paid {}, balance {} ({status}, {})",
+            node.farm_id,
+            format_diff_tft(node.owed),
+            format_diff_tft(node.paid),
+            format_diff_tft(outstanding(node)),
+            ledger.status(*node_id),
+        );
+    }
+
+    println!("Outstanding balance per farm:");
+    for farm in &farms {
+        let farm_nodes = nodes.values().filter(|node| node.farm_id == farm.farm_id);
+        let (owed, paid, balance) = farm_nodes.fold((0, 0, 0), |(owed, paid, balance), node| {
+            (
+                owed + node.owed,
+                paid + node.paid,
+                balance + outstanding(node),
+            )
+        });
+        if owed == 0 {
+            continue;
+        }
+        println!(
+            "  farm {} {}: owed {}, paid {}, balance {}",
+            farm.farm_id,
+            farm.farm_name,
+            format_diff_tft(owed),
+            format_diff_tft(paid),
+            format_diff_tft(balance),
+        );
+    }
+
+    println!("Payments not matching an obligation: {}", unmatched.len());
+    for (payment, amount, reason) in unmatched {
+        println!(
+            "  {} to {}: {} of {} TFT, {reason}",
+            payment.id,
+            payment.address,
+            format_tft(amount as u64),
+            payment.amount,
+        );
+    }
+
+    Ok(())
+}
+
+/// Backfill the ledger with past payments. With `--plan`, the payments which would be added are
+/// only printed.
+pub fn import(path: &Path, from: &ImportFrom, args: &Args) -> Result<(), Error> {
+    let mut ledger = Ledger::load(path)?;
+    let payments = match from {
+        ImportFrom::Horizon(account) => {
+            let url = args.horizon_url.as_deref().unwrap_or(horizon::DEFAULT_URL);
+            horizon::tft_payments(url, account)?
+                .into_iter()
+                // Only payments sent by the account are compensation payments.
+                .filter(|p| p.from == *account && p.to != *account)
+                .map(|p| Payment {
+                    id: format!("{}:{}", p.transaction_hash, p.operation_id),
+                    address: p.to,
+                    amount: p.amount,
+                    node_id: None,
+                    farm_id: None,
+                    date: Some(p.created_at),
+                    memo: p.memo,
+                })
+                .collect::<Vec<_>>()
+        }
+        ImportFrom::Csv(csv) => read_csv(csv)?,
+    };
+    for payment in &payments {
+        payment.tft()?;
+    }
+    let found = payments.len();
+    let before = ledger.payments.len();
+    let added = ledger.add(payments);
+    if args.plan {
+        let mut plan = Plan::default();
+        plan.note(format!(
+            "{}: add {added} payment(s), skip {} already in the ledger",
+            path.display(),
+            found - added
+        ));
+        for payment in &ledger.payments[before..] {
+            plan.note(format!(
+                "    {}: {} TFT to {}",
+                payment.id, payment.amount, payment.address
+            ));
+        }
+        plan.print("ledger import");
+        return Ok(());
+    }
+    ledger.save(path)?;
+    eprintln!(
+        "Imported {added} payment(s) into {}, skipped {} already in the ledger",
+        path.display(),
+        found - added
+    );
+    Ok(())
+}
+
+/// Columns of a payments CSV file, the first three are required.
+const CSV_COLUMNS: [&str; 7] = [
+    "id", "address", "amount", "node_id", "farm_id", "date", "memo",
+];
+
+/// Read payments from a CSV file with a header naming the columns.
+fn read_csv(path: &Path) -> Result<Vec<Payment>, Error> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let mut records = source::parse_csv(&content).into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| format!("{} is empty", path.display()))?;
+    // Index of every known column in the file.
+    let mut columns = [None; CSV_COLUMNS.len()];
+    for (idx, name) in header.iter().enumerate() {
+        let column = CSV_COLUMNS
+            .iter()
+            .position(|c| *c == name.trim())
+            .ok_or_else(|| format!("unknown column {name} in {}", path.display()))?;
+        columns[column] = Some(idx);
+    }
+    if let Some(missing) = (0..3).find(|c| columns[*c].is_none()) {
+        return Err(format!("{} has no {} column", path.display(), CSV_COLUMNS[missing]).into());
+    }
+
+    let mut payments = Vec::new();
+    for (line, record) in records.enumerate() {
+        let field = |column: usize| {
+            columns[column]
+                .and_then(|idx| record.get(idx))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        let id = |column| -> Result<Option<u32>, Error> {
+            field(column)
+                .map(|v| {
+                    v.parse().map_err(|_| {
+                        format!(
+                            "invalid {} {v} on line {} of {}",
+                            CSV_COLUMNS[column],
+                            line + 2,
+                            path.display()
+                        )
+                        .into()
+                    })
+                })
+                .transpose()
+        };
+        let required = |column| {
+            field(column).map(str::to_string).ok_or_else(|| {
+                format!(
+                    "missing {} on line {} of {}",
+                    CSV_COLUMNS[column],
+                    line + 2,
+                    path.display()
+                )
+            })
+        };
+        payments.push(Payment {
+            id: required(0)?,
+            address: required(1)?,
+            amount: required(2)?,
+            node_id: id(3)?,
+            farm_id: id(4)?,
+            date: field(5).map(str::to_string),
+            memo: field(6).map(str::to_string),
+        });
+    }
+    Ok(payments)
+}
+
+/// Move nodes to a new status and save the ledger. Either all nodes are moved, or none. With
+/// `plan`, the moves are only printed.
+pub fn mark(
+    path: &Path,
+    status: Status,
+    node_ids: &[u32],
+    note: Option<&str>,
+    plan: bool,
+) -> Result<(), Error> {
+    if status == Status::Approved {
+        return Err(
+            "nodes are approved with the approve command, which records the reviewer".into(),
+        );
+    }
+    let mut ledger = Ledger::load(path)?;
+    let mut changes = Plan::default();
+    for node_id in node_ids {
+        changes.note(format!(
+            "{}: node {node_id} from {} to {status}",
+            path.display(),
+            ledger.status(*node_id)
+        ));
+        ledger.transition(*node_id, status, note)?;
+    }
+    if plan {
+        changes.print("ledger mark");
+        return Ok(());
+    }
+    ledger.save(path)?;
+    eprintln!("Marked {} node(s) as {status}", node_ids.len());
+    Ok(())
+}
+
+/// Approve the amounts owed to the nodes listed in a file, one node id per line, on behalf of a
+/// reviewer. Either all nodes are approved, or none. With `--plan`, the approvals are only
+/// printed.
+pub fn approve(
+    path: &Path,
+    node_results: &[(u32, NodeResult)],
+    reviewer: &str,
+    nodes_file: &Path,
+    args: &Args,
+) -> Result<(), Error> {
+    let content = fs::read_to_string(nodes_file)
+        .map_err(|e| format!("could not read {}: {e}", nodes_file.display()))?;
+    let mut node_ids = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        node_ids.push(
+            line.parse::<u32>()
+                .map_err(|_| format!("invalid node id {line} in {}", nodes_file.display()))?,
+        );
+    }
+
+    let opt_outs = match &args.opt_outs {
+        Some(path) => OptOuts::load(path, args.opt_outs_signers.as_deref())?,
+        None => OptOuts::default(),
+    };
+    let policies = args.policies.snapshot(&args.periods);
+    let at = date::format_timestamp(date::now());
+    let note = format!("approved by {reviewer}");
+    let mut ledger = Ledger::load(path)?;
+    let mut plan = Plan::default();
+    let mut total = 0;
+    for node_id in &node_ids {
+        let result = node_results
+            .iter()
+            .find(|(id, _)| id == node_id)
+            .map(|(_, result)| result);
+        let farm_id = result.and_then(NodeResult::latest).map(|r| r.farm_id);
+        if let Some((farm_id, opt_out)) =
+            farm_id.and_then(|farm_id| Some((farm_id, opt_outs.get(farm_id)?)))
+        {
+            return Err(format!(
+                "node {node_id} is of farm {farm_id}, which opted out of compensation ({})",
+                opt_out.describe()
+            )
+            .into());
+        }
+        let owed = result
+            .map(|result| result.difference(&args.tolerances))
+            .filter(|owed| *owed > 0)
+            .ok_or_else(|| format!("node {node_id} is not owed anything in the report"))?;
+        plan.note(format!(
+            "{}: node {node_id} from {} to {}, {} TFT",
+            path.display(),
+            ledger.status(*node_id),
+            Status::Approved,
+            format_diff_tft(owed)
+        ));
+        ledger.transition(*node_id, Status::Approved, Some(&note))?;
+        ledger
+            .nodes
+            .get_mut(node_id)
+            .expect("Node was just approved")
+            .approval = Some(Approval {
+            reviewer: reviewer.to_string(),
+            at: at.clone(),
+            amount: format_diff_tft(owed),
+            snapshot: snapshot_digest(*node_id, result.expect("Node is owed an amount")),
+            policies: policies.clone(),
+        });
+        total += owed;
+    }
+    if args.plan {
+        plan.note(format!(
+            "{reviewer} approves {} node(s) for a total of {} TFT",
+            node_ids.len(),
+            format_diff_tft(total)
+        ));
+        plan.print("approve");
+        return Ok(());
+    }
+    ledger.save(path)?;
+    eprintln!(
+        "{reviewer} approved {} node(s) for a total of {} TFT",
+        node_ids.len(),
+        format_diff_tft(total)
+    );
+    Ok(())
+}
+
+/// Digest of the results of a node, which identifies the data an approval was based on. Covers
+/// every value of every period the amount is calculated from, and is independent of the output
+/// format.
+pub fn snapshot_digest(node_id: u32, result: &NodeResult) -> String {
+    let mut data = String::new();
+    for (period, r) in result.periods() {
+        data.push_str(&format!(
+            "{node_id},{period},{},{},{},{},{},{},{},{},{}\n",
+            r.farm_id,
+            r.twin_id,
+            r.payout_address,
+            r.farming_policy,
+            r.is_certified,
+            format_percentage(r.uptime_percentage),
+            format_tft(r.expected_payout),
+            format_tft(r.actual_payout),
+            format_tft(r.recomputed_payout),
+        ));
+    }
+    sha256::hex_digest(data.as_bytes())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use trace_titans::{
+        aggregate_node_results,
+        pipeline::{NodeReceipts, ParsedReceipt},
+        policy::{Policies, TitanSignal, UptimeSla},
+        receipt::ReceiptSummary,
+    };
+
+    use super::*;
+
+    /// The account of the all zero key.
+    pub(crate) const ZERO: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+    /// An empty directory for ledgers and their inputs, removed when the test is done.
+    pub(crate) struct TempDir(pub(crate) PathBuf);
+
+    impl TempDir {
+        pub(crate) fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("trace_titans-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Result of a titan of a farm which was up all of period 57, and was minted `minted` TFT
+    /// units for it.
+    pub(crate) fn node_result(
+        node_id: u32,
+        farm_id: u32,
+        address: &str,
+        minted: u64,
+    ) -> (u32, NodeResult) {
+        let receipt = serde_json::from_str::<ReceiptSummary>(&format!(
+            r#"{{"period": {{"start": 1672461160, "end": 1675092040}}, "node_id": {node_id},
+            "twin_id": 1, "farm_id": {farm_id}, "farm_name": "farm {farm_id}",
+            "stellar_payout_address": "{address}", "measured_uptime": 2630880,
+            "tft_connection_price": 100, "cloud_units": {{"cu": 1.0, "su": 2.0, "nu": 1.0}},
+            "resource_units": {{"cru": 8, "mru": 32, "hru": 0, "sru": 1000}},
+            "resource_utilization": {{"cru": 0, "mru": 0, "hru": 0, "sru": 0, "ip": 0}},
+            "reward": {{"musd": 0, "tft": {minted}}}, "node_type": "CERTIFIED",
+            "farming_policy_id": 2}}"#
+        ))
+        .unwrap()
+        .into_interned();
+        let parsed = ParsedReceipt {
+            period: 57,
+            origin: format!("57/{node_id}.json"),
+            receipt,
+            fixups: Vec::new(),
+        };
+        let receipts = NodeReceipts::from([(node_id, BTreeMap::from([(57, parsed)]))]);
+        let policies = Policies::default();
+        aggregate_node_results(
+            receipts,
+            &[57],
+            &policies,
+            TitanSignal::default(),
+            UptimeSla::default(),
+        )
+        .remove(0)
+    }
+
+    /// Arguments for a run over period 57.
+    pub(crate) fn args() -> Args {
+        Args {
+            periods: vec![57],
+            ..Args::default()
+        }
+    }
+
+    #[test]
+    fn approvals_hold_for_the_results_and_policies_they_were_made_on() {
+        let (node_id, result) = node_result(1, 1, ZERO, 0);
+        let owed = result.difference(&args().tolerances);
+        assert!(owed > 0);
+        let policies = args().policies.snapshot(&[57]);
+        let approval = Approval {
+            reviewer: "alice".into(),
+            at: "2024-01-01T00:00:00Z".into(),
+            amount: format_diff_tft(owed),
+            snapshot: snapshot_digest(node_id, &result),
+            policies: policies.clone(),
+        };
+        assert_eq!(approval.check(node_id, &result, &policies), Ok(owed as u64));
+
+        // The node was minted more since the approval.
+        let (_, changed) = node_result(1, 1, ZERO, 10_000_000);
+        let e = approval.check(node_id, &changed, &policies).unwrap_err();
+        assert!(
+            e.starts_with("the results changed since alice approved"),
+            "{e}"
+        );
+        // The same results under another policy.
+        let mut other = policies.clone();
+        other[0].minimal_uptime -= 1;
+        let e = approval.check(node_id, &result, &other).unwrap_err();
+        assert!(e.starts_with("the policies changed"), "{e}");
+        // Approvals made before policies were recorded only check the results.
+        let old = Approval {
+            policies: Vec::new(),
+            ..approval.clone()
+        };
+        assert!(old.check(node_id, &result, &other).is_ok());
+
+        let invalid = Approval {
+            amount: "a lot".into(),
+            ..approval
+        };
+        assert!(invalid.check(node_id, &result, &policies).is_err());
+    }
+
+    #[test]
+    fn approve_records_the_approval() {
+        let dir = TempDir::new("approve");
+        let (path, nodes) = (dir.0.join("ledger.json"), dir.0.join("nodes.txt"));
+        let results = [node_result(1, 1, ZERO, 0), node_result(2, 1, ZERO, 0)];
+        fs::write(&nodes, "# reviewed\n1\n").unwrap();
+        approve(&path, &results, "alice", &nodes, &args()).unwrap();
+
+        let ledger = Ledger::load(&path).unwrap();
+        assert_eq!(ledger.status(1), Status::Approved);
+        assert_eq!(ledger.status(2), Status::Computed);
+        let approval = ledger.nodes[&1].approval.as_ref().unwrap();
+        assert_eq!(approval.reviewer, "alice");
+        assert_eq!(approval.snapshot, snapshot_digest(1, &results[0].1));
+        assert_eq!(approval.policies, args().policies.snapshot(&[57]));
+        let owed = results[0].1.difference(&args().tolerances);
+        assert_eq!(
+            approval.check(1, &results[0].1, &approval.policies),
+            Ok(owed as u64)
+        );
+    }
+
+    #[test]
+    fn approve_rejects_opted_out_farms() {
+        let dir = TempDir::new("approve-opt-out");
+        let (path, nodes) = (dir.0.join("ledger.json"), dir.0.join("nodes.txt"));
+        let opt_outs = dir.0.join("opt-outs.csv");
+        fs::write(&opt_outs, "farm_id,signed_by,date\n2,bob,2024-01-01\n").unwrap();
+        let args = Args {
+            opt_outs: Some(opt_outs),
+            ..args()
+        };
+        let results = [node_result(1, 1, ZERO, 0), node_result(2, 2, ZERO, 0)];
+        fs::write(&nodes, "1\n2\n").unwrap();
+        let e = approve(&path, &results, "alice", &nodes, &args).unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("node 2 is of farm 2, which opted out of compensation"),
+            "{e}"
+        );
+        // No node is approved if any node can't be.
+        assert!(!path.exists());
+
+        fs::write(&nodes, "1\n").unwrap();
+        approve(&path, &results, "alice", &nodes, &args).unwrap();
+        assert_eq!(Ledger::load(&path).unwrap().status(1), Status::Approved);
+    }
+
+    /// A payment of `amount` TFT units to an address, for a node or a farm.
+    fn payment(
+        id: &str,
+        address: &str,
+        amount: i64,
+        node_id: Option<u32>,
+        farm_id: Option<u32>,
+    ) -> Payment {
+        Payment {
+            id: id.into(),
+            address: address.into(),
+            amount: format_tft(amount as u64),
+            node_id,
+            farm_id,
+            date: None,
+            memo: None,
+        }
+    }
+
+    /// An unmatched payment by its id, with the amount and the reason.
+    type UnmatchedById = (String, i64, &'static str);
+
+    /// Settle the payments against the results, with the amount paid to every node and the
+    /// unmatched payments by id.
+    fn settled(
+        payments: Vec<Payment>,
+        results: &[(u32, NodeResult)],
+    ) -> (Vec<(u32, i64)>, Vec<UnmatchedById>) {
+        let ledger = Ledger {
+            payments,
+            ..Ledger::default()
+        };
+        let tolerances = &args().tolerances;
+        let farms = address_book::build(results, tolerances);
+        let (nodes, unmatched) = settle(&ledger, results, &farms, tolerances).unwrap();
+        (
+            nodes.iter().map(|(id, node)| (*id, node.paid)).collect(),
+            unmatched
+                .into_iter()
+                .map(|(payment, amount, reason)| (payment.id.clone(), amount, reason))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn farm_payments_are_spread_over_the_nodes_of_the_farm() {
+        let results = [
+            node_result(1, 1, "GFARM1", 0),
+            node_result(2, 1, "GFARM1", 0),
+            node_result(3, 2, "GFARM2", 0),
+        ];
+        let owed = results[0].1.difference(&args().tolerances);
+        // By farm id, and by the payout address of the farm.
+        let payments = vec![
+            payment("a", "GFARM1", owed + owed / 2, None, Some(1)),
+            payment("b", "GFARM2", owed / 4, None, None),
+        ];
+        let (paid, unmatched) = settled(payments, &results);
+        assert_eq!(paid, [(1, owed), (2, owed / 2), (3, owed / 4)]);
+        assert!(unmatched.is_empty());
+
+        let ledger = Ledger {
+            payments: vec![payment("a", "GFARM1", 2 * owed, None, Some(1))],
+            ..Ledger::default()
+        };
+        let settled = settled_nodes(&ledger, &results, &args().tolerances).unwrap();
+        assert_eq!(settled, BTreeSet::from([1, 2]));
+    }
+
+    #[test]
+    fn overpayments_are_unmatched() {
+        let results = [
+            node_result(1, 1, "GFARM1", 0),
+            node_result(2, 1, "GFARM1", 0),
+        ];
+        let owed = results[0].1.difference(&args().tolerances);
+        let payments = vec![
+            payment("node", "GFARM1", owed + 5, Some(1), None),
+            payment("farm", "GFARM1", owed + 7, None, Some(1)),
+        ];
+        let (paid, unmatched) = settled(payments, &results);
+        // The node payment settles node 1, so the farm payment only goes to node 2.
+        assert_eq!(paid, [(1, owed), (2, owed)]);
+        assert_eq!(
+            unmatched,
+            [
+                ("node".into(), 5, "overpays the node"),
+                ("farm".into(), 7, "overpays the farm"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_payments_have_a_reason() {
+        // Node 2 received more than expected, so it isn't owed anything.
+        let results = [
+            node_result(1, 1, "GFARM1", 0),
+            node_result(2, 2, "GFARM2", u64::MAX / 4),
+        ];
+        let payments = vec![
+            payment("minted", "GFARM2", 1, Some(2), None),
+            payment("unknown node", "GFARM1", 1, Some(3), None),
+            payment("unknown farm", "GFARM1", 1, None, Some(3)),
+            payment("unknown address", "GOTHER", 1, None, None),
+            payment("other address", "GFARM2", 1, None, Some(1)),
+        ];
+        let (paid, unmatched) = settled(payments, &results);
+        assert_eq!(paid, [(1, 0)]);
+        let no_farm = "no farm with this id or payout address is owed anything";
+        assert_eq!(
+            unmatched,
+            [
+                ("minted".into(), 1, "node is not owed anything"),
+                ("unknown node".into(), 1, "node is not owed anything"),
+                ("unknown farm".into(), 1, no_farm),
+                ("unknown address".into(), 1, no_farm),
+                (
+                    "other address".into(),
+                    1,
+                    "address is not a payout address of the farm"
+                ),
+            ]
+        );
+    }
+
+    /// Past payments as exported from a spreadsheet: the columns in another order, a memo with
+    /// a separator and quotes, and the optional columns left empty.
+    const PAYMENTS_CSV: &str = "\
+amount,address,id,farm_id,node_id,memo,date
+12.5,GFARM1,tx1,1,,\"titan correction, \"\"p57\"\"\",2024-01-02
+0.1234567,GFARM2,tx2,,3,,
+";
+
+    #[test]
+    fn import_payments_from_csv() {
+        let dir = TempDir::new("import");
+        let (path, csv) = (dir.0.join("ledger.json"), dir.0.join("payments.csv"));
+        fs::write(&csv, PAYMENTS_CSV).unwrap();
+        import(&path, &ImportFrom::Csv(csv.clone()), &args()).unwrap();
+
+        let ledger = Ledger::load(&path).unwrap();
+        let [first, second] = ledger.payments.as_slice() else {
+            panic!("expected 2 payments, got {:?}", ledger.payments);
+        };
+        assert_eq!(
+            (
+                first.id.as_str(),
+                first.address.as_str(),
+                first.tft().unwrap()
+            ),
+            ("tx1", "GFARM1", 125_000_000)
+        );
+        assert_eq!((first.farm_id, first.node_id), (Some(1), None));
+        assert_eq!(first.memo.as_deref(), Some("titan correction, \"p57\""));
+        assert_eq!(first.date.as_deref(), Some("2024-01-02"));
+        assert_eq!(
+            (second.id.as_str(), second.tft().unwrap()),
+            ("tx2", 1_234_567)
+        );
+        assert_eq!((second.farm_id, second.node_id), (None, Some(3)));
+        assert_eq!((second.memo.as_ref(), second.date.as_ref()), (None, None));
+
+        // Importing again skips the payments which are in the ledger already.
+        fs::write(&csv, format!("{PAYMENTS_CSV}0.5,GFARM1,tx3,,,,\n")).unwrap();
+        import(&path, &ImportFrom::Csv(csv), &args()).unwrap();
+        let ids = Ledger::load(&path)
+            .unwrap()
+            .payments
+            .into_iter()
+            .map(|p| p.id);
+        assert_eq!(ids.collect::<Vec<_>>(), ["tx1", "tx2", "tx3"]);
+    }
+
+    #[test]
+    fn invalid_payment_csvs() {
+        let dir = TempDir::new("import-invalid");
+        let (path, csv) = (dir.0.join("ledger.json"), dir.0.join("payments.csv"));
+        for (content, error) in [
+            ("", "is empty"),
+            ("id,address\ntx1,G\n", "has no amount column"),
+            ("id,address,amount,fee\n", "unknown column fee"),
+            ("id,address,amount\ntx1,,1\n", "missing address on line 2"),
+            (
+                "id,address,amount,node_id\ntx1,G,1,\ntx2,G,1,n\n",
+                "invalid node_id n on line 3",
+            ),
+            ("id,address,amount\ntx1,G,-1\n", "invalid amount -1"),
+        ] {
+            fs::write(&csv, content).unwrap();
+            let e = import(&path, &ImportFrom::Csv(csv.clone()), &args()).unwrap_err();
+            assert!(e.to_string().contains(error), "{content:?}: {e}");
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn status_transitions() {
+        use Status::*;
+        // Every status can only move to the next one, or back to computed.
+        for (from, to, allowed) in [
+            (Computed, Approved, true),
+            (Computed, Queued, false),
+            (Computed, Paid, false),
+            (Computed, Verified, false),
+            (Computed, Computed, false),
+            (Approved, Queued, true),
+            (Approved, Paid, false),
+            (Approved, Approved, false),
+            (Approved, Computed, true),
+            (Queued, Paid, true),
+            (Queued, Approved, false),
+            (Queued, Verified, false),
+            (Queued, Computed, true),
+            (Paid, Verified, true),
+            (Paid, Queued, false),
+            (Paid, Computed, true),
+            (Verified, Verified, false),
+            (Verified, Paid, false),
+            (Verified, Computed, true),
+        ] {
+            let mut ledger = Ledger::default();
+            for status in Status::ALL.into_iter().skip(1).take_while(|s| *s <= from) {
+                ledger.transition(1, status, None).unwrap();
+            }
+            assert_eq!(ledger.status(1), from);
+            let result = ledger.transition(1, to, Some("note"));
+            assert_eq!(result.is_ok(), allowed, "{from} to {to}: {result:?}");
+            let expected = if allowed { to } else { from };
+            assert_eq!(ledger.status(1), expected, "{from} to {to}");
+        }
+
+        let mut ledger = Ledger::default();
+        let e = ledger.transition(1, Queued, None).unwrap_err();
+        assert_eq!(
+            e,
+            "node 1 can't go from computed to queued, only to approved"
+        );
+        ledger.transition(1, Approved, None).unwrap();
+        let e = ledger.transition(1, Paid, None).unwrap_err();
+        assert_eq!(
+            e,
+            "node 1 can't go from approved to paid, only to queued or computed"
+        );
+    }
+
+    #[test]
+    fn starting_over_drops_the_approval() {
+        let dir = TempDir::new("start-over");
+        let (path, nodes) = (dir.0.join("ledger.json"), dir.0.join("nodes.txt"));
+        fs::write(&nodes, "1\n").unwrap();
+        approve(
+            &path,
+            &[node_result(1, 1, ZERO, 0)],
+            "alice",
+            &nodes,
+            &args(),
+        )
+        .unwrap();
+        mark(&path, Status::Computed, &[1], Some("wrong farm"), false).unwrap();
+
+        let ledger = Ledger::load(&path).unwrap();
+        let record = &ledger.nodes[&1];
+        assert_eq!(record.status, Status::Computed);
+        assert!(record.approval.is_none());
+        let history = record.history.iter().map(|c| (c.status, c.note.as_deref()));
+        assert_eq!(
+            history.collect::<Vec<_>>(),
+            [
+                (Status::Approved, Some("approved by alice")),
+                (Status::Computed, Some("wrong farm")),
+            ]
+        );
+    }
+
+    #[test]
+    fn mark_moves_all_nodes_or_none() {
+        let dir = TempDir::new("mark");
+        let path = dir.0.join("ledger.json");
+        let mut ledger = Ledger::default();
+        ledger.transition(1, Status::Approved, None).unwrap();
+        ledger.transition(1, Status::Queued, None).unwrap();
+        ledger.save(&path).unwrap();
+
+        // Node 2 was never queued, so node 1 isn't marked either.
+        assert!(mark(&path, Status::Paid, &[1, 2], None, false).is_err());
+        assert_eq!(Ledger::load(&path).unwrap().status(1), Status::Queued);
+        // A plan only prints the moves.
+        mark(&path, Status::Paid, &[1], None, true).unwrap();
+        assert_eq!(Ledger::load(&path).unwrap().status(1), Status::Queued);
+        mark(&path, Status::Paid, &[1], None, false).unwrap();
+        assert_eq!(Ledger::load(&path).unwrap().status(1), Status::Paid);
+        // Approvals need a reviewer.
+        assert!(mark(&path, Status::Approved, &[2], None, false).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Cross-check of the reward calculation against the calculation of earlier versions.
+//!
+//! Earlier versions upscaled cloud units by multiplying the floating point number with the
+//! precision, which can truncate to a unit less than the decimal number in the receipt. While the
+//! old numbers are still trusted, this lists the receipts of which the payouts moved by more than
+//! the tolerance.
+
+use trace_titans::{
+    format_diff_tft, format_tft, pipeline::NodeReceipts, policy::Policies, reward::RewardBreakdown,
+};
+
+/// A payout of a receipt which differs from the payout calculated like earlier versions did.
+#[derive(Debug, PartialEq)]
+pub struct Disagreement {
+    pub node_id: u32,
+    pub period: u32,
+    pub origin: String,
+    /// Which payout differs, `expected` or `recomputed`.
+    pub payout: &'static str,
+    /// Payout of the calculation, in TFT units.
+    pub fixed: u64,
+    /// Payout of the legacy calculation, in TFT units.
+    pub legacy: u64,
+}
+
+impl Disagreement {
+    /// Describe the disagreement for stderr.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}: {} payout of node {} in period {} is {} TFT, the legacy calculation gives {} TFT \
+             ({} TFT)",
+            self.origin,
+            self.payout,
+            self.node_id,
+            self.period,
+            format_tft(self.fixed),
+            format_tft(self.legacy),
+            format_diff_tft(self.fixed as i64 - self.legacy as i64)
+        )
+    }
+}
+
+/// Calculate the expected payout, with the titan rates of the period, and the recomputed payout,
+/// with the rates of the receipt, of every receipt both ways, and return those which differ by
+/// more than the tolerance in TFT units.
+pub fn check(
+    node_receipts: &NodeReceipts,
+    policies: &Policies,
+    tolerance: u64,
+) -> Vec<Disagreement> {
+    let mut disagreements = Vec::new();
+    for (&node_id, periods) in node_receipts {
+        for parsed in periods.values() {
+            let receipt = &parsed.receipt;
+            let titan = policies.for_period(receipt.period.offset() as u32).rewards;
+            let payouts = [
+                ("expected", &titan),
+                ("recomputed", &receipt.resource_rewards),
+            ];
+            for (payout, rewards) in payouts {
+                let fixed = RewardBreakdown::calculate(receipt, rewards).expected();
+                let legacy = RewardBreakdown::calculate_legacy(receipt, rewards).expected();
+                if fixed.abs_diff(legacy) > tolerance {
+                    disagreements.push(Disagreement {
+                        node_id,
+                        period: parsed.period,
+                        origin: parsed.origin.clone(),
+                        payout,
+                        fixed,
+                        legacy,
+                    });
+                }
+            }
+        }
+    }
+    disagreements
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use trace_titans::{pipeline::ParsedReceipt, receipt::ReceiptSummary};
+
+    use super::*;
+
+    fn receipts(cu: f64) -> NodeReceipts {
+        let receipt = serde_json::from_str::<ReceiptSummary>(&format!(
+            r#"{{"period": {{"start": 1672461160, "end": 1675092040}}, "node_id": 1,
+            "twin_id": 1, "farm_id": 1, "farm_name": "farm",
+            "stellar_payout_address": "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF",
+            "measured_uptime": 2630880, "tft_connection_price": 10,
+            "cloud_units": {{"cu": {cu}, "su": 0.0, "nu": 0.0}},
+            "resource_units": {{"cru": 8, "mru": 32, "hru": 0, "sru": 1000}},
+            "resource_utilization": {{"cru": 0, "mru": 0, "hru": 0, "sru": 0, "ip": 0}},
+            "reward": {{"musd": 0, "tft": 0}}, "node_type": "CERTIFIED",
+            "farming_policy_id": 2}}"#
+        ))
+        .unwrap()
+        .into_interned();
+        let parsed = ParsedReceipt {
+            period: 57,
+            origin: "57/1.json".to_string(),
+            receipt,
+            fixups: Vec::new(),
+        };
+        NodeReceipts::from([(1, BTreeMap::from([(57, parsed)]))])
+    }
+
+    #[test]
+    fn payouts_which_moved_beyond_the_tolerance_are_listed() {
+        let policies = Policies::default();
+        assert_eq!(check(&receipts(1.5), &policies, 0), []);
+
+        let disagreements = check(&receipts(0.0029), &policies, 0);
+        let payouts = disagreements.iter().map(|d| d.payout).collect::<Vec<_>>();
+        assert_eq!(payouts, ["expected", "recomputed"]);
+        let disagreement = &disagreements[0];
+        assert!(disagreement.fixed > disagreement.legacy);
+        assert!(disagreement
+            .describe()
+            .starts_with("57/1.json: expected payout of node 1 in period 57"));
+
+        let tolerance = disagreements
+            .iter()
+            .map(|d| d.fixed - d.legacy)
+            .max()
+            .unwrap();
+        assert_eq!(check(&receipts(0.0029), &policies, tolerance), []);
+    }
+}
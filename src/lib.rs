@@ -0,0 +1,411 @@
+//! Receipt parsing and reward math of trace_titans, to reuse in other tools.
+//!
+//! Receipts are loaded from a [`ReceiptSource`] with [`load_receipts`], and aggregated per node
+//! with [`aggregate_node_results`], which calculates the payout every node should have received
+//! as a titan with [`calculate_expected_reward`]. [`findings`] points out what needs a closer look
+//! in the results, and [`events`] reports the progress of all of this. The `trace_titans` binary
+//! is the command line interface on top of this.
+//!
+//! [`prelude`] exports the stable surface of the library, which follows semantic versioning.
+//! Modules hidden from the documentation serve the binary, and apart from the items the prelude
+//! exports can change in any release.
+
+use std::collections::BTreeMap;
+
+pub use currency::{
+    format_diff_tft, format_tft, format_tft_styled, format_usd, parse_tft, NumberStyle,
+    TFT_PRECISION,
+};
+use events::EventHandler;
+use hardware::{HardwareClass, SpecTolerances};
+use pipeline::{Metrics, NodeReceipts, ParsedReceipt, PipelineConfig};
+use policy::{Policies, TitanSignal, UptimeSla};
+use receipt::{ReceiptSummary, ResourceUnits};
+use reward::RewardBreakdown;
+use source::{ReceiptSource, SourceError};
+use tolerance::Tolerances;
+
+use crate::period::STANDARD_PERIOD_DURATION;
+
+#[doc(hidden)]
+pub mod cancel;
+#[doc(hidden)]
+pub mod currency;
+#[doc(hidden)]
+pub mod date;
+pub mod events;
+pub mod findings;
+#[doc(hidden)]
+pub mod hardware;
+pub mod period;
+#[doc(hidden)]
+pub mod pipeline;
+#[doc(hidden)]
+pub mod policy;
+pub mod prelude;
+pub mod receipt;
+pub mod reward;
+#[doc(hidden)]
+pub mod source;
+#[doc(hidden)]
+pub mod tolerance;
+
+/// Offsets of the periods to scan if no periods are given. The receipts of a period are stored in
+/// a directory named after the period offset.
+pub const DEFAULT_PERIODS: [u32; 6] = [52, 53, 54, 55, 56, 57];
+/// node_type value for certified nodes.
+pub const CERTIFIED_NODE_TYPE: &str = "CERTIFIED";
+/// Additional scale for percentages.
+pub const PERCENTAGE_PRECISION: u32 = 1_000;
+
+/// Error type of the library and the tool, errors are only reported to the user.
+pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Aggregated results of a node.
+#[derive(Debug, Default)]
+pub struct NodeResult {
+    /// Result of every scanned period, in order.
+    periods: Vec<(u32, NodePeriodResult)>,
+}
+
+impl NodeResult {
+    /// Result without a receipt in any of the given periods.
+    pub fn empty(periods: &[u32]) -> Self {
+        NodeResult {
+            periods: periods
+                .iter()
+                .map(|&period| (period, NodePeriodResult::default()))
+                .collect(),
+        }
+    }
+
+    /// Result of every scanned period, with the period.
+    pub fn periods(&self) -> impl Iterator<Item = (u32, &NodePeriodResult)> {
+        self.periods.iter().map(|(period, r)| (*period, r))
+    }
+
+    /// Result of a period, if it is scanned.
+    pub fn get(&self, period: u32) -> Option<&NodePeriodResult> {
+        self.periods().find_map(|(p, r)| (p == period).then_some(r))
+    }
+
+    /// The parts of the result in every farm the node was in, by farm id. Every part only has the
+    /// periods in which the receipt of the node has that farm, the other periods are empty, so a
+    /// node which moved between farms owes each farm what it missed while in that farm.
+    pub fn by_farm(&self) -> BTreeMap<u32, NodeResult> {
+        let mut farms = BTreeMap::<u32, NodeResult>::new();
+        for (idx, (_, r)) in self.periods.iter().enumerate() {
+            if r.farm_id == 0 {
+                continue;
+            }
+            let part = farms.entry(r.farm_id).or_insert_with(|| NodeResult {
+                periods: self
+                    .periods
+                    .iter()
+                    .map(|(period, _)| (*period, NodePeriodResult::default()))
+                    .collect(),
+            });
+            part.periods[idx].1 = r.clone();
+        }
+        farms
+    }
+
+    /// Result of the latest period the node has a receipt for, if any.
+    pub fn latest(&self) -> Option<&NodePeriodResult> {
+        self.into_iter().rfind(|r| r.farm_id != 0)
+    }
+
+    /// Total expected payout over all periods.
+    pub fn total_expected(&self) -> u64 {
+        self.into_iter().map(|r| r.expected_payout).sum()
+    }
+
+    /// Total actual payout over all periods.
+    pub fn total_received(&self) -> u64 {
+        self.into_iter().map(|r| r.actual_payout).sum()
+    }
+
+    /// Total carbon offset over all periods.
+    pub fn total_carbon_offset(&self) -> u64 {
+        self.into_iter().map(|r| r.carbon_offset).sum()
+    }
+
+    /// Count the carbon offset as part of the expected and the actual payout in the periods in
+    /// which the node is in one of the farms, which receive the offset separately.
+    pub fn include_carbon_offset(&mut self, farms: &[u32]) {
+        for (_, r) in &mut self.periods {
+            if r.farm_id != 0 && farms.contains(&r.farm_id) {
+                r.expected_payout += r.carbon_offset;
+                r.actual_payout += r.carbon_offset;
+            }
+        }
+    }
+
+    /// Difference between the expected and actual payout, i.e. the amount still to send.
+    /// Differences within the tolerance are reported as 0.
+    pub fn difference(&self, tolerances: &Tolerances) -> i64 {
+        tolerance::denoise(
+            self.total_expected() as i64 - self.total_received() as i64,
+            tolerances.difference,
+        )
+    }
+
+    /// Hardware class of the node in the latest period it has a receipt for.
+    pub fn hardware_class(&self, tolerances: &SpecTolerances) -> HardwareClass {
+        self.latest()
+            .map_or(HardwareClass::Unknown, |r| r.hardware_class(tolerances))
+    }
+
+    pub fn is_titan(&self) -> bool {
+        self.into_iter().any(NodePeriodResult::is_titan)
+    }
+}
+
+impl<'a> IntoIterator for &'a NodeResult {
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (u32, NodePeriodResult)>,
+        fn(&'a (u32, NodePeriodResult)) -> &'a NodePeriodResult,
+    >;
+    type Item = &'a NodePeriodResult;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.periods.iter().map(|(_, r)| r)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct NodePeriodResult {
+    /// Farm of the node in this period, 0 if there is no receipt for the period.
+    pub farm_id: u32,
+    pub farm_name: String,
+    pub twin_id: u32,
+    pub payout_address: String,
+    pub farming_policy: u32,
+    pub uptime_percentage: u32,
+    /// Seconds the node was measured to be up in the period.
+    pub measured_uptime: u64,
+    /// TFT price on connection of the receipt in milli USD.
+    pub connection_price: u64,
+    /// Payout of the receipt in milli USD, without fixups.
+    pub payout_musd: u64,
+    pub expected_payout: u64,
+    /// How the expected payout is made up.
+    pub expected: RewardBreakdown,
+    /// Payout of the receipt and its fixups.
+    pub actual_payout: u64,
+    /// Payout of the fixup receipts of the period, included in the actual payout.
+    pub fixup_payout: u64,
+    /// Carbon offset minted along with the reward, in TFT units.
+    pub carbon_offset: u64,
+    /// Payout recalculated with the reward rates in the receipt, i.e. what minting should have
+    /// paid under the policy it used.
+    pub recomputed_payout: u64,
+    pub is_certified: bool,
+    /// Whether the node is a titan in this period, according to the [`TitanSignal`] of the run.
+    pub is_titan: bool,
+    /// Whether the uptime is below the minimal uptime of the titan policy. With
+    /// [`UptimeSla::Enforce`], no payout is expected then.
+    pub below_minimal_uptime: bool,
+    pub resource_units: ResourceUnits,
+}
+
+impl NodePeriodResult {
+    pub fn from_receipt(
+        receipt: &ReceiptSummary,
+        policies: &Policies,
+        signal: TitanSignal,
+        sla: UptimeSla,
+    ) -> Self {
+        let is_certified = policies.is_certified(&receipt.node_type);
+        let expected = calculate_expected_reward(receipt, policies);
+        let uptime_percentage = u32::min(
+            (receipt.measured_uptime * 100 * PERCENTAGE_PRECISION as u64 / STANDARD_PERIOD_DURATION)
+                as u32,
+            100 * PERCENTAGE_PRECISION,
+        );
+        let minimal_uptime = policies
+            .for_period(receipt.period.offset() as u32)
+            .minimal_uptime;
+        let below_minimal_uptime = uptime_percentage < minimal_uptime;
+        NodePeriodResult {
+            farm_id: receipt.farm_id,
+            farm_name: receipt.farm_name.to_string(),
+            twin_id: receipt.twin_id,
+            payout_address: receipt.stellar_payout_address.to_string(),
+            farming_policy: receipt.farming_policy_id(),
+            uptime_percentage,
+            measured_uptime: receipt.measured_uptime,
+            connection_price: receipt.tft_connection_price,
+            payout_musd: receipt.reward.musd,
+            expected_payout: if below_minimal_uptime && sla == UptimeSla::Enforce {
+                0
+            } else {
+                expected.expected()
+            },
+            expected,
+            actual_payout: receipt.reward.tft,
+            fixup_payout: 0,
+            carbon_offset: receipt.carbon_offset.tft,
+            recomputed_payout: RewardBreakdown::calculate(receipt, &receipt.resource_rewards)
+                .expected(),
+            is_certified,
+            is_titan: signal.is_titan(receipt.farming_policy_id(), is_certified),
+            below_minimal_uptime,
+            resource_units: receipt.resource_units,
+        }
+    }
+
+    /// Result of a loaded receipt, of which the fixups are added to the actual payout.
+    pub fn from_parsed(
+        parsed: &ParsedReceipt,
+        policies: &Policies,
+        signal: TitanSignal,
+        sla: UptimeSla,
+    ) -> Self {
+        let mut result = Self::from_receipt(&parsed.receipt, policies, signal, sla);
+        result.fixup_payout = parsed.fixup_payout();
+        result.actual_payout += result.fixup_payout;
+        result
+    }
+
+    pub fn hardware_class(&self, tolerances: &SpecTolerances) -> HardwareClass {
+        if self.farm_id == 0 {
+            HardwareClass::Unknown
+        } else {
+            HardwareClass::of(&self.resource_units, tolerances)
+        }
+    }
+
+    pub fn is_titan(&self) -> bool {
+        self.is_titan
+    }
+}
+
+/// Load the receipts of the given periods from the source, and aggregate them per node and period.
+/// Progress is reported to the event handler, [`events::ignore`] ignores it.
+///
+/// Uses as many workers as there are CPU's available, see [`pipeline::load`] to configure the
+/// workers and collect timings.
+pub fn load_receipts(
+    source: &dyn ReceiptSource,
+    periods: &[u32],
+    events: &dyn EventHandler,
+) -> Result<NodeReceipts, SourceError> {
+    pipeline::load(
+        source,
+        periods,
+        PipelineConfig::default(),
+        &Metrics::default(),
+        events,
+    )
+}
+
+/// Calculate the result of every node in the given periods from its receipts, under the policies.
+/// Periods without a receipt get an empty result.
+pub fn aggregate_node_results(
+    node_receipts: NodeReceipts,
+    periods: &[u32],
+    policies: &Policies,
+    signal: TitanSignal,
+    sla: UptimeSla,
+) -> Vec<(u32, NodeResult)> {
+    node_receipts
+        .into_iter()
+        .map(|(node_id, mut receipts)| {
+            let result = NodeResult {
+                periods: periods
+                    .iter()
+                    .map(|&period| {
+                        let result = receipts
+                            .remove(&period)
+                            .map(|parsed| {
+                                NodePeriodResult::from_parsed(&parsed, policies, signal, sla)
+                            })
+                            .unwrap_or_default();
+                        (period, result)
+                    })
+                    .collect(),
+            };
+            (node_id, result)
+        })
+        .collect()
+}
+
+/// Calculate the expected reward as if the node had farming policy 2, with the reward rates of the
+/// period of the receipt.
+pub fn calculate_expected_reward(receipt: &ReceiptSummary, policies: &Policies) -> RewardBreakdown {
+    let period = receipt.period.offset() as u32;
+    RewardBreakdown::calculate(receipt, &policies.for_period(period).rewards)
+}
+
+/// Format a percentage with 3 digits of precision.
+pub fn format_percentage(p: u32) -> String {
+    format!(
+        "{}.{:03}%",
+        p / PERCENTAGE_PRECISION,
+        p % PERCENTAGE_PRECISION
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A receipt of a titan which was up for 92% of the period.
+    fn receipt() -> ReceiptSummary<'static> {
+        let uptime = STANDARD_PERIOD_DURATION * 92 / 100;
+        serde_json::from_str::<ReceiptSummary>(&format!(
+            r#"{{"period": {{"start": 1669830280, "end": 1672461160}}, "node_id": 3,
+            "twin_id": 103, "farm_id": 10, "farm_name": "farm", "stellar_payout_address": "G",
+            "measured_uptime": {uptime}, "tft_connection_price": 90,
+            "cloud_units": {{"cu": 1.0, "su": 2.0, "nu": 1.0}},
+            "resource_units": {{"cru": 8, "mru": 32, "hru": 0, "sru": 1000}},
+            "resource_utilization": {{"cru": 0, "mru": 0, "hru": 0, "sru": 0, "ip": 0}},
+            "reward": {{"musd": 6000, "tft": 600000000}}, "node_type": "CERTIFIED",
+            "farming_policy_id": 2}}"#
+        ))
+        .unwrap()
+        .into_interned()
+    }
+
+    #[test]
+    fn minimal_uptime_of_the_period() {
+        let receipt = receipt();
+        let result = |policies: &Policies, sla| {
+            NodePeriodResult::from_receipt(&receipt, policies, TitanSignal::Either, sla)
+        };
+        let default = Policies::default();
+        assert!(result(&default, UptimeSla::Flag).below_minimal_uptime);
+        assert_eq!(result(&default, UptimeSla::Enforce).expected_payout, 0);
+
+        let mut lenient = Policies::default();
+        let period = receipt.period.offset() as u32;
+        lenient
+            .minimal_uptime
+            .insert(period, 90 * PERCENTAGE_PRECISION);
+        let enforced = result(&lenient, UptimeSla::Enforce);
+        assert!(!enforced.below_minimal_uptime);
+        assert_eq!(
+            enforced.expected_payout,
+            result(&default, UptimeSla::Flag).expected_payout
+        );
+        assert!(enforced.expected_payout > 0);
+
+        // Only the period of the receipt uses the minimal uptime of that period.
+        let mut other = Policies::default();
+        other
+            .minimal_uptime
+            .insert(period + 1, 90 * PERCENTAGE_PRECISION);
+        assert!(result(&other, UptimeSla::Flag).below_minimal_uptime);
+    }
+
+    #[test]
+    fn percentages_have_every_digit() {
+        assert_eq!(format_percentage(100_000), "100.000%");
+        assert_eq!(format_percentage(95_500), "95.500%");
+        assert_eq!(format_percentage(95_050), "95.050%");
+        assert_eq!(format_percentage(95_005), "95.005%");
+        assert_eq!(format_percentage(0), "0.000%");
+    }
+}
@@ -0,0 +1,66 @@
+//! Guard against concurrent runs in the same receipt directory.
+//!
+//! Runs which write to disk take an exclusive lock on a lock file in the working directory, so
+//! overlapping invocations, e.g. from cron, don't interleave their writes. The lock is held by the
+//! operating system and released when the process exits, also when it crashes, so a stale lock
+//! file never blocks later runs.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::Path,
+};
+
+use trace_titans::Error;
+
+/// Name of the lock file.
+pub const LOCK_FILE: &str = ".trace_titans.lock";
+
+/// What to do if another run holds the lock.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Fail right away.
+    #[default]
+    Fail,
+    /// Wait until the other run releases the lock.
+    Wait,
+    /// Don't take the lock at all.
+    Force,
+}
+
+/// Exclusive lock of a directory, released when dropped.
+#[derive(Debug)]
+pub struct RunLock {
+    _file: File,
+}
+
+/// Lock the directory according to the mode. Returns no lock with [`LockMode::Force`].
+pub fn acquire(dir: &Path, mode: LockMode) -> Result<Option<RunLock>, Error> {
+    if mode == LockMode::Force {
+        return Ok(None);
+    }
+    let path = dir.join(LOCK_FILE);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| format!("could not open lock file {}: {e}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) if mode == LockMode::Wait => {
+            eprintln!("Waiting for another run to release {}", path.display());
+            file.lock()?;
+        }
+        Err(TryLockError::WouldBlock) => {
+            return Err(format!(
+            "another run holds the lock on {}, use --wait to wait for it or --force to run anyway",
+            path.display()
+        )
+            .into())
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(format!("could not lock {}: {e}", path.display()).into())
+        }
+    }
+    Ok(Some(RunLock { _file: file }))
+}
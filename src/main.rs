@@ -1,184 +1,1135 @@
-use std::{collections::BTreeMap, fs};
-
-use receipt::{MintingReceipt, ResourceRewards};
-
-use crate::period::STANDARD_PERIOD_DURATION;
-
-mod period;
-mod receipt;
-
-/// Directory names in the receipt directory to scan.
-const DIR_NAMES: [&str; 6] = ["52", "53", "54", "55", "56", "57"];
-/// Precision of 1 TFT.
-const TFT_PRECISION: u64 = 10_000_000;
-/// node_type value for certified nodes.
-const CERTIFIED_NODE_TYPE: &str = "CERTIFIED";
-/// Additional scale for percentages.
-const PERCENTAGE_PRECISION: u32 = 1_000;
-
-/// Aggregated results of a node
-#[derive(Debug, Default)]
-struct NodeResult {
-    p52: NodePeriodResult,
-    p53: NodePeriodResult,
-    p54: NodePeriodResult,
-    p55: NodePeriodResult,
-    p56: NodePeriodResult,
-    p57: NodePeriodResult,
-}
-
-impl NodeResult {
-    fn is_titan(&self) -> bool {
-        self.p52.is_titan()
-            || self.p53.is_titan()
-            || self.p54.is_titan()
-            || self.p55.is_titan()
-            || self.p56.is_titan()
-            || self.p57.is_titan()
-    }
-}
-
-impl<'a> IntoIterator for &'a NodeResult {
-    type IntoIter = std::array::IntoIter<&'a NodePeriodResult, 6>;
-    type Item = &'a NodePeriodResult;
-
-    fn into_iter(self) -> Self::IntoIter {
-        IntoIterator::into_iter([
-            &self.p52, &self.p53, &self.p54, &self.p55, &self.p56, &self.p57,
-        ])
-    }
-}
-
-#[derive(Debug, Default)]
-struct NodePeriodResult {
-    farming_policy: u32,
-    uptime_percentage: u32,
-    expected_payout: u64,
-    actual_payout: u64,
-    is_certified: bool,
-}
-
-impl NodePeriodResult {
-    fn is_titan(&self) -> bool {
-        self.farming_policy == 2 || (self.farming_policy == 1 && self.is_certified)
-    }
-}
-
-fn main() -> Result<(), Box<dyn std::error::Error + 'static>> {
-    let mut node_receipts = BTreeMap::<_, Vec<(_, _)>>::new();
-    // aggregate all the receipts
-    for dir_name in DIR_NAMES {
-        let period = u32::from_str_radix(dir_name, 10).expect("Dir name is period offset");
-        for entry in fs::read_dir(dir_name)? {
-            let entry = entry?;
-            let receipt =
-                serde_json::from_reader::<_, MintingReceipt>(fs::File::open(entry.path())?)?;
-            node_receipts
-                .entry(receipt.node_id)
-                .or_default()
-                .push((period, receipt));
-        }
-    }
-
-    let mut node_results = BTreeMap::new();
-    for (node_id, receipts) in node_receipts {
-        // Technically we could allocate this map outside of the loop an reuse it everytime, but
-        // this offers an implicit sanity check.
-        let mut receipts_parsed = BTreeMap::new();
-        for (period, receipt) in receipts {
-            receipts_parsed.insert(
-                period,
-                NodePeriodResult {
-                    farming_policy: receipt.farming_policy_id,
-                    uptime_percentage: u32::min(
-                        (receipt.measured_uptime * 100 * PERCENTAGE_PRECISION as u64
-                            / STANDARD_PERIOD_DURATION) as u32,
-                        100 * PERCENTAGE_PRECISION,
-                    ),
-                    expected_payout: calculate_expected_titan_reward(&receipt),
-                    actual_payout: receipt.reward.tft,
-                    is_certified: receipt.node_type == CERTIFIED_NODE_TYPE,
-                },
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+use trace_titans::{
+    aggregate_node_results, cancel,
+    currency::{Amount, USD},
+    date,
+    events::{self, Event},
+    findings::{self, Finding},
+    format_diff_tft, format_percentage, format_tft, format_tft_styled, format_usd,
+    period::Period,
+    pipeline::{self, Metrics, NodeReceipts, Stage},
+    policy,
+    source::{ColumnMapping, CsvSource, DirectorySource, GitSource, ReceiptSource},
+    tolerance, Error, NodeResult, NumberStyle, PERCENTAGE_PRECISION,
+};
+
+use cli::{Args, Command, GroupBy, LedgerCommand, ReportFormat};
+use digest::Snapshot;
+use labels::NodeLabels;
+use lang::{Lang, Texts};
+use ledger::Ledger;
+use monitoring::Monitoring;
+use opt_out::{OptOut, OptOuts};
+use prices::Prices;
+use totals::PublishedTotals;
+
+mod address_book;
+mod blake2b;
+mod blame;
+mod bundle;
+mod cli;
+mod config;
+mod digest;
+mod explain;
+mod fetch;
+mod health;
+mod horizon;
+mod html;
+mod http;
+mod issues;
+mod labels;
+mod lang;
+mod ledger;
+mod legacy;
+mod lock;
+mod memos;
+mod migrate;
+mod minting;
+mod missing;
+mod monitoring;
+mod opt_out;
+mod pay;
+mod pdf;
+mod plan;
+mod prices;
+mod profile;
+mod prune;
+mod query;
+mod rates;
+mod received;
+mod runs;
+mod serve;
+mod sha256;
+mod site;
+mod statements;
+mod stats;
+mod stellar;
+mod subperiod;
+mod table;
+mod test_vectors;
+mod token;
+mod totals;
+mod validate;
+mod xxhash;
+mod zip;
+
+/// Exit code of a report which left out receipts which could not be used.
+const PARTIAL_EXIT_CODE: i32 = 2;
+
+/// First line of a report of an interrupted run, before its header.
+const PARTIAL_MARKER: &str =
+    "# PARTIAL: the run was interrupted, only the receipts loaded before it are reported";
+
+fn main() -> Result<(), Error> {
+    let mut args = Args::parse()?;
+    validate::check(&args)?;
+    let _lock = if args.writes().is_some() {
+        lock::acquire(Path::new("."), args.lock)?
+    } else {
+        None
+    };
+    missing::resolve(&mut args)?;
+    // The vectors are calculated with the rates of their own period.
+    let rated_periods = match args.command {
+        Command::TestVectors { .. } => &[test_vectors::PERIOD][..],
+        _ => args.scanned_periods(),
+    };
+    let titan_policies = match (&args.policy_rates, args.titan_rates) {
+        (Some(from), _) => rates::load(from, rated_periods)?,
+        (None, Some(rewards)) => rated_periods
+            .iter()
+            .map(|&period| {
+                let policy = policy::FarmingPolicy {
+                    rewards,
+                    ..policy::TITAN
+                };
+                (period, policy)
+            })
+            .collect(),
+        (None, None) => BTreeMap::new(),
+    };
+    for (period, policy) in titan_policies {
+        args.policies.insert(period, policy);
+    }
+    args.policies.certified_node_types = args.certified_node_types.clone();
+    let source = open_source(&args)?;
+    if args.handles_interrupts() {
+        cancel::install_handler();
+    }
+    match args.command {
+        Command::Report => {
+            let metrics = Metrics::default();
+            let (started_at, start) = (date::now(), Instant::now());
+            let (outcome, invalid) = match report(&args, &*source, &metrics) {
+                Ok(invalid) => (Ok(()), invalid),
+                Err(e) => (Err(e), 0),
+            };
+            if let Some(path) = &args.runs {
+                runs::record(
+                    path,
+                    &args.periods,
+                    started_at,
+                    start.elapsed(),
+                    &metrics,
+                    &outcome,
+                )?;
+            }
+            outcome?;
+            if cancel::is_interrupted() {
+                // Conventional exit code for a process stopped by SIGINT.
+                std::process::exit(130);
+            }
+            if invalid > 0 {
+                std::process::exit(PARTIAL_EXIT_CODE);
+            }
+            Ok(())
+        }
+        Command::Explain { node_id, period } => explain::explain(&*source, node_id, period, &args),
+        Command::Blame { node_id } => blame::blame(&args, node_id),
+        Command::ProfileSchema { ref dir } => profile::print(dir, &args),
+        Command::PublishSite { ref out_dir } => {
+            let (node_results, labels) = load_results(&args, &*source)?;
+            let ledger = Ledger::load(args.ledger_path())?;
+            let settled = ledger::settled_nodes(&ledger, &node_results, &args.tolerances)?;
+            let opt_outs = match &args.opt_outs {
+                Some(path) => OptOuts::load(path, args.opt_outs_signers.as_deref())?,
+                None => OptOuts::default(),
+            };
+            if args.plan {
+                return site::plan(out_dir, &node_results, &settled, &args, &labels, &opt_outs);
+            }
+            site::publish(out_dir, &node_results, &settled, &args, &labels, &opt_outs)?;
+            if let Some(key) = &args.sign_tokens {
+                token::write(out_dir, &node_results, &args, key)?;
+            }
+            Ok(())
+        }
+        Command::Ledger(LedgerCommand::Report) => {
+            let ledger = Ledger::load(args.ledger_path())?;
+            let (node_results, _) = load_results(&args, &*source)?;
+            ledger::report(&ledger, &node_results, &args)
+        }
+        Command::Ledger(LedgerCommand::Import(ref from)) => {
+            ledger::import(args.ledger_path(), from, &args)
+        }
+        Command::Ledger(LedgerCommand::Mark {
+            status,
+            ref node_ids,
+        }) => ledger::mark(
+            args.ledger_path(),
+            status,
+            node_ids,
+            args.note.as_deref(),
+            args.plan,
+        ),
+        Command::Approve {
+            ref reviewer,
+            ref nodes,
+        } => {
+            let (node_results, _) = load_results(&args, &*source)?;
+            ledger::approve(args.ledger_path(), &node_results, reviewer, nodes, &args)
+        }
+        Command::Pay {
+            ref payer,
+            ref out_dir,
+        } => {
+            let (node_results, _) = load_results(&args, &*source)?;
+            pay::pay(args.ledger_path(), &node_results, payer, out_dir, &args)
+        }
+        Command::Statements { ref out_dir } => {
+            let (node_results, _) = load_results(&args, &*source)?;
+            statements::write(out_dir, &node_results, &args)
+        }
+        Command::Migrate => migrate::run(&args),
+        Command::Fetch(target) => fetch::fetch(&args, target),
+        Command::VerifyMemos => memos::verify(&args, &*source),
+        Command::CompareUptime { ref path } => {
+            let monitoring = Monitoring::load(path)?;
+            let node_receipts = pipeline::load(
+                &*source,
+                &args.periods,
+                args.pipeline,
+                &Metrics::default(),
+                &print_event,
+            )?;
+            let comparisons = monitoring::compare(&monitoring, &node_receipts);
+            monitoring::write(&mut io::stdout().lock(), &comparisons, args.format)?;
+            eprintln!(
+                "{} of {} period(s) where the monitoring disagrees with the measured uptime",
+                comparisons.iter().filter(|c| c.disagrees()).count(),
+                comparisons.len()
+            );
+            Ok(())
+        }
+        Command::TestVectors { ref path } => test_vectors::write(path, &args.policies),
+        Command::Bundle { node_id, ref path } => bundle::write(&args, &*source, node_id, path),
+        Command::VerifyToken { ref token } => token::print(
+            token,
+            args.token_signers
+                .as_deref()
+                .expect("checked by the parser"),
+        ),
+        Command::Prune {
+            ref snapshots,
+            keep,
+        } => prune::prune(&args, &*source, snapshots, keep),
+        Command::Serve {
+            ref out_dir,
+            ref schedule,
+        } => serve::serve(
+            schedule,
+            out_dir,
+            args.notify.as_deref(),
+            &serve::forwarded(std::env::args().skip(1)),
+        ),
+        Command::HistoryRuns => runs::list(args.runs.as_deref().expect("checked by the parser")),
+        Command::Stats => {
+            let node_receipts = pipeline::load(
+                &*source,
+                &args.periods,
+                args.pipeline,
+                &Metrics::default(),
+                &print_event,
+            )?;
+            stats::print(&node_receipts, &args.policies);
+            let node_results = aggregate_node_results(
+                node_receipts,
+                &args.periods,
+                &args.policies,
+                args.titan_signal,
+                args.uptime_sla.unwrap_or_default(),
             );
+            stats::print_farm_health(&health::farms(&node_results, &args.tolerances));
+            Ok(())
         }
-        node_results.insert(
+    }
+}
+
+/// Print the events of a run which need attention on stderr.
+fn print_event(event: Event) {
+    match event {
+        Event::SkippedFile {
+            period,
+            path,
+            reason,
+        } => eprintln!("Period {period}: skipping {path}, {reason}"),
+        Event::Fallback {
+            period,
+            receipts,
+            primary,
+            fallback,
+        } => eprintln!(
+            "Period {period}: {receipts} receipts missing in {primary} taken from fallback \
+             {fallback}"
+        ),
+        Event::DefaultFarmingPolicy {
+            period,
+            receipts,
+            farming_policy,
+        } => eprintln!(
+            "Period {period}: {receipts} receipt(s) without farming_policy_id, assumed farming \
+             policy {farming_policy}"
+        ),
+        Event::DuplicateReceipt {
             node_id,
-            NodeResult {
-                p52: receipts_parsed.remove(&52).unwrap_or_default(),
-                p53: receipts_parsed.remove(&53).unwrap_or_default(),
-                p54: receipts_parsed.remove(&54).unwrap_or_default(),
-                p55: receipts_parsed.remove(&55).unwrap_or_default(),
-                p56: receipts_parsed.remove(&56).unwrap_or_default(),
-                p57: receipts_parsed.remove(&57).unwrap_or_default(),
-            },
+            period,
+            kept,
+            ignored,
+        } => eprintln!(
+            "Node {node_id} has multiple receipts in period {period}: {kept} and {ignored}, \
+             using {kept}"
+        ),
+        Event::UnmatchedFixup {
+            node_id,
+            period,
+            origin,
+        } => eprintln!(
+            "{origin}: fixup of node {node_id} in period {period}, which has no minting receipt, \
+             is ignored"
+        ),
+        _ => {}
+    }
+}
+
+/// Checks on the loaded receipts which have to pass before anything is reported from them.
+fn check_receipts(node_receipts: &NodeReceipts, args: &Args) -> Result<(), Error> {
+    policy::check_defaults(node_receipts, args.no_policy_default, &print_event)?;
+    if let Some(path) = &args.published_totals {
+        let published = PublishedTotals::load(path)?;
+        totals::check(
+            node_receipts,
+            &args.periods,
+            &published,
+            args.tolerances.totals,
+        )?;
+    }
+    if args.cross_check_legacy {
+        let disagreements = legacy::check(node_receipts, &args.policies, args.tolerances.legacy);
+        for disagreement in &disagreements {
+            eprintln!("{}", disagreement.describe());
+        }
+        eprintln!(
+            "{} payouts differ from the legacy calculation by more than {} TFT",
+            disagreements.len(),
+            format_tft(args.tolerances.legacy)
         );
     }
+    Ok(())
+}
 
-    println!("node_id,p52 titan,p52 uptime,p52 expected TFT,p52 received TFT,p53 titan,p53 uptime,p53 expected TFT,p53 received TFT,p54 titan,p54 uptime,p54 expected TFT,p54 received TFT,p55 titan,p55 uptime,p55 expected TFT,p55 received TFT,p56 titan,p56 uptime,p56 expected TFT,p56 received TFT,p57 titan,p57 uptime,p57 expected TFT,p57 received TFT,Total expected TFT, Total received TFT,Difference (to send)");
-    for (node_id, result) in node_results {
-        // We only really care about nodes which have been a titan at some point
-        if !result.is_titan() {
-            continue;
-        }
-
-        let total_expected: u64 = result.into_iter().map(|r| r.expected_payout).sum();
-        let total_received: u64 = result.into_iter().map(|r| r.actual_payout).sum();
-        let difference = total_expected as i64 - total_received as i64;
-        println!("{node_id},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
-            result.p52.is_titan(), format_percentage(result.p52.uptime_percentage), format_tft(result.p52.expected_payout),format_tft(result.p52.actual_payout),
-            result.p53.is_titan(), format_percentage(result.p53.uptime_percentage), format_tft(result.p53.expected_payout),format_tft(result.p53.actual_payout),
-            result.p54.is_titan(), format_percentage(result.p54.uptime_percentage), format_tft(result.p54.expected_payout),format_tft(result.p54.actual_payout),
-            result.p55.is_titan(), format_percentage(result.p55.uptime_percentage), format_tft(result.p55.expected_payout),format_tft(result.p55.actual_payout),
-            result.p56.is_titan(), format_percentage(result.p56.uptime_percentage), format_tft(result.p56.expected_payout),format_tft(result.p56.actual_payout),
-            result.p57.is_titan(), format_percentage(result.p57.uptime_percentage), format_tft(result.p57.expected_payout),format_tft(result.p57.actual_payout),
-            format_tft(total_expected), format_tft(total_received), format_diff_tft(difference)
+/// Open the receipt source selected by the arguments.
+fn open_source(args: &Args) -> Result<Box<dyn ReceiptSource>, Error> {
+    Ok(match &args.csv {
+        Some(path) => {
+            let mapping = match &args.csv_mapping {
+                Some(mapping) => ColumnMapping::load(mapping)?,
+                None => ColumnMapping::default(),
+            };
+            Box::new(CsvSource::open(path, &mapping)?)
+        }
+        None => match &args.at {
+            Some(revision) => Box::new(
+                GitSource::open(args.receipts_dir(), revision)?
+                    .with_period_dirs(args.period_dirs.clone()),
+            ),
+            None => Box::new(
+                args.fallback_dirs
+                    .iter()
+                    .fold(DirectorySource::new(args.receipts_dir()), |source, dir| {
+                        source.with_fallback(dir)
+                    })
+                    .with_period_dirs(args.period_dirs.clone())
+                    .with_symlinks_followed(args.follow_symlinks),
+            ),
+        },
+    })
+}
+
+/// Print the report of all titan nodes. Receipts which can't be used are left out of the report and
+/// listed on stderr at the end, the amount of them is returned.
+fn report(args: &Args, source: &dyn ReceiptSource, metrics: &Metrics) -> Result<usize, Error> {
+    let labels = match &args.labels {
+        Some(path) => NodeLabels::load(path)?,
+        None => NodeLabels::default(),
+    };
+    let opt_outs = match &args.opt_outs {
+        Some(path) => OptOuts::load(path, args.opt_outs_signers.as_deref())?,
+        None => OptOuts::default(),
+    };
+    let prices = match &args.prices {
+        Some(from) => Some(Prices::load(from, &args.periods)?),
+        None => None,
+    };
+
+    let (mut node_receipts, invalid) =
+        pipeline::load_partial(source, &args.periods, args.pipeline, metrics, &print_event)?;
+    check_receipts(&node_receipts, args)?;
+    if args.received_from_chain {
+        received::reconcile(&mut node_receipts, args, source)?;
+    }
+
+    let start = Instant::now();
+    let ledger = statuses(args)?;
+    let (node_results, findings) = analyze(node_receipts, args, &labels, &ledger)?;
+    metrics.record(Stage::Analyze, start, node_results.len() as u64, 0);
+
+    let start = Instant::now();
+    let output = args.output_path(date::now());
+    // The receipts loaded before an interruption are still reported, marked as partial, but the
+    // other files are not written from them, like the outputs of `load_results`.
+    let partial = cancel::is_interrupted();
+    let mut out: Box<dyn Write> = match &output {
+        Some(path) => {
+            Box::new(io::BufWriter::new(fs::File::create(path).map_err(|e| {
+                format!("could not create {}: {e}", path.display())
+            })?))
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+    if partial {
+        writeln!(out, "{PARTIAL_MARKER}")?;
+    }
+    match (args.group_by, args.format) {
+        (Some(group_by), _) => write_groups(
+            &mut out,
+            &node_results,
+            group_by,
+            &labels,
+            prices.as_ref(),
+            args,
+        )?,
+        (None, ReportFormat::Csv | ReportFormat::CsvMachine) => write_nodes(
+            &mut out,
+            &node_results,
+            args,
+            &labels,
+            &opt_outs,
+            prices.as_ref(),
+        )?,
+        (None, ReportFormat::Table) => write_node_table(
+            &mut out,
+            &node_results,
+            args,
+            &labels,
+            &opt_outs,
+            prices.as_ref(),
+        )?,
+    }
+    if args.period_dates {
+        write_period_dates(&mut out, args)?;
+    }
+    if args.summary {
+        write_summary(&mut out, &node_results, args)?;
+    }
+    out.flush()?;
+    if let Some(path) = &output {
+        eprintln!("Report written to {}", path.display());
+    }
+    metrics.record(Stage::Render, start, node_results.len() as u64, 0);
+
+    findings::print_summary(&findings);
+    if !partial {
+        write_exports(args, &node_results, &findings, &invalid)?;
+    }
+
+    if args.timings {
+        eprintln!("{metrics}");
+    }
+
+    if !invalid.is_empty() {
+        eprintln!(
+            "{} receipt(s) could not be used and are left out of the report:",
+            invalid.len()
         );
+        for receipt in &invalid {
+            eprintln!("  period {}: {receipt}", receipt.period);
+        }
     }
 
+    if partial {
+        eprintln!(
+            "Run interrupted: the report is PARTIAL and only contains the receipts loaded before \
+             the interruption, no other files are written from it"
+        );
+    }
+
+    Ok(invalid.len())
+}
+
+/// Write the files exported next to the report.
+fn write_exports(
+    args: &Args,
+    node_results: &[(u32, NodeResult)],
+    findings: &[Finding],
+    invalid: &[pipeline::InvalidReceipt],
+) -> Result<(), Error> {
+    if let Some(path) = &args.address_book {
+        address_book::write(path, &address_book::build(node_results, &args.tolerances))?;
+    }
+    if let Some(path) = &args.html {
+        html::write(
+            path,
+            node_results,
+            &args.periods,
+            &args.columns,
+            &args.tolerances,
+            args.lang,
+            args.number_styles.html,
+        )?;
+    }
+    if let Some(path) = &args.findings {
+        findings::write(path, findings)?;
+    }
+    if let Some(path) = &args.issues {
+        issues::write(path, findings, &args.periods)?;
+    }
+    if let Some(path) = &args.invalid_receipts {
+        pipeline::write_invalid(path, invalid)?;
+    }
+    if let (Some(path), Some(since)) = (&args.digest, &args.since) {
+        let ledger = Ledger::load(args.ledger_path())?;
+        let settled = ledger::settled_nodes(&ledger, node_results, &args.tolerances)?;
+        digest::write(path, &Snapshot::load(since)?, node_results, &settled, args)?;
+    }
     Ok(())
 }
 
-/// Parses an amount of TFT to it's string form.
-fn format_tft(amount: u64) -> String {
-    format!("{}.{:07}", amount / TFT_PRECISION, amount % TFT_PRECISION)
+/// Load the results of the nodes to report on, for outputs which are written as a whole. Partial
+/// results of an interrupted run are not returned.
+fn load_results(
+    args: &Args,
+    source: &dyn ReceiptSource,
+) -> Result<(Vec<(u32, NodeResult)>, NodeLabels), Error> {
+    let labels = match &args.labels {
+        Some(path) => NodeLabels::load(path)?,
+        None => NodeLabels::default(),
+    };
+    let mut node_receipts = pipeline::load(
+        source,
+        &args.periods,
+        args.pipeline,
+        &Metrics::default(),
+        &print_event,
+    )?;
+    check_receipts(&node_receipts, args)?;
+    if cancel::is_interrupted() {
+        return Err("run interrupted, nothing is written from partial results".into());
+    }
+    if args.received_from_chain {
+        received::reconcile(&mut node_receipts, args, source)?;
+    }
+    let (node_results, _) = analyze(node_receipts, args, &labels, &statuses(args)?)?;
+    Ok((node_results, labels))
 }
 
-/// Parses an amount of TFT to it's string form, where the amount can potentially be negative.
-fn format_diff_tft(amount: i64) -> String {
-    format!(
-        "{}.{:07}",
-        amount / TFT_PRECISION as i64,
-        amount.abs() % TFT_PRECISION as i64
-    )
+/// Load the ledger if it is needed to filter nodes by status.
+fn statuses(args: &Args) -> Result<Ledger, Error> {
+    if args.status_filter.is_empty() {
+        Ok(Ledger::default())
+    } else {
+        Ledger::load(args.ledger_path())
+    }
 }
 
-/// Farming policy 2, taken from chain.
-const TITAN_RESOURCE_REWARDS: ResourceRewards = ResourceRewards {
-    cu: 3000,
-    su: 1250,
-    nu: 38,
-    ipv4: 6,
-};
+/// The selected nodes with their results, and the findings over all nodes.
+type Analysis = (Vec<(u32, NodeResult)>, Vec<Finding>);
 
-/// Calculate the expected reward as if the node had farming policy 2
-fn calculate_expected_titan_reward(receipt: &MintingReceipt) -> u64 {
-    let full_musd_reward_upscaled = ((receipt.cloud_units.cu * TFT_PRECISION as f64) as u64
-        * TITAN_RESOURCE_REWARDS.cu)
-        + ((receipt.cloud_units.su * TFT_PRECISION as f64) as u64 * TITAN_RESOURCE_REWARDS.su)
-        + ((receipt.cloud_units.nu * TFT_PRECISION as f64) as u64 * TITAN_RESOURCE_REWARDS.nu)
-        + ((receipt.resource_utilization.ip * TFT_PRECISION as f64) as u64
-            * TITAN_RESOURCE_REWARDS.ipv4);
+/// Calculate the results of every node, and select the nodes to report on. Findings are collected
+/// over all nodes, not only the selected ones.
+fn analyze(
+    node_receipts: NodeReceipts,
+    args: &Args,
+    labels: &NodeLabels,
+    ledger: &Ledger,
+) -> Result<Analysis, Error> {
+    let mut node_results = aggregate_node_results(
+        node_receipts,
+        &args.periods,
+        &args.policies,
+        args.titan_signal,
+        args.uptime_sla.unwrap_or_default(),
+    );
+    if !args.carbon_farms.is_empty() {
+        for (_, result) in &mut node_results {
+            result.include_carbon_offset(&args.carbon_farms);
+        }
+    }
 
-    // Don't divide by TFT_PRECISION as the conenction price is expressed as mUSD/TFT which is
-    // actually mUSD / TFT_PRECISION
-    let full_tft_reward = full_musd_reward_upscaled / receipt.tft_connection_price;
+    let findings = findings::all(
+        &node_results,
+        &args.tolerances,
+        &args.spec_tolerances,
+        &events::ignore,
+    );
 
-    // scale, use default period duration so we account for nodes which did not come online until
-    // the period already started
-    full_tft_reward * receipt.measured_uptime / STANDARD_PERIOD_DURATION
+    let mut query_error = None;
+    node_results.retain(|(node_id, result)| {
+        // We only really care about nodes which have been a titan at some point
+        result.is_titan()
+            && (args.label_filter.is_empty() || labels.has_any(*node_id, &args.label_filter))
+            && (args.class_filter.is_empty()
+                || args
+                    .class_filter
+                    .contains(&result.hardware_class(&args.spec_tolerances)))
+            && (args.status_filter.is_empty()
+                || args.status_filter.contains(&ledger.status(*node_id)))
+            && (args.node_filter.is_empty() || args.node_filter.contains(node_id))
+            && (args.farm_filter.is_empty()
+                || result
+                    .into_iter()
+                    .any(|r| r.farm_id != 0 && args.farm_filter.contains(&r.farm_id)))
+            && (args.twin_filter.is_empty()
+                || result
+                    .into_iter()
+                    .any(|r| r.farm_id != 0 && args.twin_filter.contains(&r.twin_id)))
+            && args.query.as_ref().is_none_or(|query| {
+                query
+                    .matches(*node_id, result, &args.tolerances)
+                    .unwrap_or_else(|e| {
+                        query_error.get_or_insert(e);
+                        false
+                    })
+            })
+            && exceeds_thresholds(result, args)
+    });
+    if let Some(e) = query_error {
+        return Err(format!("invalid --where: {e}").into());
+    }
+
+    Ok((node_results, findings))
+}
+
+/// Indicates the difference of a node is beyond `--min-diff` or `--max-diff`, always true if
+/// neither is set.
+fn exceeds_thresholds(result: &NodeResult, args: &Args) -> bool {
+    if args.min_diff.is_none() && args.max_diff.is_none() {
+        return true;
+    }
+    let difference = result.difference(&args.tolerances);
+    args.min_diff.is_some_and(|min| difference > min as i64)
+        || args.max_diff.is_some_and(|max| difference < -(max as i64))
 }
 
-/// Format a percentage with 3 digits of precision
-fn format_percentage(p: u32) -> String {
-    format!("{}.{}%", p / PERCENTAGE_PRECISION, p % PERCENTAGE_PRECISION)
+/// Write one row per node.
+fn write_nodes(
+    out: &mut dyn Write,
+    node_results: &[(u32, NodeResult)],
+    args: &Args,
+    labels: &NodeLabels,
+    opt_outs: &OptOuts,
+    prices: Option<&Prices>,
+) -> io::Result<()> {
+    let t = report_texts(args);
+    let mut header = vec!["node_id".to_string()];
+    for period in &args.periods {
+        header.extend(
+            [t.titan, t.uptime, t.expected_tft, t.received_tft]
+                .map(|name| format!("p{period} {name}")),
+        );
+        if args.include_carbon {
+            header.push(format!("p{period} {}", t.carbon_tft));
+        }
+    }
+    // The space before the total received column is part of the original header.
+    header.extend([
+        t.total_expected_tft.to_string(),
+        format!(" {}", t.total_received_tft),
+        t.difference_to_send.to_string(),
+    ]);
+    if prices.is_some() {
+        header.push(t.difference_usd.to_string());
+    }
+    if args.include_carbon {
+        header.push(t.total_carbon_tft.to_string());
+    }
+    if args.labels.is_some() {
+        header.push(t.labels.to_string());
+    }
+    if args.opt_outs.is_some() {
+        header.push(t.opted_out.to_string());
+    }
+    if args.uptime_sla.is_some() {
+        header.push(t.sla_violations.to_string());
+    }
+    let mut header = header
+        .iter()
+        .map(|name| column_name(args, name))
+        .collect::<Vec<_>>();
+    header.extend(
+        args.columns
+            .iter()
+            .map(|column| address_book::csv_field(&column.name)),
+    );
+    writeln!(out, "{}", header.join(","))?;
+    for (node_id, result) in node_results {
+        let total_expected = result.total_expected();
+        let total_received = result.total_received();
+        let difference = result.difference(&args.tolerances);
+        let usd_column = match prices {
+            Some(prices) => format!(",{}", format_usd(prices.difference_usd(result, difference))),
+            None => String::new(),
+        };
+        let carbon_column = if args.include_carbon {
+            format!(",{}", format_tft(result.total_carbon_offset()))
+        } else {
+            String::new()
+        };
+        let label_column = if args.labels.is_some() {
+            format!(",{}", labels.get(*node_id).collect::<Vec<_>>().join(";"))
+        } else {
+            String::new()
+        };
+        let opt_out_column = if args.opt_outs.is_some() {
+            let opt_out = result.latest().and_then(|r| opt_outs.get(r.farm_id));
+            format!(
+                ",{}",
+                address_book::csv_field(&opt_out.map(OptOut::describe).unwrap_or_default())
+            )
+        } else {
+            String::new()
+        };
+        let sla_column = if args.uptime_sla.is_some() {
+            format!(",{}", below_minimal_uptime(result))
+        } else {
+            String::new()
+        };
+        let derived_columns = args
+            .columns
+            .iter()
+            .map(|column| format!(",{}", column.value(*node_id, result, &args.tolerances)))
+            .collect::<String>();
+        let mut row = node_id.to_string();
+        for r in result {
+            row.push_str(&format!(
+                ",{},{},{},{}",
+                r.is_titan(),
+                format_uptime(r.uptime_percentage, args.format),
+                format_tft(r.expected_payout),
+                format_tft(r.actual_payout)
+            ));
+            if args.include_carbon {
+                row.push_str(&format!(",{}", format_tft(r.carbon_offset)));
+            }
+        }
+        writeln!(
+            out,
+            "{row},{},{},{}{usd_column}{carbon_column}{label_column}{opt_out_column}{sla_column}\
+             {derived_columns}",
+            format_tft(total_expected),
+            format_tft(total_received),
+            format_diff_tft(difference)
+        )?;
+    }
+    Ok(())
+}
+
+/// Texts of the report, in English in the machine CSV, which doesn't depend on `--lang`.
+fn report_texts(args: &Args) -> &'static Texts {
+    match args.format {
+        ReportFormat::CsvMachine => Lang::En.texts(),
+        _ => args.lang.texts(),
+    }
+}
+
+/// Name of a column of the report, in the machine CSV in snake case, e.g. `total_expected_tft`.
+fn column_name(args: &Args, name: &str) -> String {
+    if args.format != ReportFormat::CsvMachine {
+        return name.to_string();
+    }
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Uptime percentage, in the machine CSV with all 3 decimals and without the `%`.
+fn format_uptime(uptime: u32, format: ReportFormat) -> String {
+    match format {
+        ReportFormat::CsvMachine => format!(
+            "{}.{:03}",
+            uptime / PERCENTAGE_PRECISION,
+            uptime % PERCENTAGE_PRECISION
+        ),
+        _ => format_percentage(uptime),
+    }
+}
+
+/// The periods in which a node was up for less than the minimal uptime of the titan policy,
+/// separated by `;`.
+fn below_minimal_uptime(result: &NodeResult) -> String {
+    result
+        .periods()
+        .filter(|(_, r)| r.below_minimal_uptime)
+        .map(|(period, _)| period.to_string())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Write one row per node as an aligned table, with only the difference of every period and the
+/// totals. Periods without a receipt are written as `-`.
+fn write_node_table(
+    out: &mut dyn Write,
+    node_results: &[(u32, NodeResult)],
+    args: &Args,
+    labels: &NodeLabels,
+    opt_outs: &OptOuts,
+    prices: Option<&Prices>,
+) -> io::Result<()> {
+    let t = args.lang.texts();
+    let style = args.number_styles.table;
+    let mut header = vec!["node_id".to_string(), t.farm.to_string()];
+    header.extend(args.periods.iter().map(|period| format!("p{period}")));
+    header.extend(
+        [
+            t.total_expected_tft,
+            t.total_received_tft,
+            t.difference_to_send,
+        ]
+        .map(String::from),
+    );
+    if prices.is_some() {
+        header.push(t.difference_usd.to_string());
+    }
+    if args.include_carbon {
+        header.push(t.total_carbon_tft.to_string());
+    }
+    if args.labels.is_some() {
+        header.push(t.labels.to_string());
+    }
+    if args.opt_outs.is_some() {
+        header.push(t.opted_out.to_string());
+    }
+    if args.uptime_sla.is_some() {
+        header.push(t.sla_violations.to_string());
+    }
+    header.extend(args.columns.iter().map(|column| column.name.clone()));
+
+    let mut rows = Vec::new();
+    for (node_id, result) in node_results {
+        let difference = result.difference(&args.tolerances);
+        let latest = result.latest();
+        let mut row = vec![
+            node_id.to_string(),
+            latest.map(|r| r.farm_name.clone()).unwrap_or_default(),
+        ];
+        row.extend(result.into_iter().map(|r| {
+            if r.farm_id == 0 {
+                "-".to_string()
+            } else {
+                let difference = r.expected_payout as i64 - r.actual_payout as i64;
+                format_tft_styled(
+                    tolerance::denoise(difference, args.tolerances.difference),
+                    style,
+                )
+            }
+        }));
+        row.push(format_tft_styled(result.total_expected(), style));
+        row.push(format_tft_styled(result.total_received(), style));
+        row.push(format_tft_styled(difference, style));
+        if let Some(prices) = prices {
+            row.push(Amount::new(prices.difference_usd(result, difference), &USD).format(style));
+        }
+        if args.include_carbon {
+            row.push(format_tft_styled(result.total_carbon_offset(), style));
+        }
+        if args.labels.is_some() {
+            row.push(labels.get(*node_id).collect::<Vec<_>>().join(";"));
+        }
+        if args.opt_outs.is_some() {
+            let opt_out = latest.and_then(|r| opt_outs.get(r.farm_id));
+            row.push(opt_out.map(OptOut::describe).unwrap_or_default());
+        }
+        if args.uptime_sla.is_some() {
+            row.push(below_minimal_uptime(result));
+        }
+        row.extend(
+            args.columns
+                .iter()
+                .map(|column| column.value(*node_id, result, &args.tolerances)),
+        );
+        rows.push(row);
+    }
+    table::write(out, &header, &rows)
+}
+
+/// Write the start and end dates of the scanned periods after the report, separated by an empty
+/// line, as a legend of the period offsets in the column names.
+fn write_period_dates(out: &mut dyn Write, args: &Args) -> io::Result<()> {
+    let t = report_texts(args);
+    let rows = args
+        .periods
+        .iter()
+        .map(|period| {
+            (
+                format!("p{period}"),
+                Period::at_offset(*period as i64).date_range(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    writeln!(out)?;
+    match args.format {
+        // The machine CSV has no legend, see `Args::parse_from`.
+        ReportFormat::Csv | ReportFormat::CsvMachine => {
+            writeln!(out, "{},", t.periods)?;
+            for (name, dates) in rows {
+                writeln!(out, "{name},{dates}")?;
+            }
+            Ok(())
+        }
+        ReportFormat::Table => {
+            let rows = rows
+                .into_iter()
+                .map(|(name, dates)| vec![name, dates])
+                .collect::<Vec<_>>();
+            table::write(out, &[t.periods.to_string(), String::new()], &rows)
+        }
+    }
+}
+
+/// Write the totals over all reported nodes after the report, separated by an empty line: the
+/// correction to send is the sum of what is owed to the nodes which received too little.
+fn write_summary(
+    out: &mut dyn Write,
+    node_results: &[(u32, NodeResult)],
+    args: &Args,
+) -> io::Result<()> {
+    let t = report_texts(args);
+    let style = match args.format {
+        ReportFormat::Csv | ReportFormat::CsvMachine => NumberStyle::Full,
+        ReportFormat::Table => args.number_styles.table,
+    };
+    let (mut expected, mut received, mut correction, mut nodes_owed) = (0, 0, 0, 0);
+    let mut largest = None::<(u32, i64)>;
+    for (node_id, result) in node_results {
+        expected += result.total_expected();
+        received += result.total_received();
+        let difference = result.difference(&args.tolerances);
+        if difference > 0 {
+            correction += difference;
+            nodes_owed += 1;
+            if largest.is_none_or(|(_, largest)| difference > largest) {
+                largest = Some((*node_id, difference));
+            }
+        }
+    }
+    let rows = [
+        (t.total_expected_tft, format_tft_styled(expected, style)),
+        (t.total_received_tft, format_tft_styled(received, style)),
+        (t.correction_to_send, format_tft_styled(correction, style)),
+        (t.nodes_owed, nodes_owed.to_string()),
+        (
+            t.largest_difference,
+            largest.map_or(String::new(), |(_, difference)| {
+                format_tft_styled(difference, style)
+            }),
+        ),
+        (
+            t.largest_difference_node,
+            largest.map_or(String::new(), |(node_id, _)| node_id.to_string()),
+        ),
+    ];
+
+    writeln!(out)?;
+    match args.format {
+        // The machine CSV has no summary, see `Args::parse_from`.
+        ReportFormat::Csv | ReportFormat::CsvMachine => {
+            writeln!(out, "{},", t.summary)?;
+            for (name, value) in rows {
+                writeln!(out, "{name},{value}")?;
+            }
+            Ok(())
+        }
+        ReportFormat::Table => {
+            let rows = rows
+                .into_iter()
+                .map(|(name, value)| vec![name.to_string(), value])
+                .collect::<Vec<_>>();
+            table::write(out, &[t.summary.to_string(), String::new()], &rows)
+        }
+    }
+}
+
+/// Write one row per group, summing the totals of all nodes in the group.
+///
+/// When grouping by label, a node with multiple labels is counted in every one of them, nodes
+/// without labels are grouped as "unlabeled". When grouping by farm, every period of a node counts
+/// for the farm of its receipt in the period. When grouping by hardware class or twin, nodes are
+/// grouped by the class or twin of their latest receipt.
+fn write_groups(
+    out: &mut dyn Write,
+    node_results: &[(u32, NodeResult)],
+    group_by: GroupBy,
+    labels: &NodeLabels,
+    prices: Option<&Prices>,
+    args: &Args,
+) -> io::Result<()> {
+    // (id, group) -> (node count, total expected, total received, difference, difference in
+    // USD). Groups are sorted by the farm or twin id first, which is 0 for the other groupings.
+    let mut groups = BTreeMap::<(u32, String), (usize, u64, u64, i64, i64)>::new();
+    let totals = |result: &NodeResult| {
+        let difference = result.difference(&args.tolerances);
+        (
+            result.total_expected(),
+            result.total_received(),
+            difference,
+            prices.map_or(0, |prices| prices.difference_usd(result, difference)),
+        )
+    };
+    for (node_id, result) in node_results {
+        let node_groups: Vec<((u32, String), _)> = match group_by {
+            GroupBy::Label => {
+                let mut node_labels = labels.get(*node_id).peekable();
+                if node_labels.peek().is_none() {
+                    vec![((0, "unlabeled".to_string()), totals(result))]
+                } else {
+                    node_labels
+                        .map(|label| ((0, label.to_string()), totals(result)))
+                        .collect()
+                }
+            }
+            GroupBy::Class => vec![(
+                (
+                    0,
+                    result
+                        .hardware_class(&args.spec_tolerances)
+                        .name()
+                        .to_string(),
+                ),
+                totals(result),
+            )],
+            // A node which moved between farms counts in every farm, with the periods it was in it.
+            GroupBy::Farm => result
+                .by_farm()
+                .into_iter()
+                .map(|(farm_id, part)| {
+                    let name = part.latest().map(|r| r.farm_name.clone());
+                    ((farm_id, name.unwrap_or_default()), totals(&part))
+                })
+                .collect(),
+            GroupBy::Twin => vec![(
+                (result.latest().map_or(0, |r| r.twin_id), String::new()),
+                totals(result),
+            )],
+        };
+        for (key, (expected, received, difference, difference_usd)) in node_groups {
+            let group = groups.entry(key).or_default();
+            group.0 += 1;
+            group.1 += expected;
+            group.2 += received;
+            group.3 += difference;
+            group.4 += difference_usd;
+        }
+    }
+
+    let t = report_texts(args);
+    // The CSV always has amounts in full.
+    let style = match args.format {
+        ReportFormat::Csv | ReportFormat::CsvMachine => NumberStyle::Full,
+        ReportFormat::Table => args.number_styles.table,
+    };
+    let mut header = match group_by {
+        GroupBy::Label => vec![t.label],
+        GroupBy::Class => vec![t.hardware_class],
+        GroupBy::Farm => vec![t.farm, t.name],
+        GroupBy::Twin => vec![t.twin],
+    };
+    header.extend([
+        t.nodes,
+        t.total_expected_tft,
+        t.total_received_tft,
+        t.difference_to_send,
+    ]);
+    if prices.is_some() {
+        header.push(t.difference_usd);
+    }
+    let header = header
+        .into_iter()
+        .map(|name| column_name(args, name))
+        .collect::<Vec<_>>();
+
+    let mut rows = Vec::new();
+    for ((id, label), (nodes, total_expected, total_received, difference, difference_usd)) in groups
+    {
+        let mut row = match group_by {
+            GroupBy::Farm => vec![id.to_string(), label],
+            GroupBy::Twin => vec![id.to_string()],
+            _ => vec![label],
+        };
+        row.extend([
+            nodes.to_string(),
+            format_tft_styled(total_expected, style),
+            format_tft_styled(total_received, style),
+            format_tft_styled(difference, style),
+        ]);
+        if prices.is_some() {
+            row.push(Amount::new(difference_usd, &USD).format(style));
+        }
+        rows.push(row);
+    }
+
+    match args.format {
+        ReportFormat::Csv | ReportFormat::CsvMachine => {
+            for row in std::iter::once(&header).chain(&rows) {
+                let fields = row
+                    .iter()
+                    .map(|field| address_book::csv_field(field))
+                    .collect::<Vec<_>>();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            Ok(())
+        }
+        ReportFormat::Table => table::write(out, &header, &rows),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn machine_uptime_has_every_decimal() {
+        let machine = |uptime| format_uptime(uptime, ReportFormat::CsvMachine);
+        assert_eq!(machine(95_050), "95.050");
+        assert_eq!(machine(100_000), "100.000");
+        assert_eq!(machine(99_999), "99.999");
+        assert_eq!(machine(7), "0.007");
+    }
+
+    #[test]
+    fn machine_csv_has_one_table() {
+        let parse = |args: &[&str]| Args::parse_from(args.iter().map(|a| a.to_string()));
+        assert!(parse(&["--format", "csv-machine"]).is_ok());
+        assert!(parse(&["--format", "csv-machine", "--summary"]).is_err());
+        assert!(parse(&["--format", "csv-machine", "--period-dates"]).is_err());
+        assert!(parse(&["--summary", "--period-dates"]).is_ok());
+    }
 }
@@ -0,0 +1,294 @@
+//! Verification of the memos of the payments of minting receipts.
+//!
+//! Minting pays the reward of a receipt with a payment whose memo is the BLAKE2b hash of the
+//! receipt, so every payment can be traced back to the receipt it pays for. The hash of every
+//! receipt is computed from its JSON as published, and looked up in the TFT payments received by
+//! its payout address.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use trace_titans::{
+    cancel, format_tft, parse_tft,
+    receipt::{FixupReceipt, MintingReceipt},
+    source::ReceiptSource,
+    Error,
+};
+
+use crate::{blake2b, cli::Args, horizon};
+
+/// A receipt which should have been paid.
+pub struct Paid {
+    pub period: u32,
+    pub node_id: u32,
+    /// Where the receipt was loaded from.
+    pub origin: String,
+    pub address: String,
+    /// Reward in TFT units.
+    pub amount: u64,
+    /// Expected memo, as Horizon shows hash memos.
+    pub memo: String,
+}
+
+/// The payment found for a receipt.
+pub enum Located {
+    /// A payment with the memo of the receipt, of the amount in TFT units.
+    Memo { amount: u64 },
+    /// No payment of the receipt, by memo or by amount.
+    NoPayment,
+    /// A payment of the amount of the receipt was found, with another memo.
+    OtherMemo {
+        transaction: String,
+        memo: Option<String>,
+    },
+}
+
+/// Hash of a receipt: the BLAKE2b hash of its JSON as published. The receipt is not serialized
+/// again, which would drop the fields this tool doesn't know, add the defaults of the ones old
+/// receipts lack and could change the order of the fields and how numbers are written.
+pub fn hash(data: &[u8]) -> [u8; 32] {
+    blake2b::digest(data)
+}
+
+/// Memo of the payment of a receipt: the hash of its JSON as published, base64 encoded.
+pub fn memo(data: &[u8]) -> String {
+    base64(&hash(data))
+}
+
+/// Read the minting receipts of the periods which should have been paid. Also returns the amount
+/// of receipts of every period which aren't, the fixup receipts and receipts without reward.
+pub fn paid_receipts(
+    source: &dyn ReceiptSource,
+    periods: &[u32],
+) -> Result<(Vec<Paid>, BTreeMap<u32, usize>), Error> {
+    let mut receipts = Vec::new();
+    let mut unpaid = BTreeMap::<u32, usize>::new();
+    for &period in periods {
+        for receipt in source.discover(period, &crate::print_event)? {
+            let raw = source.read(&receipt)?;
+            let parsed = match raw.parse::<MintingReceipt>() {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    if raw.parse::<FixupReceipt>().is_err() {
+                        return Err(e);
+                    }
+                    *unpaid.entry(period).or_default() += 1;
+                    continue;
+                }
+            };
+            if parsed.reward.tft == 0 {
+                *unpaid.entry(period).or_default() += 1;
+                continue;
+            }
+            receipts.push(Paid {
+                period,
+                node_id: parsed.node_id,
+                origin: raw.origin.clone(),
+                address: parsed.stellar_payout_address.to_string(),
+                amount: parsed.reward.tft,
+                memo: memo(raw.data()),
+            });
+        }
+    }
+    Ok((receipts, unpaid))
+}
+
+/// Locate the payment of every receipt in the TFT payments received by its payout address on the
+/// Horizon instance at the URL. Returns the payment of every receipt, in the same order.
+pub fn locate(receipts: &[Paid], url: &str) -> Result<Vec<Located>, Error> {
+    let addresses = receipts
+        .iter()
+        .map(|r| r.address.as_str())
+        .collect::<BTreeSet<_>>();
+    let mut payments = BTreeMap::new();
+    for address in addresses {
+        if cancel::is_interrupted() {
+            return Err("interrupted while reading the payments from Horizon".into());
+        }
+        let received = horizon::tft_payments(url, address)?
+            .into_iter()
+            .filter(|p| p.to == address)
+            .collect::<Vec<_>>();
+        payments.insert(address, received);
+    }
+
+    // Every payment pays for a single receipt. Payments are matched by memo first, so a payment
+    // with another memo is never taken for a receipt which has its own payment.
+    let mut used = BTreeSet::new();
+    let mut located = Vec::with_capacity(receipts.len());
+    for receipt in receipts {
+        let found = payments[receipt.address.as_str()]
+            .iter()
+            .find(|p| p.memo.as_deref() == Some(&receipt.memo) && !used.contains(&p.operation_id));
+        located.push(match found {
+            Some(payment) => {
+                used.insert(payment.operation_id.clone());
+                Some(Located::Memo {
+                    amount: parse_tft(&payment.amount)
+                        .ok_or_else(|| format!("invalid amount {} of payment", payment.amount))?,
+                })
+            }
+            None => None,
+        });
+    }
+    Ok(receipts
+        .iter()
+        .zip(located)
+        .map(|(receipt, located)| {
+            located.unwrap_or_else(|| {
+                let found = payments[receipt.address.as_str()].iter().find(|p| {
+                    parse_tft(&p.amount) == Some(receipt.amount) && !used.contains(&p.operation_id)
+                });
+                match found {
+                    Some(payment) => {
+                        used.insert(payment.operation_id.clone());
+                        Located::OtherMemo {
+                            transaction: payment.transaction_hash.clone(),
+                            memo: payment.memo.clone(),
+                        }
+                    }
+                    None => Located::NoPayment,
+                }
+            })
+        })
+        .collect())
+}
+
+/// Check the memos of the payments of all minting receipts of the periods against the hashes of
+/// the receipts. Fixup receipts and receipts without reward are not checked. Fails if a receipt
+/// could not be matched to its payment.
+pub fn verify(args: &Args, source: &dyn ReceiptSource) -> Result<(), Error> {
+    let (receipts, unchecked) = paid_receipts(source, &args.periods)?;
+    let url = args.horizon_url.as_deref().unwrap_or(horizon::DEFAULT_URL);
+    let problems = receipts
+        .iter()
+        .zip(locate(&receipts, url)?)
+        .filter(|(_, located)| !matches!(located, Located::Memo { .. }))
+        .collect::<Vec<_>>();
+
+    for &period in &args.periods {
+        let checked = receipts.iter().filter(|r| r.period == period).count();
+        let flagged = problems
+            .iter()
+            .filter(|(r, _)| r.period == period)
+            .collect::<Vec<_>>();
+        println!(
+            "Period {period}: {} of {checked} receipt(s) paid with a matching memo, {} not checked",
+            checked - flagged.len(),
+            unchecked.get(&period).copied().unwrap_or(0)
+        );
+        for (receipt, located) in flagged {
+            let paid = format!(
+                "{} TFT to {} for node {} ({})",
+                format_tft(receipt.amount),
+                receipt.address,
+                receipt.node_id,
+                receipt.origin
+            );
+            match located {
+                Located::Memo { .. } => {}
+                Located::NoPayment => println!("  no payment of {paid}"),
+                Located::OtherMemo { transaction, memo } => println!(
+                    "  payment {transaction} of {paid} has memo {}, expected {}",
+                    memo.as_deref().unwrap_or("none"),
+                    receipt.memo
+                ),
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(format!(
+            "{} receipt(s) could not be matched to their payment",
+            problems.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Standard base64 encoding, with padding.
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use trace_titans::source::DirectorySource;
+
+    use super::*;
+
+    /// A receipt of period 57, with the given fields after the common ones.
+    fn receipt(node_id: u32, rest: &str) -> String {
+        format!(
+            concat!(
+                r#"{{"period":{{"start":1672461160,"end":1675091920}},"node_id":{},"#,
+                r#""twin_id":1,"farm_id":1,"farm_name":"f","stellar_payout_address":"G","#,
+                r#""measured_uptime":100,"tft_connection_price":80,"#,
+                r#""cloud_units":{{"cu":1.50,"su":1,"nu":0}},"#,
+                r#""resource_units":{{"cru":8,"mru":32,"hru":0,"sru":1000}},"#,
+                r#""resource_utilization":{{"cru":0,"mru":0,"hru":0,"sru":0,"ip":0}},"#,
+                r#""reward":{{"musd":1,"tft":10}},"carbon_offset":{{"musd":0,"tft":0}},"#,
+                r#""node_type":"DIY"{}}}"#
+            ),
+            node_id, rest
+        )
+    }
+
+    #[test]
+    fn base64_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn memo_is_the_hash_of_the_published_receipt() {
+        let root = std::env::temp_dir().join(format!("trace_titans-memos-{}", std::process::id()));
+        let dir = root.join("57");
+        fs::create_dir_all(&dir).unwrap();
+        // One with a field this tool doesn't know, and an old one without farming policy.
+        let receipts = [
+            receipt(
+                1,
+                concat!(
+                    r#","farming_policy_id":1,"#,
+                    r#""resource_rewards":{"cu":1,"su":2,"nu":3,"ipv4":4},"extension":[1]"#
+                ),
+            ),
+            receipt(2, ""),
+        ];
+        for (node, receipt) in (1..).zip(&receipts) {
+            fs::write(dir.join(format!("{node}.json")), receipt).unwrap();
+        }
+        let paid = paid_receipts(&DirectorySource::new(&root), &[57]);
+        let _ = fs::remove_dir_all(&root);
+        let (mut paid, _) = paid.unwrap();
+        paid.sort_by_key(|p| p.node_id);
+
+        assert_eq!(paid.len(), 2);
+        for (paid, receipt) in paid.iter().zip(&receipts) {
+            assert_eq!(paid.memo, base64(&blake2b::digest(receipt.as_bytes())));
+            let parsed = serde_json::from_str::<MintingReceipt>(receipt).unwrap();
+            let serialized = serde_json::to_vec(&parsed).unwrap();
+            assert_ne!(paid.memo, memo(&serialized), "node {}", paid.node_id);
+        }
+    }
+}
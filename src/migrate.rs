@@ -0,0 +1,126 @@
+//! Upgrades of the files the tool keeps between runs.
+//!
+//! Every file the tool keeps state in carries a `version` stamp of its format. Files with an
+//! older format are refused until they are upgraded with `migrate`, which applies the migrations
+//! between the versions one by one, so state never needs to be thrown away after an upgrade of
+//! the tool.
+
+use std::{fs, io, path::Path};
+
+use serde_json::Value;
+
+use trace_titans::Error;
+
+use crate::{cli::Args, ledger};
+
+/// An upgrade of a format from one version to the next.
+struct Migration {
+    /// Version the migration upgrades from, it upgrades to the version after it.
+    from: u64,
+    /// What the migration changes, printed when it is applied.
+    description: &'static str,
+    apply: fn(&mut Value) -> Result<(), Error>,
+}
+
+/// Migrations of the ledger, in order.
+const LEDGER_MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    description: "add the format version",
+    apply: |_| Ok(()),
+}];
+
+/// Version stamp of a file, files from before the stamps were introduced are version 0.
+pub fn version(value: &Value) -> Result<u64, Error> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| format!("invalid format version {version}").into()),
+    }
+}
+
+/// Check that a file has the format version this version of the tool uses.
+pub fn check_version(path: &Path, value: &Value, current: u64) -> Result<(), Error> {
+    let version = version(value)?;
+    if version < current {
+        return Err(format!(
+            "{} has format version {version}, run `trace_titans migrate` to upgrade it to \
+             version {current}",
+            path.display()
+        )
+        .into());
+    }
+    if version > current {
+        return Err(format!(
+            "{} has format version {version}, which was written by a newer version of \
+             trace_titans, this version supports up to version {current}",
+            path.display()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Upgrade all state files of the run to their current format.
+pub fn run(args: &Args) -> Result<(), Error> {
+    migrate(
+        args.ledger_path(),
+        LEDGER_MIGRATIONS,
+        ledger::FORMAT_VERSION,
+    )
+}
+
+/// Upgrade a single file. The original file is kept next to it with its version as extension,
+/// e.g. `ledger.json.v0`, until it is removed by hand.
+fn migrate(path: &Path, migrations: &[Migration], current: u64) -> Result<(), Error> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("{}: not found, nothing to migrate", path.display());
+            return Ok(());
+        }
+        Err(e) => return Err(format!("could not read {}: {e}", path.display()).into()),
+    };
+    let mut value: Value = serde_json::from_slice(&data)
+        .map_err(|e| format!("invalid JSON in {}: {e}", path.display()))?;
+    let original = version(&value)?;
+    if original > current {
+        // Reports the newer version.
+        return check_version(path, &value, current);
+    }
+    if original == current {
+        eprintln!("{}: already at version {current}", path.display());
+        return Ok(());
+    }
+
+    for version in original..current {
+        let migration = migrations
+            .iter()
+            .find(|m| m.from == version)
+            .ok_or_else(|| format!("no migration from version {version}"))?;
+        (migration.apply)(&mut value)?;
+        value
+            .as_object_mut()
+            .ok_or_else(|| format!("{} does not contain a JSON object", path.display()))?
+            .insert("version".into(), (version + 1).into());
+        eprintln!(
+            "{}: version {version} to {}: {}",
+            path.display(),
+            version + 1,
+            migration.description
+        );
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{original}"));
+    fs::copy(path, &backup)?;
+    let tmp = path.with_extension("migrate.tmp");
+    fs::write(&tmp, format!("{}\n", serde_json::to_string_pretty(&value)?))?;
+    fs::rename(&tmp, path)?;
+    eprintln!(
+        "{}: migrated to version {current}, the original is kept as {}",
+        path.display(),
+        Path::new(&backup).display()
+    );
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Client of the minting API, which publishes the receipts of every period.
+//!
+//! Receipts are stored in the layout of the receipts directory, one `<node_id>.json` file per
+//! minting receipt in the directory of the period, so fetched receipts are read like any other.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use trace_titans::{period::Period, Error};
+
+use crate::http;
+
+/// Public instance of the minting API.
+pub const DEFAULT_URL: &str = "https://alpha.minting.tfchain.grid.tf";
+
+/// Get all receipts of a period. Receipts which are wrapped in their kind, as in
+/// `{"Minting": {...}}` or `{"Fixup": {...}}`, are unwrapped.
+pub fn period_receipts(url: &str, period: u32) -> Result<Vec<Value>, Error> {
+    receipts(&format!(
+        "{}/api/v1/period/{period}",
+        url.trim_end_matches('/')
+    ))
+}
+
+/// Get all receipts of a node, over all periods.
+pub fn node_receipts(url: &str, node_id: u32) -> Result<Vec<Value>, Error> {
+    receipts(&format!(
+        "{}/api/v1/node/{node_id}",
+        url.trim_end_matches('/')
+    ))
+}
+
+/// Get all receipts of the nodes of a farm, over all periods.
+pub fn farm_receipts(url: &str, farm_id: u32) -> Result<Vec<Value>, Error> {
+    receipts(&format!(
+        "{}/api/v1/farm/{farm_id}",
+        url.trim_end_matches('/')
+    ))
+}
+
+/// Offset of the period of a receipt.
+pub fn receipt_period(receipt: &Value) -> Result<u32, Error> {
+    let period = receipt
+        .get("period")
+        .cloned()
+        .ok_or("receipt without period")?;
+    let period = serde_json::from_value::<Period>(period)
+        .map_err(|e| format!("invalid period in receipt: {e}"))?;
+    u32::try_from(period.offset()).map_err(|_| "receipt of a period before the first one".into())
+}
+
+/// Get the list of receipts at a URL of the API.
+fn receipts(url: &str) -> Result<Vec<Value>, Error> {
+    let body = http::get(url)?;
+    let receipts = serde_json::from_slice::<Vec<Value>>(&body)
+        .map_err(|e| format!("invalid receipts from {url}: {e}"))?;
+    Ok(receipts
+        .into_iter()
+        .map(|receipt| match receipt {
+            Value::Object(mut object)
+                if object.len() == 1
+                    && (object.contains_key("Minting") || object.contains_key("Fixup")) =>
+            {
+                object
+                    .remove("Minting")
+                    .or_else(|| object.remove("Fixup"))
+                    .unwrap_or_default()
+            }
+            receipt => receipt,
+        })
+        .collect())
+}
+
+/// Write the receipts of a period to its directory, replacing the receipts which are already
+/// there. Returns the amount of receipts written.
+pub fn write_receipts(dir: &Path, period: u32, receipts: &[Value]) -> Result<usize, Error> {
+    fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {e}", dir.display()))?;
+    for (path, data) in receipt_files(dir, period, receipts)? {
+        fs::write(&path, data).map_err(|e| format!("could not write {}: {e}", path.display()))?;
+    }
+    Ok(receipts.len())
+}
+
+/// Files the receipts of a period are written to in its directory, with their content. Fixup
+/// receipts are written as `<node_id>-fixup.json`.
+pub fn receipt_files(
+    dir: &Path,
+    period: u32,
+    receipts: &[Value],
+) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+    receipts
+        .iter()
+        .map(|receipt| {
+            let node_id = receipt
+                .get("node_id")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| format!("receipt of period {period} without node id"))?;
+            let name = if receipt.get("fixup_reward").is_some() {
+                format!("{node_id}-fixup.json")
+            } else {
+                format!("{node_id}.json")
+            };
+            Ok((dir.join(name), serde_json::to_vec(receipt)?))
+        })
+        .collect()
+}
@@ -0,0 +1,134 @@
+//! What to do with periods which have no directory in the receipts directory.
+//!
+//! Minting publishes the receipts of a period some time after it ends, so a standing range of
+//! periods can include periods which aren't published yet. Every period can fail the run, be
+//! skipped with a warning, or have its receipts fetched from the minting API.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use trace_titans::Error;
+
+use crate::{
+    cli::{Args, Command},
+    minting,
+};
+
+/// What to do with a missing period directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissingPeriod {
+    /// Fail the run.
+    #[default]
+    Error,
+    /// Leave the period out of the run, with a warning.
+    Skip,
+    /// Download the receipts of the period from the minting API. Periods which aren't published
+    /// yet are skipped.
+    Fetch,
+}
+
+impl FromStr for MissingPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(MissingPeriod::Error),
+            "skip" => Ok(MissingPeriod::Skip),
+            "fetch" => Ok(MissingPeriod::Fetch),
+            _ => Err(format!(
+                "unknown missing period behavior {s}, expected error, skip or fetch"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MissingPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MissingPeriod::Error => "error",
+            MissingPeriod::Skip => "skip",
+            MissingPeriod::Fetch => "fetch",
+        })
+    }
+}
+
+/// Behavior of every period, with a default for periods which aren't configured.
+#[derive(Debug, Default, Clone)]
+pub struct MissingPeriods {
+    default: MissingPeriod,
+    periods: BTreeMap<u32, MissingPeriod>,
+}
+
+impl MissingPeriods {
+    /// Set the behavior given in the command line format: either a behavior, which sets the
+    /// default, or `<period>=<behavior>` for a single period.
+    pub fn set(&mut self, value: &str) -> Result<(), String> {
+        match value.split_once('=') {
+            Some((period, behavior)) => {
+                let period = period
+                    .parse()
+                    .map_err(|_| format!("invalid period {period}"))?;
+                self.periods.insert(period, behavior.parse()?);
+            }
+            None => self.default = value.parse()?,
+        }
+        Ok(())
+    }
+
+    /// Behavior of a period.
+    pub fn get(&self, period: u32) -> MissingPeriod {
+        self.periods.get(&period).copied().unwrap_or(self.default)
+    }
+
+    /// Indicates a period may be fetched.
+    pub fn fetches(&self) -> bool {
+        self.default == MissingPeriod::Fetch
+            || self.periods.values().any(|b| *b == MissingPeriod::Fetch)
+    }
+}
+
+/// Apply the behavior of every scanned period which has no directory in the receipts directory.
+/// Skipped periods are removed from the periods of the run.
+pub fn resolve(args: &mut Args) -> Result<(), Error> {
+    if args.csv.is_some() || args.at.is_some() {
+        return Ok(());
+    }
+    let root = args.receipts_dir();
+    let mut skipped = Vec::new();
+    for &period in args.scanned_periods() {
+        let dir = args.period_dirs.path(&root, period);
+        if dir.is_dir() {
+            continue;
+        }
+        let behavior = args.missing.get(period);
+        if behavior == MissingPeriod::Fetch {
+            let url = args.minting_url.as_deref().unwrap_or(minting::DEFAULT_URL);
+            let receipts = minting::period_receipts(url, period)?;
+            if !receipts.is_empty() {
+                let written = minting::write_receipts(&dir, period, &receipts)?;
+                eprintln!("Period {period}: fetched {written} receipts from {url}");
+                continue;
+            }
+            eprintln!("Period {period}: {url} has no receipts, the period is skipped");
+        } else if behavior == MissingPeriod::Skip {
+            eprintln!(
+                "Period {period}: {} has no directory for it, the period is skipped",
+                root.display()
+            );
+        } else {
+            return Err(format!("{} has no directory for period {period}", root.display()).into());
+        }
+        skipped.push(period);
+    }
+
+    if skipped.is_empty() {
+        return Ok(());
+    }
+    if let Command::Explain { period, .. } = args.command {
+        return Err(format!("period {period} has no receipts to explain").into());
+    }
+    args.periods.retain(|period| !skipped.contains(period));
+    if args.periods.is_empty() {
+        return Err("all periods are skipped, there is nothing to scan".into());
+    }
+    Ok(())
+}
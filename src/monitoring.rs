@@ -0,0 +1,223 @@
+//! Comparison of the measured uptime of receipts with the monitoring of the farmer.
+//!
+//! Farmers often monitor their nodes themselves, with tools like Uptime Kuma or Prometheus which
+//! can export the windows in which a node was up or down. Aligned to the period of every receipt,
+//! those windows show where the monitoring disagrees with the `measured_uptime` minting used, with
+//! the numbers both the farmer and the minting team can check.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use trace_titans::{date, pipeline::NodeReceipts, source, Error};
+
+use crate::{cli::ReportFormat, table};
+
+/// Seconds the measured uptime may differ from the monitoring before they disagree. Nodes report
+/// their uptime to the chain every 2 hours, so minting can miss up to that much of an uptime.
+pub const MARGIN: i64 = 2 * 60 * 60;
+
+/// Up and down windows of nodes, from the monitoring of the farmer.
+///
+/// Read from a CSV file with the columns `node_id`, `start`, `end` and `status`: the node was
+/// `up` or `down` from the unix timestamp `start` until `end`.
+#[derive(Debug, Default)]
+pub struct Monitoring {
+    /// node id -> (start, end, up) of every window.
+    windows: BTreeMap<u32, Vec<(i64, i64, bool)>>,
+}
+
+impl Monitoring {
+    /// Load the windows from a CSV file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let mut records = source::parse_csv(&content).into_iter();
+        let header = records
+            .next()
+            .ok_or_else(|| format!("{} is empty", path.display()))?;
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|c| c.trim() == name)
+                .ok_or_else(|| format!("{} has no {name} column", path.display()))
+        };
+        let columns = [column("node_id")?, column("start")?, column("end")?];
+        let status = column("status")?;
+
+        let mut windows = BTreeMap::<u32, Vec<_>>::new();
+        for (line, record) in records.enumerate() {
+            let invalid =
+                |what: &str| format!("invalid {what} on line {} of {}", line + 2, path.display());
+            let [node_id, start, end] = columns.map(|idx| {
+                record
+                    .get(idx)
+                    .and_then(|v| v.trim().parse::<i64>().ok())
+                    .ok_or_else(|| invalid("line"))
+            });
+            let node_id = u32::try_from(node_id?).map_err(|_| invalid("node id"))?;
+            let (start, end) = (start?, end?);
+            if end < start {
+                return Err(invalid("window, it ends before it starts").into());
+            }
+            let up = match record.get(status).map(|s| s.trim()) {
+                Some("up") => true,
+                Some("down") => false,
+                _ => return Err(invalid("status, expected up or down").into()),
+            };
+            windows.entry(node_id).or_default().push((start, end, up));
+        }
+        Ok(Monitoring { windows })
+    }
+
+    /// Seconds within `[start, end)` the monitoring saw the node up, and saw it at all. Overlapping
+    /// windows are counted once.
+    pub fn seconds(&self, node_id: u32, start: i64, end: i64) -> (i64, i64) {
+        let windows = self.windows.get(&node_id).map_or(&[][..], Vec::as_slice);
+        let covered = |only_up: bool| {
+            let mut clipped = windows
+                .iter()
+                .filter(|(_, _, up)| *up || !only_up)
+                .map(|(from, to, _)| ((*from).max(start), (*to).min(end)))
+                .filter(|(from, to)| from < to)
+                .collect::<Vec<_>>();
+            clipped.sort_unstable();
+            let (mut seconds, mut reached) = (0, i64::MIN);
+            for (from, to) in clipped {
+                let from = from.max(reached);
+                seconds += (to - from).max(0);
+                reached = reached.max(to);
+            }
+            seconds
+        };
+        (covered(true), covered(false))
+    }
+
+    /// Nodes with windows in the monitoring.
+    pub fn nodes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.windows.keys().copied()
+    }
+}
+
+/// The uptime of a node in a period, according to its receipt and to the monitoring.
+#[derive(Debug)]
+pub struct Comparison {
+    pub node_id: u32,
+    pub period: u32,
+    /// Start and end timestamp of the period of the receipt.
+    pub start: i64,
+    pub end: i64,
+    /// Uptime in the receipt, in seconds.
+    pub measured: i64,
+    /// Seconds the monitoring saw the node up.
+    pub up: i64,
+    /// Seconds the monitoring saw the node at all, up or down.
+    pub covered: i64,
+}
+
+impl Comparison {
+    /// Seconds of the period the monitoring has no window for.
+    pub fn unmonitored(&self) -> i64 {
+        (self.end - self.start - self.covered).max(0)
+    }
+
+    /// Whether the monitoring agrees with the receipt. The receipt is lower if the monitoring saw
+    /// the node up for longer than measured, and higher if the node was measured up for longer than
+    /// the monitoring saw it up or didn't see it at all. Without either, a period which is only
+    /// partly monitored can't be told to agree.
+    pub fn verdict(&self) -> &'static str {
+        if self.covered == 0 {
+            "not monitored"
+        } else if self.measured + MARGIN < self.up {
+            "receipt lower"
+        } else if self.measured > self.up + self.unmonitored() + MARGIN {
+            "receipt higher"
+        } else if self.unmonitored() > MARGIN {
+            "partly monitored"
+        } else {
+            "agrees"
+        }
+    }
+
+    /// Whether the receipt and the monitoring disagree.
+    pub fn disagrees(&self) -> bool {
+        matches!(self.verdict(), "receipt lower" | "receipt higher")
+    }
+}
+
+/// Compare the receipts of every node in the monitoring with its windows.
+pub fn compare(monitoring: &Monitoring, node_receipts: &NodeReceipts) -> Vec<Comparison> {
+    let mut comparisons = Vec::new();
+    for node_id in monitoring.nodes() {
+        let Some(receipts) = node_receipts.get(&node_id) else {
+            continue;
+        };
+        for (&period, parsed) in receipts {
+            let (start, end) = (parsed.receipt.period.start(), parsed.receipt.period.end());
+            let (up, covered) = monitoring.seconds(node_id, start, end);
+            comparisons.push(Comparison {
+                node_id,
+                period,
+                start,
+                end,
+                measured: parsed.receipt.measured_uptime as i64,
+                up,
+                covered,
+            });
+        }
+    }
+    comparisons
+}
+
+/// Write a row for every compared period, with the period as dates and the uptimes in seconds.
+pub fn write(
+    out: &mut dyn Write,
+    comparisons: &[Comparison],
+    format: ReportFormat,
+) -> io::Result<()> {
+    let header = [
+        "node_id",
+        "period",
+        "start",
+        "end",
+        "measured uptime",
+        "monitored up",
+        "monitored down",
+        "not monitored",
+        "difference",
+        "verdict",
+    ];
+    let rows = comparisons
+        .iter()
+        .map(|c| {
+            vec![
+                c.node_id.to_string(),
+                c.period.to_string(),
+                date::format_timestamp(c.start),
+                date::format_timestamp(c.end),
+                c.measured.to_string(),
+                c.up.to_string(),
+                (c.covered - c.up).to_string(),
+                c.unmonitored().to_string(),
+                (c.up - c.measured).to_string(),
+                c.verdict().to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    match format {
+        ReportFormat::Csv | ReportFormat::CsvMachine => {
+            writeln!(out, "{}", header.join(","))?;
+            for row in rows {
+                writeln!(out, "{}", row.join(","))?;
+            }
+            Ok(())
+        }
+        ReportFormat::Table => {
+            let header = header.map(str::to_string);
+            table::write(out, &header, &rows)
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Registry of farms which declined compensation.
+//!
+//! Some farms formally declined compensation, or settled privately. Their nodes stay in the
+//! report, flagged, so the published numbers still add up, but they are not approved for payment.
+//! Every entry records who signed the opt-out on behalf of the farm, and when.
+//!
+//! As the registry decides who isn't paid, it can be signed with an SSH key: with an allowed
+//! signers file, a registry is only loaded if one of the signers signed it.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use trace_titans::{source, Error};
+
+/// An opt-out of a farm.
+#[derive(Debug, Clone)]
+pub struct OptOut {
+    /// Who signed the opt-out on behalf of the farm.
+    pub signed_by: String,
+    /// Date the opt-out was signed.
+    pub date: String,
+    /// Why the farm opted out, if known.
+    pub reason: Option<String>,
+}
+
+/// Namespace of the signature of a registry, see `ssh-keygen -Y sign -n`.
+pub const SIGNATURE_NAMESPACE: &str = "trace-titans-opt-outs";
+
+/// The opt-out of every farm which opted out.
+#[derive(Debug, Default)]
+pub struct OptOuts {
+    farms: BTreeMap<u32, OptOut>,
+}
+
+impl OptOuts {
+    /// Load the registry from a CSV file with the columns `farm_id`, `signed_by` and `date`, and
+    /// optionally `reason`. With an allowed signers file, see `ssh-keygen(1)`, the registry must
+    /// be signed by one of them, in a file named after the registry with `.sig` appended.
+    pub fn load(path: &Path, signers: Option<&Path>) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        if let Some(signers) = signers {
+            verify(path, &content, signers)?;
+        }
+        let mut records = source::parse_csv(&content).into_iter();
+        let header = records
+            .next()
+            .ok_or_else(|| format!("{} is empty", path.display()))?;
+        let position = |name: &str| header.iter().position(|c| c.trim() == name);
+        let column = |name: &str| {
+            position(name).ok_or_else(|| format!("{} has no {name} column", path.display()))
+        };
+        let (farm_idx, signed_by_idx, date_idx) =
+            (column("farm_id")?, column("signed_by")?, column("date")?);
+        let reason_idx = position("reason");
+
+        let mut farms = BTreeMap::new();
+        for (line, record) in records.enumerate() {
+            let value = |idx: usize| record.get(idx).map(|v| v.trim()).filter(|v| !v.is_empty());
+            let field = |idx: usize, name: &str| {
+                value(idx).ok_or_else(|| {
+                    format!("missing {name} on line {} of {}", line + 2, path.display())
+                })
+            };
+            let farm_id = field(farm_idx, "farm_id")?;
+            let farm_id = farm_id
+                .parse::<u32>()
+                .map_err(|_| format!("invalid farm id {farm_id} on line {}", line + 2))?;
+            let opt_out = OptOut {
+                signed_by: field(signed_by_idx, "signed_by")?.to_string(),
+                date: field(date_idx, "date")?.to_string(),
+                reason: reason_idx.and_then(value).map(str::to_string),
+            };
+            if farms.insert(farm_id, opt_out).is_some() {
+                return Err(format!("farm {farm_id} is listed twice in {}", path.display()).into());
+            }
+        }
+        Ok(OptOuts { farms })
+    }
+
+    /// The opt-out of a farm, if it opted out.
+    pub fn get(&self, farm_id: u32) -> Option<&OptOut> {
+        self.farms.get(&farm_id)
+    }
+}
+
+/// Check that the registry is signed by one of the allowed signers.
+fn verify(path: &Path, content: &str, signers: &Path) -> Result<(), Error> {
+    let mut signature = path.as_os_str().to_owned();
+    signature.push(".sig");
+    let signature = PathBuf::from(signature);
+    if !signature.is_file() {
+        return Err(format!(
+            "{} is not signed, its signature {} doesn't exist",
+            path.display(),
+            signature.display()
+        )
+        .into());
+    }
+    verify_signature(&signature, content.as_bytes(), signers, SIGNATURE_NAMESPACE)
+        .map_err(|e| format!("the signature of {} is not valid: {e}", path.display()))?;
+    Ok(())
+}
+
+/// Check an SSH signature of the content in the namespace against an allowed signers file,
+/// returning the principal which signed it.
+pub fn verify_signature(
+    signature: &Path,
+    content: &[u8],
+    signers: &Path,
+    namespace: &str,
+) -> Result<String, String> {
+    let principals = ssh_keygen(
+        &[
+            "-Y".as_ref(),
+            "find-principals".as_ref(),
+            "-s".as_ref(),
+            signature.as_os_str(),
+            "-f".as_ref(),
+            signers.as_os_str(),
+        ],
+        b"",
+    )
+    .map_err(|e| format!("it is not signed by an allowed signer ({e})"))?;
+    let principal = principals
+        .lines()
+        .next()
+        .ok_or("it is not signed by an allowed signer")?;
+    ssh_keygen(
+        &[
+            "-Y".as_ref(),
+            "verify".as_ref(),
+            "-f".as_ref(),
+            signers.as_os_str(),
+            "-I".as_ref(),
+            principal.as_ref(),
+            "-n".as_ref(),
+            namespace.as_ref(),
+            "-s".as_ref(),
+            signature.as_os_str(),
+        ],
+        content,
+    )?;
+    Ok(principal.to_string())
+}
+
+/// Run `ssh-keygen` with the input on stdin, returning its output.
+pub fn ssh_keygen(args: &[&OsStr], input: &[u8]) -> Result<String, String> {
+    let mut child = Command::new("ssh-keygen")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run ssh-keygen, which is needed for signatures: {e}"))?;
+    child
+        .stdin
+        .take()
+        .expect("Stdin of ssh-keygen is piped")
+        .write_all(input)
+        .map_err(|e| e.to_string())?;
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl OptOut {
+    /// Short description, as shown in the report.
+    pub fn describe(&self) -> String {
+        match &self.reason {
+            Some(reason) => format!("signed by {} on {}: {reason}", self.signed_by, self.date),
+            None => format!("signed by {} on {}", self.signed_by, self.date),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for the registry, removed when the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("trace_titans-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Generate a key, returning its path and an allowed signers line for it.
+    fn key(dir: &Path, name: &str) -> (PathBuf, String) {
+        let key = dir.join(name);
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", name, "-f"])
+            .arg(&key)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let public = fs::read_to_string(key.with_extension("pub")).unwrap();
+        (key, format!("{name}@example.com {public}"))
+    }
+
+    fn sign(key: &Path, registry: &Path) {
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-Y", "sign", "-n", SIGNATURE_NAMESPACE, "-f"])
+            .arg(key)
+            .arg(registry)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn signed_registry() {
+        let dir = TempDir::new("opt-outs");
+        let registry = dir.0.join("opt-outs.csv");
+        fs::write(&registry, "farm_id,signed_by,date\n1,alice,2024-01-01\n").unwrap();
+        let (trusted, line) = key(&dir.0, "trusted");
+        let (other, _) = key(&dir.0, "other");
+        let signers = dir.0.join("allowed_signers");
+        fs::write(&signers, line).unwrap();
+
+        // Without signers the signature isn't needed.
+        assert!(OptOuts::load(&registry, None).unwrap().get(1).is_some());
+        let err = OptOuts::load(&registry, Some(&signers)).unwrap_err();
+        assert!(err.to_string().contains("is not signed"), "{err}");
+
+        sign(&trusted, &registry);
+        assert!(OptOuts::load(&registry, Some(&signers)).is_ok());
+
+        fs::write(&registry, "farm_id,signed_by,date\n2,alice,2024-01-01\n").unwrap();
+        let err = OptOuts::load(&registry, Some(&signers)).unwrap_err();
+        assert!(err.to_string().contains("is not valid"), "{err}");
+
+        fs::remove_file(dir.0.join("opt-outs.csv.sig")).unwrap();
+        sign(&other, &registry);
+        let err = OptOuts::load(&registry, Some(&signers)).unwrap_err();
+        assert!(err.to_string().contains("allowed signer"), "{err}");
+    }
+}
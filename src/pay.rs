@@ -0,0 +1,447 @@
+//! Preparation of the compensation payments as unsigned Stellar transactions.
+//!
+//! The differences to send are turned into payment operations to the payout address of every
+//! node, so nobody has to enter amounts by hand. Only approved amounts are paid, and never to
+//! farms which opted out. The transactions are written unsigned, signing stays with whoever holds
+//! the key of the paying account.
+
+use std::{fs, path::Path};
+
+use trace_titans::{format_diff_tft, format_tft, Error, NodeResult};
+
+use crate::{
+    cli::Args,
+    horizon,
+    ledger::{Ledger, Status},
+    memos,
+    opt_out::OptOuts,
+    plan::Plan,
+    stellar::{self, PaymentOp},
+};
+
+/// A payment to prepare for a node.
+struct NodePayment {
+    node_id: u32,
+    address: String,
+    /// Amount in TFT units.
+    amount: u64,
+    /// Indicates the amount is approved, and the node is queued once its payment is prepared.
+    approved: bool,
+}
+
+/// Payments to prepare, and the nodes owed something which are not paid.
+struct Selection {
+    payments: Vec<NodePayment>,
+    /// Nodes owed an amount which isn't approved.
+    unapproved: usize,
+    /// Nodes which changed since their approval.
+    stale: usize,
+    /// Nodes of farms which opted out.
+    opted_out: usize,
+}
+
+/// Write a transaction file `tx-<n>.xdr` to the directory for every batch of at most
+/// [`stellar::MAX_OPERATIONS`] payments, paying every node which is owed something in the
+/// report from the payer. Approved nodes are paid their approved amount if the approval still
+/// holds, and marked as queued in the ledger. Other nodes are only paid, at their difference in
+/// the report, with `--allow-unapproved`. With `--plan`, the payments and ledger changes are only
+/// printed.
+pub fn pay(
+    path: &Path,
+    node_results: &[(u32, NodeResult)],
+    payer: &str,
+    out_dir: &Path,
+    args: &Args,
+) -> Result<(), Error> {
+    stellar::decode_account_id(payer)?;
+    let opt_outs = match &args.opt_outs {
+        Some(path) => OptOuts::load(path, args.opt_outs_signers.as_deref())?,
+        None => OptOuts::default(),
+    };
+    let mut ledger = Ledger::load(path)?;
+    let Selection {
+        payments,
+        unapproved,
+        stale,
+        opted_out,
+    } = select(&ledger, node_results, &opt_outs, args)?;
+    if payments.is_empty() {
+        return Err(format!(
+            "no approved amounts to pay, {unapproved} node(s) are owed an amount which isn't \
+             approved, {stale} node(s) changed since their approval and {opted_out} node(s) \
+             opted out"
+        )
+        .into());
+    }
+
+    let memo = memo(&args.periods);
+    let batches = payments.chunks(stellar::MAX_OPERATIONS).collect::<Vec<_>>();
+    if args.plan {
+        let mut plan = Plan::default();
+        for (idx, batch) in batches.iter().enumerate() {
+            plan.note(format!(
+                "{}: {} payment(s) for a total of {} TFT from {payer}, memo \"{memo}\"",
+                out_dir.join(format!("tx-{}.xdr", idx + 1)).display(),
+                batch.len(),
+                format_tft(batch.iter().map(|p| p.amount).sum::<u64>())
+            ));
+            for payment in *batch {
+                plan.note(format!(
+                    "    node {}: {} TFT to {}{}",
+                    payment.node_id,
+                    format_tft(payment.amount),
+                    payment.address,
+                    if payment.approved {
+                        ", queued in the ledger"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+        }
+        plan.note(format!(
+            "{} payment(s) for a total of {} TFT",
+            payments.len(),
+            format_tft(payments.iter().map(|p| p.amount).sum::<u64>())
+        ));
+        if unapproved > 0 {
+            plan.note(format!(
+                "{unapproved} node(s) are owed an amount which isn't approved, and are not paid"
+            ));
+        }
+        if stale > 0 {
+            plan.note(format!(
+                "{stale} node(s) changed since their approval, and are not paid"
+            ));
+        }
+        if opted_out > 0 {
+            plan.note(format!(
+                "{opted_out} node(s) of farms which opted out are not paid"
+            ));
+        }
+        plan.print("pay");
+        return Ok(());
+    }
+
+    let url = args.horizon_url.as_deref().unwrap_or(horizon::DEFAULT_URL);
+    let sequence = horizon::sequence(url, payer)?;
+    fs::create_dir_all(out_dir)
+        .map_err(|e| format!("could not create {}: {e}", out_dir.display()))?;
+
+    let mut total = 0;
+    for (name, batch, envelope) in prepare(&mut ledger, payer, sequence, &memo, &payments)? {
+        let file = out_dir.join(&name);
+        fs::write(&file, format!("{}\n", memos::base64(&envelope)))
+            .map_err(|e| format!("could not write {}: {e}", file.display()))?;
+        let amount = batch.iter().map(|p| p.amount).sum::<u64>();
+        total += amount;
+        eprintln!(
+            "{}: {} payment(s) for a total of {} TFT",
+            file.display(),
+            batch.len(),
+            format_tft(amount)
+        );
+    }
+    ledger.save(path)?;
+
+    eprintln!(
+        "Prepared {} payment(s) for a total of {} TFT in {} transaction(s) from {payer}, memo \"{memo}\"",
+        payments.len(),
+        format_tft(total),
+        batches.len()
+    );
+    if unapproved > 0 {
+        eprintln!("{unapproved} node(s) are owed an amount which isn't approved, and are not paid");
+    }
+    if stale > 0 {
+        eprintln!("{stale} node(s) changed since their approval, and are not paid");
+    }
+    if opted_out > 0 {
+        eprintln!("{opted_out} node(s) of farms which opted out are not paid");
+    }
+    Ok(())
+}
+
+/// Select the nodes to pay, with the amount to pay them. Approved nodes are paid if their
+/// approval still holds, other nodes owed something only with `--allow-unapproved`, and nodes of
+/// farms which opted out never.
+fn select(
+    ledger: &Ledger,
+    node_results: &[(u32, NodeResult)],
+    opt_outs: &OptOuts,
+    args: &Args,
+) -> Result<Selection, Error> {
+    let policies = args.policies.snapshot(&args.periods);
+    let mut selection = Selection {
+        payments: Vec::new(),
+        unapproved: 0,
+        stale: 0,
+        opted_out: 0,
+    };
+    for (node_id, result) in node_results {
+        let difference = result.difference(&args.tolerances);
+        let Some(latest) = result.latest() else {
+            continue;
+        };
+        let status = ledger.status(*node_id);
+        let amount = match status {
+            Status::Approved => {
+                let approval = ledger.nodes[node_id]
+                    .approval
+                    .as_ref()
+                    .ok_or_else(|| format!("node {node_id} is approved without approval"))?;
+                // An approval only holds for the data it was made on, and for exactly what the
+                // node is owed now.
+                let changed = match approval.check(*node_id, result, &policies) {
+                    Ok(amount) if amount as i64 == difference => None,
+                    Ok(_) => Some(format!(
+                        "approved {} TFT, the report now owes {} TFT",
+                        approval.amount,
+                        format_diff_tft(difference)
+                    )),
+                    Err(e) => Some(e),
+                };
+                if let Some(changed) = changed {
+                    eprintln!("Node {node_id}: {changed}, not paid until it's approved again");
+                    selection.stale += 1;
+                    continue;
+                }
+                difference as u64
+            }
+            Status::Computed if difference > 0 => {
+                if !args.allow_unapproved {
+                    selection.unapproved += 1;
+                    continue;
+                }
+                difference as u64
+            }
+            _ => continue,
+        };
+        if let Some(opt_out) = opt_outs.get(latest.farm_id) {
+            eprintln!(
+                "Node {node_id}: farm {} opted out of compensation ({}), not paid",
+                latest.farm_id,
+                opt_out.describe()
+            );
+            selection.opted_out += 1;
+            continue;
+        }
+        stellar::decode_account_id(&latest.payout_address)
+            .map_err(|e| format!("node {node_id} can't be paid: {e}"))?;
+        selection.payments.push(NodePayment {
+            node_id: *node_id,
+            address: latest.payout_address.clone(),
+            amount,
+            approved: status == Status::Approved,
+        });
+    }
+    Ok(selection)
+}
+
+/// Memo of the transactions, naming the scanned periods.
+fn memo(periods: &[u32]) -> String {
+    match (periods.first(), periods.last()) {
+        (Some(first), Some(last)) if first != last => format!("titan correction p{first}-{last}"),
+        (Some(period), _) => format!("titan correction p{period}"),
+        _ => "titan correction".to_string(),
+    }
+}
+
+/// A prepared transaction, with the name of its file, its payments and its envelope.
+type Transaction<'a> = (String, &'a [NodePayment], Vec<u8>);
+
+/// Build the transaction of every batch of payments, with the sequence numbers following the
+/// current `sequence` of the payer, and queue the approved nodes of every batch in the ledger.
+fn prepare<'a>(
+    ledger: &mut Ledger,
+    payer: &str,
+    sequence: i64,
+    memo: &str,
+    payments: &'a [NodePayment],
+) -> Result<Vec<Transaction<'a>>, Error> {
+    let mut transactions = Vec::new();
+    for (idx, batch) in payments.chunks(stellar::MAX_OPERATIONS).enumerate() {
+        let ops = batch
+            .iter()
+            .map(|p| PaymentOp {
+                destination: p.address.clone(),
+                amount: p.amount,
+            })
+            .collect::<Vec<_>>();
+        let envelope = stellar::payment_envelope(payer, sequence + idx as i64 + 1, memo, &ops)?;
+        let name = format!("tx-{}.xdr", idx + 1);
+        let note = format!("queued in {name}");
+        for payment in batch.iter().filter(|p| p.approved) {
+            ledger.transition(payment.node_id, Status::Queued, Some(&note))?;
+        }
+        transactions.push((name, batch, envelope));
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::{
+        self,
+        tests::{args, node_result, TempDir, ZERO},
+    };
+
+    use super::*;
+
+    /// Approve the nodes with the approve command, returning the ledger.
+    fn approved(dir: &TempDir, results: &[(u32, NodeResult)], node_ids: &[u32]) -> Ledger {
+        let (path, nodes) = (dir.0.join("ledger.json"), dir.0.join("nodes.txt"));
+        let ids = node_ids
+            .iter()
+            .map(|id| format!("{id}\n"))
+            .collect::<String>();
+        fs::write(&nodes, ids).unwrap();
+        ledger::approve(&path, results, "alice", &nodes, &args()).unwrap();
+        Ledger::load(&path).unwrap()
+    }
+
+    #[test]
+    fn computed_nodes_need_allow_unapproved() {
+        let results = [node_result(1, 1, ZERO, 0)];
+        let ledger = Ledger::default();
+        let selection = select(&ledger, &results, &OptOuts::default(), &args()).unwrap();
+        assert!(selection.payments.is_empty());
+        assert_eq!(selection.unapproved, 1);
+
+        let args = Args {
+            allow_unapproved: true,
+            ..args()
+        };
+        let selection = select(&ledger, &results, &OptOuts::default(), &args).unwrap();
+        assert_eq!(selection.unapproved, 0);
+        let [payment] = selection.payments.as_slice() else {
+            panic!("expected a single payment");
+        };
+        assert!(!payment.approved);
+        assert_eq!(
+            payment.amount as i64,
+            results[0].1.difference(&args.tolerances)
+        );
+    }
+
+    #[test]
+    fn approvals_which_no_longer_hold_are_not_paid() {
+        let dir = TempDir::new("pay-approvals");
+        let results = [node_result(1, 1, ZERO, 0), node_result(2, 1, ZERO, 0)];
+        let mut ledger = approved(&dir, &results, &[1, 2]);
+        let selection = select(&ledger, &results, &OptOuts::default(), &args()).unwrap();
+        assert_eq!(selection.payments.len(), 2);
+        assert!(selection.payments.iter().all(|p| p.approved));
+
+        // Node 1 was minted more since its approval, so its snapshot digest changed.
+        let changed = [
+            node_result(1, 1, ZERO, 10_000_000),
+            node_result(2, 1, ZERO, 0),
+        ];
+        let selection = select(&ledger, &changed, &OptOuts::default(), &args()).unwrap();
+        assert_eq!(selection.stale, 1);
+        assert_eq!(selection.payments.len(), 1);
+        assert_eq!(selection.payments[0].node_id, 2);
+
+        // The approved amount of node 2 no longer is what the report owes.
+        ledger
+            .nodes
+            .get_mut(&2)
+            .unwrap()
+            .approval
+            .as_mut()
+            .unwrap()
+            .amount = "1".into();
+        let selection = select(&ledger, &results, &OptOuts::default(), &args()).unwrap();
+        assert_eq!(selection.stale, 1);
+        assert_eq!(selection.payments.len(), 1);
+        assert_eq!(selection.payments[0].node_id, 1);
+    }
+
+    #[test]
+    fn opted_out_farms_are_not_paid() {
+        let dir = TempDir::new("pay-opt-outs");
+        let results = [node_result(1, 1, ZERO, 0), node_result(2, 2, ZERO, 0)];
+        let ledger = approved(&dir, &results, &[1, 2]);
+        // Farm 2 opted out after its node was approved.
+        let path = dir.0.join("opt-outs.csv");
+        fs::write(&path, "farm_id,signed_by,date\n2,bob,2024-01-01\n").unwrap();
+        let opt_outs = OptOuts::load(&path, None).unwrap();
+        let selection = select(&ledger, &results, &opt_outs, &args()).unwrap();
+        assert_eq!(selection.opted_out, 1);
+        assert_eq!(selection.payments.len(), 1);
+        assert_eq!(selection.payments[0].node_id, 1);
+    }
+
+    #[test]
+    fn memo_names_the_periods() {
+        assert_eq!(memo(&[]), "titan correction");
+        assert_eq!(memo(&[57]), "titan correction p57");
+        assert_eq!(memo(&[52, 53, 57]), "titan correction p52-57");
+    }
+
+    #[test]
+    fn batches_of_max_operations() {
+        let mut ledger = Ledger::default();
+        let payments = (0..2 * stellar::MAX_OPERATIONS as u32 + 1)
+            .map(|node_id| NodePayment {
+                node_id,
+                address: ZERO.to_string(),
+                amount: 10_000_000,
+                // Every other node is approved, the others are paid with `--allow-unapproved`.
+                approved: node_id % 2 == 0,
+            })
+            .collect::<Vec<_>>();
+        for payment in payments.iter().filter(|p| p.approved) {
+            ledger
+                .transition(payment.node_id, Status::Approved, None)
+                .unwrap();
+        }
+
+        let transactions = prepare(&mut ledger, ZERO, 41, "memo", &payments).unwrap();
+        let names = transactions.iter().map(|(name, _, _)| name.as_str());
+        assert_eq!(
+            names.collect::<Vec<_>>(),
+            ["tx-1.xdr", "tx-2.xdr", "tx-3.xdr"]
+        );
+        let sizes = transactions.iter().map(|(_, batch, _)| batch.len());
+        assert_eq!(
+            sizes.collect::<Vec<_>>(),
+            [stellar::MAX_OPERATIONS, stellar::MAX_OPERATIONS, 1]
+        );
+        // Every transaction uses the sequence number after the one before it.
+        let sequences = transactions
+            .iter()
+            .map(|(_, _, envelope)| i64::from_be_bytes(envelope[44..52].try_into().unwrap()));
+        assert_eq!(sequences.collect::<Vec<_>>(), [42, 43, 44]);
+
+        // Only approved nodes are queued, in the transaction which pays them.
+        for payment in &payments {
+            let expected = if payment.approved {
+                Status::Queued
+            } else {
+                Status::Computed
+            };
+            assert_eq!(ledger.status(payment.node_id), expected);
+        }
+        let queued = &ledger.nodes[&(stellar::MAX_OPERATIONS as u32)].history;
+        assert_eq!(
+            queued.last().unwrap().note.as_deref(),
+            Some("queued in tx-2.xdr")
+        );
+    }
+
+    #[test]
+    fn a_node_queued_twice_fails() {
+        let mut ledger = Ledger::default();
+        let payment = NodePayment {
+            node_id: 1,
+            address: ZERO.to_string(),
+            amount: 1,
+            approved: true,
+        };
+        ledger.transition(1, Status::Approved, None).unwrap();
+        prepare(&mut ledger, ZERO, 0, "memo", std::slice::from_ref(&payment)).unwrap();
+        assert!(prepare(&mut ledger, ZERO, 1, "memo", &[payment]).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Minimal PDF writer.
+//!
+//! Only supports what the statements need: A4 pages with text in the standard Helvetica fonts and
+//! straight lines. The standard fonts don't need to be embedded, which keeps the files small and
+//! the writer simple. Text is encoded as WinAnsi, which covers the languages of the reports.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Width of an A4 page in points.
+pub const PAGE_WIDTH: f32 = 595.;
+/// Height of an A4 page in points.
+pub const PAGE_HEIGHT: f32 = 842.;
+
+/// Fonts available on a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+/// A document, consisting of pages.
+#[derive(Debug, Default)]
+pub struct Document {
+    pages: Vec<Page>,
+}
+
+/// A single page. Coordinates are in points, from the bottom left corner of the page.
+#[derive(Debug, Default)]
+pub struct Page {
+    content: String,
+}
+
+impl Document {
+    /// Add a new empty page and return it.
+    pub fn page(&mut self) -> &mut Page {
+        self.pages.push(Page::default());
+        self.pages.last_mut().expect("A page was just added")
+    }
+
+    /// Write the document to a file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut out = Vec::new();
+        // Byte offset of every object, object numbers start at 1.
+        let mut offsets = Vec::new();
+        let mut object = |out: &mut Vec<u8>, body: &[u8]| {
+            offsets.push(out.len());
+            writeln!(out, "{} 0 obj", offsets.len())?;
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\nendobj\n");
+            io::Result::Ok(())
+        };
+
+        // The binary comment marks the file as containing binary data.
+        out.extend_from_slice(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n");
+        object(&mut out, b"<< /Type /Catalog /Pages 2 0 R >>")?;
+        // Page objects are numbered after the fonts, each followed by its content stream.
+        let kids = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", 5 + 2 * i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        object(
+            &mut out,
+            format!(
+                "<< /Type /Pages /Kids [{kids}] /Count {} >>",
+                self.pages.len()
+            )
+            .as_bytes(),
+        )?;
+        for font in ["Helvetica", "Helvetica-Bold"] {
+            object(
+                &mut out,
+                format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /{font} /Encoding /WinAnsiEncoding >>"
+                )
+                .as_bytes(),
+            )?;
+        }
+        for (i, page) in self.pages.iter().enumerate() {
+            object(
+                &mut out,
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                     /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                    6 + 2 * i
+                )
+                .as_bytes(),
+            )?;
+            let content = encode(&page.content);
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(&content);
+            stream.extend_from_slice(b"\nendstream");
+            object(&mut out, &stream)?;
+        }
+
+        let xref = out.len();
+        write!(out, "xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1)?;
+        for offset in &offsets {
+            writeln!(out, "{offset:010} 00000 n ")?;
+        }
+        write!(
+            out,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            offsets.len() + 1
+        )?;
+
+        fs::write(path, out)
+    }
+}
+
+impl Page {
+    /// Draw text with its baseline starting at the given position.
+    pub fn text(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        let _ = writeln!(
+            self.content,
+            "BT /{} {size} Tf {x:.2} {y:.2} Td ({}) Tj ET",
+            font.resource(),
+            escape(text)
+        );
+    }
+
+    /// Draw text with its baseline ending at the given position, e.g. for amounts in a column.
+    pub fn text_right(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        self.text(x - text_width(text, size), y, font, size, text);
+    }
+
+    /// Draw a thin line.
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        let _ = writeln!(self.content, "0.5 w {x1:.2} {y1:.2} m {x2:.2} {y2:.2} l S");
+    }
+}
+
+/// Approximate width of text in Helvetica. Digits and the characters used in amounts have their
+/// exact width, so amounts can be aligned, other characters use an average width.
+pub fn text_width(text: &str, size: f32) -> f32 {
+    let units = text
+        .chars()
+        .map(|c| match c {
+            '0'..='9' => 556,
+            '.' | ',' | ' ' => 278,
+            '-' => 333,
+            _ => 556,
+        })
+        .sum::<u32>();
+    units as f32 * size / 1000.
+}
+
+/// Escape text for use in a PDF string literal. Characters are kept as is, and encoded to WinAnsi
+/// when the content is written.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '(' | ')') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Encode content to WinAnsi. Characters which WinAnsi can't represent are replaced by `?`.
+fn encode(content: &str) -> Vec<u8> {
+    content
+        .chars()
+        .map(|c| match c {
+            '\u{20}'..='\u{7e}' | '\n' => c as u8,
+            '\u{a0}'..='\u{ff}' => c as u32 as u8,
+            '€' => 0x80,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '–' => 0x96,
+            '—' => 0x97,
+            'Œ' => 0x8c,
+            'œ' => 0x9c,
+            _ => b'?',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_encoding() {
+        assert_eq!(escape(r"a (b) \c"), r"a \(b\) \\c");
+        assert_eq!(encode("é € – ✓\n"), b"\xe9 \x80 \x96 ?\n");
+        // Five digits of 556 units and two separators of 278.
+        assert_eq!(text_width("1,000.5", 10.), 3336. * 10. / 1000.);
+    }
+
+    #[test]
+    fn cross_references_point_at_objects() {
+        let mut document = Document::default();
+        let page = document.page();
+        page.text(10., 20., Font::Bold, 12., "Statement (1)");
+        page.line(0., 0., PAGE_WIDTH, 0.);
+        document
+            .page()
+            .text_right(100., 20., Font::Regular, 10., "1.5");
+        let path = std::env::temp_dir().join(format!("trace_titans-{}.pdf", std::process::id()));
+        document.write(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let text = String::from_utf8_lossy(&data);
+        let xref = text
+            .rsplit("startxref\n")
+            .next()
+            .and_then(|s| s.lines().next())
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap();
+        assert!(data[xref..].starts_with(b"xref\n0 9\n"));
+        let offsets = std::str::from_utf8(&data[xref..])
+            .unwrap()
+            .lines()
+            .skip(3)
+            .take(8)
+            .map(|line| line[..10].parse::<usize>().unwrap());
+        for (number, offset) in (1..).zip(offsets) {
+            let object = format!("{number} 0 obj\n");
+            assert!(data[offset..].starts_with(object.as_bytes()), "{object}");
+        }
+        assert!(text.contains("/Kids [5 0 R 7 0 R] /Count 2"));
+        assert!(text.contains("(Statement \\(1\\)) Tj"));
+    }
+}
@@ -7,6 +7,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::date;
+
 /// Timestamp of the start of the first period.
 const FIRST_PERIOD_START_TIMESTAMP: i64 = 1522501000;
 /// The duration of a standard period, as used by the minting payouts, in seconds.
@@ -44,6 +46,14 @@ impl Period {
         }
     }
 
+    /// Offset of the period from the start.
+    ///
+    /// The offset is derived from the end of the period, as the start can be adjusted for nodes
+    /// which came online during the period.
+    pub fn offset(&self) -> i64 {
+        (self.end - FIRST_PERIOD_START_TIMESTAMP - 1).div_euclid(STANDARD_PERIOD_DURATION as i64)
+    }
+
     /// Start timestamp of the period.
     pub fn start(&self) -> i64 {
         self.start
@@ -54,6 +64,15 @@ impl Period {
         self.end
     }
 
+    /// Dates of the start and the end of the period, e.g. `2023-06-08 → 2023-07-08`.
+    pub fn date_range(&self) -> String {
+        format!(
+            "{} → {}",
+            date::format_date(self.start),
+            date::format_date(self.end)
+        )
+    }
+
     /// The duration of the period in seconds.
     pub fn duration(&self) -> u64 {
         (self.end - self.start) as u64
@@ -0,0 +1,802 @@
+//! The stages loading receipts and aggregating them per node.
+//!
+//! Receipts flow through the stages over channels: discover → read → parse → validate →
+//! aggregate. Every period is discovered on its own thread, reading and parsing run on a
+//! configurable amount of worker threads, validation and aggregation run on a single thread each.
+//! Progress is reported to an [`EventHandler`].
+//!
+//! Analyzing and rendering need every node to be aggregated, so they can't overlap with loading
+//! and are not stages of the pipeline. The caller runs them on the aggregated nodes, and records
+//! their timing in the same [`Metrics`] as [`Stage::Analyze`] and [`Stage::Render`].
+//!
+//! Once the process is interrupted, discovery and the workers stop taking new items, and the
+//! receipts which were already aggregated are returned as a partial result.
+//!
+//! A receipt which can't be read, parsed or validated doesn't stop the other receipts: it is
+//! passed on to the aggregate stage as an [`InvalidReceipt`]. Only failing to discover the
+//! receipts of a period stops the run.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, Write},
+    num::NonZeroUsize,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    cancel,
+    events::{Event, EventHandler},
+    receipt::{FixupReceipt, ReceiptSummary},
+    source::{RawReceipt, ReceiptRef, ReceiptSource, SourceError},
+};
+
+/// Amount of items which can be queued between two stages before the producing stage blocks.
+const STAGE_QUEUE_SIZE: usize = 1024;
+
+/// Receipts of every node, keyed by node id and period offset.
+pub type NodeReceipts = BTreeMap<u32, BTreeMap<u32, ParsedReceipt>>;
+
+/// Configuration of the pipeline.
+#[derive(Debug, Clone, Copy)]
+pub struct PipelineConfig {
+    /// Amount of threads reading receipts.
+    pub read_workers: NonZeroUsize,
+    /// Amount of threads parsing receipts.
+    pub parse_workers: NonZeroUsize,
+}
+
+impl Default for PipelineConfig {
+    /// Use as many read and parse workers as there are CPU's available.
+    fn default() -> Self {
+        let workers = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+        PipelineConfig {
+            read_workers: workers,
+            parse_workers: workers,
+        }
+    }
+}
+
+/// A parsed receipt, with the fixups of its period.
+pub struct ParsedReceipt {
+    /// Offset of the period the receipt was found in.
+    pub period: u32,
+    /// Where the receipt was loaded from.
+    pub origin: String,
+    pub receipt: ReceiptSummary<'static>,
+    /// Fixup receipts correcting the payout of the receipt, in the order of their origin.
+    pub fixups: Vec<FixupReceipt<'static>>,
+}
+
+impl ParsedReceipt {
+    /// Payout of the fixups of the receipt, in TFT units.
+    pub fn fixup_payout(&self) -> u64 {
+        self.fixups.iter().map(|fixup| fixup.fixup_reward.tft).sum()
+    }
+}
+
+/// A receipt which could not be used, and is left out of the results.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvalidReceipt {
+    /// Offset of the period the receipt was found in.
+    pub period: u32,
+    /// Where the receipt was loaded from.
+    pub origin: String,
+    /// Dotted path of the field which is wrong or missing, if the error is about a field.
+    pub field: Option<String>,
+    pub error: String,
+}
+
+impl InvalidReceipt {
+    fn new(period: u32, origin: &str, error: SourceError) -> Self {
+        // Errors of sources start with the origin, which is kept on its own.
+        let error = error.to_string();
+        InvalidReceipt {
+            period,
+            origin: origin.to_string(),
+            field: None,
+            error: error
+                .strip_prefix(&format!("{origin}: "))
+                .unwrap_or(&error)
+                .to_string(),
+        }
+    }
+}
+
+/// Write receipts which could not be used to a file, as JSON if the file has a `.json`
+/// extension, as CSV otherwise.
+pub fn write_invalid(path: &Path, invalid: &[InvalidReceipt]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::to_writer_pretty(&mut file, invalid)?;
+        writeln!(file)?;
+    } else {
+        writeln!(file, "period,origin,field,error")?;
+        for receipt in invalid {
+            writeln!(
+                file,
+                "{},\"{}\",{},\"{}\"",
+                receipt.period,
+                receipt.origin.replace('"', "\"\""),
+                receipt.field.as_deref().unwrap_or_default(),
+                receipt.error.replace('"', "\"\"")
+            )?;
+        }
+    }
+    file.flush()
+}
+
+impl fmt::Display for InvalidReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.origin, self.error)
+    }
+}
+
+/// Why an item didn't make it through a stage.
+enum Failure {
+    /// The run can't continue, e.g. the receipts of a period could not be discovered.
+    Fatal(SourceError),
+    /// A single receipt can't be used, the other receipts are loaded as usual.
+    Invalid(InvalidReceipt),
+}
+
+/// A parsed receipt of either kind, as passed from the parse to the aggregate stage.
+enum Parsed {
+    Minting(ParsedReceipt),
+    /// A fixup receipt, with the period and the origin it was found in.
+    Fixup(u32, String, FixupReceipt<'static>),
+}
+
+/// The stages of a run, in the order receipts flow through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Discover,
+    Read,
+    Parse,
+    Validate,
+    Aggregate,
+    /// Calculating the results of the aggregated nodes, run by the caller.
+    Analyze,
+    /// Writing the report of the results, run by the caller.
+    Render,
+}
+
+impl Stage {
+    /// All stages, in order.
+    pub const ALL: [Stage; 7] = [
+        Stage::Discover,
+        Stage::Read,
+        Stage::Parse,
+        Stage::Validate,
+        Stage::Aggregate,
+        Stage::Analyze,
+        Stage::Render,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Discover => "discover",
+            Stage::Read => "read",
+            Stage::Parse => "parse",
+            Stage::Validate => "validate",
+            Stage::Aggregate => "aggregate",
+            Stage::Analyze => "analyze",
+            Stage::Render => "render",
+        }
+    }
+}
+
+/// Timing metrics of every stage. Metrics are shared by all workers of a stage.
+#[derive(Default)]
+pub struct Metrics {
+    stages: [StageMetrics; 7],
+}
+
+#[derive(Default)]
+struct StageMetrics {
+    /// Amount of items processed.
+    items: AtomicU64,
+    /// Amount of receipt bytes processed.
+    bytes: AtomicU64,
+    /// Time spent processing items, summed over all workers, in nanoseconds.
+    busy: AtomicU64,
+    /// Start of the first and end of the last processed item.
+    span: Mutex<Option<(Instant, Instant)>>,
+}
+
+impl Metrics {
+    /// Record that a stage processed some items, starting at the given instant and ending now.
+    pub fn record(&self, stage: Stage, start: Instant, items: u64, bytes: u64) {
+        let end = Instant::now();
+        let metrics = &self.stages[stage as usize];
+        metrics.items.fetch_add(items, Ordering::Relaxed);
+        metrics.bytes.fetch_add(bytes, Ordering::Relaxed);
+        metrics
+            .busy
+            .fetch_add((end - start).as_nanos() as u64, Ordering::Relaxed);
+        let mut span = metrics.span.lock().expect("Stage workers don't panic");
+        *span = Some(match *span {
+            Some((first, last)) => (first.min(start), last.max(end)),
+            None => (start, end),
+        });
+    }
+
+    /// Amount of items a stage processed.
+    pub fn items(&self, stage: Stage) -> u64 {
+        self.stages[stage as usize].items.load(Ordering::Relaxed)
+    }
+
+    /// Amount of receipt bytes a stage processed.
+    pub fn bytes(&self, stage: Stage) -> u64 {
+        self.stages[stage as usize].bytes.load(Ordering::Relaxed)
+    }
+
+    /// Wall time between the first item entering any stage and the last item leaving any stage.
+    fn wall_time(&self) -> Duration {
+        let spans = self
+            .stages
+            .iter()
+            .filter_map(|s| *s.span.lock().expect("Stage workers don't panic"));
+        let (first, last) = spans.fold((None, None), |(first, last), (start, end)| {
+            (
+                Some(first.map_or(start, |f: Instant| f.min(start))),
+                Some(last.map_or(end, |l: Instant| l.max(end))),
+            )
+        });
+        match (first, last) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Per stage timing report, with one line per stage.
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10} {:>10} {:>14} {:>12} {:>12} {:>14}",
+            "stage", "items", "bytes", "wall (s)", "busy (s)", "items/s"
+        )?;
+        for stage in Stage::ALL {
+            let metrics = &self.stages[stage as usize];
+            let items = metrics.items.load(Ordering::Relaxed);
+            let wall = metrics
+                .span
+                .lock()
+                .expect("Stage workers don't panic")
+                .map(|(first, last)| last - first)
+                .unwrap_or_default();
+            let busy = Duration::from_nanos(metrics.busy.load(Ordering::Relaxed));
+            writeln!(
+                f,
+                "{:<10} {:>10} {:>14} {:>12.3} {:>12.3} {:>14.0}",
+                stage.name(),
+                items,
+                metrics.bytes.load(Ordering::Relaxed),
+                wall.as_secs_f64(),
+                busy.as_secs_f64(),
+                per_second(items, wall)
+            )?;
+        }
+        let receipts = self.stages[Stage::Parse as usize]
+            .items
+            .load(Ordering::Relaxed);
+        let wall = self.wall_time();
+        write!(
+            f,
+            "{receipts} receipts in {:.3}s ({:.0} receipts/s)",
+            wall.as_secs_f64(),
+            per_second(receipts, wall)
+        )
+    }
+}
+
+/// Throughput of a stage, 0 if no time has passed.
+fn per_second(items: u64, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        0.
+    } else {
+        items as f64 / duration.as_secs_f64()
+    }
+}
+
+/// Load the receipts of the given periods from the source, and aggregate them per node. Fails if
+/// any receipt can't be used, see [`load_partial`] to leave them out instead.
+pub fn load(
+    source: &dyn ReceiptSource,
+    periods: &[u32],
+    config: PipelineConfig,
+    metrics: &Metrics,
+    events: &dyn EventHandler,
+) -> Result<NodeReceipts, SourceError> {
+    let (node_receipts, invalid) = load_partial(source, periods, config, metrics, events)?;
+    match invalid.as_slice() {
+        [] => Ok(node_receipts),
+        [receipt] => Err(receipt.to_string().into()),
+        [receipt, rest @ ..] => {
+            Err(format!("{receipt}, and {} other invalid receipt(s)", rest.len()).into())
+        }
+    }
+}
+
+/// Load the receipts of the given periods from the source, and aggregate them per node. Receipts
+/// which can't be read, parsed or validated are left out, and returned by period and origin.
+pub fn load_partial(
+    source: &dyn ReceiptSource,
+    periods: &[u32],
+    config: PipelineConfig,
+    metrics: &Metrics,
+    events: &dyn EventHandler,
+) -> Result<(NodeReceipts, Vec<InvalidReceipt>), SourceError> {
+    thread::scope(|s| {
+        let (discovered_tx, discovered_rx) = mpsc::sync_channel(STAGE_QUEUE_SIZE);
+        let (read_tx, read_rx) = mpsc::sync_channel(STAGE_QUEUE_SIZE);
+        let (parsed_tx, parsed_rx) = mpsc::sync_channel(STAGE_QUEUE_SIZE);
+        let (validated_tx, validated_rx) = mpsc::sync_channel(STAGE_QUEUE_SIZE);
+
+        for &period in periods {
+            let discovered_tx = discovered_tx.clone();
+            s.spawn(move || discover(source, period, discovered_tx, metrics, events));
+        }
+        // The receivers are shared by the workers of a stage, and dropped once the last worker
+        // stops. This way a stage which stops early also stops the stages before it.
+        let discovered_rx = Arc::new(Mutex::new(discovered_rx));
+        let read_rx = Arc::new(Mutex::new(read_rx));
+        for _ in 0..config.read_workers.get() {
+            let (discovered_rx, read_tx) = (discovered_rx.clone(), read_tx.clone());
+            s.spawn(move || {
+                worker(&discovered_rx, read_tx, |receipt| {
+                    let start = Instant::now();
+                    let raw = source.read(&receipt).map_err(|e| {
+                        Failure::Invalid(InvalidReceipt::new(
+                            receipt.period,
+                            &receipt.location(),
+                            e,
+                        ))
+                    })?;
+                    metrics.record(Stage::Read, start, 1, raw.size() as u64);
+                    events.handle(Event::Read {
+                        origin: &raw.origin,
+                        bytes: raw.size(),
+                    });
+                    Ok(raw)
+                })
+            });
+        }
+        for _ in 0..config.parse_workers.get() {
+            let (read_rx, parsed_tx) = (read_rx.clone(), parsed_tx.clone());
+            s.spawn(move || worker(&read_rx, parsed_tx, |raw| parse(raw, metrics, events)));
+        }
+        // Drop the originals, so channels close once all workers are done.
+        drop((discovered_tx, discovered_rx, read_rx, read_tx, parsed_tx));
+        s.spawn(move || validate(parsed_rx, validated_tx, metrics));
+
+        aggregate(validated_rx, metrics, events)
+    })
+}
+
+/// Discover stage: find all receipts in a period.
+fn discover(
+    source: &dyn ReceiptSource,
+    period: u32,
+    tx: SyncSender<Result<ReceiptRef, Failure>>,
+    metrics: &Metrics,
+    events: &dyn EventHandler,
+) {
+    let start = Instant::now();
+    match source.discover(period, events) {
+        Ok(receipts) => {
+            metrics.record(Stage::Discover, start, receipts.len() as u64, 0);
+            events.handle(Event::Discovered {
+                period,
+                receipts: receipts.len(),
+            });
+            for receipt in receipts {
+                if cancel::is_interrupted() || tx.send(Ok(receipt)).is_err() {
+                    return;
+                }
+            }
+        }
+        Err(e) => {
+            let _ = tx.send(Err(Failure::Fatal(e)));
+        }
+    }
+}
+
+/// Run a stage on a worker thread. The worker takes items from the shared receiver until it is
+/// closed, and stops early if the next stage hung up or the process is interrupted. Errors of
+/// previous stages are passed on.
+fn worker<I, O>(
+    rx: &Mutex<Receiver<Result<I, Failure>>>,
+    tx: SyncSender<Result<O, Failure>>,
+    f: impl Fn(I) -> Result<O, Failure>,
+) {
+    while !cancel::is_interrupted() {
+        // Only hold the lock while receiving, so other workers can take items while this one is
+        // busy.
+        let item = match rx.lock().expect("Stage workers don't panic").recv() {
+            Ok(item) => item,
+            Err(_) => return,
+        };
+        if tx.send(item.and_then(&f)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Parse stage: deserialize the raw receipt data.
+///
+/// Fixup receipts lack most fields of a minting receipt, so receipts which are not a minting
+/// receipt are parsed as fixup receipt. If that fails too, the minting receipt error is returned.
+/// Strings borrow from the raw data while parsing, and are interned so the data can be dropped
+/// without copying them for every receipt.
+fn parse(raw: RawReceipt, metrics: &Metrics, events: &dyn EventHandler) -> Result<Parsed, Failure> {
+    let start = Instant::now();
+    let parsed = match raw.parse_json::<ReceiptSummary>() {
+        Ok(receipt) => Parsed::Minting(ParsedReceipt {
+            period: raw.period,
+            origin: raw.origin.clone(),
+            receipt: receipt.into_interned(),
+            fixups: Vec::new(),
+        }),
+        Err(e) => match raw.parse::<FixupReceipt>() {
+            Ok(fixup) => Parsed::Fixup(raw.period, raw.origin.clone(), fixup.into_interned()),
+            Err(_) => {
+                return Err(Failure::Invalid(InvalidReceipt {
+                    period: raw.period,
+                    origin: raw.origin.clone(),
+                    field: raw.error_field(&e),
+                    error: e.to_string(),
+                }))
+            }
+        },
+    };
+    metrics.record(Stage::Parse, start, 1, raw.size() as u64);
+    events.handle(Event::Parsed {
+        origin: &raw.origin,
+    });
+    Ok(parsed)
+}
+
+/// Validate stage: check that receipts can be used to calculate the expected reward.
+fn validate(
+    rx: Receiver<Result<Parsed, Failure>>,
+    tx: SyncSender<Result<Parsed, Failure>>,
+    metrics: &Metrics,
+) {
+    for parsed in rx {
+        let start = Instant::now();
+        let validated = parsed.and_then(|parsed| {
+            let (period, origin, receipt_period) = match &parsed {
+                Parsed::Minting(parsed) => (
+                    parsed.period,
+                    &parsed.origin,
+                    parsed.receipt.period.offset(),
+                ),
+                Parsed::Fixup(period, origin, fixup) => (*period, origin, fixup.period.offset()),
+            };
+            let invalid = |field: &str, error: String| {
+                Failure::Invalid(InvalidReceipt {
+                    period,
+                    origin: origin.clone(),
+                    field: Some(field.to_string()),
+                    error,
+                })
+            };
+            if receipt_period != period as i64 {
+                return Err(invalid(
+                    "period",
+                    format!(
+                        "receipt is for period {receipt_period} but was found in period {period}"
+                    ),
+                ));
+            }
+            if let Parsed::Minting(parsed) = &parsed {
+                if parsed.receipt.tft_connection_price == 0 {
+                    return Err(invalid(
+                        "tft_connection_price",
+                        "TFT connection price is 0".into(),
+                    ));
+                }
+            }
+            Ok(parsed)
+        });
+        metrics.record(Stage::Validate, start, 1, 0);
+        if tx.send(validated).is_err() {
+            return;
+        }
+    }
+}
+
+/// Aggregate stage: group the receipts per node and period.
+///
+/// Receipts arrive in any order, if a node has multiple receipts for the same period the one
+/// loaded from the first origin in lexicographical order is kept, so the result does not depend
+/// on the order in which workers finish. Fixup receipts are added to the receipt of their node
+/// and period once all receipts are in.
+fn aggregate(
+    rx: Receiver<Result<Parsed, Failure>>,
+    metrics: &Metrics,
+    events: &dyn EventHandler,
+) -> Result<(NodeReceipts, Vec<InvalidReceipt>), SourceError> {
+    let mut nodes = NodeReceipts::new();
+    let mut fixups = Vec::new();
+    let mut invalid = Vec::new();
+    for parsed in rx {
+        let start = Instant::now();
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(Failure::Fatal(e)) => return Err(e),
+            Err(Failure::Invalid(receipt)) => {
+                events.handle(Event::InvalidReceipt {
+                    period: receipt.period,
+                    origin: &receipt.origin,
+                    error: &receipt.error,
+                });
+                invalid.push(receipt);
+                continue;
+            }
+        };
+        let parsed = match parsed {
+            Parsed::Minting(parsed) => parsed,
+            Parsed::Fixup(period, origin, fixup) => {
+                fixups.push((origin, period, fixup));
+                metrics.record(Stage::Aggregate, start, 1, 0);
+                continue;
+            }
+        };
+        let (node_id, period) = (parsed.receipt.node_id, parsed.period);
+        let periods = nodes.entry(node_id).or_default();
+        match periods.get(&period) {
+            Some(existing) => {
+                let replace = parsed.origin < existing.origin;
+                let (kept, ignored) = if replace {
+                    (&parsed.origin, &existing.origin)
+                } else {
+                    (&existing.origin, &parsed.origin)
+                };
+                events.handle(Event::DuplicateReceipt {
+                    node_id,
+                    period,
+                    kept,
+                    ignored,
+                });
+                if replace {
+                    periods.insert(period, parsed);
+                }
+            }
+            None => {
+                periods.insert(period, parsed);
+                events.handle(Event::Aggregated { node_id, period });
+            }
+        }
+        metrics.record(Stage::Aggregate, start, 1, 0);
+    }
+
+    fixups.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    for (origin, period, fixup) in fixups {
+        let node_id = fixup.node_id;
+        match nodes
+            .get_mut(&node_id)
+            .and_then(|periods| periods.get_mut(&period))
+        {
+            Some(parsed) => parsed.fixups.push(fixup),
+            None => events.handle(Event::UnmatchedFixup {
+                node_id,
+                period,
+                origin: &origin,
+            }),
+        }
+    }
+    invalid.sort_unstable_by(|a, b| (a.period, &a.origin).cmp(&(b.period, &b.origin)));
+    Ok((nodes, invalid))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::source::DirectorySource;
+
+    /// Runs which use the interrupted flag can't overlap.
+    static RUNS: Mutex<()> = Mutex::new(());
+
+    /// A receipts directory, removed when the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("trace_titans-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+
+        /// Write a receipt file in the directory of a period.
+        fn write(&self, period: u32, name: &str, receipt: &str) {
+            let dir = self.0.join(period.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(name), receipt).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Start and end of period 57.
+    const PERIOD: &str = r#"{"start": 1672461160, "end": 1675092040}"#;
+
+    /// A minting receipt of period 57, minted `tft` TFT units at the given connection price.
+    fn receipt(node_id: u32, tft: u64, connection_price: u64) -> String {
+        format!(
+            r#"{{"period": {PERIOD}, "node_id": {node_id}, "twin_id": 1, "farm_id": 1,
+            "farm_name": "farm", "stellar_payout_address": "G", "measured_uptime": 2630880,
+            "tft_connection_price": {connection_price},
+            "cloud_units": {{"cu": 1.0, "su": 2.0, "nu": 1.0}},
+            "resource_units": {{"cru": 8, "mru": 32, "hru": 0, "sru": 1000}},
+            "resource_utilization": {{"cru": 0, "mru": 0, "hru": 0, "sru": 0, "ip": 0}},
+            "reward": {{"musd": 1, "tft": {tft}}}, "node_type": "CERTIFIED"}}"#
+        )
+    }
+
+    /// A fixup receipt of period 57, paying `tft` TFT units.
+    fn fixup(node_id: u32, tft: u64) -> String {
+        let units = r#"{"cu": 1.0, "su": 2.0, "nu": 1.0}"#;
+        let reward = format!(r#"{{"musd": 0, "tft": {tft}}}"#);
+        format!(
+            r#"{{"period": {PERIOD}, "node_id": {node_id}, "minted_cloud_units": {units},
+            "correct_cloud_units": {units}, "fixup_cloud_units": {units},
+            "minted_reward": {reward}, "correct_reward": {reward}, "fixup_reward": {reward},
+            "stellar_payout_address": "G"}}"#
+        )
+    }
+
+    /// Load period 57 with the given amount of workers per stage, with the events which are not
+    /// about progress.
+    fn load(dir: &TempDir, workers: usize) -> (NodeReceipts, Vec<InvalidReceipt>, Vec<String>) {
+        let _run = RUNS.lock().unwrap_or_else(|e| e.into_inner());
+        let workers = NonZeroUsize::new(workers).unwrap();
+        let config = PipelineConfig {
+            read_workers: workers,
+            parse_workers: workers,
+        };
+        let events = Mutex::new(Vec::new());
+        let name = |origin: &str| origin.rsplit('/').next().unwrap().to_string();
+        let handler = |event: Event| {
+            let event = match event {
+                Event::DuplicateReceipt { kept, ignored, .. } => {
+                    format!("duplicate, kept {} over {}", name(kept), name(ignored))
+                }
+                Event::UnmatchedFixup {
+                    node_id, origin, ..
+                } => {
+                    format!("unmatched fixup of node {node_id} in {}", name(origin))
+                }
+                _ => return,
+            };
+            events.lock().unwrap().push(event);
+        };
+        let source = DirectorySource::new(&dir.0);
+        let (nodes, invalid) =
+            load_partial(&source, &[57], config, &Metrics::default(), &handler).unwrap();
+        (nodes, invalid, events.into_inner().unwrap())
+    }
+
+    #[test]
+    fn duplicates_keep_the_first_origin() {
+        let dir = TempDir::new("pipeline-duplicates");
+        dir.write(57, "b.json", &receipt(1, 20, 100));
+        dir.write(57, "a.json", &receipt(1, 10, 100));
+        dir.write(57, "c.json", &receipt(1, 30, 100));
+        // The result doesn't depend on which worker finishes first.
+        for workers in [1, 4] {
+            let (nodes, invalid, events) = load(&dir, workers);
+            assert!(invalid.is_empty());
+            let kept = &nodes[&1][&57];
+            assert!(kept.origin.ends_with("a.json"), "{}", kept.origin);
+            assert_eq!(kept.receipt.reward.tft, 10);
+            // Which receipts are compared depends on the order they arrive in, but the first
+            // origin is never ignored.
+            assert_eq!(events.len(), 2, "{events:?}");
+            assert!(
+                events.iter().all(|e| !e.ends_with("over a.json")),
+                "{events:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn fixups_are_added_in_the_order_of_their_origin() {
+        let dir = TempDir::new("pipeline-fixups");
+        dir.write(57, "1.json", &receipt(1, 10, 100));
+        dir.write(57, "fixup-b.json", &fixup(1, 2));
+        dir.write(57, "fixup-a.json", &fixup(1, 1));
+        dir.write(57, "fixup-c.json", &fixup(2, 3));
+        for workers in [1, 4] {
+            let (nodes, _, events) = load(&dir, workers);
+            let parsed = &nodes[&1][&57];
+            let fixups = parsed.fixups.iter().map(|f| f.fixup_reward.tft);
+            assert_eq!(fixups.collect::<Vec<_>>(), [1, 2]);
+            assert_eq!(parsed.fixup_payout(), 3);
+            assert_eq!(events, ["unmatched fixup of node 2 in fixup-c.json"]);
+        }
+    }
+
+    #[test]
+    fn invalid_receipts_are_left_out() {
+        let dir = TempDir::new("pipeline-invalid");
+        dir.write(57, "1.json", &receipt(1, 10, 100));
+        dir.write(57, "2.json", &receipt(2, 10, 0));
+        dir.write(57, "3.json", "{\"node_id\": 3");
+        // A receipt of period 57 in the directory of period 56 isn't loaded with period 57.
+        dir.write(56, "4.json", &receipt(4, 10, 100));
+        let (nodes, invalid, _) = load(&dir, 2);
+        assert_eq!(nodes.keys().copied().collect::<Vec<_>>(), [1]);
+        let invalid = invalid
+            .iter()
+            .map(|r| (r.origin.rsplit('/').next().unwrap(), r.field.as_deref()));
+        assert_eq!(
+            invalid.collect::<Vec<_>>(),
+            [("2.json", Some("tft_connection_price")), ("3.json", None)]
+        );
+
+        let source = DirectorySource::new(&dir.0);
+        let periods = [56, 57];
+        let config = PipelineConfig::default();
+        let _run = RUNS.lock().unwrap_or_else(|e| e.into_inner());
+        let e = super::load(
+            &source,
+            &periods,
+            config,
+            &Metrics::default(),
+            &crate::events::ignore,
+        );
+        let e = e.err().unwrap().to_string();
+        assert!(e.ends_with("and 2 other invalid receipt(s)"), "{e}");
+    }
+
+    #[test]
+    fn interrupted_runs_stop_early() {
+        let dir = TempDir::new("pipeline-interrupted");
+        for node_id in 0..50 {
+            dir.write(57, &format!("{node_id}.json"), &receipt(node_id, 10, 100));
+        }
+        let _run = RUNS.lock().unwrap_or_else(|e| e.into_inner());
+        let config = PipelineConfig {
+            read_workers: NonZeroUsize::MIN,
+            parse_workers: NonZeroUsize::MIN,
+        };
+        // Interrupted once the first receipt is read.
+        let handler = |event: Event| {
+            if let Event::Read { .. } = event {
+                cancel::set_interrupted(true);
+            }
+        };
+        let metrics = Metrics::default();
+        let source = DirectorySource::new(&dir.0);
+        let result = load_partial(&source, &[57], config, &metrics, &handler);
+        cancel::set_interrupted(false);
+
+        // The receipts which made it through are still returned.
+        let (nodes, invalid) = result.unwrap();
+        assert!(invalid.is_empty());
+        assert!(nodes.len() <= 1, "{} nodes loaded", nodes.len());
+        assert_eq!(metrics.items(Stage::Discover), 50);
+        assert_eq!(metrics.items(Stage::Read), 1);
+        assert_eq!(metrics.items(Stage::Aggregate), nodes.len() as u64);
+    }
+}
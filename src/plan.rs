@@ -0,0 +1,92 @@
+//! Plans of the commands with side effects, printed instead of running them with `--plan`.
+//!
+//! A plan lists every file which would be written and every change to the ledger, so an operator
+//! can review the effects of a run before making it. Commands prepare everything as usual, and
+//! only skip the writes.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use trace_titans::Error;
+
+/// What a command would do.
+#[derive(Default)]
+pub struct Plan {
+    lines: Vec<String>,
+}
+
+impl Plan {
+    /// Add a line to the plan.
+    pub fn note(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into());
+    }
+
+    /// Add files which would be written, as a summary line and a line for every file which would
+    /// be created or changed. Files which would be written with the content they already have are
+    /// only counted.
+    pub fn files(&mut self, what: &str, files: &[(PathBuf, Vec<u8>)]) {
+        let (mut new, mut changed) = (Vec::new(), Vec::new());
+        for (path, data) in files {
+            match fs::read(path) {
+                Ok(current) if current == *data => {}
+                Ok(current) => changed.push(format!(
+                    "    change {} ({} bytes, now {} bytes)",
+                    path.display(),
+                    data.len(),
+                    current.len()
+                )),
+                Err(_) => new.push(format!(
+                    "    create {} ({} bytes)",
+                    path.display(),
+                    data.len()
+                )),
+            }
+        }
+        let bytes = files.iter().map(|(_, data)| data.len()).sum::<usize>();
+        self.note(format!(
+            "{what}: {} file(s) of {bytes} bytes, {} new, {} changed, {} unchanged",
+            files.len(),
+            new.len(),
+            changed.len(),
+            files.len() - new.len() - changed.len()
+        ));
+        self.lines.extend(new);
+        self.lines.extend(changed);
+    }
+
+    /// Print the plan on stdout.
+    pub fn print(&self, command: &str) {
+        println!("Plan of {command}, nothing is changed:");
+        if self.lines.is_empty() {
+            println!("  nothing to do");
+        }
+        for line in &self.lines {
+            println!("  {line}");
+        }
+    }
+}
+
+/// All files in a directory and its subdirectories, by their path relative to the directory, with
+/// their content.
+pub fn read_tree(dir: &Path) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(rel) = dirs.pop() {
+        let abs = dir.join(&rel);
+        let entries =
+            fs::read_dir(&abs).map_err(|e| format!("could not read {}: {e}", abs.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let rel = rel.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                dirs.push(rel);
+            } else {
+                files.push((rel, fs::read(entry.path())?));
+            }
+        }
+    }
+    files.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
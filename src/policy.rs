@@ -0,0 +1,277 @@
+//! The farming policy the expected payouts are calculated with.
+//!
+//! The reward rates of a policy can change on chain, so the rates used for every period are
+//! embedded in the outputs which are kept, which keeps them interpretable after a change. The
+//! rates and minimal uptime of [`TITAN`] are used unless [`Policies`] has those of a period, e.g.
+//! from the chain.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{Event, EventHandler},
+    pipeline::NodeReceipts,
+    receipt::{ResourceRewards, DEFAULT_FARMING_POLICY_ID},
+    Error, CERTIFIED_NODE_TYPE, PERCENTAGE_PRECISION,
+};
+
+/// A farming policy with its reward rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FarmingPolicy {
+    /// Id of the policy on chain.
+    pub id: u32,
+    /// Reward rates in mUSD per unit.
+    pub rewards: ResourceRewards,
+    /// Uptime percentage below which no payout is due for a period, scaled by
+    /// [`PERCENTAGE_PRECISION`].
+    pub minimal_uptime: u32,
+}
+
+/// Farming policy 2, taken from chain.
+pub const TITAN: FarmingPolicy = FarmingPolicy {
+    id: 2,
+    rewards: ResourceRewards {
+        cu: 3000,
+        su: 1250,
+        nu: 38,
+        ipv4: 6,
+    },
+    minimal_uptime: 95 * PERCENTAGE_PRECISION,
+};
+
+/// What the expected payouts are calculated with: the reward rates and minimal uptime of the titan
+/// policy and the node types of certified nodes. The default uses [`TITAN`] and
+/// [`CERTIFIED_NODE_TYPE`].
+#[derive(Debug, Default, Clone)]
+pub struct Policies {
+    /// Reward rates of the titan policy by period. Periods which are not included use the rates
+    /// of [`TITAN`].
+    pub titan_rates: BTreeMap<u32, ResourceRewards>,
+    /// Minimal uptime of the titan policy by period, scaled by [`PERCENTAGE_PRECISION`]. Periods
+    /// which are not included use the minimal uptime of [`TITAN`].
+    pub minimal_uptime: BTreeMap<u32, u32>,
+    /// node_type values of certified nodes, empty to use [`CERTIFIED_NODE_TYPE`].
+    pub certified_node_types: Vec<String>,
+}
+
+impl Policies {
+    /// The policy the expected payouts of a period are calculated with.
+    pub fn for_period(&self, period: u32) -> FarmingPolicy {
+        FarmingPolicy {
+            id: TITAN.id,
+            rewards: self
+                .titan_rates
+                .get(&period)
+                .copied()
+                .unwrap_or(TITAN.rewards),
+            minimal_uptime: self
+                .minimal_uptime
+                .get(&period)
+                .copied()
+                .unwrap_or(TITAN.minimal_uptime),
+        }
+    }
+
+    /// Use the rates and minimal uptime of a policy in a period.
+    pub fn insert(&mut self, period: u32, policy: FarmingPolicy) {
+        self.titan_rates.insert(period, policy.rewards);
+        self.minimal_uptime.insert(period, policy.minimal_uptime);
+    }
+
+    /// Indicates a node_type value is the one of certified nodes.
+    pub fn is_certified(&self, node_type: &str) -> bool {
+        if self.certified_node_types.is_empty() {
+            node_type == CERTIFIED_NODE_TYPE
+        } else {
+            self.certified_node_types.iter().any(|t| t == node_type)
+        }
+    }
+
+    /// Snapshot of the policy used for every given period.
+    pub fn snapshot(&self, periods: &[u32]) -> Vec<PolicySnapshot> {
+        periods
+            .iter()
+            .map(|&period| {
+                let policy = self.for_period(period);
+                PolicySnapshot {
+                    period,
+                    farming_policy: policy.id,
+                    cu: policy.rewards.cu,
+                    su: policy.rewards.su,
+                    nu: policy.rewards.nu,
+                    ipv4: policy.rewards.ipv4,
+                    minimal_uptime: policy.minimal_uptime,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Which signal decides if a node is a titan.
+///
+/// Titans are certified nodes and should be on the titan farming policy, so the farming policy
+/// and the node type of a receipt normally agree. When they don't, e.g. a DIY node on farming
+/// policy 2, one of them has to win.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TitanSignal {
+    /// A node is a titan if it is on the titan policy, or certified on the default policy.
+    #[default]
+    Either,
+    /// A node is a titan if it is on the titan policy.
+    Policy,
+    /// A node is a titan if it is certified, on the titan or the default policy.
+    NodeType,
+}
+
+impl TitanSignal {
+    pub const ALL: [TitanSignal; 3] = [
+        TitanSignal::Either,
+        TitanSignal::Policy,
+        TitanSignal::NodeType,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TitanSignal::Either => "either",
+            TitanSignal::Policy => "policy",
+            TitanSignal::NodeType => "node-type",
+        }
+    }
+
+    /// Indicates if a node with the given farming policy and certification is a titan.
+    pub fn is_titan(self, farming_policy: u32, certified: bool) -> bool {
+        let titan_policy = farming_policy == TITAN.id;
+        let default_policy = farming_policy == DEFAULT_FARMING_POLICY_ID;
+        match self {
+            TitanSignal::Either => titan_policy || (default_policy && certified),
+            TitanSignal::Policy => titan_policy,
+            TitanSignal::NodeType => certified && (titan_policy || default_policy),
+        }
+    }
+}
+
+/// What happens with periods in which a node is up for less than the minimal uptime of the titan
+/// policy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UptimeSla {
+    /// The period is marked, the expected payout is scaled to the uptime like any other period.
+    #[default]
+    Flag,
+    /// The period is marked, and no payout is expected.
+    Enforce,
+}
+
+impl UptimeSla {
+    pub const ALL: [UptimeSla; 2] = [UptimeSla::Flag, UptimeSla::Enforce];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UptimeSla::Flag => "flag",
+            UptimeSla::Enforce => "enforce",
+        }
+    }
+}
+
+impl FromStr for UptimeSla {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UptimeSla::ALL
+            .into_iter()
+            .find(|sla| sla.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown uptime SLA handling {s}, expected one of {}",
+                    UptimeSla::ALL.map(UptimeSla::name).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for UptimeSla {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Indicates if the farming policy and the certification of a node disagree on whether it is a
+/// titan.
+pub fn certification_conflict(farming_policy: u32, certified: bool) -> bool {
+    TitanSignal::Policy.is_titan(farming_policy, certified)
+        != TitanSignal::NodeType.is_titan(farming_policy, certified)
+}
+
+impl FromStr for TitanSignal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TitanSignal::ALL
+            .into_iter()
+            .find(|signal| signal.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown titan signal {s}, expected one of {}",
+                    TitanSignal::ALL.map(TitanSignal::name).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for TitanSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The parameters of the policy used for a period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicySnapshot {
+    pub period: u32,
+    pub farming_policy: u32,
+    /// Reward rates in mUSD per unit.
+    pub cu: u64,
+    pub su: u64,
+    pub nu: u64,
+    pub ipv4: u64,
+    /// Minimal uptime percentage, scaled by [`PERCENTAGE_PRECISION`]. Older snapshots lack it.
+    #[serde(default)]
+    pub minimal_uptime: u32,
+}
+
+/// Report the receipts which don't declare their farming policy, and are assumed to have
+/// [`DEFAULT_FARMING_POLICY_ID`], to the handler. Only old receipts should lack it, so with
+/// `forbid` any such receipt is an error instead.
+pub fn check_defaults(
+    node_receipts: &NodeReceipts,
+    forbid: bool,
+    events: &dyn EventHandler,
+) -> Result<(), Error> {
+    // period -> (receipts without policy, origin of the first one)
+    let mut defaults = BTreeMap::<u32, (usize, &str)>::new();
+    for parsed in node_receipts.values().flat_map(BTreeMap::values) {
+        if parsed.receipt.declared_farming_policy_id.is_none() {
+            defaults
+                .entry(parsed.period)
+                .or_insert((0, &parsed.origin))
+                .0 += 1;
+        }
+    }
+
+    if forbid {
+        if let Some((period, (count, origin))) = defaults.first_key_value() {
+            return Err(format!(
+                "period {period}: {count} receipt(s) without farming_policy_id, e.g. {origin}"
+            )
+            .into());
+        }
+    }
+    for (period, (receipts, _)) in defaults {
+        events.handle(Event::DefaultFarmingPolicy {
+            period,
+            receipts,
+            farming_policy: DEFAULT_FARMING_POLICY_ID,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! The stable surface of the library, to import with `use trace_titans::prelude::*`.
+//!
+//! Everything a tool needs to load receipts, calculate what they should have paid and aggregate
+//! them per node is exported here. Items are only removed or changed in a breaking way with a new
+//! minor version while the version is 0.x, and a new major version after.
+
+pub use crate::{
+    aggregate_node_results, calculate_expected_reward,
+    currency::{
+        format_diff_tft, format_tft, format_tft_styled, format_usd, parse_tft, NumberStyle,
+        TFT_PRECISION,
+    },
+    events::{Event, EventHandler},
+    findings::{Finding, FindingKind},
+    format_percentage, load_receipts,
+    period::Period,
+    pipeline::{NodeReceipts, ParsedReceipt},
+    policy::{FarmingPolicy, Policies, TitanSignal, UptimeSla},
+    receipt::{
+        CloudUnits, FixupReceipt, MintingReceipt, ReceiptSummary, ResourceRewards, ResourceUnits,
+        Reward,
+    },
+    reward::{expected_rewards, RewardBreakdown, RewardInputs},
+    source::{CsvSource, DirectorySource, GitSource, ReceiptSource, SourceError},
+    tolerance::Tolerances,
+    Error, NodePeriodResult, NodeResult, PERCENTAGE_PRECISION,
+};
@@ -0,0 +1,133 @@
+//! Historical TFT prices, to value the amounts of the report in USD.
+//!
+//! Farmers who account in fiat care about what a shortfall was worth when it should have been
+//! paid, not what it is worth today. Every period is valued at the TFT price of the date its period
+//! ends, which is when minting pays out.
+
+use std::{collections::BTreeMap, fs};
+
+use serde::Deserialize;
+
+use trace_titans::{
+    currency::{Amount, USD},
+    date,
+    period::Period,
+    source, Error, NodeResult, TFT_PRECISION,
+};
+
+use crate::http;
+
+/// Price of 1 TFT in USD units, by date.
+#[derive(Debug, Default)]
+pub struct Prices {
+    dates: BTreeMap<String, u64>,
+}
+
+/// Response of a CoinGecko style `market_chart` API.
+#[derive(Deserialize)]
+struct MarketChart {
+    /// Unix timestamp in milliseconds and price of every point.
+    prices: Vec<(i64, f64)>,
+}
+
+impl Prices {
+    /// Load the prices from a CSV file with the columns `date` and `usd`, or from the URL of a
+    /// CoinGecko style `market_chart` API. With the API, the last price of every date is used.
+    /// Fails if one of the periods has no price at the date it ends.
+    pub fn load(from: &str, periods: &[u32]) -> Result<Self, Error> {
+        let prices = if is_url(from) {
+            Prices::fetch(from)?
+        } else {
+            Prices::read(from)?
+        };
+        let missing = periods
+            .iter()
+            .filter(|&&period| prices.at(period).is_none())
+            .map(|&period| format!("{period} ({})", end_date(period)))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(format!(
+                "{from} has no TFT price at the end of period(s) {}",
+                missing.join(", ")
+            )
+            .into());
+        }
+        Ok(prices)
+    }
+
+    fn read(path: &str) -> Result<Self, Error> {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+        let mut records = source::parse_csv(&content).into_iter();
+        let header = records.next().ok_or_else(|| format!("{path} is empty"))?;
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|c| c.trim() == name)
+                .ok_or_else(|| format!("{path} has no {name} column"))
+        };
+        let (date_idx, usd_idx) = (column("date")?, column("usd")?);
+
+        let mut dates = BTreeMap::new();
+        for (line, record) in records.enumerate() {
+            let field = |idx: usize, name: &str| {
+                record
+                    .get(idx)
+                    .map(|v| v.trim())
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| format!("missing {name} on line {} of {path}", line + 2))
+            };
+            let date = field(date_idx, "date")?;
+            let usd = field(usd_idx, "usd")?;
+            let price = Amount::parse(usd, &USD)
+                .ok_or_else(|| format!("invalid price {usd} on line {}", line + 2))?;
+            if dates.insert(date.to_string(), price.units as u64).is_some() {
+                return Err(format!("date {date} is listed twice in {path}").into());
+            }
+        }
+        Ok(Prices { dates })
+    }
+
+    fn fetch(url: &str) -> Result<Self, Error> {
+        let chart = serde_json::from_slice::<MarketChart>(&http::get(url)?)
+            .map_err(|e| format!("invalid prices from {url}: {e}"))?;
+        let mut dates = BTreeMap::new();
+        for (timestamp, price) in chart.prices {
+            let price = Amount::parse(&format!("{price:.*}", USD.decimals as usize), &USD)
+                .ok_or_else(|| format!("invalid price {price} from {url}"))?;
+            dates.insert(date::format_date(timestamp / 1000), price.units as u64);
+        }
+        Ok(Prices { dates })
+    }
+
+    /// Price of 1 TFT in USD units at the end of a period.
+    pub fn at(&self, period: u32) -> Option<u64> {
+        self.dates.get(&end_date(period)).copied()
+    }
+
+    /// Value of the amount still to send to a node in USD units, with the difference of every
+    /// period valued at the price of that period. Differences within the tolerance, which are
+    /// reported as 0 TFT, are worth nothing.
+    pub fn difference_usd(&self, result: &NodeResult, difference: i64) -> i64 {
+        if difference == 0 {
+            return 0;
+        }
+        result
+            .periods()
+            .map(|(period, r)| {
+                let owed = r.expected_payout as i128 - r.actual_payout as i128;
+                owed * self.at(period).unwrap_or(0) as i128 / TFT_PRECISION as i128
+            })
+            .sum::<i128>() as i64
+    }
+}
+
+/// Indicates the prices are fetched from an API rather than read from a file.
+pub fn is_url(from: &str) -> bool {
+    from.starts_with("http://") || from.starts_with("https://")
+}
+
+/// Date at which a period ends.
+fn end_date(period: u32) -> String {
+    date::format_date(Period::at_offset(period as i64).end())
+}
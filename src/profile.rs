@@ -0,0 +1,178 @@
+//! Profile of the fields of the receipts of every period.
+//!
+//! When minting changes its output, receipts gain, lose or change fields, which the receipt types
+//! of this tool need to follow. The profile works on the raw JSON, so it also works on receipts
+//! the tool can't parse yet, and shows how often every field is present and what values it has.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use serde_json::Value;
+
+use trace_titans::{
+    source::{DirectorySource, ReceiptSource},
+    Error,
+};
+
+use crate::cli::Args;
+
+/// Fields with at most this many distinct values are listed with the count of every value.
+const MAX_LISTED_VALUES: usize = 10;
+
+/// What was seen of a single field in the receipts of a period.
+#[derive(Default)]
+struct FieldProfile {
+    /// Amount of receipts which have the field.
+    present: usize,
+    /// Kinds of JSON values seen.
+    kinds: BTreeSet<&'static str>,
+    /// Range of numeric values.
+    range: Option<(f64, f64)>,
+    /// Count of every distinct scalar value, until there are more than [`MAX_LISTED_VALUES`].
+    values: BTreeMap<String, usize>,
+    /// Indicates there were too many distinct values to list.
+    many_values: bool,
+}
+
+impl FieldProfile {
+    fn record(&mut self, value: &Value) {
+        self.present += 1;
+        let (kind, listed) = match value {
+            Value::Null => ("null", None),
+            Value::Bool(b) => ("bool", Some(b.to_string())),
+            Value::Number(n) => {
+                if let Some(n) = n.as_f64() {
+                    self.range = Some(match self.range {
+                        Some((min, max)) => (min.min(n), max.max(n)),
+                        None => (n, n),
+                    });
+                }
+                ("number", Some(n.to_string()))
+            }
+            Value::String(s) => ("string", Some(s.clone())),
+            Value::Array(_) => ("array", None),
+            Value::Object(_) => ("object", None),
+        };
+        self.kinds.insert(kind);
+        if let Some(listed) = listed.filter(|_| !self.many_values) {
+            *self.values.entry(listed).or_default() += 1;
+            if self.values.len() > MAX_LISTED_VALUES {
+                self.many_values = true;
+                self.values.clear();
+            }
+        }
+    }
+
+    /// Short description of the values. Fields with few distinct values compared to the
+    /// amount of receipts, like `node_type`, are enum-like and their values are listed.
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.kinds.len() > 1 {
+            parts.push(format!(
+                "mixed {}",
+                self.kinds.iter().copied().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        let enum_like =
+            !self.many_values && !self.values.is_empty() && self.values.len() * 2 <= self.present;
+        if enum_like {
+            let mut values = self.values.iter().collect::<Vec<_>>();
+            // Numbers in numeric order, before other values.
+            values.sort_by(
+                |(a, _), (b, _)| match (a.parse::<f64>(), b.parse::<f64>()) {
+                    (Ok(a), Ok(b)) => a.total_cmp(&b),
+                    (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                    (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                    (Err(_), Err(_)) => a.cmp(b),
+                },
+            );
+            parts.push(
+                values
+                    .into_iter()
+                    .map(|(value, count)| format!("{value} ({count})"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        } else if let Some((min, max)) = self.range {
+            parts.push(format!("{min} to {max}"));
+        } else if self.many_values {
+            parts.push(format!("more than {MAX_LISTED_VALUES} distinct values"));
+        } else if !self.values.is_empty() {
+            parts.push(format!("{} distinct values", self.values.len()));
+        } else {
+            parts.push(self.kinds.iter().copied().collect::<Vec<_>>().join(", "));
+        }
+        parts.join("; ")
+    }
+}
+
+/// Record all fields of a JSON object, nested fields with their `.` separated path. The elements
+/// of arrays are recorded under the path of the array with `[]` appended.
+fn record_fields(profile: &mut BTreeMap<String, FieldProfile>, prefix: &str, value: &Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                profile.entry(path.clone()).or_default().record(value);
+                record_fields(profile, &path, value);
+            }
+        }
+        Value::Array(items) => {
+            let path = format!("{prefix}[]");
+            for item in items {
+                profile.entry(path.clone()).or_default().record(item);
+                record_fields(profile, &path, item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Print the profile of the receipts of every period directory in a directory.
+pub fn print(dir: &Path, args: &Args) -> Result<(), Error> {
+    let periods = args.period_dirs.find(dir);
+    if periods.is_empty() {
+        return Err(format!("{} has no period directories", dir.display()).into());
+    }
+    let source = DirectorySource::new(dir)
+        .with_period_dirs(args.period_dirs.clone())
+        .with_symlinks_followed(args.follow_symlinks);
+    for period in periods {
+        let mut receipts = 0;
+        let mut invalid = 0;
+        let mut profile = BTreeMap::new();
+        for receipt in source.discover(period, &crate::print_event)? {
+            let raw = source.read(&receipt)?;
+            receipts += 1;
+            match raw.parse::<Value>() {
+                Ok(value) => record_fields(&mut profile, "", &value),
+                Err(_) => invalid += 1,
+            }
+        }
+
+        println!("Period {period}: {receipts} receipts");
+        if invalid > 0 {
+            println!("  not valid JSON: {invalid}");
+        }
+        let width = profile.keys().map(String::len).max().unwrap_or(0);
+        for (path, field) in &profile {
+            // Array elements are counted per element rather than per receipt.
+            let presence = if path.contains("[]") {
+                format!("{:>7}x", field.present)
+            } else {
+                format!(
+                    "{:>7.1}%",
+                    field.present as f64 * 100. / (receipts - invalid).max(1) as f64
+                )
+            };
+            println!("  {path:<width$}  {presence}  {}", field.describe());
+        }
+    }
+    Ok(())
+}
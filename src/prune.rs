@@ -0,0 +1,259 @@
+//! Removal of the receipts of old periods, for mirrors on constrained storage.
+//!
+//! Receipts of a period can only be removed once nothing but their hashes and aggregates is needed
+//! anymore: the period is older than the retention, it is in a snapshot of `serve` or `publish
+//! site`, and it is reconciled, i.e. every receipt was paid with the memo of its hash. The hash,
+//! node and payout of every removed receipt are kept in `pruned/<period>.json` in the receipts
+//! directory, so payments can still be traced back to their receipt.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use trace_titans::{date, period::Period, source::ReceiptSource, Error};
+
+use crate::{
+    cli::Args,
+    digest::Snapshot,
+    horizon,
+    memos::{self, Located},
+    plan::Plan,
+};
+
+/// Version of the format of the records of pruned periods.
+pub const FORMAT_VERSION: u64 = 1;
+
+/// What is kept of the receipts of a pruned period.
+#[derive(Serialize)]
+struct Pruned {
+    version: u64,
+    period: u32,
+    pruned_at: i64,
+    /// Total payout of the receipts, in TFT units.
+    total_tft: u64,
+    receipts: Vec<PrunedReceipt>,
+}
+
+#[derive(Serialize)]
+struct PrunedReceipt {
+    file: String,
+    /// Hash of the receipt, base64 encoded as in the memo of its payment.
+    hash: String,
+    /// Node and payout of minting receipts with a reward.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tft: Option<u64>,
+}
+
+/// Why the receipts of a period are kept, if they are: periods must be over for more than `keep`
+/// periods, be in a snapshot and have no unreconciled receipts.
+fn kept(
+    period: u32,
+    last: u32,
+    keep: u32,
+    snapshotted: &BTreeSet<u32>,
+    unreconciled: &BTreeMap<u32, usize>,
+) -> Option<String> {
+    if period + keep > last {
+        Some(format!("within the retention of {keep} periods"))
+    } else if !snapshotted.contains(&period) {
+        Some("in no snapshot".to_string())
+    } else {
+        unreconciled.get(&period).map(|unreconciled| {
+            format!("{unreconciled} receipt(s) could not be matched to their payment")
+        })
+    }
+}
+
+/// The periods which aren't kept, printing why the others are.
+fn select(periods: &[u32], kept: impl Fn(u32) -> Option<String>) -> Vec<u32> {
+    let mut selected = Vec::new();
+    for &period in periods {
+        match kept(period) {
+            Some(reason) => println!("Period {period}: {reason}, kept"),
+            None => selected.push(period),
+        }
+    }
+    selected
+}
+
+/// Periods of all `report.json` snapshots in the directory and its subdirectories.
+fn snapshotted_periods(dir: &Path) -> Result<BTreeSet<u32>, Error> {
+    let mut periods = BTreeSet::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries =
+            fs::read_dir(&dir).map_err(|e| format!("could not read {}: {e}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.file_name().is_some_and(|name| name == "report.json") {
+                periods.extend(Snapshot::load(&path)?.periods());
+            }
+        }
+    }
+    Ok(periods)
+}
+
+/// Remove the receipts of the scanned periods which are older than `keep` periods, in a snapshot
+/// in `snapshots` and reconciled, keeping their hashes. With `--plan`, only prints what would be
+/// removed.
+pub fn prune(
+    args: &Args,
+    source: &dyn ReceiptSource,
+    snapshots: &Path,
+    keep: u32,
+) -> Result<(), Error> {
+    let root = args.receipts_dir();
+    let periods = args
+        .periods
+        .iter()
+        .copied()
+        .filter(|&period| args.period_dirs.path(&root, period).is_dir())
+        .collect::<Vec<_>>();
+    let last = (Period::current().offset() - 1).try_into().unwrap_or(0);
+    let snapshotted = snapshotted_periods(snapshots)?;
+    // Reconciling needs the payments from Horizon, so it is only done for the other periods.
+    let candidates = select(&periods, |period| {
+        kept(period, last, keep, &snapshotted, &BTreeMap::new())
+    });
+
+    let (paid, _) = memos::paid_receipts(source, &candidates)?;
+    let url = args.horizon_url.as_deref().unwrap_or(horizon::DEFAULT_URL);
+    let mut unreconciled = BTreeMap::<u32, usize>::new();
+    for (receipt, located) in paid.iter().zip(memos::locate(&paid, url)?) {
+        if !matches!(located, Located::Memo { .. }) {
+            *unreconciled.entry(receipt.period).or_default() += 1;
+        }
+    }
+    let prunable = select(&candidates, |period| {
+        kept(period, last, keep, &snapshotted, &unreconciled)
+    });
+    let paid = paid
+        .iter()
+        .map(|receipt| (receipt.origin.as_str(), receipt))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut plan = Plan::default();
+    for period in prunable {
+        let mut receipts = Vec::new();
+        for receipt in source.discover(period, &crate::print_event)? {
+            let raw = source.read(&receipt)?;
+            let paid = paid.get(raw.origin.as_str());
+            receipts.push(PrunedReceipt {
+                hash: memos::memo(raw.data()),
+                node_id: paid.map(|paid| paid.node_id),
+                tft: paid.map(|paid| paid.amount),
+                file: raw.origin,
+            });
+        }
+        let pruned = Pruned {
+            version: FORMAT_VERSION,
+            period,
+            pruned_at: date::now(),
+            total_tft: receipts.iter().filter_map(|r| r.tft).sum(),
+            receipts,
+        };
+        let record = root.join("pruned").join(format!("{period}.json"));
+        let data = serde_json::to_vec_pretty(&pruned)?;
+        let dir = args.period_dirs.path(&root, period);
+        if args.plan {
+            plan.files(&format!("hashes of period {period}"), &[(record, data)]);
+            plan.note(format!(
+                "remove {} receipt(s) of period {period} and {}",
+                pruned.receipts.len(),
+                dir.display()
+            ));
+            continue;
+        }
+        write_record(&record, &data)?;
+        remove_receipts(&pruned, &dir)?;
+        println!(
+            "Period {period}: {} receipt(s) removed, their hashes are kept in {}",
+            pruned.receipts.len(),
+            record.display()
+        );
+    }
+    if args.plan {
+        plan.print("prune");
+    }
+    Ok(())
+}
+
+/// Write the record of a pruned period, before anything is removed.
+fn write_record(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let dir = path.parent().expect("Record is in the pruned directory");
+    fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {e}", dir.display()))?;
+    fs::write(path, data).map_err(|e| format!("could not write {}: {e}", path.display()).into())
+}
+
+/// Remove the receipts of a pruned period, and its directory if nothing else is left in it.
+fn remove_receipts(pruned: &Pruned, dir: &Path) -> Result<(), Error> {
+    for receipt in &pruned.receipts {
+        let path = PathBuf::from(&receipt.file);
+        fs::remove_file(&path).map_err(|e| format!("could not remove {}: {e}", path.display()))?;
+    }
+    if fs::remove_dir(dir).is_err() {
+        eprintln!(
+            "Period {}: {} is kept, it has more than receipts",
+            pruned.period,
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::tests::TempDir;
+
+    #[test]
+    fn only_old_snapshotted_and_reconciled_periods_are_pruned() {
+        let snapshotted = BTreeSet::from([50, 51, 52, 55]);
+        let unreconciled = BTreeMap::from([(51, 2)]);
+        let pruned = |keep| {
+            select(&[50, 51, 52, 53, 54, 55], |period| {
+                kept(period, 57, keep, &snapshotted, &unreconciled)
+            })
+        };
+        assert_eq!(pruned(3), [50, 52]);
+        assert_eq!(pruned(0), [50, 52, 55]);
+        assert_eq!(
+            kept(51, 57, 3, &snapshotted, &unreconciled).unwrap(),
+            "2 receipt(s) could not be matched to their payment"
+        );
+    }
+
+    #[test]
+    fn pruned_receipts_are_removed_with_their_directory() {
+        let dir = TempDir::new("prune");
+        let period_dir = dir.0.join("57");
+        fs::create_dir_all(&period_dir).unwrap();
+        let file = period_dir.join("1.json");
+        fs::write(&file, "{}").unwrap();
+        let pruned = Pruned {
+            version: FORMAT_VERSION,
+            period: 57,
+            pruned_at: 0,
+            total_tft: 0,
+            receipts: vec![PrunedReceipt {
+                file: file.display().to_string(),
+                hash: memos::memo(b"{}"),
+                node_id: None,
+                tft: None,
+            }],
+        };
+        let record = dir.0.join("pruned").join("57.json");
+        write_record(&record, &serde_json::to_vec(&pruned).unwrap()).unwrap();
+        remove_receipts(&pruned, &period_dir).unwrap();
+        assert!(!period_dir.exists());
+        assert!(record.is_file());
+    }
+}
@@ -0,0 +1,540 @@
+//! A small expression language to select nodes in the report.
+//!
+//! Expressions combine node fields and numbers with arithmetic (`+ - * /`), comparisons
+//! (`< <= > >= == !=`) and logic (`&& || !`), e.g. `difference > 10 && uptime[p55] < 95`. All
+//! values are numbers, comparisons and logic evaluate to 1 for true and 0 for false, and any
+//! nonzero value counts as true.
+//!
+//! Fields are either node totals, such as `difference`, or period values which take the period
+//! offset as index, such as `uptime[p55]` or `uptime[55]`. See [`node_field`] for all fields.
+//!
+//! The same expressions define the derived columns of the configuration file, see [`Column`].
+
+use std::{fmt, str::FromStr};
+
+use trace_titans::{
+    currency::{Amount, TFT},
+    tolerance::Tolerances,
+    NodeResult, PERCENTAGE_PRECISION,
+};
+
+/// Looks up the value of a field by name and optional period.
+pub type FieldLookup<'a> = dyn Fn(&str, Option<u32>) -> Result<f64, String> + 'a;
+
+/// A parsed expression.
+#[derive(Debug, Clone)]
+pub struct Query {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(f64),
+    Field { name: String, period: Option<u32> },
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    Or,
+    And,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Query {
+    /// Evaluate the expression, looking up the value of fields with the given function.
+    pub fn eval(&self, field: &FieldLookup) -> Result<f64, String> {
+        self.expr.eval(field)
+    }
+
+    /// Indicates if the node matches the expression. Fields which don't exist are an error when
+    /// the query is parsed by [`Query::for_nodes`], but are still reported if they end up here.
+    pub fn matches(
+        &self,
+        node_id: u32,
+        result: &NodeResult,
+        tolerances: &Tolerances,
+    ) -> Result<bool, String> {
+        self.eval(&|name, period| node_field(node_id, result, tolerances, name, period))
+            .map(|value| value != 0.)
+    }
+
+    /// Numbers a field is compared with (`== !=`) anywhere in the expression, in any period.
+    pub fn compared_with(&self, name: &str) -> Vec<f64> {
+        let mut numbers = Vec::new();
+        self.expr.compared_with(name, &mut numbers);
+        numbers
+    }
+
+    /// Parse an expression over node fields, checking that all fields exist in the scanned
+    /// periods.
+    pub fn for_nodes(s: &str, periods: &[u32]) -> Result<Self, String> {
+        let query = s.parse::<Query>()?;
+        // Every field is looked up, also the ones evaluation would skip by short circuiting.
+        let mut fields = Vec::new();
+        query.expr.fields(&mut fields);
+        let empty = NodeResult::empty(periods);
+        for (name, period) in fields {
+            node_field(0, &empty, &Tolerances::default(), name, period)?;
+        }
+        Ok(query)
+    }
+}
+
+/// A column of the report with a value derived from the fields of a node, such as
+/// `shortfall_pct = difference / expected * 100`.
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    query: Query,
+}
+
+impl Column {
+    /// Parse the expression of a column, checking that all fields exist.
+    pub fn parse(name: &str, expr: &str, periods: &[u32]) -> Result<Self, String> {
+        Ok(Column {
+            name: name.to_string(),
+            query: Query::for_nodes(expr, periods)?,
+        })
+    }
+
+    /// Value of the column for a node, with at most 7 decimals like TFT amounts. Values which are
+    /// not a number, e.g. after a division by 0, are empty.
+    pub fn value(&self, node_id: u32, result: &NodeResult, tolerances: &Tolerances) -> String {
+        match self
+            .query
+            .eval(&|name, period| node_field(node_id, result, tolerances, name, period))
+        {
+            Ok(value) if value.is_finite() => {
+                let value = format!("{value:.7}");
+                let value = value.trim_end_matches('0').trim_end_matches('.');
+                match value {
+                    "-0" => "0".to_string(),
+                    value => value.to_string(),
+                }
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+impl Expr {
+    /// All fields in the expression, with their period.
+    fn fields<'a>(&'a self, fields: &mut Vec<(&'a str, Option<u32>)>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Field { name, period } => fields.push((name, *period)),
+            Expr::Not(e) | Expr::Neg(e) => e.fields(fields),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.fields(fields);
+                rhs.fields(fields);
+            }
+        }
+    }
+
+    fn compared_with(&self, name: &str, numbers: &mut Vec<f64>) {
+        match self {
+            Expr::Number(_) | Expr::Field { .. } => {}
+            Expr::Not(e) | Expr::Neg(e) => e.compared_with(name, numbers),
+            Expr::Binary(op, lhs, rhs) => {
+                if matches!(op, BinOp::Eq | BinOp::Ne) {
+                    match (&**lhs, &**rhs) {
+                        (Expr::Field { name: field, .. }, Expr::Number(n))
+                        | (Expr::Number(n), Expr::Field { name: field, .. })
+                            if field == name =>
+                        {
+                            numbers.push(*n)
+                        }
+                        _ => {}
+                    }
+                }
+                lhs.compared_with(name, numbers);
+                rhs.compared_with(name, numbers);
+            }
+        }
+    }
+
+    fn eval(&self, field: &FieldLookup) -> Result<f64, String> {
+        Ok(match self {
+            Expr::Number(n) => *n,
+            Expr::Field { name, period } => field(name, *period)?,
+            Expr::Not(e) => bool_value(e.eval(field)? == 0.),
+            Expr::Neg(e) => -e.eval(field)?,
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(field)?;
+                // Short circuit logic, so fields on the right are only needed if used.
+                match op {
+                    BinOp::Or if lhs != 0. => return Ok(1.),
+                    BinOp::And if lhs == 0. => return Ok(0.),
+                    _ => {}
+                }
+                let rhs = rhs.eval(field)?;
+                match op {
+                    BinOp::Or | BinOp::And => bool_value(rhs != 0.),
+                    BinOp::Lt => bool_value(lhs < rhs),
+                    BinOp::Le => bool_value(lhs <= rhs),
+                    BinOp::Gt => bool_value(lhs > rhs),
+                    BinOp::Ge => bool_value(lhs >= rhs),
+                    BinOp::Eq => bool_value(lhs == rhs),
+                    BinOp::Ne => bool_value(lhs != rhs),
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div => lhs / rhs,
+                }
+            }
+        })
+    }
+}
+
+fn bool_value(b: bool) -> f64 {
+    if b {
+        1.
+    } else {
+        0.
+    }
+}
+
+/// Value of a field of a node.
+///
+/// Node fields: `node_id`, `farm_id` and `twin_id` (of the latest receipt), `titan` (1 if the
+/// node was a titan in any period), and the totals over all periods in TFT: `expected`,
+/// `received` and `difference`.
+///
+/// Period fields, which need a period index: `uptime` (percentage), `expected`, `received` (TFT),
+/// `policy` (farming policy id), `titan` and `certified` (1 or 0). Periods without a receipt have
+/// 0 for all period fields.
+pub fn node_field(
+    node_id: u32,
+    result: &NodeResult,
+    tolerances: &Tolerances,
+    name: &str,
+    period: Option<u32>,
+) -> Result<f64, String> {
+    let tft = |amount: i64| Amount::new(amount, &TFT).as_f64();
+    let Some(period) = period else {
+        let latest = result.latest();
+        return Ok(match name {
+            "node_id" => node_id as f64,
+            "farm_id" => latest.map_or(0., |r| r.farm_id as f64),
+            "twin_id" => latest.map_or(0., |r| r.twin_id as f64),
+            "titan" => bool_value(result.is_titan()),
+            "expected" => tft(result.total_expected() as i64),
+            "received" => tft(result.total_received() as i64),
+            "difference" => tft(result.difference(tolerances)),
+            _ => return Err(format!("unknown field {name}")),
+        });
+    };
+    let r = result
+        .get(period)
+        .ok_or_else(|| format!("period {period} is not scanned"))?;
+    Ok(match name {
+        "uptime" => r.uptime_percentage as f64 / PERCENTAGE_PRECISION as f64,
+        "expected" => tft(r.expected_payout as i64),
+        "received" => tft(r.actual_payout as i64),
+        "policy" => r.farming_policy as f64,
+        "titan" => bool_value(r.is_titan()),
+        "certified" => bool_value(r.is_certified),
+        _ => return Err(format!("unknown period field {name}")),
+    })
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Query { expr }),
+            Some(token) => Err(format!("unexpected {token}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    /// A period index, e.g. `[p55]`.
+    Index(u32),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{n}"),
+            Token::Ident(name) => write!(f, "{name}"),
+            Token::Index(period) => write!(f, "[p{period}]"),
+            Token::Op(op) => write!(f, "{op}"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+/// Operators, longest first so `<=` is not read as `<`.
+const OPERATORS: [&str; 13] = [
+    "&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "+", "-", "*", "/",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().expect("Rest is not empty");
+        let len = if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = &rest[..len];
+            tokens.push(Token::Number(
+                number
+                    .parse()
+                    .map_err(|_| format!("invalid number {number}"))?,
+            ));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            len
+        } else if c == '[' {
+            let end = rest.find(']').ok_or("missing ] after period index")?;
+            let index = rest[1..end].trim();
+            let period = index
+                .strip_prefix('p')
+                .unwrap_or(index)
+                .parse()
+                .map_err(|_| format!("invalid period index {index}"))?;
+            tokens.push(Token::Index(period));
+            end + 1
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            1
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            1
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("unexpected character {c}"))?;
+            tokens.push(Token::Op(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, with one function per precedence level.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the next token if it is one of the given operators.
+    fn op(&mut self, ops: &[(&str, BinOp)]) -> Option<BinOp> {
+        let Some(Token::Op(op)) = self.peek() else {
+            return None;
+        };
+        let (_, bin_op) = ops.iter().find(|(o, _)| o == op)?;
+        self.pos += 1;
+        Some(*bin_op)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.and()?;
+        while let Some(op) = self.op(&[("||", BinOp::Or)]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.not()?;
+        while let Some(op) = self.op(&[("&&", BinOp::And)]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Op("!")) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let lhs = self.additive()?;
+        let ops = [
+            ("<", BinOp::Lt),
+            ("<=", BinOp::Le),
+            (">", BinOp::Gt),
+            (">=", BinOp::Ge),
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+        ];
+        match self.op(&ops) {
+            Some(op) => Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.additive()?))),
+            None => Ok(lhs),
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.multiplicative()?;
+        while let Some(op) = self.op(&[("+", BinOp::Add), ("-", BinOp::Sub)]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+        Ok(lhs)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.op(&[("*", BinOp::Mul), ("/", BinOp::Div)]) {
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Op("-")) {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Ident(name)) => {
+                let period = match self.peek() {
+                    Some(Token::Index(period)) => {
+                        let period = *period;
+                        self.pos += 1;
+                        Some(period)
+                    }
+                    _ => None,
+                };
+                Ok(Expr::Field { name, period })
+            }
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing )".into()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {token}")),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(s: &str) -> f64 {
+        s.parse::<Query>()
+            .unwrap()
+            .eval(&|name, period| match (name, period) {
+                ("a", None) => Ok(2.),
+                ("b", Some(55)) => Ok(10.),
+                _ => Err(format!("unknown field {name}")),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.);
+        assert_eq!(eval("(1 + 2) * 3"), 9.);
+        assert_eq!(eval("-a * b[p55]"), -20.);
+        assert_eq!(eval("10 - 4 - 3"), 3.);
+        assert_eq!(eval("a < 3 && b[55] >= 10"), 1.);
+        assert_eq!(eval("a > 3 || !(b[p55] == 10)"), 0.);
+        assert_eq!(eval("1 || 0 && 0"), 1.);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("1 +".parse::<Query>().is_err());
+        assert!("(1".parse::<Query>().is_err());
+        assert!("a[p5".parse::<Query>().is_err());
+        assert!("1 2".parse::<Query>().is_err());
+        assert!("a # 1".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn every_field_is_checked() {
+        let periods = [52, 53];
+        assert!(Query::for_nodes("difference > 0 && uptime[p52] < 95", &periods).is_ok());
+        assert!(Query::for_nodes("difference > 0 && uptiem[p52] < 95", &periods).is_err());
+        assert!(Query::for_nodes("1 || uptiem[p52] < 95", &periods).is_err());
+        assert!(Query::for_nodes("difference > 0 && uptime[p99] < 95", &periods).is_err());
+        assert!(Query::for_nodes("uptime > 0", &periods).is_err());
+    }
+
+    #[test]
+    fn matches_reports_unknown_fields() {
+        let periods = [52];
+        let result = NodeResult::empty(&periods);
+        let tolerances = Tolerances::default();
+        let query = "node_id == 7".parse::<Query>().unwrap();
+        assert_eq!(query.matches(7, &result, &tolerances), Ok(true));
+        assert_eq!(query.matches(8, &result, &tolerances), Ok(false));
+        let query = "uptime[p99] < 95".parse::<Query>().unwrap();
+        assert!(query.matches(7, &result, &tolerances).is_err());
+    }
+
+    #[test]
+    fn column_values() {
+        let periods = [52];
+        let result = NodeResult::empty(&periods);
+        let tolerances = Tolerances::default();
+        let value = |expr: &str| {
+            Column::parse("c", expr, &periods)
+                .unwrap()
+                .value(3, &result, &tolerances)
+        };
+        assert_eq!(value("node_id / 4"), "0.75");
+        assert_eq!(value("node_id / 3"), "1");
+        assert_eq!(value("1 / 3"), "0.3333333");
+        assert_eq!(value("expected / received"), "");
+        assert_eq!(value("-0 * node_id"), "0");
+    }
+}
@@ -0,0 +1,378 @@
+//! Reward rates and minimal uptime of the titan farming policy, loaded from TFChain instead of the
+//! built in ones.
+//!
+//! The rates of [`policy::TITAN`] are copied from the chain by hand, and go stale when the policy
+//! is changed on chain. The rates of every period can be read from the chain itself, as stored at
+//! the end of the period, or from a dump of the policies of earlier runs, such as the `policies` of
+//! the `report.json` of the site.
+//!
+//! The chain is read over the JSON-RPC API of a TFChain node. Storage entries are located with the
+//! hashes Substrate uses, and only the leading fields of the policy which hold the rates and the
+//! minimal uptime are decoded.
+
+use std::{collections::BTreeMap, fs};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use trace_titans::{
+    period::Period,
+    policy::{self, FarmingPolicy, PolicySnapshot},
+    receipt::ResourceRewards,
+    Error, PERCENTAGE_PRECISION,
+};
+
+use crate::{blake2b, http, prices, xxhash};
+
+/// Average time between two blocks of TFChain, in milliseconds.
+const BLOCK_TIME_MS: i64 = 6_000;
+
+/// Maximum amount of blocks looked at to find the block at the end of a period.
+const MAX_BLOCK_LOOKUPS: usize = 20;
+
+/// A dump of policy snapshots, either a plain list or the `policies` of a report.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Dump {
+    List(Vec<PolicySnapshot>),
+    Report { policies: Vec<PolicySnapshot> },
+}
+
+/// Load the titan policy of every period from the URL of a TFChain node, or from a JSON file with
+/// policy snapshots. Fails if one of the periods has no rates.
+pub fn load(from: &str, periods: &[u32]) -> Result<BTreeMap<u32, FarmingPolicy>, Error> {
+    let rates = if prices::is_url(from) {
+        let chain = Chain { url: from };
+        periods
+            .iter()
+            .map(|&period| Ok((period, chain.titan_policy(period)?)))
+            .collect::<Result<BTreeMap<_, _>, Error>>()?
+    } else {
+        read(from)?
+    };
+    let missing = periods
+        .iter()
+        .filter(|period| !rates.contains_key(period))
+        .map(u32::to_string)
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(format!(
+            "{from} has no rates of farming policy {} for period(s) {}",
+            policy::TITAN.id,
+            missing.join(", ")
+        )
+        .into());
+    }
+    for (period, policy) in &rates {
+        let rewards = policy.rewards;
+        if rewards != policy::TITAN.rewards {
+            eprintln!(
+                "Period {period}: farming policy {} has rates cu {}, su {}, nu {}, ipv4 {} mUSD",
+                policy::TITAN.id,
+                rewards.cu,
+                rewards.su,
+                rewards.nu,
+                rewards.ipv4
+            );
+        }
+        if policy.minimal_uptime != policy::TITAN.minimal_uptime {
+            eprintln!(
+                "Period {period}: farming policy {} has a minimal uptime of {}",
+                policy::TITAN.id,
+                trace_titans::format_percentage(policy.minimal_uptime)
+            );
+        }
+    }
+    Ok(rates)
+}
+
+fn read(path: &str) -> Result<BTreeMap<u32, FarmingPolicy>, Error> {
+    let data = fs::read(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let snapshots = match serde_json::from_slice(&data)
+        .map_err(|e| format!("invalid policy snapshots {path}: {e}"))?
+    {
+        Dump::List(snapshots)
+        | Dump::Report {
+            policies: snapshots,
+        } => snapshots,
+    };
+    let mut rates = BTreeMap::new();
+    for snapshot in snapshots {
+        if snapshot.farming_policy != policy::TITAN.id {
+            continue;
+        }
+        let policy = FarmingPolicy {
+            id: snapshot.farming_policy,
+            rewards: ResourceRewards {
+                cu: snapshot.cu,
+                su: snapshot.su,
+                nu: snapshot.nu,
+                ipv4: snapshot.ipv4,
+            },
+            // Snapshots from before the minimal uptime was recorded have 0.
+            minimal_uptime: match snapshot.minimal_uptime {
+                0 => policy::TITAN.minimal_uptime,
+                minimal_uptime => minimal_uptime,
+            },
+        };
+        if rates.insert(snapshot.period, policy).is_some() {
+            return Err(format!("period {} is listed twice in {path}", snapshot.period).into());
+        }
+    }
+    Ok(rates)
+}
+
+/// JSON-RPC API of a TFChain node.
+struct Chain<'a> {
+    url: &'a str,
+}
+
+/// Response to a JSON-RPC call.
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    result: Value,
+    error: Option<Value>,
+}
+
+impl Chain<'_> {
+    /// The titan policy at the end of a period, or now if the period isn't over.
+    fn titan_policy(&self, period: u32) -> Result<FarmingPolicy, Error> {
+        let block = self.block_at(Period::at_offset(period as i64).end() * 1000)?;
+        // `FarmingPoliciesMap` is a map with `Blake2_128Concat` hashed policy ids.
+        let id = policy::TITAN.id.to_le_bytes();
+        let mut key = storage_prefix("TfgridModule", "FarmingPoliciesMap");
+        key.extend(blake2b::digest_sized::<16>(&id));
+        key.extend(id);
+        let value = self.storage(&key, &block)?.ok_or_else(|| {
+            format!(
+                "farming policy {} doesn't exist on {} in period {period}",
+                policy::TITAN.id,
+                self.url
+            )
+        })?;
+        decode_policy(&value)
+            .ok_or_else(|| {
+                format!(
+                    "invalid farming policy {} from {}",
+                    policy::TITAN.id,
+                    self.url
+                )
+            })
+            .map_err(Into::into)
+    }
+
+    /// Hash of the last block at or before a unix timestamp in milliseconds, or of the latest
+    /// block if the timestamp is after it. Blocks are produced at a steady pace, so the block is
+    /// estimated from the block time, and corrected by the timestamps of the estimated blocks.
+    fn block_at(&self, timestamp: i64) -> Result<String, Error> {
+        let header = self.call("chain_getHeader", json!([]))?;
+        let head = header
+            .get("number")
+            .and_then(Value::as_str)
+            .and_then(|n| i64::from_str_radix(n.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| format!("invalid block header from {}", self.url))?;
+        let head_hash = self.block_hash(head)?;
+        let head_time = self.timestamp(&head_hash)?;
+        if timestamp >= head_time {
+            return Ok(head_hash);
+        }
+
+        // The block is between `before`, at or before the timestamp, and `after`, past it.
+        let (mut before, mut after) = ((0, None), head);
+        let mut number = head - (head_time - timestamp) / BLOCK_TIME_MS;
+        for _ in 0..MAX_BLOCK_LOOKUPS {
+            number = number.clamp(before.0 + 1, after - 1);
+            let hash = self.block_hash(number)?;
+            let time = self.timestamp(&hash)?;
+            if time <= timestamp {
+                before = (number, Some(hash));
+            } else {
+                after = number;
+            }
+            if before.0 + 1 == after {
+                match before {
+                    (_, Some(hash)) => return Ok(hash),
+                    (number, None) => return self.block_hash(number),
+                }
+            }
+            number += match (timestamp - time) / BLOCK_TIME_MS {
+                0 if time <= timestamp => 1,
+                0 => -1,
+                step => step,
+            };
+        }
+        Err(format!(
+            "could not find the block at {} on {}",
+            trace_titans::date::format_timestamp(timestamp / 1000),
+            self.url
+        )
+        .into())
+    }
+
+    fn block_hash(&self, number: i64) -> Result<String, Error> {
+        self.call("chain_getBlockHash", json!([number]))?
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("block {number} doesn't exist on {}", self.url).into())
+    }
+
+    /// Time of a block, as a unix timestamp in milliseconds.
+    fn timestamp(&self, block: &str) -> Result<i64, Error> {
+        let value = self
+            .storage(&storage_prefix("Timestamp", "Now"), block)?
+            .ok_or_else(|| format!("block {block} has no timestamp"))?;
+        let bytes = value
+            .get(..8)
+            .ok_or_else(|| format!("invalid timestamp of block {block}"))?;
+        Ok(u64::from_le_bytes(bytes.try_into()?) as i64)
+    }
+
+    /// Value of a storage entry at a block, `None` if it doesn't exist.
+    fn storage(&self, key: &[u8], block: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self.call("state_getStorage", json!([hex(key), block]))? {
+            Value::Null => Ok(None),
+            Value::String(value) => {
+                Ok(Some(unhex(&value).ok_or_else(|| {
+                    format!("invalid storage value {value} from {}", self.url)
+                })?))
+            }
+            value => Err(format!("invalid storage value {value} from {}", self.url).into()),
+        }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response: Response =
+            serde_json::from_slice(&http::post_json(self.url, &request.to_string())?)
+                .map_err(|e| format!("invalid response to {method} from {}: {e}", self.url))?;
+        match response.error {
+            Some(error) => Err(format!("{method} failed on {}: {error}", self.url).into()),
+            None => Ok(response.result),
+        }
+    }
+}
+
+/// Key prefix of the storage entries of an item of a module.
+fn storage_prefix(module: &str, item: &str) -> Vec<u8> {
+    let mut key = xxhash::twox128(module.as_bytes()).to_vec();
+    key.extend(xxhash::twox128(item.as_bytes()));
+    key
+}
+
+/// Rates and minimal uptime of a SCALE encoded farming policy, which starts with its version, id,
+/// name and the rates of cu, su, nu and ipv4, all as 32 bit integers, followed by the minimal
+/// uptime as a 16 bit percentage.
+fn decode_policy(value: &[u8]) -> Option<FarmingPolicy> {
+    let u32_at = |offset: usize| {
+        value
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().expect("Slice is 4 bytes")) as u64)
+    };
+    let id = u32_at(4)? as u32;
+    // The name is prefixed with its length as a compact integer. Names are short, so only the one
+    // and two byte modes are expected.
+    let (name_len, len_size) = match value.get(8)? & 0b11 {
+        0b00 => ((*value.get(8)? >> 2) as usize, 1),
+        0b01 => (
+            (u16::from_le_bytes([*value.get(8)?, *value.get(9)?]) >> 2) as usize,
+            2,
+        ),
+        _ => return None,
+    };
+    let rates = 8 + len_size + name_len;
+    let minimal_uptime = value.get(rates + 16..rates + 18)?;
+    let minimal_uptime = u16::from_le_bytes([minimal_uptime[0], minimal_uptime[1]]) as u32;
+    if minimal_uptime > 100 {
+        return None;
+    }
+    Some(FarmingPolicy {
+        id,
+        rewards: ResourceRewards {
+            cu: u32_at(rates)?,
+            su: u32_at(rates + 4)?,
+            nu: u32_at(rates + 8)?,
+            ipv4: u32_at(rates + 12)?,
+        },
+        minimal_uptime: minimal_uptime * PERCENTAGE_PRECISION,
+    })
+}
+
+fn hex(data: &[u8]) -> String {
+    let digits = data.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    format!("0x{digits}")
+}
+
+fn unhex(value: &str) -> Option<Vec<u8>> {
+    let digits = value.strip_prefix("0x")?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A SCALE encoded farming policy, with the fields after the minimal uptime left out.
+    fn encoded(name: &[u8], rates: [u32; 4], minimal_uptime: u16) -> Vec<u8> {
+        let mut value = Vec::new();
+        value.extend(2u32.to_le_bytes());
+        value.extend(policy::TITAN.id.to_le_bytes());
+        value.push((name.len() as u8) << 2);
+        value.extend(name);
+        for rate in rates {
+            value.extend(rate.to_le_bytes());
+        }
+        value.extend(minimal_uptime.to_le_bytes());
+        value
+    }
+
+    #[test]
+    fn decodes_rates_and_minimal_uptime() {
+        let policy = decode_policy(&encoded(b"titan", [3000, 1250, 38, 6], 90)).unwrap();
+        assert_eq!(
+            policy,
+            FarmingPolicy {
+                minimal_uptime: 90 * PERCENTAGE_PRECISION,
+                ..policy::TITAN
+            }
+        );
+        // A name with a two byte length.
+        let name = [b'x'; 70];
+        let mut value = encoded(&name, [1, 2, 3, 4], 95);
+        value.splice(8..9, (70u16 << 2 | 0b01).to_le_bytes());
+        let policy = decode_policy(&value).unwrap();
+        assert_eq!(policy.rewards.ipv4, 4);
+        assert_eq!(policy.minimal_uptime, 95 * PERCENTAGE_PRECISION);
+
+        let truncated = encoded(b"titan", [3000, 1250, 38, 6], 95);
+        assert_eq!(decode_policy(&truncated[..truncated.len() - 1]), None);
+        assert_eq!(decode_policy(&encoded(b"titan", [1, 2, 3, 4], 950)), None);
+    }
+
+    #[test]
+    fn snapshots_without_minimal_uptime_use_the_titan_one() {
+        let path = std::env::temp_dir().join(format!("trace_titans-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"policies": [
+                {"period": 56, "farming_policy": 2, "cu": 1, "su": 2, "nu": 3, "ipv4": 4},
+                {"period": 57, "farming_policy": 2, "cu": 1, "su": 2, "nu": 3, "ipv4": 4,
+                 "minimal_uptime": 90000},
+                {"period": 57, "farming_policy": 1, "cu": 1, "su": 2, "nu": 3, "ipv4": 4}
+            ]}"#,
+        )
+        .unwrap();
+        let policies = read(path.to_str().unwrap());
+        let _ = fs::remove_file(&path);
+        let policies = policies.unwrap();
+        assert_eq!(policies[&56].minimal_uptime, policy::TITAN.minimal_uptime);
+        assert_eq!(policies[&57].minimal_uptime, 90_000);
+        assert_eq!(policies[&57].rewards.su, 2);
+    }
+}
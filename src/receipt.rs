@@ -1,4 +1,4 @@
-use std::ops::Sub;
+use std::{borrow::Cow, collections::BTreeSet, ops::Sub, sync::Mutex};
 
 use crate::period::Period;
 use serde::{Deserialize, Serialize};
@@ -8,13 +8,18 @@ use serde::{Deserialize, Serialize};
 /// create the payment memo.
 ///
 /// Note that this only makes sense for valid mints, hence there is no error field here.
-pub struct MintingReceipt {
+///
+/// String fields borrow from the data the receipt is deserialized from where possible.
+#[non_exhaustive]
+pub struct MintingReceipt<'a> {
     pub period: Period,
     pub node_id: u32,
     pub twin_id: u32,
     pub farm_id: u32,
-    pub farm_name: String,
-    pub stellar_payout_address: String,
+    #[serde(borrow)]
+    pub farm_name: Cow<'a, str>,
+    #[serde(borrow)]
+    pub stellar_payout_address: Cow<'a, str>,
     pub measured_uptime: u64,
     /// TFT price on connection in milli USD.
     pub tft_connection_price: u64,
@@ -24,20 +29,133 @@ pub struct MintingReceipt {
     pub reward: Reward,
     pub carbon_offset: Reward,
     /// Certification type of the node, "Certified" or "DIY".
-    pub node_type: String,
+    #[serde(borrow)]
+    pub node_type: Cow<'a, str>,
     #[serde(default = "default_farming_policy_id")]
     pub farming_policy_id: u32,
     #[serde(default)]
     pub resource_rewards: ResourceRewards,
 }
 
+/// The part of a [`MintingReceipt`] needed to compare the payout of a node with the expected
+/// titan payout, and to report the node.
+///
+/// Fields which are not needed for this, such as the resource utilization other than public IPs,
+/// are skipped without being converted, and receipts which lack them can be used. String fields
+/// borrow from the data the summary is deserialized from where possible.
+#[derive(Deserialize)]
+#[non_exhaustive]
+pub struct ReceiptSummary<'a> {
+    pub period: Period,
+    pub node_id: u32,
+    pub twin_id: u32,
+    pub farm_id: u32,
+    #[serde(borrow)]
+    pub farm_name: Cow<'a, str>,
+    #[serde(borrow)]
+    pub stellar_payout_address: Cow<'a, str>,
+    pub measured_uptime: u64,
+    /// TFT price on connection in milli USD.
+    pub tft_connection_price: u64,
+    pub cloud_units: CloudUnits,
+    pub resource_units: ResourceUnits,
+    pub resource_utilization: IpUtilization,
+    pub reward: Reward,
+    /// Carbon offset minted along with the reward, which is not paid to the farmer.
+    #[serde(default)]
+    pub carbon_offset: Reward,
+    /// Certification type of the node, "Certified" or "DIY".
+    #[serde(borrow)]
+    pub node_type: Cow<'a, str>,
+    /// Farming policy as declared in the receipt, old receipts don't have it. Use
+    /// [`ReceiptSummary::farming_policy_id`] for the policy of the receipt.
+    #[serde(default, rename = "farming_policy_id")]
+    pub declared_farming_policy_id: Option<u32>,
+    #[serde(default)]
+    pub resource_rewards: ResourceRewards,
+}
+
+impl ReceiptSummary<'_> {
+    /// Farming policy of the receipt, [`DEFAULT_FARMING_POLICY_ID`] if it doesn't declare one.
+    pub fn farming_policy_id(&self) -> u32 {
+        self.declared_farming_policy_id
+            .unwrap_or(DEFAULT_FARMING_POLICY_ID)
+    }
+
+    /// Convert the summary to one which does not borrow from the receipt data, by interning its
+    /// strings, see [`intern`].
+    pub fn into_interned(self) -> ReceiptSummary<'static> {
+        ReceiptSummary {
+            farm_name: intern(self.farm_name),
+            stellar_payout_address: intern(self.stellar_payout_address),
+            node_type: intern(self.node_type),
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// A correction of the payout of a node in a period, made after the minting of that period.
+///
+/// Only the difference between the minted and the correct reward is paid out with the fixup, the
+/// minting receipt of the period itself is not changed.
+pub struct FixupReceipt<'a> {
+    /// The period which is corrected.
+    pub period: Period,
+    pub node_id: u32,
+    pub minted_cloud_units: CloudUnits,
+    pub correct_cloud_units: CloudUnits,
+    pub fixup_cloud_units: CloudUnits,
+    pub minted_reward: Reward,
+    pub correct_reward: Reward,
+    /// Reward paid with the fixup, the correct reward minus the minted reward.
+    pub fixup_reward: Reward,
+    #[serde(borrow)]
+    pub stellar_payout_address: Cow<'a, str>,
+}
+
+impl FixupReceipt<'_> {
+    /// Convert the receipt to one which does not borrow from the receipt data, by interning its
+    /// strings, see [`intern`].
+    pub fn into_interned(self) -> FixupReceipt<'static> {
+        FixupReceipt {
+            stellar_payout_address: intern(self.stellar_payout_address),
+            ..self
+        }
+    }
+}
+
+/// Strings which were interned, see [`intern`].
+static INTERNED: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+/// Get a string which lives as long as the process, only allocating it the first time it is
+/// seen.
+///
+/// Receipts of all nodes repeat a few thousand farm names, payout addresses and node types, so
+/// interning them keeps receipts from allocating their strings once the receipt data is dropped.
+/// Interned strings are never freed.
+pub fn intern(s: Cow<'_, str>) -> Cow<'static, str> {
+    let mut interned = INTERNED.lock().expect("Interning doesn't panic");
+    Cow::Borrowed(match interned.get(&*s) {
+        Some(&s) => s,
+        None => {
+            let s = Box::leak(s.into_owned().into_boxed_str());
+            interned.insert(s);
+            s
+        }
+    })
+}
+
+/// Farming policy of old minting receipts which did not have a farming policy id.
+pub const DEFAULT_FARMING_POLICY_ID: u32 = 1;
+
 /// Helper function so old minting receipts which did not have a farming policy id can be
 /// deserialized.
 const fn default_farming_policy_id() -> u32 {
-    1
+    DEFAULT_FARMING_POLICY_ID
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ResourceRewards {
     pub cu: u64,
     pub su: u64,
@@ -76,7 +194,7 @@ impl Sub for CloudUnits {
     }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 /// Payout for a node.
 pub struct Reward {
     /// Reward in milli USD.
@@ -87,6 +205,7 @@ pub struct Reward {
 
 impl Sub for Reward {
     type Output = Self;
+    #[allow(clippy::implicit_saturating_sub)]
     fn sub(self, rhs: Self) -> Self::Output {
         // If we would end up with a negative, set to 0
         Self {
@@ -104,7 +223,7 @@ impl Sub for Reward {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 /// Resource units as reported by the node.
 pub struct ResourceUnits {
     pub cru: f64,
@@ -113,6 +232,13 @@ pub struct ResourceUnits {
     pub sru: f64,
 }
 
+#[derive(Deserialize)]
+/// Utilization of public IPs on a node, the only resource utilization needed in a
+/// [`ReceiptSummary`].
+pub struct IpUtilization {
+    pub ip: f64,
+}
+
 #[derive(Serialize, Deserialize)]
 /// Utilization of resoures on a node as measured through capacity reports on the chain.
 pub struct ResourceUtilization {
@@ -122,3 +248,16 @@ pub struct ResourceUtilization {
     pub sru: f64,
     pub ip: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interned_strings_are_shared() {
+        let owned = intern(Cow::Owned("GINTERNED".to_string()));
+        let borrowed = intern(Cow::Borrowed("GINTERNED"));
+        assert!(matches!(owned, Cow::Borrowed("GINTERNED")));
+        assert!(std::ptr::eq(owned.as_ptr(), borrowed.as_ptr()));
+    }
+}
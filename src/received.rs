@@ -0,0 +1,70 @@
+//! Received payouts according to Stellar, instead of according to the receipts.
+//!
+//! The reward in a receipt is what minting meant to pay, what matters is what landed in the wallet
+//! of the farmer. The payment of every receipt is located by its memo, see [`crate::memos`], which
+//! also tells apart the payments of nodes sharing a payout address.
+
+use trace_titans::{format_tft, pipeline::NodeReceipts, source::ReceiptSource, Error};
+
+use crate::{
+    cli::Args,
+    horizon,
+    memos::{self, Located},
+};
+
+/// Replace the reward of every receipt with the amount of its payment on Stellar, and report the
+/// receipts of which it differs. Receipts without payment received nothing. Fixups are kept as
+/// they are in their receipts.
+pub fn reconcile(
+    node_receipts: &mut NodeReceipts,
+    args: &Args,
+    source: &dyn ReceiptSource,
+) -> Result<(), Error> {
+    let (mut receipts, _) = memos::paid_receipts(source, &args.periods)?;
+    // Only the receipts which are reported on, which are not ignored as duplicate.
+    receipts.retain(|paid| {
+        node_receipts
+            .get(&paid.node_id)
+            .and_then(|periods| periods.get(&paid.period))
+            .is_some_and(|parsed| parsed.origin == paid.origin)
+    });
+    let url = args.horizon_url.as_deref().unwrap_or(horizon::DEFAULT_URL);
+    let located = memos::locate(&receipts, url)?;
+
+    let mut discrepancies = 0;
+    for (paid, located) in receipts.iter().zip(located) {
+        let received = match located {
+            Located::Memo { amount } => amount,
+            Located::OtherMemo { transaction, .. } => {
+                eprintln!(
+                    "Period {}: node {} was paid with {transaction}, which doesn't have the hash of {} as memo",
+                    paid.period, paid.node_id, paid.origin
+                );
+                paid.amount
+            }
+            Located::NoPayment => 0,
+        };
+        if received != paid.amount {
+            discrepancies += 1;
+            eprintln!(
+                "Period {}: node {} received {} TFT on chain, {} pays {} TFT",
+                paid.period,
+                paid.node_id,
+                format_tft(received),
+                paid.origin,
+                format_tft(paid.amount)
+            );
+        }
+        let parsed = node_receipts
+            .get_mut(&paid.node_id)
+            .and_then(|periods| periods.get_mut(&paid.period))
+            .expect("Only loaded receipts are located");
+        parsed.receipt.reward.tft = received;
+    }
+    eprintln!(
+        "Received TFT taken from {url}: {} of {} payment(s) differ from their receipt",
+        discrepancies,
+        receipts.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,312 @@
+//! Calculation of the reward of a receipt under a farming policy.
+//!
+//! The calculation follows minting: cloud units are upscaled by [`TFT_PRECISION`], multiplied with
+//! the reward rates, converted to TFT at the connection price and scaled to the measured uptime.
+//! Every step rounds down, like minting does. Cloud units are upscaled from their decimal value
+//! with [`upscale`], so the whole calculation is integer math and doesn't depend on floating point
+//! rounding. [`RewardBreakdown::calculate_legacy`] keeps the floating point upscaling of earlier
+//! versions, to cross-check the numbers against.
+
+use serde::Serialize;
+
+use crate::{
+    period::STANDARD_PERIOD_DURATION,
+    policy::FarmingPolicy,
+    receipt::{CloudUnits, MintingReceipt, ReceiptSummary, ResourceRewards, Reward},
+    TFT_PRECISION,
+};
+
+/// The values of a receipt the reward is calculated from.
+pub trait RewardInputs {
+    fn cloud_units(&self) -> CloudUnits;
+    /// Public IPs used in the period.
+    fn public_ips(&self) -> f64;
+    /// TFT price on connection in milli USD.
+    fn tft_connection_price(&self) -> u64;
+    fn measured_uptime(&self) -> u64;
+}
+
+impl RewardInputs for MintingReceipt<'_> {
+    fn cloud_units(&self) -> CloudUnits {
+        self.cloud_units
+    }
+
+    fn public_ips(&self) -> f64 {
+        self.resource_utilization.ip
+    }
+
+    fn tft_connection_price(&self) -> u64 {
+        self.tft_connection_price
+    }
+
+    fn measured_uptime(&self) -> u64 {
+        self.measured_uptime
+    }
+}
+
+impl RewardInputs for ReceiptSummary<'_> {
+    fn cloud_units(&self) -> CloudUnits {
+        self.cloud_units
+    }
+
+    fn public_ips(&self) -> f64 {
+        self.resource_utilization.ip
+    }
+
+    fn tft_connection_price(&self) -> u64 {
+        self.tft_connection_price
+    }
+
+    fn measured_uptime(&self) -> u64 {
+        self.measured_uptime
+    }
+}
+
+/// Intermediate values of the reward calculation, with the contribution of every resource.
+///
+/// The amounts are in mUSD and TFT units, each rounded down on its own, so the contributions of
+/// the resources can add up to slightly less than the full period reward.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct RewardBreakdown {
+    /// Cloud units and public IPs, upscaled by [`TFT_PRECISION`] and truncated.
+    #[serde(skip)]
+    pub cu_upscaled: u64,
+    #[serde(skip)]
+    pub su_upscaled: u64,
+    #[serde(skip)]
+    pub nu_upscaled: u64,
+    #[serde(skip)]
+    pub ip_upscaled: u64,
+    /// Reward of every resource for the full period in mUSD, upscaled by [`TFT_PRECISION`].
+    #[serde(skip)]
+    pub cu_musd_upscaled: u64,
+    #[serde(skip)]
+    pub su_musd_upscaled: u64,
+    #[serde(skip)]
+    pub nu_musd_upscaled: u64,
+    #[serde(skip)]
+    pub ip_musd_upscaled: u64,
+    /// Reward for the full period in mUSD, upscaled by [`TFT_PRECISION`].
+    #[serde(skip)]
+    pub full_musd_reward_upscaled: u64,
+    /// Reward of every resource for the full period.
+    pub cu_reward: Reward,
+    pub su_reward: Reward,
+    pub nu_reward: Reward,
+    pub ip_reward: Reward,
+    /// Reward for the full period, before scaling to the measured uptime.
+    pub full_period_reward: Reward,
+    /// Reward scaled to the measured uptime, i.e. the expected payout.
+    pub reward: Reward,
+}
+
+impl RewardBreakdown {
+    /// Calculate the reward of a receipt with the given reward rates.
+    pub fn calculate(receipt: &impl RewardInputs, rewards: &ResourceRewards) -> Self {
+        Self::calculate_with(receipt, rewards, upscale)
+    }
+
+    /// Calculate the reward of a receipt like earlier versions did, with [`legacy_upscale`], to
+    /// cross-check the calculation against.
+    pub fn calculate_legacy(receipt: &impl RewardInputs, rewards: &ResourceRewards) -> Self {
+        Self::calculate_with(receipt, rewards, legacy_upscale)
+    }
+
+    fn calculate_with(
+        receipt: &impl RewardInputs,
+        rewards: &ResourceRewards,
+        upscale: fn(f64) -> u64,
+    ) -> Self {
+        let cloud_units = receipt.cloud_units();
+        let cu_upscaled = upscale(cloud_units.cu);
+        let su_upscaled = upscale(cloud_units.su);
+        let nu_upscaled = upscale(cloud_units.nu);
+        let ip_upscaled = upscale(receipt.public_ips());
+        let cu_musd_upscaled = cu_upscaled * rewards.cu;
+        let su_musd_upscaled = su_upscaled * rewards.su;
+        let nu_musd_upscaled = nu_upscaled * rewards.nu;
+        let ip_musd_upscaled = ip_upscaled * rewards.ipv4;
+        let full_musd_reward_upscaled =
+            cu_musd_upscaled + su_musd_upscaled + nu_musd_upscaled + ip_musd_upscaled;
+
+        // Don't divide by TFT_PRECISION as the conenction price is expressed as mUSD/TFT which is
+        // actually mUSD / TFT_PRECISION
+        let price = receipt.tft_connection_price();
+        let reward = |musd_upscaled: u64| Reward {
+            musd: musd_upscaled / TFT_PRECISION,
+            tft: musd_upscaled / price,
+        };
+        let full_period_reward = reward(full_musd_reward_upscaled);
+
+        // scale, use default period duration so we account for nodes which did not come online
+        // until the period already started. The products overflow u64 for large nodes.
+        let uptime = receipt.measured_uptime() as u128;
+        let scale =
+            |amount: u64| (amount as u128 * uptime / STANDARD_PERIOD_DURATION as u128) as u64;
+        let scaled_reward = Reward {
+            musd: scale(full_musd_reward_upscaled) / TFT_PRECISION,
+            tft: scale(full_period_reward.tft),
+        };
+
+        RewardBreakdown {
+            cu_upscaled,
+            su_upscaled,
+            nu_upscaled,
+            ip_upscaled,
+            cu_musd_upscaled,
+            su_musd_upscaled,
+            nu_musd_upscaled,
+            ip_musd_upscaled,
+            full_musd_reward_upscaled,
+            cu_reward: reward(cu_musd_upscaled),
+            su_reward: reward(su_musd_upscaled),
+            nu_reward: reward(nu_musd_upscaled),
+            ip_reward: reward(ip_musd_upscaled),
+            full_period_reward,
+            reward: scaled_reward,
+        }
+    }
+
+    /// The expected payout, in TFT units.
+    pub fn expected(&self) -> u64 {
+        self.reward.tft
+    }
+}
+
+/// Upscale a value by [`TFT_PRECISION`] and truncate it, as a fixed point number with an integer
+/// mantissa.
+///
+/// Receipts hold cloud units as decimal numbers, of which the parsed `f64` is only the closest
+/// binary value, e.g. `0.0029` is parsed as slightly less, which multiplied by [`TFT_PRECISION`]
+/// truncates to `28999` instead of `29000`. Instead the shortest decimal representation of the
+/// `f64`, which is the number in the receipt, is upscaled by moving its decimal point. Negative
+/// values are 0, and values which don't fit saturate.
+pub fn upscale(value: f64) -> u64 {
+    if !value.is_finite() || value <= 0. {
+        return value as u64;
+    }
+    // `Display` of `f64` writes the shortest representation which parses back to the same value,
+    // without an exponent.
+    let decimal = value.to_string();
+    let (whole, fraction) = decimal.split_once('.').unwrap_or((&decimal, ""));
+    let decimals = TFT_PRECISION.ilog10() as usize;
+    let fraction = format!("{fraction:0<decimals$}");
+    let fraction = fraction[..decimals]
+        .parse::<u64>()
+        .expect("Fraction of a float is digits");
+    whole
+        .parse::<u64>()
+        .ok()
+        .and_then(|whole| whole.checked_mul(TFT_PRECISION))
+        .and_then(|whole| whole.checked_add(fraction))
+        .unwrap_or(u64::MAX)
+}
+
+/// Upscale a value by multiplying the `f64` with [`TFT_PRECISION`] and truncating the product, as
+/// earlier versions did. This can be a unit less than [`upscale`], e.g. `28999` for `0.0029`.
+pub fn legacy_upscale(value: f64) -> u64 {
+    (value * TFT_PRECISION as f64) as u64
+}
+
+/// Calculate the reward of every receipt under a farming policy.
+pub fn expected_rewards<'a, R: RewardInputs + 'a>(
+    receipts: impl Iterator<Item = &'a R>,
+    policy: &FarmingPolicy,
+) -> Vec<RewardBreakdown> {
+    receipts
+        .map(|receipt| RewardBreakdown::calculate(receipt, &policy.rewards))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::TITAN;
+
+    struct Node {
+        cloud_units: CloudUnits,
+        public_ips: f64,
+        measured_uptime: u64,
+    }
+
+    impl RewardInputs for Node {
+        fn cloud_units(&self) -> CloudUnits {
+            self.cloud_units
+        }
+
+        fn public_ips(&self) -> f64 {
+            self.public_ips
+        }
+
+        fn tft_connection_price(&self) -> u64 {
+            80
+        }
+
+        fn measured_uptime(&self) -> u64 {
+            self.measured_uptime
+        }
+    }
+
+    fn large_node(measured_uptime: u64) -> Node {
+        Node {
+            cloud_units: CloudUnits {
+                cu: 300.,
+                su: 200.,
+                nu: 50.,
+            },
+            public_ips: 2.,
+            measured_uptime,
+        }
+    }
+
+    #[test]
+    fn large_node_over_full_period() {
+        let breakdown =
+            RewardBreakdown::calculate(&large_node(STANDARD_PERIOD_DURATION), &TITAN.rewards);
+        assert_eq!(breakdown.full_musd_reward_upscaled, 11_519_120_000_000);
+        assert_eq!(breakdown.full_period_reward.musd, 1_151_912);
+        assert_eq!(breakdown.reward.musd, 1_151_912);
+        assert_eq!(breakdown.reward.tft, 143_989_000_000);
+    }
+
+    #[test]
+    fn large_node_over_half_period() {
+        let breakdown =
+            RewardBreakdown::calculate(&large_node(STANDARD_PERIOD_DURATION / 2), &TITAN.rewards);
+        assert_eq!(breakdown.reward.musd, 575_956);
+        assert_eq!(breakdown.reward.tft, 71_994_500_000);
+    }
+
+    #[test]
+    fn upscale_decimal() {
+        assert_eq!(upscale(0.0029), 29_000);
+        assert_eq!(upscale(1.5), 15_000_000);
+        assert_eq!(upscale(0.123456789), 1_234_567);
+        assert_eq!(upscale(-1.), 0);
+        assert_eq!(upscale(f64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn legacy_calculation_truncates_the_float() {
+        assert_eq!(legacy_upscale(0.0029), 28_999);
+        assert_eq!(legacy_upscale(1.5), 15_000_000);
+        let node = Node {
+            cloud_units: CloudUnits {
+                cu: 0.0029,
+                su: 0.,
+                nu: 0.,
+            },
+            public_ips: 0.,
+            measured_uptime: STANDARD_PERIOD_DURATION,
+        };
+        let fixed = RewardBreakdown::calculate(&node, &TITAN.rewards);
+        let legacy = RewardBreakdown::calculate_legacy(&node, &TITAN.rewards);
+        assert_eq!(fixed.cu_upscaled, legacy.cu_upscaled + 1);
+        assert!(fixed.expected() > legacy.expected());
+        let large = RewardBreakdown::calculate_legacy(
+            &large_node(STANDARD_PERIOD_DURATION),
+            &TITAN.rewards,
+        );
+        assert_eq!(large.expected(), 143_989_000_000);
+    }
+}
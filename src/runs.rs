@@ -0,0 +1,203 @@
+//! Statistics of past report runs, kept on the local disk only.
+//!
+//! With `--runs`, every report run records how many receipts it found and parsed, how many nodes
+//! it reported and how long it took. A monthly run which suddenly parses far fewer receipts than
+//! the runs before it usually means receipts are missing from the mirror, which is easy to miss in
+//! a report which looks fine otherwise. `history runs` lists the recorded runs and points out the
+//! ones which stand out.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use trace_titans::{
+    cancel, date,
+    pipeline::{Metrics, Stage},
+    Error,
+};
+
+use crate::{migrate, table};
+
+/// Version of the runs file format, see [`migrate`].
+pub const FORMAT_VERSION: u64 = 1;
+
+/// Runs with fewer receipts per period than this share of the median of the runs before them are
+/// pointed out, in percent.
+const FEWER_RECEIPTS_THRESHOLD: u64 = 90;
+
+/// All recorded runs, oldest first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Runs {
+    pub version: u64,
+    #[serde(default)]
+    pub runs: Vec<Run>,
+}
+
+impl Default for Runs {
+    fn default() -> Self {
+        Runs {
+            version: FORMAT_VERSION,
+            runs: Vec::new(),
+        }
+    }
+}
+
+/// Statistics of a single run.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Run {
+    /// When the run started, as an ISO 8601 timestamp.
+    pub started_at: String,
+    pub periods: Vec<u32>,
+    /// Receipts found in the period directories.
+    pub receipts_found: u64,
+    /// Receipts which were parsed. Less than the receipts found if the run failed or was
+    /// interrupted.
+    pub receipts_parsed: u64,
+    /// Size of the parsed receipts, in bytes.
+    pub bytes: u64,
+    /// Nodes in the report.
+    pub nodes: u64,
+    /// Duration of the whole run, in milliseconds.
+    pub wall_time_ms: u64,
+    /// Why the run failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub interrupted: bool,
+}
+
+impl Run {
+    /// Receipts parsed per scanned period.
+    fn receipts_per_period(&self) -> u64 {
+        self.receipts_parsed / self.periods.len().max(1) as u64
+    }
+
+    /// Indicates the run completed, and its statistics can be compared to other runs.
+    fn completed(&self) -> bool {
+        self.error.is_none() && !self.interrupted
+    }
+}
+
+impl Runs {
+    /// Load the runs from a file, no runs if the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Runs::default()),
+            Err(e) => return Err(format!("could not read runs {}: {e}", path.display()).into()),
+        };
+        let value = serde_json::from_slice(&data)
+            .map_err(|e| format!("invalid runs {}: {e}", path.display()))?;
+        migrate::check_version(path, &value, FORMAT_VERSION)?;
+        Ok(serde_json::from_value(value)
+            .map_err(|e| format!("invalid runs {}: {e}", path.display()))?)
+    }
+
+    /// Save the runs to a file, through a temporary file like the ledger.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let tmp = path.with_extension("json.tmp");
+        let mut file = io::BufWriter::new(fs::File::create(&tmp)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)?;
+        file.into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Add a run to the runs in a file, from the metrics it recorded and its outcome.
+pub fn record(
+    path: &Path,
+    periods: &[u32],
+    started_at: i64,
+    wall_time: Duration,
+    metrics: &Metrics,
+    outcome: &Result<(), Error>,
+) -> Result<(), Error> {
+    let mut runs = Runs::load(path)?;
+    runs.runs.push(Run {
+        started_at: date::format_timestamp(started_at),
+        periods: periods.to_vec(),
+        receipts_found: metrics.items(Stage::Discover),
+        receipts_parsed: metrics.items(Stage::Parse),
+        bytes: metrics.bytes(Stage::Parse),
+        nodes: metrics.items(Stage::Analyze),
+        wall_time_ms: wall_time.as_millis() as u64,
+        error: outcome.as_ref().err().map(|e| e.to_string()),
+        interrupted: cancel::is_interrupted(),
+    });
+    runs.save(path)
+}
+
+/// Print the runs in a file as a table, pointing out failed runs and runs which parsed far fewer
+/// receipts per period than the completed runs before them.
+pub fn list(path: &Path) -> Result<(), Error> {
+    let runs = Runs::load(path)?;
+    if runs.runs.is_empty() {
+        eprintln!("No runs recorded in {}", path.display());
+        return Ok(());
+    }
+    let header = [
+        "started_at",
+        "periods",
+        "receipts",
+        "parsed",
+        "nodes",
+        "wall (s)",
+        "note",
+    ]
+    .map(String::from);
+    let mut rows = Vec::new();
+    let mut earlier = Vec::new();
+    for run in &runs.runs {
+        let periods = match (run.periods.first(), run.periods.last()) {
+            (Some(first), Some(last)) if first != last => format!("{first}-{last}"),
+            (Some(period), _) => period.to_string(),
+            _ => String::new(),
+        };
+        let note = if let Some(error) = &run.error {
+            format!("failed: {error}")
+        } else if run.interrupted {
+            "interrupted".to_string()
+        } else {
+            match median(&mut earlier.clone()) {
+                Some(usual)
+                    if run.receipts_per_period() * 100 < usual * FEWER_RECEIPTS_THRESHOLD =>
+                {
+                    format!(
+                        "{} receipts per period, usually {usual}",
+                        run.receipts_per_period()
+                    )
+                }
+                _ => String::new(),
+            }
+        };
+        if run.completed() {
+            earlier.push(run.receipts_per_period());
+        }
+        rows.push(vec![
+            run.started_at.clone(),
+            periods,
+            run.receipts_found.to_string(),
+            run.receipts_parsed.to_string(),
+            run.nodes.to_string(),
+            format!("{:.3}", run.wall_time_ms as f64 / 1000.),
+            note,
+        ]);
+    }
+    table::write(&mut io::stdout().lock(), &header, &rows)?;
+    Ok(())
+}
+
+/// Median of some values, if there are any.
+fn median(values: &mut [u64]) -> Option<u64> {
+    values.sort_unstable();
+    values.get(values.len() / 2).copied()
+}
@@ -0,0 +1,313 @@
+//! Long running mode which runs the monthly report on a schedule.
+//!
+//! Instead of a cron job with a wrapper script, `serve` waits for the times of a cron expression,
+//! and at every one of them fetches the last period which is over, publishes the site of the
+//! report as a snapshot of that period, writes the digest of the changes since the previous
+//! snapshot, and runs a notification command. Every step is a run of the tool itself with the
+//! arguments `serve` was given, so a run behaves exactly like one started by hand, and takes the
+//! run lock like one.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use trace_titans::{date, period::Period, Error};
+
+/// Times of a cron expression: minute, hour, day of the month, month and day of the week, in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the days of the month and of the week are restricted, as a day matches if either
+    /// restricted field does.
+    restricts: (bool, bool),
+}
+
+impl Schedule {
+    /// Indicates the minute of the timestamp is one of the times.
+    pub fn matches(&self, timestamp: i64) -> bool {
+        let bit = |set: u64, value: i64| set & 1 << value != 0;
+        let (month, day, weekday) = date::calendar_day(timestamp);
+        let day_matches = bit(self.days, day.into());
+        let weekday_matches = bit(self.weekdays, weekday.into());
+        let day_matches = match self.restricts {
+            (true, true) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        };
+        bit(self.minutes, timestamp.rem_euclid(3600) / 60)
+            && bit(self.hours, timestamp.rem_euclid(86_400) / 3600)
+            && bit(self.months, month.into())
+            && day_matches
+    }
+
+    /// The first of the times after the timestamp, if there is one in the next 5 years.
+    pub fn next(&self, after: i64) -> Option<i64> {
+        let start = after.div_euclid(60) * 60 + 60;
+        (0..5 * 366 * 24 * 60)
+            .map(|minute| start + minute * 60)
+            .find(|&timestamp| self.matches(timestamp))
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "invalid schedule {s}, expected 5 fields: minute, hour, day, month and weekday"
+            ));
+        };
+        // Sunday is both 0 and 7.
+        let weekday_set = field(weekdays, 0, 7)?;
+        Ok(Schedule {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: (weekday_set | weekday_set >> 7) & 0x7f,
+            restricts: (days != "*", weekdays != "*"),
+        })
+    }
+}
+
+/// Parse a field of a cron expression as the set of its values: a comma separated list of `*`,
+/// values and ranges like `1-5`, each optionally with a step like `*/15`.
+fn field(spec: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid schedule field {spec}, expected values from {min} to {max}");
+    let value = |v: &str| {
+        v.parse::<u32>()
+            .ok()
+            .filter(|v| (min..=max).contains(v))
+            .ok_or_else(invalid)
+    };
+    let mut set = 0;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<usize>().map_err(|_| invalid())?)),
+            None => (part, None),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from)?, value(to)?),
+            // A single value with a step runs up to the end.
+            None if step.is_some() => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if from > to || step == Some(0) {
+            return Err(invalid());
+        }
+        for v in (from..=to).step_by(step.unwrap_or(1)) {
+            set |= 1 << v;
+        }
+    }
+    Ok(set)
+}
+
+/// Arguments of `serve` which are its own, and not passed on to the runs it starts.
+const OWN_FLAGS: [&str; 3] = ["--schedule", "--notify", "--out-dir"];
+
+/// The command line arguments without `serve` and its own flags, to start the runs with.
+pub fn forwarded(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut forwarded = Vec::new();
+    let mut args = args.into_iter();
+    let mut command = false;
+    while let Some(arg) = args.next() {
+        let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+        if OWN_FLAGS.contains(&flag) {
+            if flag == arg {
+                args.next();
+            }
+        } else if arg == "serve" && !command {
+            command = true;
+        } else {
+            forwarded.push(arg);
+        }
+    }
+    forwarded
+}
+
+/// Wait for the times of the schedule and run the report at every one of them, until stopped.
+/// Snapshots are written to a directory per period in `out_dir`.
+pub fn serve(
+    schedule: &Schedule,
+    out_dir: &Path,
+    notify: Option<&str>,
+    args: &[String],
+) -> Result<(), Error> {
+    loop {
+        let now = date::now();
+        let next = schedule
+            .next(now)
+            .ok_or("the schedule has no times in the next 5 years")?;
+        eprintln!("Next run at {}", date::format_timestamp(next));
+        thread::sleep(Duration::from_secs((next - now) as u64));
+
+        let period = (Period::current().offset() - 1) as u32;
+        let snapshot = out_dir.join(period.to_string());
+        let status = match run(period, out_dir, &snapshot, args) {
+            Ok(()) => {
+                eprintln!(
+                    "Period {period}: snapshot written to {}",
+                    snapshot.display()
+                );
+                "ok"
+            }
+            Err(e) => {
+                eprintln!("Period {period}: the run failed: {e}");
+                "failed"
+            }
+        };
+        if let Some(notify) = notify {
+            if let Err(e) = command(notify, [OsStr::new(status), snapshot.as_os_str()]) {
+                eprintln!("Period {period}: the notification failed: {e}");
+            }
+        }
+    }
+}
+
+/// Fetch the period, publish the site into the snapshot directory and write the digest of the
+/// changes since the previous snapshot into it.
+fn run(period: u32, out_dir: &Path, snapshot: &Path, args: &[String]) -> Result<(), Error> {
+    let exe = std::env::current_exe().map_err(|e| format!("could not find the tool: {e}"))?;
+    let exe = exe.to_string_lossy();
+    let arg = |arg: &str| arg.to_string();
+    // The minting URL is only used to fetch.
+    let report_args = forwarded_without(args, "--minting-url");
+
+    let mut fetch = args.to_vec();
+    fetch.extend([arg("fetch"), arg("period"), period.to_string()]);
+    command(&exe, &fetch)?;
+
+    let previous = previous_snapshot(out_dir, period)?;
+    let mut publish = report_args.clone();
+    publish.extend([arg("publish"), arg("site"), arg("--out-dir")]);
+    publish.push(snapshot.display().to_string());
+    command(&exe, &publish)?;
+
+    if let Some(previous) = previous {
+        let mut digest = report_args;
+        digest.push(arg("--output"));
+        digest.push(snapshot.join("report.csv").display().to_string());
+        digest.push(arg("--digest"));
+        digest.push(snapshot.join("digest.txt").display().to_string());
+        digest.push(arg("--since"));
+        digest.push(previous.join("report.json").display().to_string());
+        command(&exe, &digest)?;
+    }
+    Ok(())
+}
+
+/// The arguments without a flag and its value.
+fn forwarded_without(args: &[String], flag: &str) -> Vec<String> {
+    let mut without = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            args.next();
+        } else if !arg.starts_with(&format!("{flag}=")) {
+            without.push(arg.clone());
+        }
+    }
+    without
+}
+
+/// The snapshot of the latest period before the given one in the output directory.
+fn previous_snapshot(out_dir: &Path, period: u32) -> Result<Option<PathBuf>, Error> {
+    if !out_dir.is_dir() {
+        return Ok(None);
+    }
+    let mut previous = None;
+    for entry in
+        fs::read_dir(out_dir).map_err(|e| format!("could not read {}: {e}", out_dir.display()))?
+    {
+        let path = entry?.path();
+        let snapshot = path
+            .file_name()
+            .and_then(|name| name.to_str()?.parse::<u32>().ok())
+            .filter(|&p| p < period && path.join("report.json").is_file());
+        if snapshot > previous.as_ref().map(|(p, _)| *p) {
+            previous = snapshot.map(|p| (p, path));
+        }
+    }
+    Ok(previous.map(|(_, path)| path))
+}
+
+/// Run a program and wait for it to succeed.
+fn command<S: AsRef<OsStr>>(program: &str, args: impl IntoIterator<Item = S>) -> Result<(), Error> {
+    let status = process::Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| format!("could not run {program}: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{program} failed with {status}").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2023-06-05T03:00:00Z, a Monday.
+    const MONDAY: i64 = 1_685_934_000;
+
+    #[test]
+    fn monthly_schedule() {
+        let schedule = "0 3 5 * *".parse::<Schedule>().unwrap();
+        assert!(schedule.matches(MONDAY));
+        assert!(!schedule.matches(MONDAY + 60));
+        assert_eq!(schedule.next(MONDAY - 1), Some(MONDAY));
+        // 2023-07-05T03:00:00Z
+        assert_eq!(schedule.next(MONDAY), Some(1_688_526_000));
+    }
+
+    #[test]
+    fn days_match_either_restricted_field() {
+        let schedule = "0 3 1 * sun".parse::<Schedule>();
+        assert!(schedule.is_err());
+        // Every Sunday, and the 1st of every month.
+        let schedule = "0 3 1 * 0".parse::<Schedule>().unwrap();
+        assert_eq!(schedule.next(MONDAY), Some(MONDAY + 6 * 86_400));
+        let sunday = "*/30 * * * 7".parse::<Schedule>().unwrap();
+        assert_eq!(sunday.next(MONDAY), Some(MONDAY + 5 * 86_400 + 21 * 3600));
+        let weekdays = "0 9 * * 1-5".parse::<Schedule>().unwrap();
+        assert_eq!(weekdays.next(MONDAY), Some(MONDAY + 6 * 3600));
+    }
+
+    #[test]
+    fn invalid_schedules() {
+        for schedule in [
+            "0 3 5 *",
+            "60 3 5 * *",
+            "0 3 0 * *",
+            "0 3 5-1 * *",
+            "*/0 * * * *",
+        ] {
+            assert!(schedule.parse::<Schedule>().is_err(), "{schedule}");
+        }
+        assert_eq!("0 0 30 2 *".parse::<Schedule>().unwrap().next(MONDAY), None);
+    }
+
+    #[test]
+    fn own_flags_are_not_forwarded() {
+        let args = "--receipts-dir r serve --schedule=x --notify n --out-dir o --from-period 50";
+        let forwarded = forwarded(args.split(' ').map(str::to_string));
+        assert_eq!(forwarded, ["--receipts-dir", "r", "--from-period", "50"]);
+        assert_eq!(
+            forwarded_without(&forwarded, "--receipts-dir"),
+            ["--from-period", "50"]
+        );
+    }
+}
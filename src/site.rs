@@ -0,0 +1,413 @@
+//! Static website of the report.
+//!
+//! The site is meant to be hosted as is, e.g. on GitHub Pages, as the canonical compensation
+//! report of a run. It consists of an index with the summary and all farms, a page per farm with
+//! its nodes, a page per node with the details of every period, and the report as downloadable
+//! CSV and JSON files. All links are relative, so the directory can be hosted under any path.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use trace_titans::{
+    format_diff_tft, format_percentage, format_tft, format_tft_styled, policy::PolicySnapshot,
+    reward::RewardBreakdown, Error, NodeResult,
+};
+
+use crate::{
+    address_book, cli::Args, html, labels::NodeLabels, opt_out::OptOuts, plan, plan::Plan,
+    statements::capitalize, write_nodes,
+};
+
+/// The JSON download.
+#[derive(Serialize)]
+struct Report<'a> {
+    /// Policy the expected payouts of every period are calculated with.
+    policies: Vec<PolicySnapshot>,
+    nodes: Vec<NodeEntry<'a>>,
+}
+
+/// Node in the JSON download.
+#[derive(Serialize)]
+struct NodeEntry<'a> {
+    node_id: u32,
+    /// Farm of the latest receipt of the node.
+    farm_id: u32,
+    labels: Vec<&'a str>,
+    periods: Vec<PeriodEntry<'a>>,
+    total_expected: String,
+    total_received: String,
+    difference: String,
+    /// Indicates the payments in the ledger settle the difference of the node.
+    settled: bool,
+}
+
+/// Period of a node in the JSON download.
+#[derive(Serialize)]
+struct PeriodEntry<'a> {
+    period: u32,
+    /// Indicates there is a receipt for the period, all other values are empty if there isn't.
+    receipt: bool,
+    farm_id: u32,
+    farm_name: &'a str,
+    twin_id: u32,
+    payout_address: &'a str,
+    farming_policy: u32,
+    certified: bool,
+    titan: bool,
+    uptime: String,
+    expected: String,
+    received: String,
+    recomputed: String,
+    /// How the expected payout is made up, if there is a receipt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expected_breakdown: Option<RewardBreakdown>,
+}
+
+/// Print which files publishing the site to a directory would write. The site is published to a
+/// temporary directory, and compared with the files in the directory.
+pub fn plan(
+    out_dir: &Path,
+    node_results: &[(u32, NodeResult)],
+    settled: &BTreeSet<u32>,
+    args: &Args,
+    labels: &NodeLabels,
+    opt_outs: &OptOuts,
+) -> Result<(), Error> {
+    let tmp = std::env::temp_dir().join(format!("trace_titans-site-{}", std::process::id()));
+    let published = publish(&tmp, node_results, settled, args, labels, opt_outs)
+        .map_err(Error::from)
+        .and_then(|()| plan::read_tree(&tmp));
+    // Nothing of the temporary site is kept, also if publishing it failed.
+    let _ = fs::remove_dir_all(&tmp);
+    let files = published?
+        .into_iter()
+        .map(|(path, data)| (out_dir.join(path), data))
+        .collect::<Vec<_>>();
+    let mut plan = Plan::default();
+    plan.files(&format!("site in {}", out_dir.display()), &files);
+    plan.print("publish site");
+    Ok(())
+}
+
+/// Write the site of the given nodes to a directory, which is created if needed. Existing files
+/// of a previous run are overwritten.
+pub fn publish(
+    out_dir: &Path,
+    node_results: &[(u32, NodeResult)],
+    settled: &BTreeSet<u32>,
+    args: &Args,
+    labels: &NodeLabels,
+    opt_outs: &OptOuts,
+) -> io::Result<()> {
+    fs::create_dir_all(out_dir.join("farms"))?;
+    fs::create_dir_all(out_dir.join("nodes"))?;
+
+    let tolerances = &args.tolerances;
+    let style = args.number_styles.site;
+    let t = args.lang.texts();
+    let farms = address_book::build(node_results, tolerances);
+
+    let mut file = io::BufWriter::new(fs::File::create(out_dir.join("report.csv"))?);
+    write_nodes(&mut file, node_results, args, labels, opt_outs, None)?;
+    file.flush()?;
+
+    let report = Report {
+        policies: args.policies.snapshot(&args.periods),
+        nodes: node_results
+            .iter()
+            .map(|(node_id, result)| {
+                node_entry(*node_id, result, settled.contains(node_id), args, labels)
+            })
+            .collect(),
+    };
+    let mut file = io::BufWriter::new(fs::File::create(out_dir.join("report.json"))?);
+    serde_json::to_writer_pretty(&mut file, &report)?;
+    writeln!(file)?;
+    file.flush()?;
+
+    let mut file = page(&out_dir.join("index.html"), t.report_title, "", args)?;
+    let total_expected = node_results
+        .iter()
+        .map(|(_, r)| r.total_expected())
+        .sum::<u64>();
+    let total_received = node_results
+        .iter()
+        .map(|(_, r)| r.total_received())
+        .sum::<u64>();
+    let difference = node_results
+        .iter()
+        .map(|(_, r)| r.difference(tolerances))
+        .sum::<i64>();
+    writeln!(
+        file,
+        "<p>{}</p>",
+        t.site_intro
+            .replace("{from}", &args.periods[0].to_string())
+            .replace("{to}", &args.periods[args.periods.len() - 1].to_string())
+    )?;
+    writeln!(file, "<table><tbody>")?;
+    writeln!(
+        file,
+        "<tr><th>{}</th><td>{}</td></tr>",
+        t.nodes,
+        node_results.len()
+    )?;
+    writeln!(
+        file,
+        "<tr><th>{}</th><td>{}</td></tr>",
+        t.farms,
+        farms.len()
+    )?;
+    writeln!(
+        file,
+        "<tr><th>{}</th><td>{}</td></tr>",
+        t.expected_tft,
+        format_tft_styled(total_expected, style)
+    )?;
+    writeln!(
+        file,
+        "<tr><th>{}</th><td>{}</td></tr>",
+        t.received_tft,
+        format_tft_styled(total_received, style)
+    )?;
+    writeln!(
+        file,
+        "<tr><th>{}</th><td>{}</td></tr>",
+        t.difference_to_send,
+        format_tft_styled(difference, style)
+    )?;
+    writeln!(file, "</tbody></table>")?;
+    writeln!(file, "<h2>{}</h2>", t.farms)?;
+    writeln!(
+        file,
+        "<table><thead><tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr></thead><tbody>",
+        t.farm, t.name, t.payout_address, t.nodes, t.owed_tft
+    )?;
+    for farm in &farms {
+        writeln!(
+            file,
+            "<tr><td><a href=\"farms/{0}.html\">{0}</a></td><td>{1}</td><td>{2}{3}</td><td>{4}</td><td>{5}</td></tr>",
+            farm.farm_id,
+            html::escape(&farm.farm_name),
+            html::escape(&farm.payout_address),
+            if farm.conflict {
+                format!(" ({})", t.conflict)
+            } else {
+                String::new()
+            },
+            farm.nodes,
+            farm.total_owed,
+        )?;
+    }
+    writeln!(file, "</tbody></table>")?;
+    end_page(file)?;
+
+    for farm in &farms {
+        let mut file = page(
+            &out_dir.join("farms").join(format!("{}.html", farm.farm_id)),
+            &format!("{} {} {}", capitalize(t.farm), farm.farm_id, farm.farm_name),
+            "../",
+            args,
+        )?;
+        writeln!(
+            file,
+            "<p>{}</p>",
+            t.farm_summary
+                .replace("{address}", &html::escape(&farm.payout_address))
+                .replace("{owed}", &farm.total_owed)
+        )?;
+        if farm.conflict {
+            writeln!(
+                file,
+                "<p class=\"warning\">{} {}</p>",
+                t.address_conflict,
+                farm.addresses
+                    .iter()
+                    .map(|a| html::escape(a))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        writeln!(
+            file,
+            "<table><thead><tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr></thead><tbody>",
+            t.node, t.expected_tft, t.received_tft, t.difference
+        )?;
+        for (node_id, result) in node_results
+            .iter()
+            .filter_map(|(node_id, r)| Some((node_id, r.by_farm().remove(&farm.farm_id)?)))
+        {
+            writeln!(
+                file,
+                "<tr><td><a href=\"../nodes/{0}.html\">{0}</a></td><td>{1}</td><td>{2}</td><td>{3}</td></tr>",
+                node_id,
+                format_tft_styled(result.total_expected(), style),
+                format_tft_styled(result.total_received(), style),
+                format_tft_styled(result.difference(tolerances), style),
+            )?;
+        }
+        writeln!(file, "</tbody></table>")?;
+        end_page(file)?;
+    }
+
+    for (node_id, result) in node_results {
+        let mut file = page(
+            &out_dir.join("nodes").join(format!("{node_id}.html")),
+            &format!("{} {node_id}", capitalize(t.node)),
+            "../",
+            args,
+        )?;
+        if let Some(latest) = result.latest() {
+            writeln!(
+                file,
+                "<p>{0} <a href=\"../farms/{1}.html\">{1}</a>.</p>",
+                capitalize(t.farm),
+                latest.farm_id
+            )?;
+        }
+        let node_labels = labels.get(*node_id).collect::<Vec<_>>();
+        if !node_labels.is_empty() {
+            writeln!(
+                file,
+                "<p>{}: {}</p>",
+                capitalize(t.labels),
+                html::escape(&node_labels.join(", "))
+            )?;
+        }
+        writeln!(
+            file,
+            "<table><thead><tr><th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th>\
+             <th>{}</th><th>{}</th><th>{}</th><th>{}</th><th>{}</th></tr></thead><tbody>",
+            t.period,
+            t.farm,
+            t.twin,
+            t.payout_address,
+            t.policy,
+            t.certified,
+            t.uptime,
+            t.received_tft,
+            t.recomputed_tft,
+            t.titan_tft
+        )?;
+        for (period, r) in result.periods() {
+            if r.farm_id == 0 {
+                writeln!(
+                    file,
+                    "<tr><th>{period}</th><td class=\"missing\" colspan=\"9\">{}</td></tr>",
+                    t.no_receipt
+                )?;
+                continue;
+            }
+            writeln!(
+                file,
+                "<tr class=\"{}\"><th>{period}</th><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+                 <td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                if r.is_titan() { "titan" } else { "" },
+                r.farm_id,
+                r.twin_id,
+                html::escape(&r.payout_address),
+                r.farming_policy,
+                r.is_certified,
+                format_percentage(r.uptime_percentage),
+                format_tft_styled(r.actual_payout, style),
+                format_tft_styled(r.recomputed_payout, style),
+                format_tft_styled(r.expected_payout, style),
+            )?;
+        }
+        writeln!(file, "</tbody></table>")?;
+        writeln!(
+            file,
+            "<p>{}</p>",
+            t.node_summary
+                .replace(
+                    "{expected}",
+                    &format_tft_styled(result.total_expected(), style)
+                )
+                .replace(
+                    "{received}",
+                    &format_tft_styled(result.total_received(), style)
+                )
+                .replace(
+                    "{difference}",
+                    &format_tft_styled(result.difference(tolerances), style)
+                ),
+        )?;
+        end_page(file)?;
+    }
+
+    Ok(())
+}
+
+fn node_entry<'a>(
+    node_id: u32,
+    result: &'a NodeResult,
+    settled: bool,
+    args: &Args,
+    labels: &'a NodeLabels,
+) -> NodeEntry<'a> {
+    NodeEntry {
+        node_id,
+        farm_id: result.latest().map_or(0, |r| r.farm_id),
+        labels: labels.get(node_id).collect(),
+        periods: result
+            .periods()
+            .map(|(period, r)| PeriodEntry {
+                period,
+                receipt: r.farm_id != 0,
+                farm_id: r.farm_id,
+                farm_name: &r.farm_name,
+                twin_id: r.twin_id,
+                payout_address: &r.payout_address,
+                farming_policy: r.farming_policy,
+                certified: r.is_certified,
+                titan: r.is_titan(),
+                uptime: format_percentage(r.uptime_percentage),
+                expected: format_tft(r.expected_payout),
+                received: format_tft(r.actual_payout),
+                recomputed: format_tft(r.recomputed_payout),
+                expected_breakdown: (r.farm_id != 0).then_some(r.expected),
+            })
+            .collect(),
+        total_expected: format_tft(result.total_expected()),
+        total_received: format_tft(result.total_received()),
+        difference: format_diff_tft(result.difference(&args.tolerances)),
+        settled,
+    }
+}
+
+/// Create a page and write everything up to the content. `root` is the relative path from the
+/// page to the root of the site.
+fn page(path: &Path, title: &str, root: &str, args: &Args) -> io::Result<io::BufWriter<fs::File>> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    let title = html::escape(title);
+    writeln!(file, "<!DOCTYPE html>")?;
+    writeln!(
+        file,
+        "<html lang=\"{}\"><head><meta charset=\"utf-8\">",
+        args.lang
+    )?;
+    writeln!(file, "<title>{title}</title>")?;
+    writeln!(
+        file,
+        "<style>{}\np.warning {{ color: #a00; }}\ntr.titan td {{ background: #e8f0ff; }}</style></head><body>",
+        html::STYLE
+    )?;
+    writeln!(
+        file,
+        "<p><a href=\"{root}index.html\">{}</a></p>",
+        args.lang.texts().summary
+    )?;
+    writeln!(file, "<h1>{title}</h1>")?;
+    Ok(file)
+}
+
+fn end_page(mut file: io::BufWriter<fs::File>) -> io::Result<()> {
+    writeln!(file, "</body></html>")?;
+    file.flush()
+}
@@ -0,0 +1,1087 @@
+//! Sources which can provide the minting receipts of a period.
+
+use std::{
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    error::Error,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+
+use crate::{
+    date,
+    events::{Event, EventHandler},
+    period::Period,
+};
+
+/// Error returned by a [`ReceiptSource`]. Sources are used from multiple threads, so errors must
+/// be sendable.
+pub type SourceError = Box<dyn Error + Send + Sync>;
+
+/// A source of minting receipts.
+///
+/// Loading receipts is split in two steps, so receipts can be read in parallel once they are
+/// found.
+pub trait ReceiptSource: Sync {
+    /// Find all receipts of the period with the given offset. Files which are skipped are reported
+    /// to the handler.
+    fn discover(
+        &self,
+        period: u32,
+        events: &dyn EventHandler,
+    ) -> Result<Vec<ReceiptRef>, SourceError>;
+
+    /// Load the raw data of a receipt found by [`ReceiptSource::discover`].
+    fn read(&self, receipt: &ReceiptRef) -> Result<RawReceipt, SourceError>;
+}
+
+/// A receipt found by a [`ReceiptSource`], which has not been read yet.
+pub struct ReceiptRef {
+    /// Offset of the period the receipt was found in.
+    pub period: u32,
+    location: Location,
+}
+
+impl ReceiptRef {
+    /// Where the receipt is, to point out receipts which can't be read.
+    pub fn location(&self) -> String {
+        match &self.location {
+            Location::File(path) => path.display().to_string(),
+            Location::Row(row) => format!("row {} of period {}", row + 1, self.period),
+            Location::Blob(path, _) => path.clone(),
+        }
+    }
+}
+
+/// Where a source can find a receipt.
+enum Location {
+    File(PathBuf),
+    Row(usize),
+    /// Path of the file in the commit and id of its blob.
+    Blob(String, String),
+}
+
+/// The unparsed JSON data of a single receipt.
+///
+/// Sources only load the data, so the caller decides which type the receipt is parsed in. Types
+/// such as [`crate::receipt::ReceiptSummary`] only deserialize part of the receipt, and string
+/// fields borrow from the raw data where possible.
+pub struct RawReceipt {
+    /// Offset of the period the receipt was found in.
+    pub period: u32,
+    /// Where the receipt was loaded from, used in error messages.
+    pub origin: String,
+    data: Vec<u8>,
+}
+
+impl RawReceipt {
+    /// Parse the receipt.
+    pub fn parse<'a, T: Deserialize<'a>>(&'a self) -> Result<T, SourceError> {
+        self.parse_json()
+            .map_err(|e| format!("{}: {e}", self.origin).into())
+    }
+
+    /// Parse the receipt, keeping the error of the JSON parser, see [`RawReceipt::error_field`].
+    pub fn parse_json<'a, T: Deserialize<'a>>(&'a self) -> serde_json::Result<T> {
+        serde_json::from_slice(&self.data)
+    }
+
+    /// Dotted path of the field a parse error is about, e.g. `cloud_units.cu`, if the error
+    /// is inside an object. For a missing field, the path the field is expected at.
+    pub fn error_field(&self, error: &serde_json::Error) -> Option<String> {
+        // A receipt which ends early is not wrong in the field it was cut off in.
+        if error.is_eof() {
+            return None;
+        }
+        // The position of an error is just after the input it stopped at.
+        let line_start = match error.line() {
+            0 | 1 => 0,
+            line => self
+                .data
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+                .nth(line - 2)
+                .map_or(self.data.len(), |(idx, _)| idx + 1),
+        };
+        let end = (line_start + error.column()).min(self.data.len());
+
+        enum Frame {
+            Object { key: Option<String> },
+            Array { index: usize },
+        }
+        let path = |stack: &[Frame]| {
+            let mut path = String::new();
+            for frame in stack {
+                match frame {
+                    Frame::Object { key: Some(key) } if path.is_empty() => path.push_str(key),
+                    Frame::Object { key: Some(key) } => path.push_str(&format!(".{key}")),
+                    Frame::Object { key: None } => break,
+                    Frame::Array { index } => path.push_str(&format!("[{index}]")),
+                }
+            }
+            path
+        };
+        let mut stack = Vec::new();
+        // Path of the last object which was closed.
+        let mut closed = String::new();
+        // The current string, and its content if it is a key of an object.
+        let (mut in_string, mut escaped, mut key) = (false, false, None::<Vec<u8>>);
+        for &b in &self.data[..end] {
+            if in_string {
+                match (b, key.as_mut()) {
+                    _ if escaped => escaped = false,
+                    (b'\\', _) => escaped = true,
+                    (b'"', _) => {
+                        in_string = false;
+                        if let (Some(name), Some(Frame::Object { key })) =
+                            (key.take(), stack.last_mut())
+                        {
+                            *key = Some(String::from_utf8_lossy(&name).into_owned());
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+                if let Some(name) = key.as_mut() {
+                    name.push(b);
+                }
+                continue;
+            }
+            match b {
+                b'"' => {
+                    in_string = true;
+                    // Strings in an object which has no current key are keys.
+                    if let Some(Frame::Object { key: None }) = stack.last() {
+                        key = Some(Vec::new());
+                    }
+                }
+                b'{' => stack.push(Frame::Object { key: None }),
+                b'[' => stack.push(Frame::Array { index: 0 }),
+                b'}' | b']' => {
+                    stack.pop();
+                    closed = path(&stack);
+                }
+                b',' => match stack.last_mut() {
+                    Some(Frame::Object { key }) => *key = None,
+                    Some(Frame::Array { index }) => *index += 1,
+                    None => {}
+                },
+                _ => {}
+            }
+        }
+
+        let field = match error
+            .to_string()
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split_once('`'))
+        {
+            // The error is just after the object without the field.
+            Some((name, _)) if closed.is_empty() => name.to_string(),
+            Some((name, _)) => format!("{closed}.{name}"),
+            None => path(&stack),
+        };
+        (!field.is_empty()).then_some(field)
+    }
+
+    /// Size of the raw data in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// The raw data, as loaded.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// How the directories of the periods are named, as a pattern with placeholders.
+///
+/// `{period}` is the period offset, `{year}` and `{month}` are the year and month in the middle of
+/// the period, e.g. `period-{period}` or `{year}-{month}`. Patterns can contain `/` or `\` for
+/// nested directories, which are both used as the separator of the platform. The default pattern
+/// is `{period}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodDirs {
+    pattern: String,
+}
+
+impl Default for PeriodDirs {
+    fn default() -> Self {
+        PeriodDirs {
+            pattern: "{period}".to_string(),
+        }
+    }
+}
+
+impl std::str::FromStr for PeriodDirs {
+    type Err = String;
+
+    /// Parse a pattern, which must identify the period with `{period}` or `{year}` and
+    /// `{month}`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in period directory pattern {s}"))?;
+            let placeholder = &rest[start..start + end + 1];
+            if !matches!(placeholder, "{period}" | "{year}" | "{month}") {
+                return Err(format!(
+                    "unknown placeholder {placeholder} in period directory pattern {s}, \
+                     expected {{period}}, {{year}} or {{month}}"
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        let identified = s.contains("{period}") || (s.contains("{year}") && s.contains("{month}"));
+        if !identified {
+            return Err(format!(
+                "period directory pattern {s} must contain {{period}}, or {{year}} and {{month}}"
+            ));
+        }
+        Ok(PeriodDirs {
+            pattern: s.replace('\\', "/"),
+        })
+    }
+}
+
+impl PeriodDirs {
+    /// Name of the directory of a period relative to the receipts directory, with `/` separated
+    /// components.
+    pub fn name(&self, period: u32) -> String {
+        let p = Period::at_offset(period as i64);
+        // `2023-06-08`
+        let middle = date::format_date(p.start() + p.duration() as i64 / 2);
+        self.pattern
+            .replace("{period}", &period.to_string())
+            .replace("{year}", &middle[0..4])
+            .replace("{month}", &middle[5..7])
+    }
+
+    /// Directory of a period in a receipts directory.
+    pub fn path(&self, root: &Path, period: u32) -> PathBuf {
+        let mut path = root.to_path_buf();
+        path.extend(self.name(period).split('/').filter(|c| !c.is_empty()));
+        path
+    }
+
+    /// Periods up to the current one of which a directory has a period directory, sorted.
+    pub fn find(&self, root: &Path) -> Vec<u32> {
+        (0..=Period::current().offset() as u32)
+            .filter(|&period| self.path(root, period).is_dir())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for PeriodDirs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Receipts stored as individual JSON files, in a directory per period named after the period
+/// offset, or as configured with [`DirectorySource::with_period_dirs`].
+///
+/// Besides the primary root directory, fallback roots (e.g. a local mirror) can be configured in
+/// priority order. Receipt files missing in the primary root are then taken from the first
+/// fallback root which has a file with the same name.
+///
+/// Archives are often copied between file systems, so entries which can't be receipts on every
+/// file system are skipped with a warning instead of failing the run: names which are not valid
+/// UTF-8, directories, symbolic links unless they are followed, and names which only differ in
+/// case from a file before them, as they are the same file on a case-insensitive file system.
+/// Names are compared without case between roots as well.
+pub struct DirectorySource {
+    /// Root directories, in priority order. The first one is the primary root.
+    roots: Vec<PathBuf>,
+    period_dirs: PeriodDirs,
+    follow_symlinks: bool,
+}
+
+impl DirectorySource {
+    /// Create a new source reading period directories in the given root directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DirectorySource {
+            roots: vec![root.into()],
+            period_dirs: PeriodDirs::default(),
+            follow_symlinks: false,
+        }
+    }
+
+    /// Add a fallback root directory, with a lower priority than the roots added before.
+    pub fn with_fallback(mut self, root: impl Into<PathBuf>) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    /// Name the period directories of all roots after a pattern.
+    pub fn with_period_dirs(mut self, period_dirs: PeriodDirs) -> Self {
+        self.period_dirs = period_dirs;
+        self
+    }
+
+    /// Whether receipt files which are symbolic links are read from the file they link to,
+    /// instead of skipped.
+    pub fn with_symlinks_followed(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+}
+
+impl ReceiptSource for DirectorySource {
+    fn discover(
+        &self,
+        period: u32,
+        events: &dyn EventHandler,
+    ) -> Result<Vec<ReceiptRef>, SourceError> {
+        // Lowercase file name without compression extension -> index of the root and path, keeping
+        // the file of the root with the highest priority.
+        let mut files = BTreeMap::<String, (usize, PathBuf)>::new();
+        for (idx, root) in self.roots.iter().enumerate() {
+            let dir = self.period_dirs.path(root, period);
+            // Only the primary root must have every period, fallbacks are allowed to be
+            // incomplete.
+            if idx > 0 && !dir.is_dir() {
+                continue;
+            }
+            let mut from_root = 0;
+            // Sorted, so the same file is kept of names which only differ in case or compression.
+            let mut entries = fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                let path = entry.path();
+                let skip = |reason: &str| {
+                    events.handle(Event::SkippedFile {
+                        period,
+                        path: &path.display().to_string(),
+                        reason,
+                    })
+                };
+                let Some(name) = entry.file_name().to_str().map(str::to_lowercase) else {
+                    skip("its name is not valid UTF-8");
+                    continue;
+                };
+                let file_type = entry.file_type()?;
+                if file_type.is_symlink() && !self.follow_symlinks {
+                    skip("symbolic links are not followed");
+                    continue;
+                }
+                // `is_dir` follows links, the entry type doesn't.
+                if path.is_dir() {
+                    skip("it is a directory");
+                    continue;
+                }
+                match files.entry(strip_compression(&name).to_string()) {
+                    Entry::Vacant(e) => {
+                        e.insert((idx, path));
+                        from_root += 1;
+                    }
+                    Entry::Occupied(e) if e.get().0 == idx => skip(&format!(
+                        "its name only differs in case or compression from {}",
+                        e.get().1.display()
+                    )),
+                    Entry::Occupied(_) => {}
+                }
+            }
+            if idx > 0 && from_root > 0 {
+                events.handle(Event::Fallback {
+                    period,
+                    receipts: from_root,
+                    primary: &self.roots[0].display().to_string(),
+                    fallback: &root.display().to_string(),
+                });
+            }
+        }
+
+        Ok(files
+            .into_values()
+            .map(|(_, path)| ReceiptRef {
+                period,
+                location: Location::File(path),
+            })
+            .collect())
+    }
+
+    fn read(&self, receipt: &ReceiptRef) -> Result<RawReceipt, SourceError> {
+        let Location::File(path) = &receipt.location else {
+            return Err("receipt was not found by a directory source".into());
+        };
+        let origin = path.display().to_string();
+        let data = fs::read(path).map_err(|e| format!("{origin}: {e}"))?;
+        Ok(RawReceipt {
+            period: receipt.period,
+            data: decompress(&origin, data).map_err(|e| format!("{origin}: {e}"))?,
+            origin,
+        })
+    }
+}
+
+/// Reads receipts from a commit of a git repository of receipts, which has a directory per period
+/// like [`DirectorySource`].
+///
+/// The files are read from the git objects rather than the checkout, so an analysis can be pinned
+/// to the exact published revision, and rerun on an old revision after the files changed. Bare
+/// repositories work as well. Git is run as the `git` binary.
+pub struct GitSource {
+    repo: PathBuf,
+    /// Full hash of the commit.
+    commit: String,
+    period_dirs: PeriodDirs,
+}
+
+impl GitSource {
+    /// Open the given revision, e.g. a commit hash, tag or branch, of the repository at `repo`.
+    pub fn open(repo: impl Into<PathBuf>, revision: &str) -> Result<Self, SourceError> {
+        let repo = repo.into();
+        let commit = git(
+            &repo,
+            &["rev-parse", "--verify", &format!("{revision}^{{commit}}")],
+        )
+        .map_err(|e| format!("unknown revision {revision}: {e}"))?;
+        let commit = String::from_utf8(commit)?.trim().to_string();
+        Ok(GitSource {
+            repo,
+            commit,
+            period_dirs: PeriodDirs::default(),
+        })
+    }
+
+    /// Name the period directories after a pattern.
+    pub fn with_period_dirs(mut self, period_dirs: PeriodDirs) -> Self {
+        self.period_dirs = period_dirs;
+        self
+    }
+}
+
+impl GitSource {
+    /// Full hash of the commit the receipts are read from.
+    pub fn commit(&self) -> &str {
+        &self.commit
+    }
+
+    /// Path and blob id of every file in the directory of a period.
+    fn files(
+        &self,
+        period: u32,
+        events: &dyn EventHandler,
+    ) -> Result<Vec<(String, String)>, SourceError> {
+        let listing = git(
+            &self.repo,
+            &[
+                "ls-tree",
+                "-z",
+                &self.commit,
+                "--",
+                &format!("{}/", self.period_dirs.name(period)),
+            ],
+        )?;
+        // Every entry is `<mode> <type> <id>\t<path>`.
+        listing
+            .split(|b| *b == 0)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let Ok(entry) = std::str::from_utf8(entry) else {
+                    events.handle(Event::SkippedFile {
+                        period,
+                        path: &String::from_utf8_lossy(entry),
+                        reason: "its name is not valid UTF-8",
+                    });
+                    return Ok(None);
+                };
+                let (info, path) = entry
+                    .split_once('\t')
+                    .ok_or_else(|| format!("unexpected git ls-tree output {entry:?}"))?;
+                Ok(match info.split(' ').collect::<Vec<_>>().as_slice() {
+                    // Symbolic links are stored as a blob with the target.
+                    ["120000", "blob", _] => {
+                        events.handle(Event::SkippedFile {
+                            period,
+                            path,
+                            reason: "symbolic links in a commit are not followed",
+                        });
+                        None
+                    }
+                    [_, "blob", id] => Some((path.to_string(), id.to_string())),
+                    _ => None,
+                })
+            })
+            .filter_map(Result::transpose)
+            .collect()
+    }
+
+    /// Paths of the receipts of a node in the directory of a period.
+    pub fn node_files(
+        &self,
+        period: u32,
+        node_id: u32,
+        events: &dyn EventHandler,
+    ) -> Result<Vec<String>, SourceError> {
+        #[derive(Deserialize)]
+        struct Node {
+            node_id: u32,
+        }
+
+        let files = self.files(period, events)?;
+        let ids = files.iter().map(|(_, id)| id.as_str()).collect::<Vec<_>>();
+        let blobs = cat_batch(&self.repo, &ids)?;
+        Ok(files
+            .into_iter()
+            .zip(blobs)
+            .filter_map(|((path, _), blob)| {
+                let data = decompress(&path, blob?).ok()?;
+                let node = serde_json::from_slice::<Node>(&data).ok()?;
+                (node.node_id == node_id).then_some(path)
+            })
+            .collect())
+    }
+
+    /// Commits up to the commit of the source which changed a file, oldest first, with their
+    /// commit date.
+    pub fn history(&self, path: &str) -> Result<Vec<(String, String)>, SourceError> {
+        let log = git(
+            &self.repo,
+            &[
+                "log",
+                "--reverse",
+                "--format=%H %cI",
+                &self.commit,
+                "--",
+                path,
+            ],
+        )?;
+        String::from_utf8(log)?
+            .lines()
+            .map(|line| {
+                line.split_once(' ')
+                    .map(|(commit, date)| (commit.to_string(), date.to_string()))
+                    .ok_or_else(|| format!("unexpected git log output {line:?}").into())
+            })
+            .collect()
+    }
+
+    /// Content of a file in each of the given commits, `None` where the file doesn't exist.
+    pub fn read_revisions(
+        &self,
+        path: &str,
+        commits: &[&str],
+    ) -> Result<Vec<Option<Vec<u8>>>, SourceError> {
+        let objects = commits
+            .iter()
+            .map(|commit| format!("{commit}:{path}"))
+            .collect::<Vec<_>>();
+        cat_batch(
+            &self.repo,
+            &objects.iter().map(String::as_str).collect::<Vec<_>>(),
+        )?
+        .into_iter()
+        .map(|data| data.map(|data| decompress(path, data)).transpose())
+        .collect()
+    }
+}
+
+impl ReceiptSource for GitSource {
+    fn discover(
+        &self,
+        period: u32,
+        events: &dyn EventHandler,
+    ) -> Result<Vec<ReceiptRef>, SourceError> {
+        let receipts = self
+            .files(period, events)?
+            .into_iter()
+            .map(|(path, id)| ReceiptRef {
+                period,
+                location: Location::Blob(path, id),
+            })
+            .collect::<Vec<_>>();
+        if receipts.is_empty() {
+            return Err(
+                format!("period {period} has no receipts in commit {}", self.commit).into(),
+            );
+        }
+        Ok(receipts)
+    }
+
+    fn read(&self, receipt: &ReceiptRef) -> Result<RawReceipt, SourceError> {
+        let Location::Blob(path, id) = &receipt.location else {
+            return Err("receipt was not found by a git source".into());
+        };
+        let origin = format!("{}:{path}", self.commit);
+        let data = git(&self.repo, &["cat-file", "blob", id])
+            .and_then(|data| decompress(path, data))
+            .map_err(|e| format!("{origin}: {e}"))?;
+        Ok(RawReceipt {
+            period: receipt.period,
+            data,
+            origin,
+        })
+    }
+}
+
+/// Extensions of compressed receipt files, with the binary which decompresses them.
+const COMPRESSIONS: [(&str, &str); 2] = [(".gz", "gzip"), (".zst", "zstd")];
+
+/// Extension and binary of the compression of a receipt file, going by the extension of its name
+/// in any case, like discovery does.
+fn compression(name: &str) -> Option<(&'static str, &'static str)> {
+    COMPRESSIONS.into_iter().find(|(extension, _)| {
+        name.len() >= extension.len()
+            && name.as_bytes()[name.len() - extension.len()..]
+                .eq_ignore_ascii_case(extension.as_bytes())
+    })
+}
+
+/// Name of a receipt file without the extension of its compression, e.g. `1.json` of
+/// `1.json.gz`.
+pub fn strip_compression(name: &str) -> &str {
+    match compression(name) {
+        // The extension only matches ASCII, so it starts at a character boundary.
+        Some((extension, _)) => &name[..name.len() - extension.len()],
+        None => name,
+    }
+}
+
+/// Decompress the content of a receipt file if its name has the extension of a compression, with
+/// the `gzip` or `zstd` binary. Archives of receipts are often kept compressed, and are read
+/// without inflating them on disk first.
+fn decompress(name: &str, data: Vec<u8>) -> Result<Vec<u8>, SourceError> {
+    let Some((_, program)) = compression(name) else {
+        return Ok(data);
+    };
+    let mut child = Command::new(program)
+        .arg("-dc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            format!("could not run {program}, which is needed to read compressed receipts: {e}")
+        })?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .expect("Stdin of the decompressor is piped");
+    // Written from another thread, as the decompressor blocks on a full stdout.
+    let writer = std::thread::spawn(move || stdin.write_all(&data));
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .expect("Writing to the decompressor doesn't panic")?;
+    if !output.status.success() {
+        return Err(format!(
+            "could not decompress with {program}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(output.stdout)
+}
+
+/// Read many objects of a repository with a single git process. Objects which don't exist are
+/// returned as `None`.
+fn cat_batch(repo: &Path, objects: &[&str]) -> Result<Vec<Option<Vec<u8>>>, SourceError> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            format!("could not run git, which is needed to read receipts from a repository: {e}")
+        })?;
+    let mut stdin = child.stdin.take().expect("Stdin of git is piped");
+    let input = objects
+        .iter()
+        .map(|object| format!("{object}\n"))
+        .collect::<String>();
+    // Written from another thread, as git blocks on a full stdout while it is being written to.
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer.join().expect("Writing to git doesn't panic")?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()
+            .into());
+    }
+
+    // Every object is `<id> <type> <size>\n<content>\n`, or `<object> missing\n`.
+    let mut out = &output.stdout[..];
+    let mut blobs = Vec::with_capacity(objects.len());
+    for object in objects {
+        let end = out
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| format!("truncated git cat-file output for {object}"))?;
+        let header = std::str::from_utf8(&out[..end])?;
+        out = &out[end + 1..];
+        if header.ends_with(" missing") {
+            blobs.push(None);
+            continue;
+        }
+        let size = header
+            .rsplit(' ')
+            .next()
+            .and_then(|size| size.parse::<usize>().ok())
+            .filter(|size| *size < out.len())
+            .ok_or_else(|| format!("unexpected git cat-file output {header:?}"))?;
+        blobs.push(Some(out[..size].to_vec()));
+        out = &out[size + 1..];
+    }
+    Ok(blobs)
+}
+
+/// Run a git command in a repository and return its output.
+fn git(repo: &Path, args: &[&str]) -> Result<Vec<u8>, SourceError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .map_err(|e| {
+            format!("could not run git, which is needed to read receipts from a repository: {e}")
+        })?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()
+            .into());
+    }
+    Ok(output.stdout)
+}
+
+/// The kind of value stored in a receipt field.
+#[derive(Clone, Copy)]
+enum FieldKind {
+    Str,
+    Int,
+    Float,
+}
+
+/// All fields of a receipt which can be mapped to a CSV column, with their kind. Nested fields
+/// are written with a `.` separating the levels.
+const RECEIPT_FIELDS: [(&str, FieldKind); 32] = [
+    ("period.start", FieldKind::Int),
+    ("period.end", FieldKind::Int),
+    ("node_id", FieldKind::Int),
+    ("twin_id", FieldKind::Int),
+    ("farm_id", FieldKind::Int),
+    ("farm_name", FieldKind::Str),
+    ("stellar_payout_address", FieldKind::Str),
+    ("measured_uptime", FieldKind::Int),
+    ("tft_connection_price", FieldKind::Int),
+    ("cloud_units.cu", FieldKind::Float),
+    ("cloud_units.su", FieldKind::Float),
+    ("cloud_units.nu", FieldKind::Float),
+    ("resource_units.cru", FieldKind::Float),
+    ("resource_units.mru", FieldKind::Float),
+    ("resource_units.hru", FieldKind::Float),
+    ("resource_units.sru", FieldKind::Float),
+    ("resource_utilization.cru", FieldKind::Float),
+    ("resource_utilization.mru", FieldKind::Float),
+    ("resource_utilization.hru", FieldKind::Float),
+    ("resource_utilization.sru", FieldKind::Float),
+    ("resource_utilization.ip", FieldKind::Float),
+    ("reward.musd", FieldKind::Int),
+    ("reward.tft", FieldKind::Int),
+    ("carbon_offset.musd", FieldKind::Int),
+    ("carbon_offset.tft", FieldKind::Int),
+    ("node_type", FieldKind::Str),
+    ("farming_policy_id", FieldKind::Int),
+    ("resource_rewards.cu", FieldKind::Int),
+    ("resource_rewards.su", FieldKind::Int),
+    ("resource_rewards.nu", FieldKind::Int),
+    ("resource_rewards.ipv4", FieldKind::Int),
+    // Not a receipt field, but the period offset is needed to sort receipts in periods if the
+    // export does not contain the period timestamps.
+    ("period_offset", FieldKind::Int),
+];
+
+/// Mapping of receipt fields to the CSV column holding them.
+///
+/// The default mapping expects columns named after the receipt fields, e.g. `node_id` and
+/// `cloud_units.cu`. A custom mapping is read from a file with one `field = column` line per
+/// field, empty lines and lines starting with `#` are ignored. Fields which are not mentioned keep
+/// their default column name.
+pub struct ColumnMapping {
+    columns: HashMap<&'static str, String>,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            columns: RECEIPT_FIELDS
+                .iter()
+                .map(|(field, _)| (*field, field.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Load a column mapping from the file at the given path.
+    pub fn load(path: &Path) -> Result<Self, SourceError> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content).map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    /// Parse a column mapping in the mapping file format.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut mapping = ColumnMapping::default();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (field, column) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `field = column`", idx + 1))?;
+            let (field, _) = RECEIPT_FIELDS
+                .iter()
+                .find(|(f, _)| *f == field.trim())
+                .ok_or_else(|| {
+                    format!("line {}: unknown receipt field {}", idx + 1, field.trim())
+                })?;
+            mapping.columns.insert(field, column.trim().to_string());
+        }
+        Ok(mapping)
+    }
+}
+
+/// Receipts read from a single CSV file, with one receipt per row.
+pub struct CsvSource {
+    receipts: BTreeMap<u32, Vec<RawReceipt>>,
+}
+
+impl CsvSource {
+    /// Read all receipts from the CSV file at the given path, using the mapping to find the
+    /// column of every field.
+    ///
+    /// All rows are converted to JSON receipts upfront, so a malformed value is reported before
+    /// any period is processed. Missing fields are only detected once a receipt is parsed, as
+    /// this depends on the type the receipt is parsed in.
+    pub fn open(path: &Path, mapping: &ColumnMapping) -> Result<Self, SourceError> {
+        let content = fs::read_to_string(path)?;
+        let mut records = parse_csv(&content).into_iter();
+        let header = records.next().ok_or("CSV file has no header")?;
+
+        // Column index for every field present in the file.
+        let columns: Vec<(&str, FieldKind, usize)> = RECEIPT_FIELDS
+            .iter()
+            .filter_map(|(field, kind)| {
+                let name = &mapping.columns[field];
+                header
+                    .iter()
+                    .position(|h| h == name)
+                    .map(|idx| (*field, *kind, idx))
+            })
+            .collect();
+
+        let mut receipts = BTreeMap::<_, Vec<_>>::new();
+        for (idx, record) in records.enumerate() {
+            // Header is line 1.
+            let line = idx + 2;
+            let mut object = Map::new();
+            let mut period_offset = None;
+            for (field, kind, column) in &columns {
+                let raw = record.get(*column).map(String::as_str).unwrap_or("");
+                // Empty cells are treated as absent so defaults apply.
+                if raw.is_empty() {
+                    continue;
+                }
+                let value = parse_value(raw, *kind)
+                    .ok_or_else(|| format!("line {line}: invalid value {raw:?} for {field}"))?;
+                if *field == "period_offset" {
+                    period_offset = value.as_u64();
+                } else {
+                    insert_path(&mut object, field, value);
+                }
+            }
+            let period = match period_offset {
+                Some(offset) => offset,
+                None => {
+                    let period: Period = object
+                        .get("period")
+                        .cloned()
+                        .map(serde_json::from_value)
+                        .ok_or_else(|| format!("line {line}: missing period or period_offset"))?
+                        .map_err(|e| format!("line {line}: invalid period: {e}"))?;
+                    period.offset() as u64
+                }
+            };
+            receipts.entry(period as u32).or_default().push(RawReceipt {
+                period: period as u32,
+                origin: format!("{}:{line}", path.display()),
+                data: serde_json::to_vec(&object)?,
+            });
+        }
+
+        Ok(CsvSource { receipts })
+    }
+}
+
+impl ReceiptSource for CsvSource {
+    fn discover(&self, period: u32, _: &dyn EventHandler) -> Result<Vec<ReceiptRef>, SourceError> {
+        let rows = self.receipts.get(&period).map(Vec::len).unwrap_or(0);
+        Ok((0..rows)
+            .map(|row| ReceiptRef {
+                period,
+                location: Location::Row(row),
+            })
+            .collect())
+    }
+
+    fn read(&self, receipt: &ReceiptRef) -> Result<RawReceipt, SourceError> {
+        let Location::Row(row) = receipt.location else {
+            return Err("receipt was not found by a CSV source".into());
+        };
+        let raw = &self.receipts[&receipt.period][row];
+        Ok(RawReceipt {
+            period: raw.period,
+            origin: raw.origin.clone(),
+            data: raw.data.clone(),
+        })
+    }
+}
+
+/// Parse a raw CSV value as the given kind.
+fn parse_value(raw: &str, kind: FieldKind) -> Option<Value> {
+    Some(match kind {
+        FieldKind::Str => Value::String(raw.to_string()),
+        FieldKind::Int => Value::Number(raw.trim().parse::<u64>().ok()?.into()),
+        FieldKind::Float => Value::Number(Number::from_f64(raw.trim().parse().ok()?)?),
+    })
+}
+
+/// Insert a value in a JSON object at a `.` separated path, creating intermediate objects as
+/// needed.
+fn insert_path(object: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        Some((head, rest)) => {
+            let entry = object
+                .entry(head)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Value::Object(inner) = entry {
+                insert_path(inner, rest, value);
+            }
+        }
+        None => {
+            object.insert(path.to_string(), value);
+        }
+    }
+}
+
+/// Split CSV content in records. Fields can be quoted with `"`, in which case they may contain
+/// separators, newlines and `""` escaped quotes.
+pub fn parse_csv(content: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => in_quotes = !in_quotes,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    // Skip blank lines.
+    records.retain(|r| !(r.len() == 1 && r[0].is_empty()));
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory to write receipts to, removed when the test is done.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("trace_titans-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut child = Command::new("gzip")
+            .arg("-c")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(data).unwrap();
+        child.wait_with_output().unwrap().stdout
+    }
+
+    #[test]
+    fn compression_extensions() {
+        assert_eq!(strip_compression("1.json.gz"), "1.json");
+        assert_eq!(strip_compression("1.json.zst"), "1.json");
+        assert_eq!(strip_compression("1.json"), "1.json");
+        assert_eq!(strip_compression("1.gz.json"), "1.gz.json");
+        assert_eq!(strip_compression("1.JSON.Zst"), "1.JSON");
+        assert_eq!(strip_compression("é.gz"), "é");
+    }
+
+    #[test]
+    fn decompresses_by_extension() {
+        let data = br#"{"node_id": 1}"#.to_vec();
+        assert_eq!(decompress("1.json", data.clone()).unwrap(), data);
+        assert_eq!(decompress("1.json.gz", gzip(&data)).unwrap(), data);
+        assert_eq!(decompress("1.JSON.GZ", gzip(&data)).unwrap(), data);
+        assert!(decompress("1.json.gz", data).is_err());
+    }
+
+    #[test]
+    fn compressed_duplicates_load_once() {
+        let dir = TempDir::new("duplicates");
+        let period = dir.0.join("52");
+        fs::create_dir(&period).unwrap();
+        fs::write(period.join("1.json"), "{}").unwrap();
+        fs::write(period.join("1.json.gz"), gzip(b"{}")).unwrap();
+        fs::write(period.join("2.json.gz"), gzip(b"{}")).unwrap();
+
+        let receipts = DirectorySource::new(&dir.0)
+            .discover(52, &crate::events::ignore)
+            .unwrap();
+        let locations = receipts
+            .iter()
+            .map(ReceiptRef::location)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [period.join("1.json"), period.join("2.json.gz")].map(|p| p.display().to_string())
+        );
+    }
+}
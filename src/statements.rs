@@ -0,0 +1,138 @@
+//! PDF statements of the compensation owed to every farm.
+//!
+//! Statements are meant for formal communication with farmers: every farm gets a page with the
+//! periods covered, its payout address, the shortfall of each of its titan nodes, and the total
+//! owed. Farms with too many nodes for a single page continue on the next pages.
+
+use std::{fs, path::Path};
+
+use trace_titans::{format_tft_styled, Error, NodeResult};
+
+use crate::{address_book, cli::Args, pdf};
+
+const MARGIN: f32 = 50.;
+/// Lowest baseline of a table row before continuing on a new page.
+const BOTTOM: f32 = 80.;
+const FONT_SIZE: f32 = 10.;
+const LINE_HEIGHT: f32 = 15.;
+/// Right edges of the amount columns.
+const COLUMNS: [f32; 3] = [300., 420., pdf::PAGE_WIDTH - MARGIN];
+
+/// Write a statement for every farm with reported nodes to `<out_dir>/farm-<farm_id>.pdf`.
+pub fn write(out_dir: &Path, node_results: &[(u32, NodeResult)], args: &Args) -> Result<(), Error> {
+    fs::create_dir_all(out_dir)?;
+    let t = args.lang.texts();
+    let farms = address_book::build(node_results, &args.tolerances);
+    for farm in &farms {
+        let mut doc = pdf::Document::default();
+        let mut page = doc.page();
+        let mut y = pdf::PAGE_HEIGHT - MARGIN - 18.;
+        page.text(MARGIN, y, pdf::Font::Bold, 18., t.statement_title);
+        y -= 30.;
+        page.text(
+            MARGIN,
+            y,
+            pdf::Font::Bold,
+            12.,
+            &format!("{} {} {}", capitalize(t.farm), farm.farm_id, farm.farm_name),
+        );
+        y -= 20.;
+        let mut lines = vec![
+            t.periods_covered
+                .replace("{from}", &args.periods[0].to_string())
+                .replace("{to}", &args.periods[args.periods.len() - 1].to_string()),
+            t.payout_address_line
+                .replace("{address}", &farm.payout_address),
+        ];
+        if farm.conflict {
+            lines.push(format!(
+                "{} {}",
+                t.address_conflict,
+                farm.addresses
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        for line in lines {
+            page.text(MARGIN, y, pdf::Font::Regular, FONT_SIZE, &line);
+            y -= LINE_HEIGHT;
+        }
+
+        y -= LINE_HEIGHT;
+        table_header(page, y, args);
+        y -= LINE_HEIGHT;
+        for (node_id, result) in node_results
+            .iter()
+            .filter_map(|(node_id, r)| Some((node_id, r.by_farm().remove(&farm.farm_id)?)))
+        {
+            if y < BOTTOM {
+                page = doc.page();
+                y = pdf::PAGE_HEIGHT - MARGIN - FONT_SIZE;
+                table_header(page, y, args);
+                y -= LINE_HEIGHT;
+            }
+            page.text(
+                MARGIN,
+                y,
+                pdf::Font::Regular,
+                FONT_SIZE,
+                &node_id.to_string(),
+            );
+            for (x, amount) in COLUMNS.into_iter().zip([
+                format_tft_styled(result.total_expected(), args.number_styles.pdf),
+                format_tft_styled(result.total_received(), args.number_styles.pdf),
+                format_tft_styled(result.difference(&args.tolerances), args.number_styles.pdf),
+            ]) {
+                page.text_right(x, y, pdf::Font::Regular, FONT_SIZE, &amount);
+            }
+            y -= LINE_HEIGHT;
+        }
+
+        page.line(
+            MARGIN,
+            y + LINE_HEIGHT - 4.,
+            pdf::PAGE_WIDTH - MARGIN,
+            y + LINE_HEIGHT - 4.,
+        );
+        y -= 5.;
+        page.text(
+            MARGIN,
+            y,
+            pdf::Font::Bold,
+            12.,
+            &t.total_owed.replace("{owed}", &farm.total_owed),
+        );
+
+        doc.write(&out_dir.join(format!("farm-{}.pdf", farm.farm_id)))?;
+    }
+    eprintln!(
+        "Wrote {} statement(s) to {}",
+        farms.len(),
+        out_dir.display()
+    );
+    Ok(())
+}
+
+/// Write the header of the node table with its baseline at `y`.
+fn table_header(page: &mut pdf::Page, y: f32, args: &Args) {
+    let t = args.lang.texts();
+    page.text(MARGIN, y, pdf::Font::Bold, FONT_SIZE, &capitalize(t.node));
+    for (x, title) in
+        COLUMNS
+            .into_iter()
+            .zip([t.expected_tft, t.received_tft, t.difference_to_send])
+    {
+        page.text_right(x, y, pdf::Font::Bold, FONT_SIZE, title);
+    }
+    page.line(MARGIN, y - 4., pdf::PAGE_WIDTH - MARGIN, y - 4.);
+}
+
+/// Capitalize the first letter of a text, for texts used at the start of a sentence or title.
+pub fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
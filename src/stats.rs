@@ -0,0 +1,87 @@
+//! Statistics about the receipts of every period.
+//!
+//! These give context about the dataset of a period, such as how many nodes are certified and
+//! which connection prices were used, before looking at the payouts of individual nodes.
+
+use std::collections::BTreeMap;
+
+use trace_titans::{format_tft, pipeline::NodeReceipts, policy::Policies};
+
+use crate::health::FarmHealth;
+
+/// Statistics of a single period.
+#[derive(Default)]
+struct PeriodStats {
+    receipts: usize,
+    certified: usize,
+    /// Amount of receipts per farming policy id.
+    policies: BTreeMap<u32, usize>,
+    /// TFT connection prices in mUSD of all receipts, sorted.
+    connection_prices: Vec<u64>,
+    /// Total minted TFT, in TFT units.
+    minted: u64,
+}
+
+/// Print statistics of every period which has receipts.
+pub fn print(node_receipts: &NodeReceipts, policies: &Policies) {
+    let mut periods = BTreeMap::<u32, PeriodStats>::new();
+    for parsed in node_receipts.values().flat_map(BTreeMap::values) {
+        let stats = periods.entry(parsed.period).or_default();
+        let receipt = &parsed.receipt;
+        stats.receipts += 1;
+        if policies.is_certified(&receipt.node_type) {
+            stats.certified += 1;
+        }
+        *stats
+            .policies
+            .entry(receipt.farming_policy_id())
+            .or_default() += 1;
+        stats.connection_prices.push(receipt.tft_connection_price);
+        stats.minted += receipt.reward.tft;
+    }
+
+    for (period, mut stats) in periods {
+        stats.connection_prices.sort_unstable();
+        let prices = &stats.connection_prices;
+        println!("Period {period}");
+        println!("  receipts: {}", stats.receipts);
+        println!(
+            "  certified: {} ({:.1}%)",
+            stats.certified,
+            stats.certified as f64 * 100. / stats.receipts as f64
+        );
+        println!(
+            "  farming policies: {}",
+            stats
+                .policies
+                .iter()
+                .map(|(policy, count)| format!("{policy}: {count}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        println!(
+            "  connection price (mUSD): min {}, median {}, max {}",
+            prices[0],
+            prices[prices.len() / 2],
+            prices[prices.len() - 1]
+        );
+        println!("  minted: {} TFT", format_tft(stats.minted));
+    }
+}
+
+/// Print the health score of every farm, see [`crate::health`].
+pub fn print_farm_health(farms: &BTreeMap<u32, FarmHealth>) {
+    println!("Farm health");
+    for (farm_id, farm) in farms {
+        println!(
+            "  farm {farm_id} ({}): score {:.1}, {} node(s), uptime {:.1}%, consistent \
+             certification {:.1}%, payouts right {:.1}%",
+            farm.name,
+            farm.score(),
+            farm.nodes,
+            farm.uptime,
+            farm.certification,
+            farm.payouts
+        );
+    }
+}
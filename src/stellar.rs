@@ -0,0 +1,223 @@
+//! Unsigned Stellar transactions of TFT payments, in the XDR encoding of the network.
+//!
+//! Only what is needed to prepare payments is implemented: decoding account ids and encoding a
+//! transaction envelope of payment operations with a text memo, without signatures. The envelope
+//! can be signed and submitted with any Stellar wallet or the Stellar Laboratory.
+
+use trace_titans::{currency::TFT, Error};
+
+/// Account which issues TFT on Stellar.
+pub const TFT_ISSUER: &str = "GBOVQKJYHXRR3DX6NOX2RRYFRCUMSADGDESTDNBDS6CDVLGVESRTAC47";
+/// Maximum amount of operations in a transaction.
+pub const MAX_OPERATIONS: usize = 100;
+/// Maximum length of a text memo, in bytes.
+pub const MAX_MEMO_TEXT: usize = 28;
+/// Fee per operation, in stroops.
+const BASE_FEE: u32 = 100;
+
+/// Version byte of account ids in their string encoding.
+const ACCOUNT_ID_VERSION: u8 = 6 << 3;
+
+/// A payment of TFT units to an account.
+pub struct PaymentOp {
+    pub destination: String,
+    pub amount: u64,
+}
+
+/// Decode the public key of an account id, e.g. `GABC...`, validating its checksum.
+pub fn decode_account_id(account: &str) -> Result<[u8; 32], Error> {
+    let invalid = || format!("invalid Stellar account {account}");
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    let mut data = Vec::new();
+    for c in account.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return Err(invalid().into()),
+        };
+        bits = bits << 5 | value as u32;
+        bit_count += 5;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            data.push((bits >> bit_count) as u8);
+        }
+    }
+    if data.len() != 35 || data[0] != ACCOUNT_ID_VERSION {
+        return Err(invalid().into());
+    }
+    let checksum = u16::from_le_bytes([data[33], data[34]]);
+    if crc16(&data[..33]) != checksum {
+        return Err(invalid().into());
+    }
+    Ok(data[1..33].try_into().expect("Key is 32 bytes"))
+}
+
+/// CRC-16/XMODEM, the checksum of the string encoding of keys.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Writer of XDR values, which are big endian and padded to a multiple of 4 bytes.
+#[derive(Default)]
+struct Xdr(Vec<u8>);
+
+impl Xdr {
+    fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_be_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend(value.to_be_bytes());
+    }
+
+    /// Variable length opaque data or string, with its length.
+    fn var_opaque(&mut self, data: &[u8]) {
+        self.u32(data.len() as u32);
+        self.0.extend(data);
+        self.0
+            .extend(std::iter::repeat_n(0, (4 - data.len() % 4) % 4));
+    }
+
+    /// An ed25519 account, as `MuxedAccount` or `AccountID`, which are encoded the same.
+    fn account(&mut self, key: &[u8; 32]) {
+        // KEY_TYPE_ED25519 and PUBLIC_KEY_TYPE_ED25519 are both 0.
+        self.u32(0);
+        self.0.extend(key);
+    }
+}
+
+/// Encode the envelope of an unsigned transaction from the source account, which pays TFT with
+/// every operation, as XDR. The sequence number is the one of the transaction, i.e. one more than
+/// the current sequence number of the account.
+pub fn payment_envelope(
+    source: &str,
+    sequence: i64,
+    memo: &str,
+    payments: &[PaymentOp],
+) -> Result<Vec<u8>, Error> {
+    if payments.is_empty() || payments.len() > MAX_OPERATIONS {
+        return Err(format!(
+            "a transaction has 1 to {MAX_OPERATIONS} operations, not {}",
+            payments.len()
+        )
+        .into());
+    }
+    if memo.len() > MAX_MEMO_TEXT {
+        return Err(format!("memo {memo} is longer than {MAX_MEMO_TEXT} bytes").into());
+    }
+    let issuer = decode_account_id(TFT_ISSUER)?;
+    let mut code = [0u8; 4];
+    code[..TFT.code.len()].copy_from_slice(TFT.code.as_bytes());
+
+    let mut xdr = Xdr::default();
+    // ENVELOPE_TYPE_TX
+    xdr.u32(2);
+    xdr.account(&decode_account_id(source)?);
+    xdr.u32(BASE_FEE * payments.len() as u32);
+    xdr.i64(sequence);
+    // PRECOND_NONE
+    xdr.u32(0);
+    // MEMO_TEXT
+    xdr.u32(1);
+    xdr.var_opaque(memo.as_bytes());
+    xdr.u32(payments.len() as u32);
+    for payment in payments {
+        let amount = i64::try_from(payment.amount)
+            .map_err(|_| format!("amount {} is too large to pay", payment.amount))?;
+        // No source account of the operation, then the PAYMENT operation.
+        xdr.u32(0);
+        xdr.u32(1);
+        xdr.account(&decode_account_id(&payment.destination)?);
+        // ASSET_TYPE_CREDIT_ALPHANUM4
+        xdr.u32(1);
+        xdr.0.extend(code);
+        xdr.account(&issuer);
+        xdr.i64(amount);
+    }
+    // Transaction extension, then no signatures.
+    xdr.u32(0);
+    xdr.u32(0);
+    Ok(xdr.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The account of the all zero key.
+    const ZERO: &str = "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF";
+
+    #[test]
+    fn account_ids() {
+        assert_eq!(decode_account_id(ZERO).unwrap(), [0; 32]);
+        let issuer = decode_account_id(TFT_ISSUER).unwrap();
+        assert_eq!(issuer[..4], [0x5d, 0x58, 0x29, 0x38]);
+        // A changed character, a lowercase account, a seed and a truncated account.
+        for invalid in [
+            "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHG",
+            &TFT_ISSUER.to_lowercase(),
+            "SAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAWHF",
+            &TFT_ISSUER[..55],
+        ] {
+            assert!(decode_account_id(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn envelope() {
+        let payments = [PaymentOp {
+            destination: ZERO.to_string(),
+            amount: 15_000_000,
+        }];
+        let envelope = payment_envelope(ZERO, 0x0102_0304_0506_0708, "abcde", &payments).unwrap();
+
+        let mut expected = vec![0, 0, 0, 2, 0, 0, 0, 0];
+        expected.extend([0; 32]);
+        expected.extend([0, 0, 0, 100]);
+        expected.extend([1, 2, 3, 4, 5, 6, 7, 8]);
+        expected.extend([0, 0, 0, 0]);
+        // The memo, padded to 8 bytes.
+        expected.extend([0, 0, 0, 1, 0, 0, 0, 5]);
+        expected.extend(b"abcde\0\0\0");
+        expected.extend([0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        expected.extend([0; 32]);
+        expected.extend([0, 0, 0, 1]);
+        expected.extend(b"TFT\0");
+        expected.extend([0, 0, 0, 0]);
+        expected.extend(decode_account_id(TFT_ISSUER).unwrap());
+        expected.extend(15_000_000i64.to_be_bytes());
+        expected.extend([0; 8]);
+        assert_eq!(envelope, expected);
+    }
+
+    #[test]
+    fn envelope_limits() {
+        let payment = || PaymentOp {
+            destination: ZERO.to_string(),
+            amount: 1,
+        };
+        assert!(payment_envelope(ZERO, 1, "", &[]).is_err());
+        let payments = (0..=MAX_OPERATIONS).map(|_| payment()).collect::<Vec<_>>();
+        assert!(payment_envelope(ZERO, 1, "", &payments).is_err());
+        let fee = &payment_envelope(ZERO, 1, "", &payments[..MAX_OPERATIONS]).unwrap()[40..44];
+        assert_eq!(fee, (BASE_FEE * MAX_OPERATIONS as u32).to_be_bytes());
+        assert!(payment_envelope(ZERO, 1, &"m".repeat(MAX_MEMO_TEXT + 1), &[payment()]).is_err());
+        let too_large = PaymentOp {
+            destination: ZERO.to_string(),
+            amount: u64::MAX,
+        };
+        assert!(payment_envelope(ZERO, 1, "", &[too_large]).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Attribution of the reward of a period to windows within the period.
+//!
+//! A receipt only covers a full period, so claims about part of a period, e.g. a node which was
+//! only misclassified during the second half, need the uptime reports of the node to tell when in
+//! the period it was online. Every window is attributed the share of the reward matching the time
+//! the node was online in that window.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use trace_titans::{
+    date, format_tft_styled,
+    period::{Period, STANDARD_PERIOD_DURATION},
+    receipt::ReceiptSummary,
+    reward::RewardBreakdown,
+    source, Error, NumberStyle,
+};
+
+/// How to split a period in windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    /// Two windows of half a period.
+    Halves,
+    /// Windows of 7 days from the start of the period, the last one is shorter.
+    Weeks,
+}
+
+impl FromStr for Split {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "halves" => Ok(Split::Halves),
+            "weeks" => Ok(Split::Weeks),
+            _ => Err(format!("unknown split {s}, expected halves or weeks")),
+        }
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Split::Halves => "halves",
+            Split::Weeks => "weeks",
+        })
+    }
+}
+
+/// Uptime reports of nodes, as emitted by the chain.
+///
+/// Read from a CSV file with the columns `node_id`, `timestamp` and `uptime`: the node reported
+/// at the unix timestamp that it had been up for `uptime` seconds.
+#[derive(Debug, Default)]
+pub struct UptimeEvents {
+    /// node id -> (timestamp, uptime) of every report, sorted by timestamp.
+    reports: BTreeMap<u32, Vec<(i64, i64)>>,
+}
+
+impl UptimeEvents {
+    /// Load uptime reports from a CSV file.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let mut records = source::parse_csv(&content).into_iter();
+        let header = records
+            .next()
+            .ok_or_else(|| format!("{} is empty", path.display()))?;
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|c| c.trim() == name)
+                .ok_or_else(|| format!("{} has no {name} column", path.display()))
+        };
+        let columns = [column("node_id")?, column("timestamp")?, column("uptime")?];
+
+        let mut reports = BTreeMap::<u32, Vec<_>>::new();
+        for (line, record) in records.enumerate() {
+            let [node_id, timestamp, uptime] = columns.map(|idx| {
+                record
+                    .get(idx)
+                    .and_then(|v| v.trim().parse::<i64>().ok())
+                    .ok_or_else(|| format!("invalid line {} of {}", line + 2, path.display()))
+            });
+            let node_id = u32::try_from(node_id?).map_err(|_| {
+                format!("invalid node id on line {} of {}", line + 2, path.display())
+            })?;
+            reports
+                .entry(node_id)
+                .or_default()
+                .push((timestamp?, uptime?));
+        }
+        for reports in reports.values_mut() {
+            reports.sort_unstable();
+        }
+        Ok(UptimeEvents { reports })
+    }
+
+    /// Seconds the node was online within `[start, end)`.
+    ///
+    /// A report at `t` with uptime `u` means the node was online since `t - u`, but only the time
+    /// since the previous report is counted, so overlapping reports aren't counted twice.
+    pub fn online(&self, node_id: u32, start: i64, end: i64) -> i64 {
+        let Some(reports) = self.reports.get(&node_id) else {
+            return 0;
+        };
+        let mut online = 0;
+        let mut previous = i64::MIN;
+        for &(timestamp, uptime) in reports {
+            let from = (timestamp - uptime).max(previous).max(start);
+            let to = timestamp.min(end);
+            online += (to - from).max(0);
+            previous = timestamp;
+        }
+        online
+    }
+}
+
+/// The windows of a period, as `[start, end)` timestamps.
+pub fn windows(period: Period, split: Split) -> Vec<(i64, i64)> {
+    let (start, end) = (period.start(), period.end());
+    match split {
+        Split::Halves => {
+            let middle = start + (end - start) / 2;
+            vec![(start, middle), (middle, end)]
+        }
+        Split::Weeks => {
+            const WEEK: i64 = 7 * 24 * 60 * 60;
+            (start..end)
+                .step_by(WEEK as usize)
+                .map(|from| (from, (from + WEEK).min(end)))
+                .collect()
+        }
+    }
+}
+
+/// Write the share of the expected and the received payout of a receipt attributed to every
+/// window of its period.
+pub fn write(
+    out: &mut dyn Write,
+    period: u32,
+    expected: &RewardBreakdown,
+    receipt: &ReceiptSummary,
+    events: &UptimeEvents,
+    split: Split,
+    style: NumberStyle,
+) -> io::Result<()> {
+    let windows = windows(Period::at_offset(period.into()), split);
+    let (start, end) = (windows[0].0, windows[windows.len() - 1].1);
+    let node_id = receipt.node_id;
+    let total_online = events.online(node_id, start, end);
+
+    writeln!(out, "Per window ({split}), from the uptime reports:")?;
+    if total_online == 0 {
+        writeln!(
+            out,
+            "   no uptime reports of node {node_id} in period {period}"
+        )?;
+        return Ok(());
+    }
+    for (from, to) in windows {
+        let online = events.online(node_id, from, to);
+        // Expected rewards accrue with the time online, as in the receipt, received rewards are
+        // spread over the windows in proportion to it.
+        let expected_share = expected.full_period_reward.tft as i128 * online as i128
+            / STANDARD_PERIOD_DURATION as i128;
+        let received_share = receipt.reward.tft as i128 * online as i128 / total_online as i128;
+        writeln!(
+            out,
+            "   {} to {}: online {online}s, expected {} TFT, received {} TFT, difference {} TFT",
+            date::format_timestamp(from),
+            date::format_timestamp(to),
+            format_tft_styled(expected_share as u64, style),
+            format_tft_styled(received_share as u64, style),
+            format_tft_styled((expected_share - received_share) as i64, style)
+        )?;
+    }
+    writeln!(
+        out,
+        "   online in total: {total_online}s, measured uptime in the receipt: {}s",
+        receipt.measured_uptime
+    )?;
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Aligned tables, to read the report in a terminal.
+//!
+//! Columns are padded to their widest cell, cells which are too wide are cut off, and columns of
+//! amounts are aligned to the right so their decimals line up. The last column is never cut off,
+//! as nothing has to line up after it.
+
+use std::io::{self, Write};
+
+/// Maximum width of a column, in characters.
+pub const MAX_WIDTH: usize = 24;
+
+/// Write a table with a header line.
+pub fn write(out: &mut dyn Write, header: &[String], rows: &[Vec<String>]) -> io::Result<()> {
+    let last = header.len().saturating_sub(1);
+    let truncate = |idx: usize, cell: &str| {
+        if idx < last && cell.chars().count() > MAX_WIDTH {
+            let mut cut = cell.chars().take(MAX_WIDTH - 1).collect::<String>();
+            cut.push('…');
+            cut
+        } else {
+            cell.to_string()
+        }
+    };
+    let truncate_row = |row: &[String]| {
+        row.iter()
+            .enumerate()
+            .map(|(idx, cell)| truncate(idx, cell))
+            .collect::<Vec<_>>()
+    };
+    let header = truncate_row(header);
+    let rows = rows.iter().map(|row| truncate_row(row)).collect::<Vec<_>>();
+
+    let widths = (0..header.len())
+        .map(|idx| {
+            rows.iter()
+                .chain(std::iter::once(&header))
+                .filter_map(|row| row.get(idx))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let numeric = (0..header.len())
+        .map(|idx| {
+            rows.iter()
+                .filter_map(|row| row.get(idx))
+                .filter(|cell| !cell.is_empty() && *cell != "-")
+                .all(|cell| is_number(cell))
+        })
+        .collect::<Vec<_>>();
+
+    let line = |cells: &[String]| {
+        let mut line = cells
+            .iter()
+            .enumerate()
+            .map(|(idx, cell)| {
+                let pad = " ".repeat(widths[idx] - cell.chars().count());
+                if numeric[idx] {
+                    format!("{pad}{cell}")
+                } else {
+                    format!("{cell}{pad}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        line.truncate(line.trim_end().len());
+        line
+    };
+    writeln!(out, "{}", line(&header))?;
+    writeln!(
+        out,
+        "{}",
+        widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>()
+            .join("  ")
+    )?;
+    for row in &rows {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+/// Indicates a cell holds a number, such as an amount or a percentage.
+fn is_number(cell: &str) -> bool {
+    let digits = cell.strip_prefix('-').unwrap_or(cell);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '%'))
+}
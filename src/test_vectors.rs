@@ -0,0 +1,187 @@
+//! Test vectors of the math of this tool, for other implementations to check themselves against.
+//!
+//! Every vector is a receipt with the values this tool derives from it: the hash of the receipt as
+//! published which forms the memo of its payment, the expected reward under farming policies 1 and
+//! 2 with every intermediate step, and the uptime percentage. The receipts are fixed and cover the
+//! edge cases of the calculation, so the file only changes when the math does.
+
+use std::{fs, path::Path};
+
+use serde_json::{json, Value};
+
+use trace_titans::{
+    period::{Period, STANDARD_PERIOD_DURATION},
+    policy::{self, Policies, TitanSignal, UptimeSla},
+    receipt::{ReceiptSummary, ResourceRewards, DEFAULT_FARMING_POLICY_ID},
+    reward::RewardBreakdown,
+    Error, NodePeriodResult, PERCENTAGE_PRECISION, TFT_PRECISION,
+};
+
+use crate::memos;
+
+/// Period of the vectors.
+pub const PERIOD: u32 = 57;
+
+/// A titan receipt of a full period, which the vectors are variations of.
+fn base_receipt() -> Value {
+    json!({
+        "period": Period::at_offset(PERIOD as i64),
+        "node_id": 1,
+        "twin_id": 101,
+        "farm_id": 1,
+        "farm_name": "vectors",
+        "stellar_payout_address": "GAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+        "measured_uptime": STANDARD_PERIOD_DURATION,
+        "tft_connection_price": 80,
+        "cloud_units": {
+            "cu": 3.491851255562119,
+            "su": 1.3368103757628762,
+            "nu": 0.40081491093234534,
+        },
+        "resource_units": { "cru": 8, "mru": 32, "hru": 0, "sru": 1000 },
+        "resource_utilization": { "cru": 0, "mru": 0, "hru": 0, "sru": 0, "ip": 0 },
+        "reward": { "musd": 12161, "tft": 1520224712 },
+        "carbon_offset": { "musd": 10, "tft": 1000 },
+        "node_type": "CERTIFIED",
+        "resource_rewards": policy::TITAN.rewards,
+        "farming_policy_id": policy::TITAN.id,
+    })
+}
+
+/// Name, description and changes to the base receipt of every vector. A `null` change removes the
+/// field.
+fn variations() -> Vec<(&'static str, &'static str, Value)> {
+    vec![
+        (
+            "titan-full-uptime",
+            "certified node on farming policy 2, up the whole period",
+            json!({}),
+        ),
+        (
+            "titan-partial-uptime",
+            "up for a third of the period, the reward is scaled down",
+            json!({ "measured_uptime": STANDARD_PERIOD_DURATION / 3 }),
+        ),
+        (
+            "titan-over-uptime",
+            "measured up for longer than a standard period, the percentage is capped at 100%",
+            json!({ "measured_uptime": STANDARD_PERIOD_DURATION + 3600 }),
+        ),
+        (
+            "zero-uptime",
+            "never seen up in the period",
+            json!({ "measured_uptime": 0 }),
+        ),
+        (
+            "public-ips",
+            "uses 2 public IPs on average",
+            json!({
+                "resource_utilization": { "cru": 0, "mru": 0, "hru": 0, "sru": 0, "ip": 2.0 },
+            }),
+        ),
+        (
+            "fractional-cloud-units",
+            "cloud units which don't upscale to whole numbers, every step rounds down",
+            json!({ "cloud_units": { "cu": 0.1, "su": 2.675, "nu": 1e-8 } }),
+        ),
+        (
+            "float-cloud-units",
+            "cloud units of which the closest float is below the decimal value, they are upscaled \
+             from the decimal value",
+            json!({ "cloud_units": { "cu": 0.0029, "su": 1.0012, "nu": 0.0093 } }),
+        ),
+        (
+            "high-connection-price",
+            "connected at a TFT price of 1 USD",
+            json!({ "tft_connection_price": 1000 }),
+        ),
+        (
+            "diy-policy-1",
+            "DIY node on farming policy 1",
+            json!({
+                "node_type": "DIY",
+                "farming_policy_id": DEFAULT_FARMING_POLICY_ID,
+                "resource_rewards": ResourceRewards::default(),
+            }),
+        ),
+        (
+            "legacy-without-policy",
+            "old receipt without farming policy and reward rates, which default to policy 1",
+            json!({ "farming_policy_id": null, "resource_rewards": null }),
+        ),
+    ]
+}
+
+/// Build the vector of a receipt, with the titan policy of the given policies.
+fn vector(
+    name: &str,
+    description: &str,
+    receipt: Value,
+    policies: &Policies,
+) -> Result<Value, Error> {
+    let raw = serde_json::to_string(&receipt)?;
+    let summary = serde_json::from_str::<ReceiptSummary>(&raw)?;
+    let hash = memos::hash(raw.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    let result =
+        NodePeriodResult::from_receipt(&summary, policies, TitanSignal::default(), UptimeSla::Flag);
+    Ok(json!({
+        "name": name,
+        "description": description,
+        "receipt": receipt,
+        "published": raw,
+        "hash": hash,
+        "memo": memos::memo(raw.as_bytes()),
+        "rewards": {
+            "1": RewardBreakdown::calculate(&summary, &ResourceRewards::default()),
+            "2": RewardBreakdown::calculate(&summary, &policies.for_period(PERIOD).rewards),
+        },
+        "uptime_percentage": result.uptime_percentage,
+        "below_minimal_uptime": result.below_minimal_uptime,
+    }))
+}
+
+/// Write the test vectors to a file, calculated under the given policies.
+pub fn write(path: &Path, policies: &Policies) -> Result<(), Error> {
+    let mut vectors = Vec::new();
+    for (name, description, changes) in variations() {
+        let mut receipt = base_receipt();
+        let fields = receipt.as_object_mut().expect("Receipt is an object");
+        for (key, value) in changes.as_object().expect("Changes are an object") {
+            if value.is_null() {
+                fields.remove(key);
+            } else {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+        vectors.push(vector(name, description, receipt, policies)?);
+    }
+
+    let file = json!({
+        "description": concat!(
+            "Receipts with the values trace_titans derives from them. The hash is the BLAKE2b ",
+            "hash with a 32 byte digest of the receipt as published, the bytes of `published`, ",
+            "and the memo its base64 encoding. Amounts are in mUSD and TFT units, the uptime ",
+            "percentage in thousandths of a percent.",
+        ),
+        "tft_precision": TFT_PRECISION,
+        "percentage_precision": PERCENTAGE_PRECISION,
+        "period_duration": STANDARD_PERIOD_DURATION,
+        "policies": {
+            "1": ResourceRewards::default(),
+            "2": policies.for_period(PERIOD).rewards,
+        },
+        "titan_minimal_uptime": policies.for_period(PERIOD).minimal_uptime,
+        "vectors": vectors,
+    });
+    let content = format!("{}\n", serde_json::to_string_pretty(&file)?);
+    fs::write(path, &content).map_err(|e| format!("could not write {}: {e}", path.display()))?;
+    eprintln!(
+        "Wrote {} test vectors to {}",
+        file["vectors"].as_array().map_or(0, Vec::len),
+        path.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,239 @@
+//! Signed tokens of the amount owed to every farm.
+//!
+//! A farmer who is told an amount wants to check that it is the one in the published report, and
+//! not a typo in a message. With `publish site --sign-tokens <key>`, every farm gets a token with
+//! its farm, payout address, periods and owed amount, and the SHA-256 hash of the `report.json`
+//! they were taken from, signed with an SSH key. `verify-token` checks the signature against the
+//! published allowed signers and shows what the token says.
+//!
+//! A token is the payload JSON and the SSH signature, both base64url encoded and joined by a dot,
+//! so it fits in a single line of a message.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use trace_titans::{Error, NodeResult};
+
+use crate::{address_book, cli::Args, memos, opt_out, sha256};
+
+/// Namespace of the signature of a token, see `ssh-keygen -Y sign -n`.
+pub const SIGNATURE_NAMESPACE: &str = "trace-titans-tokens";
+
+/// What a token says about a farm.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Payload {
+    pub farm_id: u32,
+    pub payout_address: String,
+    pub from_period: u32,
+    pub to_period: u32,
+    /// Total TFT owed to the farm, negative if the farm received too much.
+    pub owed: String,
+    /// SHA-256 hash of the `report.json` of the published report, as hex.
+    pub report: String,
+}
+
+/// Write a signed token of every farm to `tokens/<farm_id>.txt` in the site directory, signed
+/// with the private key.
+pub fn write(
+    out_dir: &Path,
+    node_results: &[(u32, NodeResult)],
+    args: &Args,
+    key: &Path,
+) -> Result<(), Error> {
+    let report = fs::read(out_dir.join("report.json"))?;
+    let report = sha256::hex_digest(&report);
+    let dir = out_dir.join("tokens");
+    fs::create_dir_all(&dir).map_err(|e| format!("could not create {}: {e}", dir.display()))?;
+    let farms = address_book::build(node_results, &args.tolerances);
+    for farm in &farms {
+        let payload = Payload {
+            farm_id: farm.farm_id,
+            payout_address: farm.payout_address.clone(),
+            from_period: args.periods[0],
+            to_period: args.periods[args.periods.len() - 1],
+            owed: farm.total_owed.clone(),
+            report: report.clone(),
+        };
+        let token = sign(&payload, key)?;
+        fs::write(
+            dir.join(format!("{}.txt", farm.farm_id)),
+            format!("{token}\n"),
+        )?;
+    }
+    println!("{} farm token(s) written to {}", farms.len(), dir.display());
+    Ok(())
+}
+
+/// Sign the payload with the private key, returning the token.
+pub fn sign(payload: &Payload, key: &Path) -> Result<String, Error> {
+    let payload = serde_json::to_vec(payload)?;
+    let signature = opt_out::ssh_keygen(
+        &[
+            "-q".as_ref(),
+            "-Y".as_ref(),
+            "sign".as_ref(),
+            "-n".as_ref(),
+            SIGNATURE_NAMESPACE.as_ref(),
+            "-f".as_ref(),
+            key.as_os_str(),
+        ],
+        &payload,
+    )
+    .map_err(|e| format!("could not sign with {}: {e}", key.display()))?;
+    let armored = signature
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<String>();
+    let signature = decode(&armored).ok_or("ssh-keygen wrote an invalid signature")?;
+    Ok(format!("{}.{}", encode(&payload), encode(&signature)))
+}
+
+/// Check the signature of a token against an allowed signers file, returning what it says and
+/// who signed it.
+pub fn verify(token: &str, signers: &Path) -> Result<(Payload, String), Error> {
+    let invalid = |e: &str| format!("the token is not valid: {e}");
+    let (payload, signature) = token
+        .trim()
+        .split_once('.')
+        .and_then(|(payload, signature)| Some((decode(payload)?, decode(signature)?)))
+        .ok_or_else(|| invalid("expected two base64url parts joined by a dot"))?;
+
+    let armored = memos::base64(&signature)
+        .as_bytes()
+        .chunks(70)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let path = PathBuf::from(format!(
+        "{}/trace_titans-token-{}.sig",
+        std::env::temp_dir().display(),
+        std::process::id()
+    ));
+    fs::write(
+        &path,
+        format!("-----BEGIN SSH SIGNATURE-----\n{armored}\n-----END SSH SIGNATURE-----\n"),
+    )?;
+    let principal = opt_out::verify_signature(&path, &payload, signers, SIGNATURE_NAMESPACE);
+    let _ = fs::remove_file(&path);
+    let principal = principal.map_err(|e| invalid(&e))?;
+    let payload = serde_json::from_slice(&payload).map_err(|e| invalid(&e.to_string()))?;
+    Ok((payload, principal))
+}
+
+/// Check a token and print what it says.
+pub fn print(token: &str, signers: &Path) -> Result<(), Error> {
+    let (payload, principal) = verify(token, signers)?;
+    println!("Signed by {principal}");
+    println!(
+        "Farm {} with payout address {} is owed {} TFT for periods {} to {}",
+        payload.farm_id,
+        payload.payout_address,
+        payload.owed,
+        payload.from_period,
+        payload.to_period
+    );
+    println!(
+        "Made from the report with SHA-256 {}, compare it with the hash of report.json",
+        payload.report
+    );
+    Ok(())
+}
+
+/// Base64url encoding, without padding.
+fn encode(data: &[u8]) -> String {
+    memos::base64(data)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// Decode base64, in the url or standard alphabet, with or without padding.
+fn decode(data: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+    let data = data.trim_end_matches('=').as_bytes();
+    if data.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let n = chunk.iter().enumerate().try_fold(0u32, |n, (i, &c)| {
+            Some(n | (value(c)? as u32) << (18 - 6 * i))
+        })?;
+        out.extend(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::ledger::tests::TempDir;
+
+    #[test]
+    fn base64url_round_trip() {
+        for len in 0..8 {
+            let data = (0..len).map(|i| 250 - i as u8).collect::<Vec<_>>();
+            assert_eq!(decode(&encode(&data)), Some(data.clone()));
+            assert_eq!(decode(&memos::base64(&data)), Some(data));
+        }
+        assert!(!encode(&[0xfb, 0xff]).contains(['+', '/', '=']));
+        assert_eq!(decode("a"), None);
+        assert_eq!(decode("a*=="), None);
+    }
+
+    #[test]
+    fn signed_token() {
+        let dir = TempDir::new("token");
+        let key = dir.0.join("key");
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "wg", "-f"])
+            .arg(&key)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let public = fs::read_to_string(key.with_extension("pub")).unwrap();
+        let signers = dir.0.join("allowed_signers");
+        fs::write(&signers, format!("wg@example.com {public}")).unwrap();
+
+        let payload = Payload {
+            farm_id: 1,
+            payout_address: "G".to_string(),
+            from_period: 52,
+            to_period: 57,
+            owed: "12.5000000".to_string(),
+            report: sha256::hex_digest(b""),
+        };
+        let token = sign(&payload, &key).unwrap();
+        assert!(!token.contains(char::is_whitespace));
+        let (verified, principal) = verify(&token, &signers).unwrap();
+        assert_eq!(verified, payload);
+        assert_eq!(principal, "wg@example.com");
+
+        // A token of which the amount was changed is refused.
+        let (_, signature) = token.split_once('.').unwrap();
+        let changed = Payload {
+            owed: "125.0000000".to_string(),
+            ..payload
+        };
+        let forged = format!(
+            "{}.{signature}",
+            encode(&serde_json::to_vec(&changed).unwrap())
+        );
+        let err = verify(&forged, &signers).unwrap_err();
+        assert!(err.to_string().contains("not valid"), "{err}");
+        assert!(verify("not a token", &signers).is_err());
+    }
+}
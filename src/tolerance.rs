@@ -0,0 +1,66 @@
+//! Tolerances for comparing amounts.
+//!
+//! Converting floating point cloud units to integer amounts can cause differences of a few TFT
+//! units between the expected and the actual payout. Differences up to the tolerance of a check
+//! are treated as rounding noise and reported as 0.
+
+use crate::parse_tft;
+
+/// Tolerance of every check, in TFT units.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Tolerances {
+    /// Difference between the expected and the received payout of a node.
+    pub difference: u64,
+    /// Difference between the payout in a receipt and the payout recalculated with the reward
+    /// rates of the receipt.
+    pub recomputed: u64,
+    /// Difference between the total payout of the receipts of a period and the published total.
+    pub totals: u64,
+    /// Difference between the reward of a receipt and the reward calculated like earlier
+    /// versions did.
+    pub legacy: u64,
+}
+
+impl Tolerances {
+    /// Names of all checks, as used on the command line.
+    pub const CHECKS: [&'static str; 4] = ["difference", "recomputed", "totals", "legacy"];
+
+    /// Set the tolerance of a check given in the command line format: either an amount of TFT,
+    /// which sets the tolerance of every check, or `<check>=<amount>` for a single check.
+    pub fn set(&mut self, value: &str) -> Result<(), String> {
+        let (check, amount) = match value.split_once('=') {
+            Some((check, amount)) => (Some(check), amount),
+            None => (None, value),
+        };
+        let amount =
+            parse_tft(amount).ok_or_else(|| format!("invalid tolerance {amount}, expected TFT"))?;
+        match check {
+            None => {
+                self.difference = amount;
+                self.recomputed = amount;
+                self.totals = amount;
+                self.legacy = amount;
+            }
+            Some("difference") => self.difference = amount,
+            Some("recomputed") => self.recomputed = amount,
+            Some("totals") => self.totals = amount,
+            Some("legacy") => self.legacy = amount,
+            Some(check) => {
+                return Err(format!(
+                    "unknown check {check}, expected one of {}",
+                    Self::CHECKS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replace an amount by 0 if its absolute value is within the tolerance.
+pub fn denoise(amount: i64, tolerance: u64) -> i64 {
+    if amount.unsigned_abs() <= tolerance {
+        0
+    } else {
+        amount
+    }
+}
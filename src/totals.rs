@@ -0,0 +1,102 @@
+//! Cross-check of the receipts against the totals published by minting.
+//!
+//! Minting publishes the total payout of every period. If the receipts of a period add up to less
+//! than that, receipts are missing, and nodes without a receipt are silently absent from the
+//! report, so conclusions should not be drawn from it.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use trace_titans::{format_diff_tft, format_tft, parse_tft, pipeline::NodeReceipts, source, Error};
+
+/// Published total payout of every period, in TFT units.
+#[derive(Debug, Default)]
+pub struct PublishedTotals {
+    totals: BTreeMap<u32, u64>,
+}
+
+impl PublishedTotals {
+    /// Load the totals from a CSV file with the columns `period` and `total`, the latter in TFT.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let mut records = source::parse_csv(&content).into_iter();
+        let header = records
+            .next()
+            .ok_or_else(|| format!("{} is empty", path.display()))?;
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|c| c.trim() == name)
+                .ok_or_else(|| format!("{} has no {name} column", path.display()))
+        };
+        let (period_idx, total_idx) = (column("period")?, column("total")?);
+
+        let mut totals = BTreeMap::new();
+        for (line, record) in records.enumerate() {
+            let field = |idx: usize, name: &str| {
+                record
+                    .get(idx)
+                    .map(|v| v.trim())
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| {
+                        format!("missing {name} on line {} of {}", line + 2, path.display())
+                    })
+            };
+            let period = field(period_idx, "period")?;
+            let period = period
+                .parse::<u32>()
+                .map_err(|_| format!("invalid period {period} on line {}", line + 2))?;
+            let total = field(total_idx, "total")?;
+            let total = parse_tft(total)
+                .ok_or_else(|| format!("invalid total {total} on line {}", line + 2))?;
+            if totals.insert(period, total).is_some() {
+                return Err(
+                    format!("period {period} is listed twice in {}", path.display()).into(),
+                );
+            }
+        }
+        Ok(PublishedTotals { totals })
+    }
+}
+
+/// Check that the payouts of the receipts of every period add up to the published total, within
+/// the tolerance in TFT units.
+pub fn check(
+    node_receipts: &NodeReceipts,
+    periods: &[u32],
+    published: &PublishedTotals,
+    tolerance: u64,
+) -> Result<(), Error> {
+    let mut local = BTreeMap::<u32, u64>::new();
+    for parsed in node_receipts.values().flat_map(BTreeMap::values) {
+        *local.entry(parsed.period).or_default() += parsed.receipt.reward.tft;
+    }
+
+    let mut mismatches = Vec::new();
+    for &period in periods {
+        let Some(&total) = published.totals.get(&period) else {
+            eprintln!("Period {period}: no published total, the receipts are not cross-checked");
+            continue;
+        };
+        let sum = local.get(&period).copied().unwrap_or_default();
+        let difference = sum as i64 - total as i64;
+        if difference.unsigned_abs() > tolerance {
+            mismatches.push(format!(
+                "period {period}: the receipts pay out {} TFT, the published total is {} TFT ({} TFT)",
+                format_tft(sum),
+                format_tft(total),
+                format_diff_tft(difference)
+            ));
+        }
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the receipts don't match the published totals: {}",
+            mismatches.join("; ")
+        )
+        .into())
+    }
+}
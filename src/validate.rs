@@ -0,0 +1,240 @@
+//! Checks of the arguments against the environment before anything runs.
+//!
+//! Loading receipts can take a while, so a missing file or a filter which can't match should not
+//! only surface midway through a run, one at a time. All problems are collected upfront and
+//! reported together, with a suggestion where there is one.
+
+use std::path::Path;
+
+use trace_titans::{hardware::HardwareClass, policy, receipt::DEFAULT_FARMING_POLICY_ID, Error};
+
+use crate::{
+    cli::{Args, Command, ImportFrom},
+    labels::NodeLabels,
+    missing::MissingPeriod,
+    prices,
+};
+
+/// A problem with the arguments.
+struct Problem {
+    message: String,
+    hint: Option<String>,
+}
+
+/// Check the arguments, printing all problems on stderr.
+pub fn check(args: &Args) -> Result<(), Error> {
+    let mut problems = Vec::new();
+    let mut problem = |message: String, hint: Option<String>| {
+        problems.push(Problem { message, hint });
+    };
+
+    let mut inputs = vec![
+        ("--labels", args.labels.as_ref()),
+        ("--csv", args.csv.as_ref()),
+        ("--csv-mapping", args.csv_mapping.as_ref()),
+        ("--nodes", args.nodes.as_ref()),
+        ("--uptime-events", args.uptime_events.as_ref()),
+        ("--published-totals", args.published_totals.as_ref()),
+        ("--opt-outs", args.opt_outs.as_ref()),
+        ("--opt-outs-signers", args.opt_outs_signers.as_ref()),
+        ("--since", args.since.as_ref()),
+        ("--sign-tokens", args.sign_tokens.as_ref()),
+        ("--token-signers", args.token_signers.as_ref()),
+    ];
+    if let Some(ImportFrom::Csv(path)) = &args.import_from {
+        inputs.push(("--from-csv", Some(path)));
+    }
+    for (flag, path) in inputs {
+        if let Some(path) = path.filter(|path| !path.is_file()) {
+            problem(
+                format!("{flag} file {} doesn't exist", path.display()),
+                None,
+            );
+        }
+    }
+
+    if let Some(path) = args.prices.as_deref().filter(|from| !prices::is_url(from)) {
+        if !Path::new(path).is_file() {
+            problem(format!("--prices file {path} doesn't exist"), None);
+        }
+    }
+    if let Some(path) = args
+        .policy_rates
+        .as_deref()
+        .filter(|from| !prices::is_url(from))
+    {
+        if !Path::new(path).is_file() {
+            problem(format!("--policy-rates file {path} doesn't exist"), None);
+        }
+    }
+
+    let outputs = [
+        ("--output", args.output.as_ref()),
+        ("--address-book", args.address_book.as_ref()),
+        ("--html", args.html.as_ref()),
+        ("--findings", args.findings.as_ref()),
+        ("--issues", args.issues.as_ref()),
+        ("--invalid-receipts", args.invalid_receipts.as_ref()),
+        ("--digest", args.digest.as_ref()),
+        ("--out", args.out.as_ref()),
+        ("--runs", args.runs.as_ref()),
+    ];
+    for (flag, path) in outputs.into_iter().filter_map(|(f, p)| Some((f, p?))) {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+        if let Some(parent) = parent.filter(|parent| !parent.is_dir()) {
+            problem(
+                format!(
+                    "the directory {} of {flag} {} doesn't exist",
+                    parent.display(),
+                    path.display()
+                ),
+                Some("create it first".into()),
+            );
+        }
+    }
+
+    if let Command::CompareUptime { path } = &args.command {
+        if !path.is_file() {
+            problem(format!("{} doesn't exist", path.display()), None);
+        }
+    }
+    if let Command::ProfileSchema { dir } = &args.command {
+        if !dir.is_dir() {
+            problem(format!("{} doesn't exist", dir.display()), None);
+        }
+    }
+    // Periods which are skipped or fetched when missing are handled once the run starts.
+    let periods = args
+        .scanned_periods()
+        .iter()
+        .filter(|period| args.missing.get(**period) == MissingPeriod::Error)
+        .collect::<Vec<_>>();
+    if args.csv.is_none() && args.at.is_none() && !args.scanned_periods().is_empty() {
+        let root = args.receipts_dir();
+        if !root.is_dir() && !args.missing.fetches() {
+            problem(
+                format!("the receipts directory {} doesn't exist", root.display()),
+                None,
+            );
+        } else {
+            let missing = periods
+                .iter()
+                .filter(|period| !args.period_dirs.path(&root, ***period).is_dir())
+                .map(|period| period.to_string())
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                let available = args
+                    .period_dirs
+                    .find(&root)
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>();
+                problem(
+                    format!(
+                        "{} has no directory for period(s) {}",
+                        root.display(),
+                        missing.join(", ")
+                    ),
+                    Some(if available.is_empty() {
+                        "it has no period directories, see --receipts-dir and --period-dirs".into()
+                    } else {
+                        format!(
+                            "it has period directories {}, see --period, --from-period, \
+                             --to-period and --missing",
+                            available.join(", ")
+                        )
+                    }),
+                );
+            }
+        }
+    }
+    for dir in args.fallback_dirs.iter().filter(|dir| !dir.is_dir()) {
+        problem(
+            format!("--fallback-dir {} doesn't exist", dir.display()),
+            None,
+        );
+    }
+
+    if let Some(labels) = args
+        .labels
+        .as_deref()
+        .filter(|path| path.is_file())
+        .and_then(|path| NodeLabels::load(path).ok())
+    {
+        let known = labels.all();
+        for label in args
+            .label_filter
+            .iter()
+            .filter(|label| !known.contains(label.as_str()))
+        {
+            let closest = known
+                .iter()
+                .map(|known| (edit_distance(label, known), known))
+                .filter(|(distance, _)| *distance <= 2)
+                .min();
+            problem(
+                format!("no node has the --label {label}, so nothing would be reported"),
+                closest.map(|(_, known)| format!("did you mean {known}?")),
+            );
+        }
+    }
+
+    if args.class_filter.contains(&HardwareClass::Unknown) {
+        problem(
+            "--class unknown never matches, nodes without a receipt are never a titan".into(),
+            Some(format!(
+                "use --class {} or --class {}",
+                HardwareClass::TitanV2_1,
+                HardwareClass::Nonstandard
+            )),
+        );
+    }
+
+    if let Some(query) = &args.query {
+        let known = [DEFAULT_FARMING_POLICY_ID, policy::TITAN.id];
+        for id in query
+            .compared_with("policy")
+            .into_iter()
+            .filter(|id| !known.iter().any(|known| *known as f64 == *id))
+        {
+            problem(
+                format!("--where compares policy with {id}, which is not a known farming policy"),
+                Some(format!(
+                    "known farming policies are {} and {}",
+                    known[0], known[1]
+                )),
+            );
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for Problem { message, hint } in &problems {
+        eprintln!("- {message}");
+        if let Some(hint) = hint {
+            eprintln!("  {hint}");
+        }
+    }
+    Err(format!("found {} problem(s) with the arguments", problems.len()).into())
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,117 @@
+//! xxHash64 hashing, as used by Substrate chains to locate storage entries.
+//!
+//! TFChain derives the storage keys of its modules from `twox128` hashes of their names. Like
+//! [`crate::blake2b`], speed doesn't matter and a small implementation is preferred over an
+//! additional dependency.
+
+const PRIME_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+/// The 128 bit `twox128` hash of Substrate: xxHash64 with seeds 0 and 1, little endian.
+pub fn twox128(data: &[u8]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    out[..8].copy_from_slice(&xxh64(data, 0).to_le_bytes());
+    out[8..].copy_from_slice(&xxh64(data, 1).to_le_bytes());
+    out
+}
+
+/// xxHash64 of the data with a seed.
+pub fn xxh64(data: &[u8], seed: u64) -> u64 {
+    let word = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().expect("Words are 8 bytes"));
+
+    let mut stripes = data.chunks_exact(32);
+    let mut h = if data.len() >= 32 {
+        let mut v = [
+            seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+            seed.wrapping_add(PRIME_2),
+            seed,
+            seed.wrapping_sub(PRIME_1),
+        ];
+        for stripe in stripes.by_ref() {
+            for (lane, bytes) in v.iter_mut().zip(stripe.chunks_exact(8)) {
+                *lane = round(*lane, word(bytes));
+            }
+        }
+        let mut h = v[0]
+            .rotate_left(1)
+            .wrapping_add(v[1].rotate_left(7))
+            .wrapping_add(v[2].rotate_left(12))
+            .wrapping_add(v[3].rotate_left(18));
+        for lane in v {
+            h = (h ^ round(0, lane))
+                .wrapping_mul(PRIME_1)
+                .wrapping_add(PRIME_4);
+        }
+        h
+    } else {
+        seed.wrapping_add(PRIME_5)
+    };
+    h = h.wrapping_add(data.len() as u64);
+
+    let mut rest = stripes.remainder();
+    while rest.len() >= 8 {
+        h ^= round(0, word(&rest[..8]));
+        h = h
+            .rotate_left(27)
+            .wrapping_mul(PRIME_1)
+            .wrapping_add(PRIME_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let half = u32::from_le_bytes(rest[..4].try_into().expect("Half words are 4 bytes"));
+        h ^= (half as u64).wrapping_mul(PRIME_1);
+        h = h
+            .rotate_left(23)
+            .wrapping_mul(PRIME_2)
+            .wrapping_add(PRIME_3);
+        rest = &rest[4..];
+    }
+    for byte in rest {
+        h ^= (*byte as u64).wrapping_mul(PRIME_5);
+        h = h.rotate_left(11).wrapping_mul(PRIME_1);
+    }
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(PRIME_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(PRIME_3);
+    h ^ (h >> 32)
+}
+
+fn round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME_1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(xxh64(b"", 0), 0xef46_db37_51d8_e999);
+        assert_eq!(xxh64(b"a", 0), 0xd24e_c4f1_a98c_6e5b);
+        assert_eq!(xxh64(b"abc", 0), 0x44bc_2cf5_ad77_0999);
+        // Over 32 bytes, so the input is read in stripes.
+        let long = b"Nobody inspects the spammish repetition";
+        assert_eq!(xxh64(long, 0), 0xfbce_a83c_8a37_8bf1);
+    }
+
+    #[test]
+    fn substrate_storage_prefixes() {
+        let hex = |name: &str| -> String {
+            twox128(name.as_bytes())
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect()
+        };
+        assert_eq!(hex("System"), "26aa394eea5630e07c48ae0c9558cef7");
+        assert_eq!(hex("Account"), "b99d880ec681799c0cf30e8886371da9");
+        assert_eq!(hex("Timestamp"), "f0c365c3cf59d671eb72da0e7a4113c4");
+        assert_eq!(hex("Now"), "9f1f0515f462cdcf84e0f1d6045dfcbb");
+    }
+}
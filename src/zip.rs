@@ -0,0 +1,147 @@
+//! Writing of ZIP archives.
+//!
+//! Only what is needed to bundle a few small files is implemented: entries are stored without
+//! compression, which every unzip tool can open.
+
+use std::{fs, path::Path};
+
+use trace_titans::{date, Error};
+
+/// An archive being built in memory.
+#[derive(Default)]
+pub struct Zip {
+    data: Vec<u8>,
+    /// Central directory entries of the files added so far.
+    directory: Vec<u8>,
+    entries: u16,
+}
+
+impl Zip {
+    /// Add a file to the archive, with a `/` separated path.
+    pub fn add(&mut self, name: &str, content: &[u8]) -> Result<(), Error> {
+        if self.entries == u16::MAX || content.len() >= u32::MAX as usize {
+            return Err("too many or too large files for a ZIP archive".into());
+        }
+        let (time, date) = dos_timestamp(date::now());
+        let crc = crc32(content);
+        let offset = self.data.len() as u32;
+        // Version needed, flags (UTF-8 names), method (stored), time, date, CRC and sizes.
+        let mut fields = Vec::new();
+        fields.extend(10u16.to_le_bytes());
+        fields.extend((1u16 << 11).to_le_bytes());
+        fields.extend(0u16.to_le_bytes());
+        fields.extend(time.to_le_bytes());
+        fields.extend(date.to_le_bytes());
+        fields.extend(crc.to_le_bytes());
+        fields.extend((content.len() as u32).to_le_bytes());
+        fields.extend((content.len() as u32).to_le_bytes());
+        fields.extend((name.len() as u16).to_le_bytes());
+        // No extra field.
+        fields.extend(0u16.to_le_bytes());
+
+        self.data.extend(0x0403_4b50u32.to_le_bytes());
+        self.data.extend(&fields);
+        self.data.extend(name.as_bytes());
+        self.data.extend(content);
+
+        self.directory.extend(0x0201_4b50u32.to_le_bytes());
+        // Version made by.
+        self.directory.extend(10u16.to_le_bytes());
+        self.directory.extend(&fields);
+        // No comment, disk 0, no internal or external attributes.
+        self.directory.extend(0u16.to_le_bytes());
+        self.directory.extend(0u16.to_le_bytes());
+        self.directory.extend(0u16.to_le_bytes());
+        self.directory.extend(0u32.to_le_bytes());
+        self.directory.extend(offset.to_le_bytes());
+        self.directory.extend(name.as_bytes());
+        self.entries += 1;
+        Ok(())
+    }
+
+    /// Write the archive to a file.
+    pub fn write(mut self, path: &Path) -> Result<(), Error> {
+        let offset = self.data.len() as u32;
+        let size = self.directory.len() as u32;
+        self.data.append(&mut self.directory);
+        // End of central directory: disk numbers, entry counts, size and offset, no comment.
+        self.data.extend(0x0605_4b50u32.to_le_bytes());
+        self.data.extend(0u16.to_le_bytes());
+        self.data.extend(0u16.to_le_bytes());
+        self.data.extend(self.entries.to_le_bytes());
+        self.data.extend(self.entries.to_le_bytes());
+        self.data.extend(size.to_le_bytes());
+        self.data.extend(offset.to_le_bytes());
+        self.data.extend(0u16.to_le_bytes());
+        fs::write(path, &self.data)
+            .map_err(|e| format!("could not write {}: {e}", path.display()).into())
+    }
+}
+
+/// CRC-32 of the data, as used by ZIP.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// MS-DOS time and date of a unix timestamp, in UTC.
+fn dos_timestamp(timestamp: i64) -> (u16, u16) {
+    // `2023-06-08T13:45:00Z`
+    let formatted = date::format_timestamp(timestamp);
+    let field = |range: std::ops::Range<usize>| formatted[range].parse::<u16>().unwrap_or(0);
+    let time = field(11..13) << 11 | field(14..16) << 5 | (field(17..19) / 2);
+    let date = field(0..4).saturating_sub(1980) << 9 | field(5..7) << 5 | field(8..10);
+    (time, date)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn checksum_and_timestamp() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+        // 2023-06-08T13:45:02Z
+        assert_eq!(
+            dos_timestamp(1_686_231_902),
+            (13 << 11 | 45 << 5 | 1, 43 << 9 | 6 << 5 | 8)
+        );
+    }
+
+    #[test]
+    fn unzip_reads_the_archive() {
+        let path = std::env::temp_dir().join(format!("trace_titans-{}.zip", std::process::id()));
+        let mut zip = Zip::default();
+        zip.add("receipts/1.json", b"{\"node\": 1}").unwrap();
+        zip.add("empty.txt", b"").unwrap();
+        zip.write(&path).unwrap();
+
+        let test = Command::new("unzip")
+            .arg("-tq")
+            .arg(&path)
+            .output()
+            .unwrap();
+        let read = Command::new("unzip")
+            .arg("-p")
+            .arg(&path)
+            .arg("receipts/1.json")
+            .output()
+            .unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(test.status.success(), "{test:?}");
+        assert_eq!(read.stdout, b"{\"node\": 1}");
+    }
+}